@@ -0,0 +1,202 @@
+use std::{
+    env,
+    fs::File,
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+    time::Duration,
+};
+
+use eden_chess::{
+    control::VariantKind,
+    model::arena::{load_openings, run_match, Adjudication, Contestant, MatchConfig, Sprt},
+};
+use flexi_logger::Logger;
+
+use log::*;
+
+const USAGE: &str = "Usage: eden_chess_match --first <player> --second <player> [options]
+
+Players are engine:<level>, external:<command> or random.
+
+Options:
+  --first-name <name>, --second-name <name>   names for the PGN tags
+  --games <n>                  games to play (default 100)
+  --openings <file.epd|file.pgn>
+  --movetime <ms>              time per move for external engines (default 100)
+  --concurrency <n>            games played at once (default 1)
+  --max-moves <n>              draw after this many full moves
+  --resign <cp> <moves>        win once both engines score a side this far ahead
+  --draw <after> <cp> <moves>  draw once both engines score the game this close
+  --tablebase                  decide K+Q, K+R and K+P vs K by the built-in tables
+  --sprt <elo0> <elo1>         stop once the test is decided
+  --alpha <a>, --beta <b>      SPRT error rates (default 0.05)
+  --pgn <file>                 where games are written (default match.pgn)
+  --event <name>
+  --variant <name>             king-of-the-hill, three-check, antichess, atomic,
+                               horde, crazyhouse or capablanca";
+
+fn main() {
+    let _logger = Logger::try_with_str("warn")
+        .unwrap()
+        .log_to_stderr()
+        .start()
+        .unwrap();
+    let (config, pgn_path) = match parse_args(env::args().skip(1).collect()) {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("{}\n\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    let mut pgn = match File::create(&pgn_path) {
+        Ok(file) => file,
+        Err(e) => {
+            eprintln!("Could not create {}: {}", pgn_path.display(), e);
+            process::exit(1);
+        }
+    };
+
+    println!(
+        "{} vs {}, {} games",
+        config.first.name, config.second.name, config.games
+    );
+    let tally = run_match(&config, |record, tally| {
+        if let Err(e) = pgn.write_all(record.pgn.as_bytes()) {
+            error!("Could not write game {}: {}", record.round, e);
+        }
+        println!(
+            "Game {} ({}): {:?}, {}. Score {}-{}-{}",
+            record.round,
+            if record.first_is_white {
+                "first as white"
+            } else {
+                "first as black"
+            },
+            record.outcome,
+            record.reason,
+            tally.wins,
+            tally.losses,
+            tally.draws
+        );
+    });
+
+    println!(
+        "\n{} vs {}: +{} -{} ={} ({:.1}%)",
+        config.first.name,
+        config.second.name,
+        tally.wins,
+        tally.losses,
+        tally.draws,
+        tally.score() * 100.0
+    );
+    match tally.elo() {
+        Some((elo, margin)) => println!("Elo difference: {:.1} +/- {:.1}", elo, margin),
+        None => println!("Elo difference: unknown, every game was won or lost"),
+    }
+    if let Some(sprt) = config.sprt {
+        let (lower, upper) = sprt.bounds();
+        let verdict = match sprt.verdict(&tally) {
+            Some(verdict) => format!("{:?} accepted", verdict),
+            None => "undecided".to_string(),
+        };
+        println!(
+            "SPRT [{}, {}]: LLR {:.2} ({:.2}, {:.2}), {}",
+            sprt.elo0,
+            sprt.elo1,
+            sprt.llr(&tally),
+            lower,
+            upper,
+            verdict
+        );
+    }
+    println!("Games written to {}", pgn_path.display());
+}
+
+fn parse_args(args: Vec<String>) -> Result<(MatchConfig, PathBuf), String> {
+    let mut first = None;
+    let mut second = None;
+    let mut first_name = None;
+    let mut second_name = None;
+    let mut config = MatchConfig {
+        first: Contestant::parse("random")?,
+        second: Contestant::parse("random")?,
+        games: 100,
+        openings: Vec::new(),
+        movetime: Duration::from_millis(100),
+        concurrency: 1,
+        adjudication: Adjudication::default(),
+        sprt: None,
+        event: "eden_chess match".to_string(),
+        variant: VariantKind::Standard,
+    };
+    let mut alpha_beta = (0.05, 0.05);
+    let mut pgn = PathBuf::from("match.pgn");
+
+    let mut args = args.into_iter();
+    let mut next = |name: &str| -> Result<String, String> {
+        args.next().ok_or_else(|| format!("{} needs a value", name))
+    };
+    fn number<T: std::str::FromStr>(name: &str, value: String) -> Result<T, String> {
+        value
+            .parse()
+            .map_err(|_| format!("{} expects a number, got {:?}", name, value))
+    }
+    while let Ok(arg) = next("") {
+        match arg.as_str() {
+            "--first" => first = Some(Contestant::parse(&next(&arg)?)?),
+            "--second" => second = Some(Contestant::parse(&next(&arg)?)?),
+            "--first-name" => first_name = Some(next(&arg)?),
+            "--second-name" => second_name = Some(next(&arg)?),
+            "--games" => config.games = number(&arg, next(&arg)?)?,
+            "--openings" => config.openings = load_openings(Path::new(&next(&arg)?))?,
+            "--movetime" => config.movetime = Duration::from_millis(number(&arg, next(&arg)?)?),
+            "--concurrency" => config.concurrency = number(&arg, next(&arg)?)?,
+            "--max-moves" => config.adjudication.max_moves = Some(number(&arg, next(&arg)?)?),
+            "--resign" => {
+                config.adjudication.resign =
+                    Some((number(&arg, next(&arg)?)?, number(&arg, next(&arg)?)?))
+            }
+            "--draw" => {
+                config.adjudication.draw = Some((
+                    number(&arg, next(&arg)?)?,
+                    number(&arg, next(&arg)?)?,
+                    number(&arg, next(&arg)?)?,
+                ))
+            }
+            "--tablebase" => config.adjudication.tablebase = true,
+            "--sprt" => {
+                config.sprt = Some(Sprt::new(
+                    number(&arg, next(&arg)?)?,
+                    number(&arg, next(&arg)?)?,
+                ))
+            }
+            "--alpha" => alpha_beta.0 = number(&arg, next(&arg)?)?,
+            "--beta" => alpha_beta.1 = number(&arg, next(&arg)?)?,
+            "--pgn" => pgn = PathBuf::from(next(&arg)?),
+            "--event" => config.event = next(&arg)?,
+            "--variant" => {
+                let name = next(&arg)?;
+                config.variant = VariantKind::from_pgn_name(&name)
+                    .ok_or_else(|| format!("Unknown variant {:?}", name))?
+            }
+            other => return Err(format!("Unknown option {:?}", other)),
+        }
+    }
+
+    config.first = first.ok_or("--first is needed")?;
+    config.second = second.ok_or("--second is needed")?;
+    if let Some(name) = first_name {
+        config.first.name = name;
+    }
+    if let Some(name) = second_name {
+        config.second.name = name;
+    }
+    if config.first.name == config.second.name {
+        config.second.name.push_str(" (2)");
+    }
+    if let Some(sprt) = &mut config.sprt {
+        (sprt.alpha, sprt.beta) = alpha_beta;
+    }
+    Ok((config, pgn))
+}
@@ -0,0 +1,47 @@
+use std::{env, process, sync::atomic::AtomicBool};
+
+use eden_chess::server::{Server, DEFAULT_PORT};
+use flexi_logger::Logger;
+
+use log::*;
+
+const USAGE: &str = "Usage: eden_chess_server [--port <port>] [--threads <threads>]";
+
+fn main() {
+    let _logger = Logger::try_with_str("info")
+        .unwrap()
+        .log_to_stdout()
+        .start()
+        .unwrap();
+    let mut port = DEFAULT_PORT;
+    let mut threads = 4;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let value = args.next();
+        let parsed = match (arg.as_str(), value.as_deref().map(str::parse)) {
+            ("--port", Some(Ok(value))) => {
+                port = value as u16;
+                value == port as usize
+            }
+            ("--threads", Some(Ok(value))) => {
+                threads = value;
+                true
+            }
+            _ => false,
+        };
+        if !parsed {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+    }
+
+    let server = match Server::bind(port, threads) {
+        Ok(server) => server,
+        Err(e) => {
+            error!("{}", e);
+            eprintln!("{}", e);
+            process::exit(1);
+        }
+    };
+    server.run(&AtomicBool::new(false));
+}
@@ -1,16 +1,17 @@
 use core::panic;
 use std::{
     collections::HashMap,
-    fmt::{Debug, Write},
+    fmt::{Debug, Display},
     isize,
     iter::zip,
     ops::{Add, Sub},
+    time::Duration,
 };
 
 use log::{debug, warn};
 use ratatui::layout::Positions;
 
-use crate::model::{Piece, Side};
+use crate::model::{Material, Piece, PieceType, Side};
 
 #[derive(PartialEq, Eq, Hash, Clone, Copy)]
 pub struct CBPosition {
@@ -24,32 +25,84 @@ impl Debug for CBPosition {
     }
 }
 type Board = HashMap<CBPosition, Option<Piece>>;
+
+/// The number of files and ranks of a board. Files are lettered from `a` and ranks numbered
+/// from 1, white starting on the low ranks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Geometry {
+    pub files: u8,
+    pub ranks: u8,
+}
+impl Geometry {
+    pub const STANDARD: Geometry = Geometry { files: 8, ranks: 8 };
+    /// The biggest board that can be lettered and numbered
+    pub const LARGEST: Geometry = Geometry {
+        files: 26,
+        ranks: 26,
+    };
+
+    /// The geometry of a board, from the squares it has.
+    pub fn of(board: &Board) -> Geometry {
+        let files = board.keys().map(|pos| pos.col).max().unwrap_or('h');
+        let ranks = board.keys().map(|pos| pos.row).max().unwrap_or(8);
+        Geometry {
+            files: files as u8 - b'a' + 1,
+            ranks: ranks as u8,
+        }
+    }
+    pub fn last_file(&self) -> char {
+        (b'a' + self.files - 1) as char
+    }
+    pub fn files(&self) -> std::ops::RangeInclusive<char> {
+        'a'..=self.last_file()
+    }
+    pub fn ranks(&self) -> std::ops::RangeInclusive<usize> {
+        1..=self.ranks as usize
+    }
+    pub fn contains(&self, pos: CBPosition) -> bool {
+        self.files().contains(&pos.col) && self.ranks().contains(&pos.row)
+    }
+    /// Every square, rank by rank from a1.
+    pub fn squares(&self) -> Vec<CBPosition> {
+        self.ranks()
+            .flat_map(|row| self.files().map(move |col| CBPosition { col, row }))
+            .collect()
+    }
+}
+impl Display for Geometry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}x{}", self.files, self.ranks)
+    }
+}
+
 impl CBPosition {
-    pub fn get_vertical(&self) -> Vec<CBPosition> {
+    pub fn get_vertical(&self, geometry: Geometry) -> Vec<CBPosition> {
         let mut positions: Vec<CBPosition> = Vec::new();
-        for row in 1..=8 {
+        for row in geometry.ranks() {
             positions.push(CBPosition { col: self.col, row });
         }
         positions = positions.into_iter().filter(|x| x != self).collect();
         positions
     }
-    pub fn get_horizontal(&self) -> Vec<CBPosition> {
+    pub fn get_horizontal(&self, geometry: Geometry) -> Vec<CBPosition> {
         let mut positions: Vec<CBPosition> = Vec::new();
-        for col in 'a'..='h' {
+        for col in geometry.files() {
             positions.push(CBPosition { col, row: self.row });
         }
         positions = positions.into_iter().filter(|x| x != self).collect();
         positions
     }
-    pub fn get_diagonals(&self) -> Vec<CBPosition> {
+    pub fn get_diagonals(&self, geometry: Geometry) -> Vec<CBPosition> {
         let mut positions: Vec<CBPosition> = Vec::new();
-        positions.append(&mut self.beyond(Direction::NorthEast));
-        positions.append(&mut self.beyond(Direction::NorthWest));
-        positions.append(&mut self.beyond(Direction::SouthEast));
-        positions.append(&mut self.beyond(Direction::SouthWest));
+        positions.append(&mut self.beyond(Direction::NorthEast, geometry));
+        positions.append(&mut self.beyond(Direction::NorthWest, geometry));
+        positions.append(&mut self.beyond(Direction::SouthEast, geometry));
+        positions.append(&mut self.beyond(Direction::SouthWest, geometry));
         positions = positions.into_iter().filter(|x| x != self).collect();
         positions
     }
+    /// The square the offset away, if it is on the largest board. Smaller boards don't have
+    /// every such square, so check the board has it.
     pub fn get_offset(&self, row_offset: isize, col_offset: isize) -> Option<CBPosition> {
         let new_row = if row_offset < 0 {
             self.row as isize - row_offset.abs()
@@ -69,7 +122,11 @@ impl CBPosition {
             return None;
         };
 
-        if new_col < 'a' || new_col > 'h' || new_row > 8 || new_row < 1 {
+        if new_col < 'a'
+            || new_col > Geometry::LARGEST.last_file()
+            || new_row > Geometry::LARGEST.ranks as isize
+            || new_row < 1
+        {
             // debug!(
             //     "Returning none from get offset, origin:{:?}, offset:{},{}, new_col:{}, new_row:{}",
             //     self, row_offset, col_offset, new_col, new_row
@@ -126,112 +183,83 @@ impl CBPosition {
             vec.push(new_pos)
         }
     }
-    pub fn beyond(&self, dir: Direction) -> Vec<CBPosition> {
-        match dir {
-            Direction::North => Self::north_of(&self),
-            Direction::NorthEast => Self::northeast_of(&self),
-            Direction::East => Self::east_of(&self),
-            Direction::SouthEast => Self::southeast_of(&self),
-            Direction::South => Self::south_of(&self),
-            Direction::SouthWest => Self::southwest_of(&self),
-            Direction::West => Self::west_of(&self),
-            Direction::NorthWest => Self::northwest_of(&self),
-        }
-    }
-    pub fn east_of(pos: &CBPosition) -> Vec<CBPosition> {
-        let mut positions: Vec<CBPosition> = Vec::new();
-        let start_bound = (pos.col as u8 + 1) as char;
-        for col in start_bound..='h' {
-            positions.push(CBPosition { col, row: pos.row })
-        }
-        positions
-    }
-    pub fn north_of(pos: &CBPosition) -> Vec<CBPosition> {
-        let mut positions: Vec<CBPosition> = Vec::new();
-        for row in (pos.row + 1)..=8 {
-            positions.push(CBPosition { col: pos.col, row })
-        }
-        positions
-    }
-    pub fn south_of(pos: &CBPosition) -> Vec<CBPosition> {
-        let mut positions: Vec<CBPosition> = Vec::new();
-        for row in 1..pos.row {
-            positions.push(CBPosition { col: pos.col, row })
-        }
-        positions
-    }
-    pub fn west_of(pos: &CBPosition) -> Vec<CBPosition> {
-        let mut positions: Vec<CBPosition> = Vec::new();
-        for col in 'a'..pos.col {
-            positions.push(CBPosition { col, row: pos.row })
-        }
-        positions
-    }
-    pub fn northeast_of(pos: &CBPosition) -> Vec<CBPosition> {
+    pub fn beyond(&self, dir: Direction, geometry: Geometry) -> Vec<CBPosition> {
+        let (row_offset, col_offset) = match dir {
+            Direction::North => (1, 0),
+            Direction::NorthEast => (1, 1),
+            Direction::East => (0, 1),
+            Direction::SouthEast => (-1, 1),
+            Direction::South => (-1, 0),
+            Direction::SouthWest => (-1, -1),
+            Direction::West => (0, -1),
+            Direction::NorthWest => (1, -1),
+        };
         let mut positions: Vec<CBPosition> = Vec::new();
-        let mut cur_pos = *pos;
-
-        while let Some(new_pos) = cur_pos.get_offset(1, 1) {
+        let mut cur_pos = *self;
+        while let Some(new_pos) = cur_pos
+            .get_offset(row_offset, col_offset)
+            .filter(|pos| geometry.contains(*pos))
+        {
             positions.push(new_pos);
             cur_pos = new_pos;
         }
         positions
     }
-    pub fn southeast_of(pos: &CBPosition) -> Vec<CBPosition> {
-        let mut positions: Vec<CBPosition> = Vec::new();
-        let mut cur_pos = *pos;
-
-        while let Some(new_pos) = cur_pos.get_offset(1, -1) {
-            positions.push(new_pos);
-            cur_pos = new_pos;
+    /// Cursor movement is relative to the screen, so a flipped board mirrors it.
+    pub fn move_cursor_right(&mut self, flipped: bool, geometry: Geometry) {
+        if flipped {
+            self.step_cursor_west()
+        } else {
+            self.step_cursor_east(geometry)
         }
-
-        positions
     }
-    pub fn northwest_of(pos: &CBPosition) -> Vec<CBPosition> {
-        let mut positions: Vec<CBPosition> = Vec::new();
-        let mut cur_pos = *pos;
-        while let Some(new_pos) = cur_pos.get_offset(-1, 1) {
-            positions.push(new_pos);
-            cur_pos = new_pos;
+    pub fn move_cursor_left(&mut self, flipped: bool, geometry: Geometry) {
+        if flipped {
+            self.step_cursor_east(geometry)
+        } else {
+            self.step_cursor_west()
         }
-        positions
     }
-    pub fn southwest_of(pos: &CBPosition) -> Vec<CBPosition> {
-        let mut positions: Vec<CBPosition> = Vec::new();
-        let mut cur_pos = *pos;
-        while let Some(new_pos) = cur_pos.get_offset(-1, -1) {
-            positions.push(new_pos);
-            cur_pos = new_pos;
+    pub fn move_cursor_down(&mut self, flipped: bool, geometry: Geometry) {
+        if flipped {
+            self.step_cursor_north(geometry)
+        } else {
+            self.step_cursor_south()
+        }
+    }
+    pub fn move_cursor_up(&mut self, flipped: bool, geometry: Geometry) {
+        if flipped {
+            self.step_cursor_south()
+        } else {
+            self.step_cursor_north(geometry)
         }
-        positions
     }
-    pub fn move_cursor_right(&mut self) {
+    fn step_cursor_east(&mut self, geometry: Geometry) {
         let new_col = char_add(self.col, 1);
-        if new_col > 'h' {
-            warn!("Cursor trying to escape right!");
+        if new_col > geometry.last_file() {
+            warn!("Cursor trying to escape east!");
             return;
         };
         self.col = new_col
     }
-    pub fn move_cursor_left(&mut self) {
+    fn step_cursor_west(&mut self) {
         let new_col = char_sub(self.col, 1);
         if new_col < 'a' {
-            warn!("Cursor trying to escape left!");
+            warn!("Cursor trying to escape west!");
             return;
         };
         self.col = new_col
     }
-    pub fn move_cursor_down(&mut self) {
+    fn step_cursor_south(&mut self) {
         if self.row == 1 {
-            warn!("Cursor trying to escape up!");
+            warn!("Cursor trying to escape south!");
             return;
         }
         self.row = self.row - 1
     }
-    pub fn move_cursor_up(&mut self) {
-        if self.row == 8 {
-            warn!("Cursor trying to escape down!");
+    fn step_cursor_north(&mut self, geometry: Geometry) {
+        if self.row >= geometry.ranks as usize {
+            warn!("Cursor trying to escape north!");
             return;
         }
         self.row = self.row + 1
@@ -287,22 +315,27 @@ impl From<(char, usize)> for CBPosition {
         CBPosition { col, row }
     }
 }
+impl std::str::FromStr for CBPosition {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chars = s.chars();
+        let col = chars.next().filter(|c| c.is_ascii_lowercase());
+        let row = chars.as_str().parse::<usize>().ok();
+        match (col, row) {
+            (Some(col), Some(row))
+                if !chars.as_str().starts_with(['0', '+'])
+                    && Geometry::LARGEST.contains(CBPosition { col, row }) =>
+            {
+                Ok(CBPosition { col, row })
+            }
+            _ => Err(format!("{:?} is not a square on the board", s)),
+        }
+    }
+}
 impl From<&'static str> for CBPosition {
     fn from(value: &'static str) -> Self {
-        if value.len() != 2 {
-            panic!("String is not the correct length!")
-        }
-        let chars: Vec<char> = value.chars().collect();
-        let col = chars[0];
-        let row: usize =
-            str::parse::<usize>(&chars[1].to_string()).expect("second char should be a number!");
-        if col < 'a' || col > 'h' {
-            panic!("Col should be between a and h!")
-        }
-        if row < 1 || row > 8 {
-            panic!("row should be between 1 and 8!")
-        }
-        CBPosition { col, row }
+        value.parse().expect("Should be a square such as e4")
     }
 }
 // impl PartialOrd for CBPosition {
@@ -392,55 +425,759 @@ pub fn push_if_exists<T>(vec: &mut Vec<T>, new_obj: Option<T>) {
 }
 
 #[derive(Debug)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serialize",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
 pub enum UiMsg {
-    Debug(&'static str),
+    Debug(String),
+    /// Asks whether a move is legal for the side to move, answered with `MoveIsValid` or
+    /// `MoveIsInvalid`
     CheckValidMove((CBPosition, CBPosition)),
     GetValidMoves(CBPosition),
     MakeMove((CBPosition, CBPosition)),
+    /// Makes the move written in algebraic notation or UCI's long form, as typed when
+    /// playing blindfold
+    MakeSanMove(String),
+    /// Asks where a piece from the pocket of the side to move can be dropped
+    GetValidDrops(PieceType),
+    /// Drops a piece from the pocket of the side to move, in variants with drops
+    DropPiece(PieceType, CBPosition),
     GetBoardState,
+    /// Asks for every legal move of the side to move
+    GetLegalMoves,
+    /// Asks for the game status, including the result once the game is over
+    GetGameStatus,
+    /// Asks for the board as it was after the given number of plies
+    GetPositionAt(usize),
+    /// Asks for the game so far as PGN
+    GetPgn,
+    NewGame(GameSetup),
+    /// Starts again from a FEN position with the same players and time control
+    SetPosition(String),
+    /// Takes back the last move
+    Undo,
+    /// Continues the unfinished game saved by the last session
+    ResumeAutosave,
+    /// Starts a game against a player joining over the network, listening on the given
+    /// address such as `0.0.0.0:7878`
+    HostGame(GameSetup, String),
+    /// Joins a game hosted at the given address, with the game code the host shows
+    JoinGame(String, String),
+    /// Loads a puzzle file and starts solving its puzzles
+    StartPuzzles(PuzzleSetup),
+    /// Gives up on the puzzle being solved, if it isn't over, and moves on to another
+    NextPuzzle,
+    /// Loads a repertoire and starts drilling its lines
+    StartRepertoire(RepertoireSetup),
+    /// Moves on to the repertoire line most due for review
+    NextLine,
+    /// Sets up an endgame drill
+    StartEndgame(EndgameSetup),
+    /// Sets the endgame drill up again from its starting position
+    RetryEndgame,
     Quit,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serialize",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
 pub enum ModelMsg {
-    Debug(&'static str),
+    Debug(String),
+    /// The move asked about with `UiMsg::CheckValidMove` is legal
+    MoveIsValid,
     MoveIsInvalid,
     Moves(Vec<CBPosition>),
+    LegalMoves(Vec<(CBPosition, CBPosition)>),
     BoardState(Board),
+    PositionAt(usize, Board),
+    /// The game so far as PGN
+    Pgn(String),
+    GameStatus(GameStatus),
     Checkmate(Side),
     Stalemate,
+    OutOfTime(Side),
+    /// Something the user asked for failed, such as loading a position or starting an engine
+    Error(String),
+    /// News about the connection to the other player of a network game
+    Network(String),
+}
+
+/// The state of the game around the board, sent to the UI after every move.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameStatus {
+    pub which_turn: Side,
+    pub turn_count: usize,
+    pub last_move: Option<(CBPosition, CBPosition)>,
+    /// The side whose king is under attack, if any
+    pub in_check: Option<Side>,
+    pub material: Material,
+    /// Every move so far in algebraic notation
+    pub move_history: Vec<String>,
+    /// The odds given at the start of the game, if any
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub handicap: Option<Handicap>,
+    /// Time left for white and black when the status was sent, if the game is timed
+    #[cfg_attr(feature = "serialize", serde(with = "crate::json::clocks"))]
+    pub clocks: Option<(Duration, Duration)>,
+    /// Who plays white and black
+    pub players: (PlayerKind, PlayerKind),
+    pub result: Option<GameResult>,
+    /// The pieces each side can drop, in variants with drops
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub pockets: Option<Pockets>,
+    /// The puzzle on the board in puzzle mode
+    #[cfg_attr(
+        feature = "serialize",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub puzzle: Option<PuzzleStatus>,
+    /// The line being drilled in the opening trainer
+    #[cfg_attr(
+        feature = "serialize",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub repertoire: Option<RepertoireStatus>,
+    /// The endgame being drilled
+    #[cfg_attr(
+        feature = "serialize",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub endgame: Option<EndgameStatus>,
+}
+
+/// A move of a piece on the board, or a piece dropped onto it from the pocket.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Move {
+    Board(CBPosition, CBPosition),
+    Drop(PieceType, CBPosition),
+}
+impl Move {
+    /// The from and to squares highlighted as the last move. A drop has only the one.
+    pub fn squares(&self) -> (CBPosition, CBPosition) {
+        match *self {
+            Move::Board(from, to) => (from, to),
+            Move::Drop(_, to) => (to, to),
+        }
+    }
+}
+
+/// The captured pieces each side holds in Crazyhouse, ready to be dropped back on the board.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct Pockets {
+    pub white: Vec<PieceType>,
+    pub black: Vec<PieceType>,
+}
+impl Pockets {
+    pub fn of(&self, side: Side) -> &[PieceType] {
+        match side {
+            Side::White => &self.white,
+            Side::Black => &self.black,
+        }
+    }
+
+    pub fn of_mut(&mut self, side: Side) -> &mut Vec<PieceType> {
+        match side {
+            Side::White => &mut self.white,
+            Side::Black => &mut self.black,
+        }
+    }
+
+    /// How many pieces of `piece_type` `side` holds.
+    pub fn count(&self, side: Side, piece_type: PieceType) -> usize {
+        self.of(side).iter().filter(|p| **p == piece_type).count()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serialize",
+    serde(tag = "type", rename_all = "snake_case")
+)]
+pub enum GameResult {
+    Checkmate { winner: Side },
+    Stalemate,
+    OutOfTime { loser: Side },
+    /// Won by a rule of the variant other than checkmate
+    VariantWin { winner: Side, variant: VariantKind },
+    /// Drawn by one of the variant's draw rules
+    Draw { reason: DrawReason },
+}
+impl GameResult {
+    /// The result as written at the end of a PGN game.
+    pub fn pgn_result(&self) -> &'static str {
+        let winner = match *self {
+            GameResult::Checkmate { winner } | GameResult::VariantWin { winner, .. } => winner,
+            GameResult::OutOfTime { loser } => loser.flipped(),
+            GameResult::Stalemate | GameResult::Draw { .. } => return "1/2-1/2",
+        };
+        match winner {
+            Side::White => "1-0",
+            Side::Black => "0-1",
+        }
+    }
 }
-impl PartialEq for ModelMsg {
-    fn eq(&self, other: &Self) -> bool {
+impl Display for GameResult {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
-            ModelMsg::Debug(_) => match other {
-                ModelMsg::Debug(_) => true,
-                _ => false,
-            },
-            ModelMsg::MoveIsInvalid => match other {
-                ModelMsg::MoveIsInvalid => true,
-                _ => false,
-            },
+            GameResult::Checkmate { winner } => {
+                write!(f, "Checkmate, {} wins", String::from(*winner))
+            }
+            GameResult::Stalemate => write!(f, "Stalemate"),
+            GameResult::OutOfTime { loser } => write!(
+                f,
+                "{} ran out of time, {} wins",
+                String::from(*loser),
+                String::from(loser.flipped())
+            ),
+            GameResult::VariantWin { winner, variant } => {
+                write!(f, "{}, {} wins", variant.win_reason(), String::from(*winner))
+            }
+            GameResult::Draw { reason } => write!(f, "Draw by {}", reason),
+        }
+    }
+}
 
-            ModelMsg::Moves(_) => match other {
-                ModelMsg::Moves(_) => true,
-                _ => false,
-            },
+/// Why a game was drawn other than by stalemate, see `Variant::draw`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
+pub enum DrawReason {
+    /// The same position came up for the third time
+    Repetition,
+    /// Fifty moves each without a capture or a pawn move
+    FiftyMoves,
+    /// Neither side has the pieces left to mate
+    InsufficientMaterial,
+}
+impl Display for DrawReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DrawReason::Repetition => "threefold repetition",
+            DrawReason::FiftyMoves => "the fifty move rule",
+            DrawReason::InsufficientMaterial => "insufficient material",
+        })
+    }
+}
 
-            ModelMsg::BoardState(_) => match other {
-                ModelMsg::BoardState(_) => true,
-                _ => false,
-            },
+/// The rules a game is played by, see `model::variant`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
+pub enum VariantKind {
+    #[default]
+    Standard,
+    KingOfTheHill,
+    ThreeCheck,
+    Antichess,
+    Atomic,
+    Horde,
+    Crazyhouse,
+    Capablanca,
+}
+impl VariantKind {
+    pub const ALL: [VariantKind; 8] = [
+        VariantKind::Standard,
+        VariantKind::KingOfTheHill,
+        VariantKind::ThreeCheck,
+        VariantKind::Antichess,
+        VariantKind::Atomic,
+        VariantKind::Horde,
+        VariantKind::Crazyhouse,
+        VariantKind::Capablanca,
+    ];
 
-            ModelMsg::Checkmate(s1) => match other {
-                ModelMsg::Checkmate(s2) => s1 == s2,
-                _ => false,
-            },
+    /// The name used in the PGN `Variant` tag.
+    pub fn pgn_name(&self) -> &'static str {
+        match self {
+            VariantKind::Standard => "Standard",
+            VariantKind::KingOfTheHill => "King of the Hill",
+            VariantKind::ThreeCheck => "Three-check",
+            VariantKind::Antichess => "Antichess",
+            VariantKind::Atomic => "Atomic",
+            VariantKind::Horde => "Horde",
+            VariantKind::Crazyhouse => "Crazyhouse",
+            VariantKind::Capablanca => "Capablanca",
+        }
+    }
 
-            ModelMsg::Stalemate => match other {
-                ModelMsg::Stalemate => true,
-                _ => false,
-            },
+    /// The name of the variant in the `UCI_Variant` option of engines that play variants.
+    pub fn uci_name(&self) -> &'static str {
+        match self {
+            VariantKind::Standard => "chess",
+            VariantKind::KingOfTheHill => "kingofthehill",
+            VariantKind::ThreeCheck => "3check",
+            VariantKind::Antichess => "antichess",
+            VariantKind::Atomic => "atomic",
+            VariantKind::Horde => "horde",
+            VariantKind::Crazyhouse => "crazyhouse",
+            VariantKind::Capablanca => "capablanca",
+        }
+    }
+
+    /// Reads a `Variant` tag, ignoring case, spaces and dashes. Chess960 is played by the
+    /// standard rules from its own starting position.
+    pub fn from_pgn_name(name: &str) -> Option<VariantKind> {
+        let name: String = name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        match name.as_str() {
+            "standard" | "chess" | "chess960" | "fischerandom" => Some(VariantKind::Standard),
+            "kingofthehill" | "koth" => Some(VariantKind::KingOfTheHill),
+            "threecheck" | "3check" => Some(VariantKind::ThreeCheck),
+            "antichess" | "giveaway" => Some(VariantKind::Antichess),
+            "atomic" => Some(VariantKind::Atomic),
+            "horde" => Some(VariantKind::Horde),
+            "crazyhouse" | "zh" => Some(VariantKind::Crazyhouse),
+            "capablanca" | "capa" => Some(VariantKind::Capablanca),
+            _ => None,
+        }
+    }
+
+    fn win_reason(&self) -> &'static str {
+        match self {
+            VariantKind::Standard | VariantKind::Crazyhouse | VariantKind::Capablanca => {
+                "Won by the rules"
+            }
+            VariantKind::KingOfTheHill => "King reached the centre",
+            VariantKind::ThreeCheck => "Third check",
+            VariantKind::Antichess => "Out of pieces or moves",
+            VariantKind::Atomic => "King exploded",
+            VariantKind::Horde => "The horde is captured",
+        }
+    }
+}
+impl Display for VariantKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.pgn_name())
+    }
+}
+
+/// Who makes the moves for one side.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serialize",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+pub enum PlayerKind {
+    Human,
+    /// The built-in engine at a level from 1 to `engine::MAX_LEVEL`
+    Engine(u8),
+    /// An external UCI engine, started from this command line
+    External(String),
+    /// Plays any legal move
+    Random,
+    /// Someone playing from another machine
+    Remote,
+}
+impl Display for PlayerKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PlayerKind::Human => write!(f, "Human"),
+            PlayerKind::Engine(level) => write!(f, "Engine (level {})", level),
+            PlayerKind::External(command) => write!(f, "External engine ({})", command),
+            PlayerKind::Random => write!(f, "Random mover"),
+            PlayerKind::Remote => write!(f, "Remote player"),
+        }
+    }
+}
+
+/// Starting time for each side plus the time added after every move.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct TimeControl {
+    #[cfg_attr(feature = "serialize", serde(rename = "base_ms", with = "crate::json::millis"))]
+    pub base: Duration,
+    #[cfg_attr(
+        feature = "serialize",
+        serde(rename = "increment_ms", with = "crate::json::millis")
+    )]
+    pub increment: Duration,
+}
+impl TimeControl {
+    pub fn new(minutes: u64, increment_seconds: u64) -> TimeControl {
+        TimeControl {
+            base: Duration::from_secs(minutes * 60),
+            increment: Duration::from_secs(increment_seconds),
+        }
+    }
+}
+impl Display for TimeControl {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}+{}",
+            self.base.as_secs() / 60,
+            self.increment.as_secs()
+        )
+    }
+}
+
+/// Material the stronger player gives up before the game starts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
+pub enum Odds {
+    /// The f-pawn
+    Pawn,
+    /// The queen's knight
+    Knight,
+    /// The queen's rook
+    Rook,
+    Queen,
+    /// The f-pawn, and the other side moves first
+    PawnAndMove,
+}
+impl Odds {
+    pub const ALL: [Odds; 5] = [
+        Odds::Pawn,
+        Odds::Knight,
+        Odds::Rook,
+        Odds::Queen,
+        Odds::PawnAndMove,
+    ];
+
+    /// Reads the odds as written by `Display`, ignoring case, spaces and dashes.
+    pub fn from_name(name: &str) -> Option<Odds> {
+        let name: String = name
+            .chars()
+            .filter(|c| c.is_ascii_alphanumeric())
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        match name.trim_end_matches("odds") {
+            "pawn" => Some(Odds::Pawn),
+            "knight" => Some(Odds::Knight),
+            "rook" => Some(Odds::Rook),
+            "queen" => Some(Odds::Queen),
+            "pawnandmove" => Some(Odds::PawnAndMove),
+            _ => None,
+        }
+    }
+}
+impl Display for Odds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Odds::Pawn => "Pawn odds",
+            Odds::Knight => "Knight odds",
+            Odds::Rook => "Rook odds",
+            Odds::Queen => "Queen odds",
+            Odds::PawnAndMove => "Pawn and move",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A head start the stronger player, `giver`, gives the other in material, time or both.
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct Handicap {
+    pub giver: Side,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub odds: Option<Odds>,
+    /// Time odds, the giver's own time control in a timed game
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub time_control: Option<TimeControl>,
+}
+impl Display for Handicap {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.odds, self.time_control) {
+            (Some(odds), Some(tc)) => write!(f, "{} and {} on the clock", odds, tc)?,
+            (Some(odds), None) => write!(f, "{}", odds)?,
+            (None, Some(tc)) => write!(f, "Time odds, {} on the clock", tc)?,
+            (None, None) => write!(f, "No odds")?,
+        }
+        write!(f, " given by {}", String::from(self.giver))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serialize",
+    serde(tag = "type", content = "data", rename_all = "snake_case")
+)]
+pub enum StartPosition {
+    Standard,
+    Fen(String),
+    /// A whole game whose moves are replayed before play continues
+    Pgn(String),
+    /// A Chess960 start position by its number, or one picked at random
+    Chess960(Option<u16>),
+}
+
+/// Everything needed to start a new game.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct GameSetup {
+    pub white: PlayerKind,
+    pub black: PlayerKind,
+    pub time_control: Option<TimeControl>,
+    pub start: StartPosition,
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub variant: VariantKind,
+    /// Odds for a game between players of different strength. Material odds are taken from
+    /// the standard start, a FEN or PGN start is taken to have them already.
+    #[cfg_attr(
+        feature = "serialize",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub handicap: Option<Handicap>,
+}
+impl GameSetup {
+    /// The time controls of white and black, which differ when the handicap has time odds.
+    pub fn time_controls(&self) -> Option<(TimeControl, TimeControl)> {
+        let time_control = self.time_control?;
+        let odds = self
+            .handicap
+            .and_then(|h| h.time_control.map(|tc| (h.giver, tc)));
+        Some(match odds {
+            Some((Side::White, giver)) => (giver, time_control),
+            Some((Side::Black, giver)) => (time_control, giver),
+            None => (time_control, time_control),
+        })
+    }
+}
+impl Default for GameSetup {
+    fn default() -> Self {
+        GameSetup {
+            white: PlayerKind::Human,
+            black: PlayerKind::Human,
+            time_control: None,
+            start: StartPosition::Standard,
+            variant: VariantKind::Standard,
+            handicap: None,
+        }
+    }
+}
+/// Where puzzle mode takes its puzzles from.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct PuzzleSetup {
+    /// A Lichess puzzle CSV, or an EPD file whose records give the best move with `bm`
+    pub path: String,
+    /// Only puzzles with this theme, such as `mateIn2`, are given
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub theme: Option<String>,
+}
+
+/// The puzzle being solved and how the solver is getting on.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct PuzzleStatus {
+    pub id: String,
+    pub rating: u32,
+    pub themes: Vec<String>,
+    /// The side the solver plays
+    pub side: Side,
+    pub state: PuzzleState,
+    /// The solver's puzzle rating, already counting this puzzle once it is over
+    pub solver_rating: u32,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(
+    feature = "serialize",
+    serde(tag = "type", rename_all = "snake_case")
+)]
+pub enum PuzzleState {
+    Solving,
+    Solved,
+    /// A wrong move was played, `answer` is the move that was wanted
+    Failed { answer: String },
+}
+impl Display for PuzzleState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PuzzleState::Solving => write!(f, "Find the best move"),
+            PuzzleState::Solved => write!(f, "Solved"),
+            PuzzleState::Failed { answer } => write!(f, "Failed, the answer was {}", answer),
+        }
+    }
+}
+
+/// The repertoire drilled by the opening trainer.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct RepertoireSetup {
+    /// A PGN whose main line and variations are the repertoire
+    pub path: String,
+    /// The side the repertoire is for, whose moves are tested
+    pub side: Side,
+}
+
+/// How the drill of a repertoire line is going.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct RepertoireStatus {
+    pub side: Side,
+    /// Positions of the repertoire where the side has to move
+    pub positions: usize,
+    /// Those of them due for review
+    pub due: usize,
+    /// Wrong moves played in this line
+    pub mistakes: usize,
+    /// The move that was wanted after a mistake, until it is played
+    pub expected: Option<(CBPosition, CBPosition)>,
+    /// Whether the end of the line was reached
+    pub line_done: bool,
+}
+
+/// A textbook endgame to practise, see `model::endgame`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
+pub enum Endgame {
+    /// Mate with king and queen against king
+    QueenMate,
+    /// Mate with king and rook against king
+    RookMate,
+    /// Queen the pawn with the king in front of it, then mate
+    KingAndPawn,
+    /// Queen the pawn by building a bridge with the rook
+    Lucena,
+    /// Hold the draw with the rook on the third rank
+    Philidor,
+}
+impl Endgame {
+    pub const ALL: [Endgame; 5] = [
+        Endgame::QueenMate,
+        Endgame::RookMate,
+        Endgame::KingAndPawn,
+        Endgame::Lucena,
+        Endgame::Philidor,
+    ];
+
+    /// The position the drill starts from.
+    pub fn fen(&self) -> &'static str {
+        match self {
+            Endgame::QueenMate => "8/8/3k4/8/8/8/8/4K2Q w - - 0 1",
+            Endgame::RookMate => "8/8/3k4/8/8/8/8/R3K3 w - - 0 1",
+            Endgame::KingAndPawn => "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1",
+            Endgame::Lucena => "1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1",
+            Endgame::Philidor => "4k3/8/r7/3PK3/8/8/8/7R w - - 0 1",
+        }
+    }
+
+    /// The side the trainee plays.
+    pub fn trainee(&self) -> Side {
+        match self {
+            Endgame::Philidor => Side::Black,
+            _ => Side::White,
+        }
+    }
+
+    pub fn goal(&self) -> EndgameGoal {
+        match self {
+            Endgame::QueenMate | Endgame::RookMate | Endgame::KingAndPawn => EndgameGoal::Mate,
+            Endgame::Lucena => EndgameGoal::Promote,
+            Endgame::Philidor => EndgameGoal::Hold,
+        }
+    }
+}
+impl Display for Endgame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Endgame::QueenMate => "KQ vs K",
+            Endgame::RookMate => "KR vs K",
+            Endgame::KingAndPawn => "KP vs K",
+            Endgame::Lucena => "Lucena position",
+            Endgame::Philidor => "Philidor position",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What the trainee has to do in an endgame drill.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
+pub enum EndgameGoal {
+    Mate,
+    /// Queen a pawn where the queen can't be taken
+    Promote,
+    /// Keep the other side from mating or queening its pawn
+    Hold,
+}
+impl Display for EndgameGoal {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EndgameGoal::Mate => write!(f, "Mate"),
+            EndgameGoal::Promote => write!(f, "Queen the pawn"),
+            EndgameGoal::Hold => write!(f, "Hold the draw"),
+        }
+    }
+}
+
+/// The endgame to drill and who defends the drills the built-in tables don't cover.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct EndgameSetup {
+    pub endgame: Endgame,
+    /// Directories of Syzygy WDL and DTZ tables, separated as in `PATH`, to defend the
+    /// Lucena and Philidor positions perfectly from
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub tablebases: Option<String>,
+    /// A UCI engine to defend the Lucena and Philidor positions instead of the built-in
+    /// engine when there are no Syzygy tables for them, started from this command line
+    #[cfg_attr(feature = "serialize", serde(default))]
+    pub engine: Option<String>,
+}
+
+/// How the endgame drill is going.
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct EndgameStatus {
+    pub endgame: Endgame,
+    /// The side the trainee plays
+    pub side: Side,
+    /// The trainee's moves so far
+    pub moves: usize,
+    /// The fewest moves the goal can be reached in against perfect defence, when known. From
+    /// Syzygy tables it is the moves to the next capture or pawn move, which in the Lucena
+    /// position is the pawn queening
+    pub optimal: Option<u32>,
+    /// Whether the computer plays the other side perfectly, from a tablebase
+    pub perfect: bool,
+    pub state: EndgameState,
+}
+impl EndgameStatus {
+    /// Whether the goal was reached in the fewest moves, once it is and they are known.
+    pub fn within_optimal(&self) -> Option<bool> {
+        match (&self.state, self.optimal) {
+            (EndgameState::Succeeded, Some(optimal)) => Some(self.moves <= optimal as usize),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(tag = "type", rename_all = "snake_case"))]
+pub enum EndgameState {
+    Playing,
+    /// The goal was reached
+    Succeeded,
+    Failed {
+        reason: String,
+    },
+}
+impl Display for EndgameState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            EndgameState::Playing => write!(f, "Playing"),
+            EndgameState::Succeeded => write!(f, "Done"),
+            EndgameState::Failed { reason } => write!(f, "Failed, {}", reason),
         }
     }
 }
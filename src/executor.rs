@@ -0,0 +1,90 @@
+use std::{
+    sync::{
+        mpsc::{channel, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use log::{debug, error};
+
+type Job = Box<dyn FnOnce() + Send + 'static>;
+
+/// A fixed number of worker threads running short jobs in the order they are spawned.
+/// Jobs should not block, anything long running should be split into jobs that are spawned
+/// again.
+pub struct Executor {
+    sender: Option<Sender<Job>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl Executor {
+    pub fn new(threads: usize) -> Executor {
+        let (sender, receiver) = channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let workers = (0..threads.max(1))
+            .map(|i| {
+                let receiver = receiver.clone();
+                thread::spawn(move || loop {
+                    // The lock is only held while waiting, not while the job runs
+                    let job = receiver.lock().expect("Worker panicked").recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => {
+                            debug!("Worker {} stopping", i);
+                            break;
+                        }
+                    }
+                })
+            })
+            .collect();
+        Executor {
+            sender: Some(sender),
+            workers,
+        }
+    }
+
+    /// Queues a job to run on the next free worker.
+    pub fn spawn(&self, job: impl FnOnce() + Send + 'static) {
+        if let Some(sender) = &self.sender {
+            if let Err(e) = sender.send(Box::new(job)) {
+                error!("Executor stopped, dropping job: {}", e);
+            }
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.workers.len()
+    }
+}
+
+impl Drop for Executor {
+    /// Runs the jobs already queued, then stops the workers.
+    fn drop(&mut self) {
+        self.sender = None;
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    #[test]
+    fn runs_every_job() {
+        let count = Arc::new(AtomicUsize::new(0));
+        let executor = Executor::new(4);
+        for _ in 0..100 {
+            let count = count.clone();
+            executor.spawn(move || {
+                count.fetch_add(1, Ordering::Relaxed);
+            });
+        }
+        drop(executor);
+        assert_eq!(count.load(Ordering::Relaxed), 100);
+    }
+}
@@ -0,0 +1,179 @@
+//! `--headless` mode: the model driven by JSON lines instead of the terminal UI.
+//!
+//! Every line read from stdin is a `UiMsg` and every line written to stdout is a
+//! `ModelMsg`, both in the JSON form described in [`crate::json`]. For example
+//!
+//! ```text
+//! > {"type":"new_game","data":{"white":{"type":"human"},"black":{"type":"engine","data":2},"time_control":null,"start":{"type":"standard"}}}
+//! < {"type":"game_status","data":{"which_turn":"white",...}}
+//! > {"type":"get_legal_moves"}
+//! < {"type":"legal_moves","data":[["a2","a3"],["a2","a4"],...]}
+//! > {"type":"make_move","data":["e2","e4"]}
+//! < {"type":"game_status","data":{"which_turn":"black",...}}
+//! > {"type":"undo"}
+//! ```
+//!
+//! Lines that can't be read are answered with an `error` message. The model stops at a
+//! `quit` message or when stdin closes. Games played this way are never autosaved.
+
+use std::{
+    io::{BufRead, Write},
+    sync::mpsc::channel,
+    thread,
+};
+
+use log::{error, info};
+
+use crate::{
+    control::{ModelMsg, UiMsg},
+    json::{from_json, to_json},
+    model::init_headless_model,
+};
+
+pub fn run(input: impl BufRead, mut output: impl Write + Send + 'static) {
+    let (to_model, model_recv) = channel();
+    let (model_send, from_model) = channel::<ModelMsg>();
+    let errors = model_send.clone();
+    let model = thread::spawn(move || init_headless_model(model_send, model_recv));
+    let writer = thread::spawn(move || {
+        for msg in from_model {
+            let line = match to_json(&msg) {
+                Ok(line) => line,
+                Err(e) => {
+                    error!("Can't write {:?}: {}", msg, e);
+                    continue;
+                }
+            };
+            if writeln!(output, "{}", line)
+                .and_then(|()| output.flush())
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    for line in input.lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+        match from_json::<UiMsg>(&line) {
+            Ok(msg) => {
+                let quit = matches!(msg, UiMsg::Quit);
+                if to_model.send(msg).is_err() || quit {
+                    break;
+                }
+            }
+            Err(e) => {
+                let _ = errors.send(ModelMsg::Error(format!("Invalid command: {}", e)));
+            }
+        }
+    }
+    let _ = to_model.send(UiMsg::Quit);
+    drop(errors);
+    let _ = model.join();
+    let _ = writer.join();
+    info!("Headless session ended");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Cursor,
+        sync::{Arc, Mutex},
+    };
+
+    use super::*;
+    use crate::{
+        control::{GameResult, GameStatus},
+        model::Side,
+    };
+
+    /// Output shared with the test, as `run` hands its output to another thread.
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn session(commands: &[&str]) -> Vec<ModelMsg> {
+        let output = Output::default();
+        run(Cursor::new(commands.join("\n")), output.clone());
+        let output = String::from_utf8(output.0.lock().unwrap().clone()).unwrap();
+        output
+            .lines()
+            .map(|line| from_json(line).unwrap())
+            .collect()
+    }
+
+    fn statuses(messages: &[ModelMsg]) -> Vec<&GameStatus> {
+        messages
+            .iter()
+            .filter_map(|msg| match msg {
+                ModelMsg::GameStatus(status) => Some(status),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn plays_undoes_and_reports_results() {
+        let messages = session(&[
+            r#"{"type":"get_legal_moves"}"#,
+            r#"{"type":"check_valid_move","data":["e2","e5"]}"#,
+            r#"{"type":"check_valid_move","data":["e2","e4"]}"#,
+            r#"{"type":"make_move","data":["e2","e4"]}"#,
+            r#"{"type":"undo"}"#,
+            r#"{"type":"undo"}"#,
+            "not json",
+            r#"{"type":"set_position","data":"7k/8/6K1/8/8/8/8/R7 w - - 0 1"}"#,
+            r#"{"type":"make_move","data":["a1","a8"]}"#,
+            r#"{"type":"get_game_status"}"#,
+        ]);
+
+        // Invalid lines are answered straight away, so may come before earlier replies
+        let errors: Vec<_> = messages
+            .iter()
+            .filter_map(|msg| match msg {
+                ModelMsg::Error(e) => Some(e.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert!(errors.contains(&"There is no move to take back"));
+        assert!(errors.iter().any(|e| e.starts_with("Invalid command")));
+        let Some(ModelMsg::LegalMoves(moves)) = messages
+            .iter()
+            .find(|msg| !matches!(msg, ModelMsg::Error(_)))
+        else {
+            panic!("Expected legal moves first, got {:?}", messages);
+        };
+        assert_eq!(moves.len(), 20);
+        let checked: Vec<_> = messages
+            .iter()
+            .filter(|msg| matches!(msg, ModelMsg::MoveIsValid | ModelMsg::MoveIsInvalid))
+            .collect();
+        assert_eq!(
+            checked,
+            vec![&ModelMsg::MoveIsInvalid, &ModelMsg::MoveIsValid]
+        );
+        let statuses = statuses(&messages);
+        assert_eq!(statuses[0].move_history, vec!["e4"]);
+        assert!(statuses[1].move_history.is_empty());
+        assert!(messages.contains(&ModelMsg::Checkmate(Side::White)));
+        assert_eq!(
+            statuses.last().unwrap().result,
+            Some(GameResult::Checkmate {
+                winner: Side::White
+            })
+        );
+    }
+}
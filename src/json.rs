@@ -0,0 +1,250 @@
+//! The JSON form of the messages and model types, built with the `serialize` feature.
+//!
+//! The schema below is stable: fields and variants may be added, but existing ones keep
+//! their names and meaning.
+//!
+//! Squares are strings such as `"e4"`. A move is a `[from, to]` pair of squares, such as
+//! `["e2", "e4"]`. Sides are `"white"` or `"black"`, piece types are `"king"`, `"queen"`,
+//! `"rook"`, `"bishop"`, `"knight"`, `"pawn"` or one of the fairy pieces `"archbishop"`,
+//! `"chancellor"`, `"amazon"`, `"camel"`, `"zebra"` and `"nightrider"`, and durations are
+//! whole milliseconds.
+//!
+//! A piece is `{"side": "white", "type": "pawn", "square": "e2", "moved": false}`. A board
+//! maps every square to the piece on it, or `null` when it is empty:
+//! `{"e2": {...}, "e3": null, ...}`.
+//!
+//! Enums carrying data are written as `{"type": <variant>, "data": <value>}`, and those
+//! without as just `{"type": <variant>}`. The variants are named in snake case:
+//!
+//! | Type          | Variants                                                              |
+//! |---------------|-----------------------------------------------------------------------|
+//! | `UiMsg`       | `debug` (message), `check_valid_move` (move), `get_valid_moves` (square), `make_move` (move), `make_san_move` (move in algebraic notation), `get_valid_drops` (piece type), `drop_piece` (`[piece type, square]`), `get_board_state`, `get_legal_moves`, `get_game_status`, `get_position_at` (ply), `get_pgn`, `new_game` (setup), `set_position` (FEN string), `undo`, `resume_autosave`, `host_game` (`[setup, address]`), `join_game` (`[address, game code]`), `start_puzzles` (`{"path": <puzzle file>, "theme": <theme or null>}`), `next_puzzle`, `start_repertoire` (`{"path": <PGN file>, "side": <side>}`), `next_line`, `start_endgame` (`{"endgame": <endgame>, "tablebases": <Syzygy directories or null>, "engine": <command or null>}`), `retry_endgame`, `quit` |
+//! | `ModelMsg`    | `debug` (message), `move_is_valid`, `move_is_invalid`, `moves` (squares), `legal_moves` (moves), `board_state` (board), `position_at` (`[ply, board]`), `pgn` (PGN text), `game_status` (status), `checkmate` (side), `stalemate`, `out_of_time` (side), `error` (message), `network` (message) |
+//! | `PlayerKind`  | `human`, `engine` (level), `external` (command), `random`, `remote`             |
+//! | `StartPosition` | `standard`, `fen` (FEN string), `pgn` (PGN text), `chess960` (position number, or null for a random one) |
+//!
+//! A game setup is `{"white": <player>, "black": <player>, "time_control": <time control or
+//! null>, "start": <start position>, "variant": <variant>}`, with a time control being
+//! `{"base_ms": 300000, "increment_ms": 3000}`. The variant is one of `"standard"`,
+//! `"king_of_the_hill"`, `"three_check"`, `"antichess"`, `"atomic"`, `"horde"`,
+//! `"crazyhouse"` or `"capablanca"`, and may be left out for standard chess. Boards other
+//! than 8x8, such as Capablanca's 10x8, have squares from `a1` up to files past `h` and
+//! ranks past 8, like `j10`.
+//!
+//! An odds game adds `"handicap": {"giver": <side>, "odds": <odds or null>, "time_control":
+//! <the giver's time control or null>}` to the setup, where the odds are `"pawn"`,
+//! `"knight"`, `"rook"`, `"queen"` or `"pawn_and_move"`.
+//!
+//! The endgames drilled are `"queen_mate"`, `"rook_mate"`, `"king_and_pawn"`, `"lucena"` and
+//! `"philidor"`.
+//!
+//! A game status, sent after every move, is
+//!
+//! ```text
+//! {
+//!   "which_turn": "black",
+//!   "turn_count": 1,
+//!   "last_move": ["e2", "e4"] or null,
+//!   "in_check": "white" or null,
+//!   "material": {"captured_by_white": [<piece type>...], "captured_by_black": [...], "difference": 0},
+//!   "move_history": ["e4"],
+//!   "handicap": <handicap> or null,
+//!   "clocks": {"white_ms": 300000, "black_ms": 298500} or null,
+//!   "players": [<player>, <player>],
+//!   "result": {"type": "checkmate", "winner": "white"}, {"type": "stalemate"},
+//!             {"type": "out_of_time", "loser": "black"},
+//!             {"type": "variant_win", "winner": "white", "variant": "king_of_the_hill"},
+//!             {"type": "draw", "reason": "repetition" | "fifty_moves" |
+//!              "insufficient_material"} or null,
+//!   "pockets": {"white": [<piece type>...], "black": [...]} or null,
+//!   "puzzle": {"id": "00sHx", "rating": 1760, "themes": ["mateIn2"], "side": "white",
+//!              "state": {"type": "solving"}, {"type": "solved"} or
+//!                       {"type": "failed", "answer": "Be6"}, "solver_rating": 1500},
+//!   "repertoire": {"side": "white", "positions": 12, "due": 4, "mistakes": 1,
+//!                  "expected": ["e2", "e4"] or null, "line_done": false},
+//!   "endgame": {"endgame": "rook_mate", "side": "white", "moves": 3, "optimal": 11 or null,
+//!               "perfect": true, "state": {"type": "playing"}, {"type": "succeeded"} or
+//!                                          {"type": "failed", "reason": "stalemate"}}
+//! }
+//! ```
+//!
+//! The `puzzle` field is only there in puzzle mode, `repertoire` in the opening trainer and
+//! `endgame` in an endgame drill.
+
+use serde::{de::DeserializeOwned, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::control::CBPosition;
+
+pub fn to_json<T: Serialize>(value: &T) -> Result<String, String> {
+    serde_json::to_string(value).map_err(|e| e.to_string())
+}
+
+pub fn from_json<T: DeserializeOwned>(json: &str) -> Result<T, String> {
+    serde_json::from_str(json).map_err(|e| e.to_string())
+}
+
+impl Serialize for CBPosition {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&format_args!("{:?}", self))
+    }
+}
+
+impl<'de> Deserialize<'de> for CBPosition {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let square = String::deserialize(deserializer)?;
+        square.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// A `Duration` written as whole milliseconds.
+pub(crate) mod millis {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_millis() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_millis)
+    }
+}
+
+/// White's and black's clocks, written as `{"white_ms": .., "black_ms": ..}`.
+pub(crate) mod clocks {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Clocks {
+        white_ms: u64,
+        black_ms: u64,
+    }
+
+    pub fn serialize<S: Serializer>(
+        clocks: &Option<(Duration, Duration)>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        clocks
+            .map(|(white, black)| Clocks {
+                white_ms: white.as_millis() as u64,
+                black_ms: black.as_millis() as u64,
+            })
+            .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<(Duration, Duration)>, D::Error> {
+        Ok(Option::<Clocks>::deserialize(deserializer)?.map(|c| {
+            (
+                Duration::from_millis(c.white_ms),
+                Duration::from_millis(c.black_ms),
+            )
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc::channel, time::Duration};
+
+    use super::*;
+    use crate::{
+        control::{
+            GameResult, GameSetup, GameStatus, ModelMsg, PlayerKind, StartPosition, TimeControl,
+            UiMsg,
+        },
+        model::{Side, SteppedModel},
+    };
+
+    #[test]
+    fn messages_match_the_schema() {
+        let make_move = UiMsg::MakeMove(("e2".into(), "e4".into()));
+        assert_eq!(
+            to_json(&make_move).unwrap(),
+            r#"{"type":"make_move","data":["e2","e4"]}"#
+        );
+        assert!(matches!(
+            from_json::<UiMsg>(r#"{"type":"quit"}"#).unwrap(),
+            UiMsg::Quit
+        ));
+
+        let setup = GameSetup {
+            black: PlayerKind::Engine(2),
+            time_control: Some(TimeControl::new(5, 3)),
+            ..GameSetup::default()
+        };
+        assert_eq!(
+            to_json(&setup).unwrap(),
+            r#"{"white":{"type":"human"},"black":{"type":"engine","data":2},"time_control":{"base_ms":300000,"increment_ms":3000},"start":{"type":"standard"},"variant":"standard"}"#
+        );
+        // Setups written before variants still read
+        let old_setup = r#"{"white":{"type":"human"},"black":{"type":"human"},"time_control":null,"start":{"type":"standard"}}"#;
+        assert_eq!(
+            from_json::<GameSetup>(old_setup).unwrap(),
+            GameSetup::default()
+        );
+        let new_game = UiMsg::NewGame(GameSetup {
+            start: StartPosition::Fen("8/8/8/8/8/8/8/K6k w - - 0 1".to_string()),
+            ..setup
+        });
+        match from_json::<UiMsg>(&to_json(&new_game).unwrap()).unwrap() {
+            UiMsg::NewGame(parsed) => assert!(matches!(parsed.start, StartPosition::Fen(_))),
+            other => panic!("Parsed {:?}", other),
+        }
+
+        assert_eq!(
+            to_json(&ModelMsg::Checkmate(Side::White)).unwrap(),
+            r#"{"type":"checkmate","data":"white"}"#
+        );
+        assert_eq!(
+            to_json(&GameResult::OutOfTime { loser: Side::Black }).unwrap(),
+            r#"{"type":"out_of_time","loser":"black"}"#
+        );
+        assert!(from_json::<UiMsg>(r#"{"type":"make_move","data":["e2","z27"]}"#).is_err());
+    }
+
+    #[test]
+    fn round_trips_boards_and_statuses() {
+        let (to_model, model_recv) = channel();
+        let (model_send, from_model) = channel();
+        let mut model = SteppedModel::new(model_send, model_recv);
+        let setup = GameSetup {
+            start: StartPosition::Pgn("1. e4 e5".to_string()),
+            ..GameSetup::default()
+        };
+        to_model.send(UiMsg::NewGame(setup)).unwrap();
+        to_model.send(UiMsg::GetBoardState).unwrap();
+        model.tick();
+        let messages: Vec<ModelMsg> = from_model.try_iter().collect();
+
+        let board = messages
+            .iter()
+            .find(|msg| matches!(msg, ModelMsg::BoardState(_)))
+            .unwrap();
+        let json = to_json(board).unwrap();
+        assert!(json.contains(r#""d2":{"side":"white","type":"pawn","square":"d2","moved":false}"#));
+        assert!(json.contains(r#""e2":null"#));
+        assert_eq!(&from_json::<ModelMsg>(&json).unwrap(), board);
+
+        let Some(ModelMsg::GameStatus(status)) = messages
+            .into_iter()
+            .find(|msg| matches!(msg, ModelMsg::GameStatus(_)))
+        else {
+            panic!("No status sent");
+        };
+        assert!(to_json(&status)
+            .unwrap()
+            .contains(r#""move_history":["e4","e5"]"#));
+        let msg = ModelMsg::GameStatus(GameStatus {
+            clocks: Some((Duration::from_millis(1500), Duration::from_secs(60))),
+            ..status
+        });
+        let json = to_json(&msg).unwrap();
+        assert!(json.contains(r#""clocks":{"white_ms":1500,"black_ms":60000}"#));
+        assert_eq!(from_json::<ModelMsg>(&json).unwrap(), msg);
+    }
+}
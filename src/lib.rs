@@ -0,0 +1,20 @@
+pub mod control;
+#[cfg(feature = "executor")]
+pub mod executor;
+#[cfg(feature = "serialize")]
+pub mod headless;
+#[cfg(feature = "serialize")]
+pub mod json;
+pub mod model;
+#[cfg(feature = "executor")]
+pub mod server;
+pub mod ui;
+
+#[cfg(test)]
+fn test_init() {
+    flexi_logger::Logger::try_with_str("debug")
+        .unwrap()
+        .log_to_stdout()
+        .start()
+        .unwrap();
+}
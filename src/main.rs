@@ -1,17 +1,12 @@
 use std::{io, sync::mpsc::channel, thread};
 
 use derive_more::Display;
+use eden_chess::{model::init_model, ui};
 use flexi_logger::{FileSpec, Logger, WriteMode};
 use ui::init_ui;
 
-mod control;
-mod model;
-mod ui;
-
 use log::*;
 
-use crate::model::init_model;
-
 fn main() -> io::Result<()> {
     std::process::Command::new("rm")
         .args(["*.log"])
@@ -32,6 +27,9 @@ fn main() -> io::Result<()> {
             .unwrap();
     };
     debug!("Debug lgo");
+    if std::env::args().any(|arg| arg == "--headless") {
+        return headless();
+    }
     let (ui_send, model_recv) = channel();
     let (model_send, ui_recv) = channel();
 
@@ -42,10 +40,14 @@ fn main() -> io::Result<()> {
     Ok(())
 }
 
-fn test_init() {
-    Logger::try_with_str("debug")
-        .unwrap()
-        .log_to_stdout()
-        .start()
-        .unwrap();
+#[cfg(feature = "serialize")]
+fn headless() -> io::Result<()> {
+    eden_chess::headless::run(io::stdin().lock(), io::stdout());
+    Ok(())
+}
+
+#[cfg(not(feature = "serialize"))]
+fn headless() -> io::Result<()> {
+    eprintln!("--headless needs eden_chess built with the serialize feature");
+    std::process::exit(2);
 }
@@ -0,0 +1,637 @@
+//! Matches between two players, for testing engine changes against each other.
+
+use std::{
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::channel,
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+
+use super::{
+    autosave::parse_player,
+    pgn,
+    player::{self, External, Player, Turn},
+    tablebase::{Table, Tablebase, Wdl},
+    Model, PieceType, Side,
+};
+use crate::control::{GameResult, GameSetup, PlayerKind, StartPosition, VariantKind};
+
+/// A player taking part in a match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contestant {
+    pub name: String,
+    pub kind: PlayerKind,
+}
+
+impl Contestant {
+    /// Reads a player written as `engine:<level>`, `external:<command>` or `random`.
+    pub fn parse(spec: &str) -> Result<Contestant, String> {
+        let kind = parse_player(spec)?;
+        if matches!(kind, PlayerKind::Human | PlayerKind::Remote) {
+            return Err(format!("{} can't play in a match", kind));
+        }
+        Ok(Contestant {
+            name: kind.to_string(),
+            kind,
+        })
+    }
+
+    fn player(&self, movetime: Duration) -> Box<dyn Player> {
+        match &self.kind {
+            PlayerKind::External(command) => {
+                Box::new(External::new(command).with_movetime(movetime))
+            }
+            kind => player::from_kind(kind),
+        }
+    }
+}
+
+/// Where a game of the match starts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Opening {
+    pub start: StartPosition,
+    /// Moves played from the start before the players take over
+    pub moves: Vec<String>,
+}
+
+/// Reads an opening suite, either EPD with one position per line or PGN with one opening
+/// per game.
+pub fn load_openings(path: &Path) -> Result<Vec<Opening>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let openings: Vec<Opening> = if path.extension().is_some_and(|ext| ext == "pgn") {
+        pgn::parse_all(&text)?
+            .into_iter()
+            .map(|game| Opening {
+                start: match game.tag("FEN") {
+                    Some(fen) => StartPosition::Fen(fen.to_string()),
+                    None => StartPosition::Standard,
+                },
+                moves: game.moves,
+            })
+            .collect()
+    } else {
+        text.lines()
+            .filter(|line| !line.trim().is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                // EPD is the first four FEN fields followed by operations
+                let fields: Vec<&str> = line.split_whitespace().take(4).collect();
+                Opening {
+                    start: StartPosition::Fen(format!("{} 0 1", fields.join(" "))),
+                    moves: Vec::new(),
+                }
+            })
+            .collect()
+    };
+    if openings.is_empty() {
+        return Err(format!("{} has no openings", path.display()));
+    }
+    Ok(openings)
+}
+
+/// When games are stopped before they are over.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Adjudication {
+    /// Draw once this many full moves have been played
+    pub max_moves: Option<usize>,
+    /// A win once both players agree one side is ahead by this many centipawns, for this
+    /// many moves each
+    pub resign: Option<(i32, usize)>,
+    /// A draw after this full move once both players score the game within this many
+    /// centipawns, for this many moves each
+    pub draw: Option<(usize, i32, usize)>,
+    /// Decide endings of a king and a queen, rook or pawn against a bare king by the tables
+    /// of `tablebase`
+    pub tablebase: bool,
+}
+
+/// The sequential probability ratio test of whether the first player is stronger by
+/// `elo1` rather than `elo0`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sprt {
+    pub elo0: f64,
+    pub elo1: f64,
+    pub alpha: f64,
+    pub beta: f64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verdict {
+    /// The first player isn't `elo1` stronger
+    H0,
+    /// The first player is at least `elo1` stronger
+    H1,
+}
+
+impl Sprt {
+    pub fn new(elo0: f64, elo1: f64) -> Sprt {
+        Sprt {
+            elo0,
+            elo1,
+            alpha: 0.05,
+            beta: 0.05,
+        }
+    }
+
+    /// The log likelihood ratio below which H0 is accepted and above which H1 is.
+    pub fn bounds(&self) -> (f64, f64) {
+        (
+            (self.beta / (1.0 - self.alpha)).ln(),
+            ((1.0 - self.beta) / self.alpha).ln(),
+        )
+    }
+
+    /// The log likelihood ratio of the results so far, using the normal approximation.
+    pub fn llr(&self, tally: &Tally) -> f64 {
+        let games = tally.games() as f64;
+        let variance = tally.variance();
+        if games == 0.0 || variance == 0.0 {
+            return 0.0;
+        }
+        let (s0, s1) = (expected_score(self.elo0), expected_score(self.elo1));
+        games * (s1 - s0) * (2.0 * tally.score() - s0 - s1) / (2.0 * variance)
+    }
+
+    pub fn verdict(&self, tally: &Tally) -> Option<Verdict> {
+        let llr = self.llr(tally);
+        let (lower, upper) = self.bounds();
+        if llr >= upper {
+            Some(Verdict::H1)
+        } else if llr <= lower {
+            Some(Verdict::H0)
+        } else {
+            None
+        }
+    }
+}
+
+/// Results from the first player's point of view.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Tally {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl Tally {
+    pub fn games(&self) -> usize {
+        self.wins + self.draws + self.losses
+    }
+
+    /// Points per game, from 0 to 1.
+    pub fn score(&self) -> f64 {
+        match self.games() {
+            0 => 0.5,
+            games => (self.wins as f64 + self.draws as f64 / 2.0) / games as f64,
+        }
+    }
+
+    /// The variance of a single game's score.
+    fn variance(&self) -> f64 {
+        let games = self.games() as f64;
+        if games == 0.0 {
+            return 0.0;
+        }
+        let s = self.score();
+        (self.wins as f64 * (1.0 - s).powi(2)
+            + self.draws as f64 * (0.5 - s).powi(2)
+            + self.losses as f64 * s.powi(2))
+            / games
+    }
+
+    /// The Elo difference and its 95% error margin, `None` while every game has been won
+    /// or lost, when it can't be told.
+    pub fn elo(&self) -> Option<(f64, f64)> {
+        let score = self.score();
+        if self.games() == 0 || score <= 0.0 || score >= 1.0 {
+            return None;
+        }
+        let stderr = (self.variance() / self.games() as f64).sqrt();
+        let clamp = |s: f64| s.clamp(1e-6, 1.0 - 1e-6);
+        let margin = (elo_from_score(clamp(score + 1.96 * stderr))
+            - elo_from_score(clamp(score - 1.96 * stderr)))
+            / 2.0;
+        Some((elo_from_score(score), margin))
+    }
+
+    fn add(&mut self, outcome: Outcome, first_is_white: bool) {
+        match (outcome, first_is_white) {
+            (Outcome::Draw, _) => self.draws += 1,
+            (Outcome::WhiteWins, true) | (Outcome::BlackWins, false) => self.wins += 1,
+            _ => self.losses += 1,
+        }
+    }
+}
+
+fn expected_score(elo: f64) -> f64 {
+    1.0 / (1.0 + 10f64.powf(-elo / 400.0))
+}
+
+fn elo_from_score(score: f64) -> f64 {
+    -400.0 * (1.0 / score - 1.0).log10()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    WhiteWins,
+    BlackWins,
+    Draw,
+}
+
+impl Outcome {
+    fn pgn_result(&self) -> &'static str {
+        match self {
+            Outcome::WhiteWins => "1-0",
+            Outcome::BlackWins => "0-1",
+            Outcome::Draw => "1/2-1/2",
+        }
+    }
+
+    fn win_for(side: Side) -> Outcome {
+        match side {
+            Side::White => Outcome::WhiteWins,
+            Side::Black => Outcome::BlackWins,
+        }
+    }
+}
+
+/// One finished game of a match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameRecord {
+    /// Counting from 1
+    pub round: usize,
+    pub first_is_white: bool,
+    pub outcome: Outcome,
+    /// Why the game ended, such as "checkmate" or "adjudicated by move count"
+    pub reason: String,
+    pub pgn: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MatchConfig {
+    pub first: Contestant,
+    pub second: Contestant,
+    pub games: usize,
+    /// Each opening is played twice, once with each player as white. The standard start
+    /// is used when there are none.
+    pub openings: Vec<Opening>,
+    pub movetime: Duration,
+    /// How many games are played at once
+    pub concurrency: usize,
+    pub adjudication: Adjudication,
+    /// Stops the match early once the test is decided
+    pub sprt: Option<Sprt>,
+    pub event: String,
+    pub variant: VariantKind,
+}
+
+/// Plays the match, calling `on_game` as each game finishes. Games are handed out in round
+/// order but can finish in any order.
+pub fn run_match(config: &MatchConfig, mut on_game: impl FnMut(&GameRecord, &Tally)) -> Tally {
+    let next_round = Arc::new(AtomicUsize::new(0));
+    let stop = Arc::new(AtomicBool::new(false));
+    let (send, results) = channel();
+    let workers: Vec<_> = (0..config.concurrency.max(1))
+        .map(|_| {
+            let (config, next_round, stop, send) = (
+                config.clone(),
+                next_round.clone(),
+                stop.clone(),
+                send.clone(),
+            );
+            thread::spawn(move || loop {
+                let round = next_round.fetch_add(1, Ordering::Relaxed);
+                if round >= config.games || stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                if send.send(play_round(&config, round)).is_err() {
+                    break;
+                }
+            })
+        })
+        .collect();
+    drop(send);
+
+    let mut tally = Tally::default();
+    for record in results {
+        tally.add(record.outcome, record.first_is_white);
+        on_game(&record, &tally);
+        if let Some(verdict) = config.sprt.and_then(|sprt| sprt.verdict(&tally)) {
+            if !stop.swap(true, Ordering::Relaxed) {
+                info!(
+                    "SPRT accepted {:?}, finishing the games being played",
+                    verdict
+                );
+            }
+        }
+    }
+    for worker in workers {
+        let _ = worker.join();
+    }
+    tally
+}
+
+fn play_round(config: &MatchConfig, round: usize) -> GameRecord {
+    let first_is_white = round.is_multiple_of(2);
+    let (white, black) = match first_is_white {
+        true => (&config.first, &config.second),
+        false => (&config.second, &config.first),
+    };
+    let opening = match config.openings.len() {
+        0 => Opening {
+            start: StartPosition::Standard,
+            moves: Vec::new(),
+        },
+        count => config.openings[(round / 2) % count].clone(),
+    };
+    let (outcome, reason, mut model) = play_game(config, white, black, &opening);
+    info!(
+        "Round {}: {} vs {} {} ({})",
+        round + 1,
+        white.name,
+        black.name,
+        outcome.pgn_result(),
+        reason
+    );
+
+    let mut tags: Vec<(String, String)> = vec![
+        ("Event", config.event.clone()),
+        ("Site", "?".to_string()),
+        ("Date", "????.??.??".to_string()),
+        ("Round", (round + 1).to_string()),
+        ("White", white.name.clone()),
+        ("Black", black.name.clone()),
+        ("Result", outcome.pgn_result().to_string()),
+    ]
+    .into_iter()
+    .map(|(name, value)| (name.to_string(), value))
+    .collect();
+    let first_ply = match &mut model {
+        Some(model) => {
+            if config.variant != VariantKind::Standard {
+                tags.push(("Variant".to_string(), config.variant.pgn_name().to_string()));
+            }
+            if opening.start != StartPosition::Standard {
+                tags.push(("SetUp".to_string(), "1".to_string()));
+                tags.push(("FEN".to_string(), model.start_fen()));
+            }
+            model.game.turn_count - model.game.move_history.len()
+        }
+        None => 0,
+    };
+    let moves = model.map(|m| m.game.move_history).unwrap_or_default();
+    tags.push(("PlyCount".to_string(), moves.len().to_string()));
+    GameRecord {
+        round: round + 1,
+        first_is_white,
+        outcome,
+        pgn: pgn::write(
+            &tags,
+            first_ply,
+            &moves,
+            outcome.pgn_result(),
+            Some(&reason),
+        ),
+        reason,
+    }
+}
+
+/// Plays one game, giving the model it was played in unless the opening couldn't be set up.
+fn play_game(
+    config: &MatchConfig,
+    white: &Contestant,
+    black: &Contestant,
+    opening: &Opening,
+) -> (Outcome, String, Option<Model>) {
+    let (model_send, _model_recv) = channel();
+    let (_ui_send, ui_recv) = channel();
+    let mut model = Model::new(model_send, ui_recv);
+    let setup = GameSetup {
+        white: white.kind.clone(),
+        black: black.kind.clone(),
+        time_control: None,
+        start: opening.start.clone(),
+        variant: config.variant,
+        handicap: None,
+    };
+    if let Err(e) = model.start_game(&setup, &opening.moves) {
+        warn!("Skipping opening: {}", e);
+        return (Outcome::Draw, format!("unplayable opening: {}", e), None);
+    }
+    let mut players = [white.player(config.movetime), black.player(config.movetime)];
+    // Each move's score from white's point of view, if the player gave one
+    let mut scores: Vec<Option<i32>> = Vec::new();
+    loop {
+        if let Some((outcome, reason)) = decide(&model, &scores, &config.adjudication) {
+            return (outcome, reason, Some(model));
+        }
+        let side = model.game.which_turn;
+        let player = &mut players[side as usize];
+        let turn = Turn {
+            board: &model.game.board,
+            side,
+            turn_count: model.game.turn_count,
+            time_left: None,
+            rules: model.game.rules(),
+            pocket: model.game.pockets().of(side),
+            history: &model.game.move_history,
+            variant: model.game.variant,
+            positions: &model.game.positions,
+            moves: &model.game.moves,
+        };
+        let played = player
+            .choose_move(&turn)
+            .and_then(|mv| mv.ok_or("gave no move".to_string()))
+            .and_then(|mv| model.play(mv));
+        if let Err(e) = played {
+            let reason = format!("{} forfeits: {}", String::from(side), e);
+            return (Outcome::win_for(side.flipped()), reason, Some(model));
+        }
+        scores.push(player.score().map(|score| match side {
+            Side::White => score.centipawns(),
+            Side::Black => -score.centipawns(),
+        }));
+    }
+}
+
+/// The result of the game if it is over or can be adjudicated.
+fn decide(
+    model: &Model,
+    scores: &[Option<i32>],
+    adjudication: &Adjudication,
+) -> Option<(Outcome, String)> {
+    let game = &model.game;
+    match game.result {
+        Some(GameResult::Checkmate { winner }) => {
+            return Some((Outcome::win_for(winner), "checkmate".to_string()))
+        }
+        Some(GameResult::Stalemate) => return Some((Outcome::Draw, "stalemate".to_string())),
+        Some(GameResult::OutOfTime { loser }) => {
+            return Some((Outcome::win_for(loser.flipped()), "out of time".to_string()))
+        }
+        Some(result @ GameResult::VariantWin { winner, .. }) => {
+            return Some((Outcome::win_for(winner), result.to_string()))
+        }
+        Some(GameResult::Draw { reason }) => return Some((Outcome::Draw, reason.to_string())),
+        None => {}
+    }
+
+    // The tables are only solved for standard chess
+    if adjudication.tablebase && game.variant == VariantKind::Standard {
+        let probe = TABLES
+            .iter()
+            .find_map(|table| table.probe(&game.board, game.which_turn));
+        if let Some(probe) = probe {
+            let outcome = match probe.wdl {
+                Wdl::Win => Outcome::win_for(game.which_turn),
+                Wdl::Loss => Outcome::win_for(game.which_turn.flipped()),
+                Wdl::Draw => Outcome::Draw,
+            };
+            return Some((outcome, "adjudicated by tablebase".to_string()));
+        }
+    }
+
+    if let Some(max_moves) = adjudication.max_moves {
+        if game.turn_count / 2 >= max_moves {
+            return Some((Outcome::Draw, "adjudicated by move count".to_string()));
+        }
+    }
+    if let Some((cp, moves)) = adjudication.resign {
+        if let Some(recent) = last_scores(scores, moves * 2) {
+            if recent.iter().all(|score| *score >= cp) {
+                return Some((Outcome::WhiteWins, "adjudicated by score".to_string()));
+            }
+            if recent.iter().all(|score| *score <= -cp) {
+                return Some((Outcome::BlackWins, "adjudicated by score".to_string()));
+            }
+        }
+    }
+    if let Some((after, cp, moves)) = adjudication.draw {
+        let recent = last_scores(scores, moves * 2);
+        if game.turn_count / 2 >= after
+            && recent.is_some_and(|recent| recent.iter().all(|score| score.abs() <= cp))
+        {
+            return Some((Outcome::Draw, "adjudicated by score".to_string()));
+        }
+    }
+    None
+}
+
+/// The last `count` scores, if there are that many and every one was given.
+fn last_scores(scores: &[Option<i32>], count: usize) -> Option<Vec<i32>> {
+    if count == 0 || scores.len() < count {
+        return None;
+    }
+    scores[scores.len() - count..].iter().copied().collect()
+}
+
+lazy_static! {
+    /// The tables games are adjudicated by, solved when first needed. The pawn's table also
+    /// answers for the queen's, which it goes on into once the pawn promotes.
+    static ref TABLES: [Table; 2] = [Table::solve(PieceType::Pawn), Table::solve(PieceType::Rook)];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elo_and_sprt() {
+        let tally = Tally {
+            wins: 300,
+            draws: 100,
+            losses: 100,
+        };
+        let (elo, margin) = tally.elo().unwrap();
+        assert!((elo - 147.2).abs() < 0.1, "elo {}", elo);
+        assert!(margin > 20.0 && margin < 40.0, "margin {}", margin);
+        assert_eq!(Sprt::new(0.0, 10.0).verdict(&tally), Some(Verdict::H1));
+        assert_eq!(Sprt::new(300.0, 310.0).verdict(&tally), Some(Verdict::H0));
+        let even = Tally {
+            wins: 3,
+            draws: 4,
+            losses: 3,
+        };
+        assert_eq!(Sprt::new(0.0, 10.0).verdict(&even), None);
+        assert_eq!(
+            Tally {
+                wins: 2,
+                ..Tally::default()
+            }
+            .elo(),
+            None
+        );
+    }
+
+    #[test]
+    fn plays_a_match() {
+        let config = MatchConfig {
+            first: Contestant::parse("engine:2").unwrap(),
+            second: Contestant::parse("random").unwrap(),
+            games: 4,
+            openings: vec![Opening {
+                start: StartPosition::Fen("4k3/8/8/8/8/8/4P3/4K2R w K - 0 1".to_string()),
+                moves: vec!["Rh7".to_string()],
+            }],
+            movetime: Duration::from_millis(10),
+            concurrency: 2,
+            adjudication: Adjudication {
+                max_moves: Some(15),
+                tablebase: true,
+                ..Adjudication::default()
+            },
+            sprt: None,
+            event: "Test".to_string(),
+            variant: VariantKind::Standard,
+        };
+        let mut records = Vec::new();
+        let tally = run_match(&config, |record, _| records.push(record.clone()));
+        assert_eq!(tally.games(), 4);
+        records.sort_by_key(|record| record.round);
+        assert!(records[0].first_is_white && !records[1].first_is_white);
+        let game = pgn::parse(&records[1].pgn).unwrap();
+        assert_eq!(game.tag("White"), Some("Random mover"));
+        assert_eq!(game.tag("FEN"), Some("4k3/8/8/8/8/8/4P3/4K2R w K - 0 1"));
+        assert_eq!(game.moves[0], "Rh7");
+        assert!(Contestant::parse("human").is_err());
+    }
+
+    #[test]
+    fn adjudicates_by_tablebase() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let mut adjudication = Adjudication::default();
+        let mut decide_from = |fen: &str, adjudication: &Adjudication| {
+            let setup = GameSetup {
+                start: StartPosition::Fen(fen.to_string()),
+                ..GameSetup::default()
+            };
+            model.start_game(&setup, &[]).unwrap();
+            decide(&model, &[], adjudication).map(|(outcome, _)| outcome)
+        };
+        assert_eq!(
+            decide_from("4k3/8/8/8/8/8/8/4K2R b - - 0 1", &adjudication),
+            None
+        );
+        adjudication.tablebase = true;
+        let won = decide_from("4k3/8/8/8/8/8/8/4K2R b - - 0 1", &adjudication);
+        assert_eq!(won, Some(Outcome::WhiteWins));
+        // The black king takes the rook
+        let taken = decide_from("8/8/8/8/8/8/6k1/4K2R b - - 0 1", &adjudication);
+        assert_eq!(taken, Some(Outcome::Draw));
+        // A rook's pawn against the king in the corner
+        let corner = decide_from("k7/8/8/8/8/8/P7/K7 w - - 0 1", &adjudication);
+        assert_eq!(corner, Some(Outcome::Draw));
+        let queened = decide_from("8/8/8/8/8/2k5/8/Q3K3 b - - 0 1", &adjudication);
+        assert_eq!(queened, Some(Outcome::WhiteWins));
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::Side;
+use crate::control::{
+    GameSetup, Handicap, Odds, PlayerKind, StartPosition, TimeControl, VariantKind,
+};
+
+/// The format version written to new autosaves. Bump it when a change to `SavedGame` can't
+/// be read by `#[serde(default)]` alone, and convert the older versions in `load`.
+pub const VERSION: u32 = 1;
+
+/// An unfinished game, written to `eden_chess/autosave.toml` in the platform data directory
+/// after every move and removed once the game ends.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedGame {
+    pub version: u32,
+    /// `human`, `engine:<level>`, `external:<command>`, `random` or `remote`
+    pub white: String,
+    pub black: String,
+    #[serde(default)]
+    pub time_control: Option<SavedTimeControl>,
+    /// Time left on the clocks when the game was saved
+    #[serde(default)]
+    pub clocks: Option<SavedClocks>,
+    /// The position before the first move
+    pub start_fen: String,
+    /// Every move in algebraic notation
+    #[serde(default)]
+    pub moves: Vec<String>,
+    /// The name of the variant as in a PGN `Variant` tag, standard chess when missing
+    #[serde(default)]
+    pub variant: Option<String>,
+    /// The odds of a handicap game, kept so the PGN and time odds survive a resume
+    #[serde(default)]
+    pub handicap: Option<SavedHandicap>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SavedTimeControl {
+    pub base_secs: u64,
+    pub increment_secs: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SavedHandicap {
+    /// `white` or `black`
+    pub giver: String,
+    /// As shown in the game, such as `Knight odds`
+    #[serde(default)]
+    pub odds: Option<String>,
+    #[serde(default)]
+    pub time_control: Option<SavedTimeControl>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct SavedClocks {
+    pub white_ms: u64,
+    pub black_ms: u64,
+}
+
+impl SavedGame {
+    pub fn new(
+        white: &PlayerKind,
+        black: &PlayerKind,
+        time_control: Option<TimeControl>,
+        clocks: Option<(Duration, Duration)>,
+        start_fen: String,
+        moves: Vec<String>,
+        variant: VariantKind,
+    ) -> SavedGame {
+        SavedGame {
+            version: VERSION,
+            white: player_to_string(white),
+            black: player_to_string(black),
+            time_control: time_control.map(saved_time_control),
+            clocks: clocks.map(|(white, black)| SavedClocks {
+                white_ms: white.as_millis() as u64,
+                black_ms: black.as_millis() as u64,
+            }),
+            start_fen,
+            moves,
+            variant: (variant != VariantKind::Standard).then(|| variant.pgn_name().to_string()),
+            handicap: None,
+        }
+    }
+
+    pub fn with_handicap(mut self, handicap: Option<Handicap>) -> SavedGame {
+        self.handicap = handicap.map(|h| SavedHandicap {
+            giver: String::from(h.giver).to_lowercase(),
+            odds: h.odds.map(|odds| odds.to_string()),
+            time_control: h.time_control.map(saved_time_control),
+        });
+        self
+    }
+
+    /// The setup to start the game from, the moves still have to be replayed on top of it.
+    pub fn setup(&self) -> Result<GameSetup, String> {
+        Ok(GameSetup {
+            white: parse_player(&self.white)?,
+            black: parse_player(&self.black)?,
+            time_control: self.time_control.map(time_control),
+            start: StartPosition::Fen(self.start_fen.clone()),
+            variant: match &self.variant {
+                Some(name) => VariantKind::from_pgn_name(name)
+                    .ok_or_else(|| format!("Unknown variant {:?}", name))?,
+                None => VariantKind::Standard,
+            },
+            handicap: self.handicap.as_ref().map(parse_handicap).transpose()?,
+        })
+    }
+
+    pub fn clocks(&self) -> Option<(Duration, Duration)> {
+        self.clocks.map(|c| {
+            (
+                Duration::from_millis(c.white_ms),
+                Duration::from_millis(c.black_ms),
+            )
+        })
+    }
+
+    /// A one line description for the resume menu.
+    pub fn summary(&self) -> String {
+        let player = |spec: &str| match parse_player(spec) {
+            Ok(player) => player.to_string(),
+            Err(_) => spec.to_string(),
+        };
+        let variant = match &self.variant {
+            Some(name) => format!("{}, ", name),
+            None => String::new(),
+        };
+        format!(
+            "{}{} vs {}, {} moves played",
+            variant,
+            player(&self.white),
+            player(&self.black),
+            self.moves.len()
+        )
+    }
+}
+
+pub fn path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("eden_chess").join("autosave.toml"))
+}
+
+pub fn save(path: &Path, game: &SavedGame) -> Result<(), String> {
+    let contents = toml::to_string(game).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    // Write then rename, so a crash mid write leaves the previous save intact
+    let temp = path.with_extension("toml.tmp");
+    fs::write(&temp, contents).map_err(|e| e.to_string())?;
+    fs::rename(&temp, path).map_err(|e| e.to_string())
+}
+
+/// Reads an autosave, or `Ok(None)` if there is none.
+pub fn load(path: &Path) -> Result<Option<SavedGame>, String> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+    };
+    parse(&contents)
+        .map(Some)
+        .map_err(|e| format!("Autosave {} can't be resumed: {}", path.display(), e))
+}
+
+fn parse(contents: &str) -> Result<SavedGame, String> {
+    let table: toml::Table = contents.parse().map_err(|e| format!("{}", e))?;
+    let version = table
+        .get("version")
+        .and_then(|v| v.as_integer())
+        .ok_or("it has no format version, so it wasn't written by eden_chess")?;
+    if version > VERSION as i64 {
+        return Err(format!(
+            "it was written by a newer version of eden_chess (format {}, this version reads up to {})",
+            version, VERSION
+        ));
+    }
+    if version < 1 {
+        return Err(format!("unknown format version {}", version));
+    }
+    table
+        .try_into()
+        .map_err(|e: toml::de::Error| e.message().to_string())
+}
+
+pub fn remove(path: &Path) -> Result<(), String> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err(e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+fn saved_time_control(tc: TimeControl) -> SavedTimeControl {
+    SavedTimeControl {
+        base_secs: tc.base.as_secs(),
+        increment_secs: tc.increment.as_secs(),
+    }
+}
+
+fn time_control(tc: SavedTimeControl) -> TimeControl {
+    TimeControl {
+        base: Duration::from_secs(tc.base_secs),
+        increment: Duration::from_secs(tc.increment_secs),
+    }
+}
+
+fn parse_handicap(saved: &SavedHandicap) -> Result<Handicap, String> {
+    let giver = match saved.giver.as_str() {
+        "white" => Side::White,
+        "black" => Side::Black,
+        other => return Err(format!("Unknown side {:?} giving odds", other)),
+    };
+    let odds = match &saved.odds {
+        Some(name) => {
+            Some(Odds::from_name(name).ok_or_else(|| format!("Unknown odds {:?}", name))?)
+        }
+        None => None,
+    };
+    Ok(Handicap {
+        giver,
+        odds,
+        time_control: saved.time_control.map(time_control),
+    })
+}
+
+fn player_to_string(player: &PlayerKind) -> String {
+    match player {
+        PlayerKind::Human => "human".to_string(),
+        PlayerKind::Engine(level) => format!("engine:{}", level),
+        PlayerKind::External(command) => format!("external:{}", command),
+        PlayerKind::Random => "random".to_string(),
+        PlayerKind::Remote => "remote".to_string(),
+    }
+}
+
+pub(super) fn parse_player(spec: &str) -> Result<PlayerKind, String> {
+    match spec.split_once(':') {
+        None if spec == "human" => Ok(PlayerKind::Human),
+        None if spec == "random" => Ok(PlayerKind::Random),
+        None if spec == "remote" => Ok(PlayerKind::Remote),
+        Some(("engine", level)) => level
+            .parse()
+            .map(PlayerKind::Engine)
+            .map_err(|_| format!("Invalid engine level {:?}", level)),
+        Some(("external", command)) => Ok(PlayerKind::External(command.to_string())),
+        _ => Err(format!("Unknown player {:?}", spec)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::fen::STANDARD_START;
+
+    #[test]
+    fn round_trip() {
+        let game = SavedGame::new(
+            &PlayerKind::Human,
+            &PlayerKind::External("stockfish -q".to_string()),
+            Some(TimeControl::new(5, 3)),
+            Some((Duration::from_millis(281_500), Duration::from_secs(300))),
+            STANDARD_START.to_string(),
+            vec!["e4".to_string(), "c5".to_string()],
+            VariantKind::ThreeCheck,
+        )
+        .with_handicap(Some(Handicap {
+            giver: Side::Black,
+            odds: Some(Odds::PawnAndMove),
+            time_control: Some(TimeControl::new(1, 0)),
+        }));
+        let parsed = parse(&toml::to_string(&game).unwrap()).unwrap();
+        assert_eq!(parsed, game);
+        let setup = parsed.setup().unwrap();
+        assert_eq!(
+            setup.black,
+            PlayerKind::External("stockfish -q".to_string())
+        );
+        assert_eq!(setup.time_control, Some(TimeControl::new(5, 3)));
+        assert_eq!(setup.variant, VariantKind::ThreeCheck);
+        assert_eq!(setup.handicap.unwrap().odds, Some(Odds::PawnAndMove));
+    }
+
+    #[test]
+    fn version_checks() {
+        let minimal = format!(
+            "version = 1\nwhite = \"human\"\nblack = \"engine:2\"\nstart_fen = \"{}\"",
+            STANDARD_START
+        );
+        let game = parse(&minimal).unwrap();
+        assert!(game.moves.is_empty() && game.clocks.is_none());
+
+        let newer = minimal.replace("version = 1", "version = 99");
+        assert!(parse(&newer).unwrap_err().contains("newer version"));
+        assert!(parse("moves = []")
+            .unwrap_err()
+            .contains("no format version"));
+    }
+}
@@ -0,0 +1,168 @@
+//! Piece movement written in Betza's notation. A piece is a list of atoms, each a leap of a
+//! fixed shape in every direction, such as `N` for the knight's (2, 1) leap. Writing an
+//! atom twice makes it a rider that repeats the leap in a line until blocked, so `WW` is
+//! the rook and `NN` the nightrider. The usual shorthands `K`, `Q`, `R` and `B` are
+//! understood, so the archbishop is `BN`. Modifiers for direction or capture only moves
+//! are not supported, which is why pawns keep their own rules.
+
+use super::{Board, Side};
+use crate::control::CBPosition;
+
+/// A leap in ranks and files, taken in every direction it can be turned or mirrored to, and
+/// repeated in a line when the piece rides.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Step {
+    pub leap: (isize, isize),
+    pub ride: bool,
+}
+
+fn atom(letter: char) -> Option<(isize, isize)> {
+    match letter {
+        'W' => Some((1, 0)),
+        'F' => Some((1, 1)),
+        'D' => Some((2, 0)),
+        'N' => Some((2, 1)),
+        'A' => Some((2, 2)),
+        'H' => Some((3, 0)),
+        'C' => Some((3, 1)),
+        'Z' => Some((3, 2)),
+        'G' => Some((3, 3)),
+        _ => None,
+    }
+}
+
+/// The atoms a shorthand stands for, with whether they ride.
+fn shorthand(letter: char) -> Option<&'static [(char, bool)]> {
+    match letter {
+        'K' => Some(&[('W', false), ('F', false)]),
+        'Q' => Some(&[('W', true), ('F', true)]),
+        'R' => Some(&[('W', true)]),
+        'B' => Some(&[('F', true)]),
+        _ => None,
+    }
+}
+
+/// Reads a piece's movement, such as `RN` for the chancellor.
+pub fn parse(notation: &str) -> Result<Vec<Step>, String> {
+    let mut steps: Vec<Step> = Vec::new();
+    let mut push = |step: Step| {
+        // A leap already taken as a rider covers the single leap too
+        match steps.iter_mut().find(|s| s.leap == step.leap) {
+            Some(existing) => existing.ride |= step.ride,
+            None => steps.push(step),
+        }
+    };
+    let mut letters = notation.chars().peekable();
+    while let Some(letter) = letters.next() {
+        if let Some(atoms) = shorthand(letter) {
+            for (atom_letter, ride) in atoms {
+                let leap = atom(*atom_letter).expect("Shorthands are made of atoms");
+                push(Step { leap, ride: *ride });
+            }
+        } else if let Some(leap) = atom(letter) {
+            // A doubled atom or a trailing 0 rides
+            let ride = letters
+                .next_if(|next| *next == letter || *next == '0')
+                .is_some();
+            push(Step { leap, ride });
+        } else {
+            return Err(format!(
+                "{:?} is not a Betza atom in {:?}",
+                letter, notation
+            ));
+        }
+    }
+    if steps.is_empty() {
+        return Err("A piece needs at least one atom".to_string());
+    }
+    Ok(steps)
+}
+
+/// The eight ways a leap can be turned or mirrored, without repeats.
+fn directions((ranks, files): (isize, isize)) -> Vec<(isize, isize)> {
+    let mut directions = Vec::new();
+    for (r, f) in [(ranks, files), (files, ranks)] {
+        for (r, f) in [(r, f), (-r, f), (r, -f), (-r, -f)] {
+            if !directions.contains(&(r, f)) {
+                directions.push((r, f));
+            }
+        }
+    }
+    directions
+}
+
+/// The squares a piece of `side` on `from` can move to or capture on. Riders stop at the
+/// first piece in the way, which they may take if it is the other side's.
+pub fn moves(steps: &[Step], board: &Board, from: CBPosition, side: Side) -> Vec<CBPosition> {
+    let mut moves = Vec::new();
+    for step in steps {
+        for (ranks, files) in directions(step.leap) {
+            let mut pos = from;
+            while let Some(to) = pos.get_offset(ranks, files) {
+                match board.get(&to) {
+                    // Off the edge of the board
+                    None => break,
+                    Some(Some(piece)) => {
+                        if piece.side != side {
+                            moves.push(to);
+                        }
+                        break;
+                    }
+                    Some(None) => moves.push(to),
+                }
+                if !step.ride {
+                    break;
+                }
+                pos = to;
+            }
+        }
+    }
+    moves
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{empty_board, insert_piece, PieceType, Side::*};
+
+    #[test]
+    fn leaps_and_rides() {
+        assert_eq!(parse("R").unwrap(), parse("WW").unwrap());
+        assert_eq!(parse("Q").unwrap(), parse("W0F0").unwrap());
+        assert_eq!(
+            parse("NN").unwrap(),
+            [Step {
+                leap: (2, 1),
+                ride: true
+            }]
+        );
+        assert!(parse("fmW").is_err());
+        assert!(parse("").is_err());
+        for piece_type in [
+            PieceType::King,
+            PieceType::Archbishop,
+            PieceType::Chancellor,
+            PieceType::Amazon,
+            PieceType::Camel,
+            PieceType::Zebra,
+            PieceType::Nightrider,
+        ] {
+            assert!(parse(piece_type.movement().unwrap()).is_ok());
+        }
+
+        let mut board = empty_board();
+        insert_piece(&mut board, 1, 'a', White, PieceType::Rook);
+        insert_piece(&mut board, 4, 'a', White, PieceType::Pawn);
+        insert_piece(&mut board, 1, 'c', Black, PieceType::Pawn);
+        let rook = moves(&parse("R").unwrap(), &board, "a1".into(), White);
+        assert_eq!(rook.len(), 4, "a2, a3, b1 and taking on c1: {:?}", rook);
+
+        let camel = moves(&parse("C").unwrap(), &board, "d4".into(), White);
+        assert_eq!(camel.len(), 8);
+        assert!(camel.contains(&"e7".into()) && camel.contains(&"a3".into()));
+
+        // The archbishop on e4 sees the whole of both diagonals and every knight square
+        let archbishop = moves(&parse("BN").unwrap(), &board, "e4".into(), White);
+        assert_eq!(archbishop.len(), 13 + 8);
+    }
+}
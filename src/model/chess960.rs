@@ -0,0 +1,104 @@
+//! Chess960 (Fischer Random) start positions, numbered 0 to 959 as in Scharnagl's scheme,
+//! where 518 is the standard position.
+
+use rand::Rng;
+
+use super::{empty_board, insert_piece, Board, PieceType, Side};
+
+pub const POSITIONS: u16 = 960;
+
+/// Where the two knights go among the five squares left after the bishops and queen
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The back rank of position `index`, from the a file to the h file.
+pub fn back_rank(index: u16) -> Result<[PieceType; 8], String> {
+    if index >= POSITIONS {
+        return Err(format!(
+            "Chess960 positions are numbered 0 to {}, got {}",
+            POSITIONS - 1,
+            index
+        ));
+    }
+    let mut n = index as usize;
+    let mut rank: [Option<PieceType>; 8] = [None; 8];
+    // Light squared bishop on b, d, f or h, then the dark squared one on a, c, e or g
+    rank[n % 4 * 2 + 1] = Some(PieceType::Bishop);
+    n /= 4;
+    rank[n % 4 * 2] = Some(PieceType::Bishop);
+    n /= 4;
+    place(&mut rank, n % 6, PieceType::Queen);
+    n /= 6;
+    let (first, second) = KNIGHTS[n];
+    place(&mut rank, second, PieceType::Knight);
+    place(&mut rank, first, PieceType::Knight);
+    // The king always sits between the rooks
+    place(&mut rank, 0, PieceType::Rook);
+    place(&mut rank, 0, PieceType::King);
+    place(&mut rank, 0, PieceType::Rook);
+    Ok(rank.map(|piece| piece.expect("Every square is filled")))
+}
+
+/// Puts `piece` on the `nth` empty square of the rank.
+fn place(rank: &mut [Option<PieceType>; 8], nth: usize, piece: PieceType) {
+    let square = rank
+        .iter_mut()
+        .filter(|square| square.is_none())
+        .nth(nth)
+        .expect("There are enough empty squares");
+    *square = Some(piece);
+}
+
+/// The board of position `index`, with both sides mirroring each other.
+pub fn board(index: u16) -> Result<Board, String> {
+    let rank = back_rank(index)?;
+    let mut board = empty_board();
+    for (piece_type, col) in rank.into_iter().zip('a'..='h') {
+        insert_piece(&mut board, 1, col, Side::White, piece_type);
+        insert_piece(&mut board, 2, col, Side::White, PieceType::Pawn);
+        insert_piece(&mut board, 7, col, Side::Black, PieceType::Pawn);
+        insert_piece(&mut board, 8, col, Side::Black, piece_type);
+    }
+    Ok(board)
+}
+
+pub fn random_index() -> u16 {
+    rand::thread_rng().gen_range(0..POSITIONS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{fen, PieceType::*};
+
+    #[test]
+    fn numbers_positions() {
+        assert_eq!(
+            back_rank(518).unwrap(),
+            [Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook]
+        );
+        assert_eq!(
+            back_rank(0).unwrap(),
+            [Bishop, Bishop, Queen, Knight, Knight, Rook, King, Rook]
+        );
+        assert_eq!(
+            back_rank(959).unwrap(),
+            [Rook, King, Rook, Knight, Knight, Queen, Bishop, Bishop]
+        );
+        assert!(back_rank(960).is_err());
+        assert_eq!(
+            fen::to_fen(&board(518).unwrap(), Side::White, 0),
+            fen::STANDARD_START
+        );
+    }
+}
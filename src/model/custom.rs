@@ -0,0 +1,180 @@
+//! Pieces defined by the player instead of built in, read from `[[pieces]]` in the config
+//! file when the model starts:
+//!
+//! ```toml
+//! [[pieces]]
+//! letter = "G"
+//! name = "Gnu"
+//! betza = "NC"
+//! value = 5
+//! ```
+//!
+//! Each plays as `PieceType::Custom` with its letter, which can then be used in FEN
+//! positions and notation. A letter of one of the built-in pieces can't be taken.
+
+use std::{
+    collections::HashMap,
+    fs,
+    sync::{Arc, RwLock},
+};
+
+use lazy_static::lazy_static;
+use log::{error, info};
+use serde::Deserialize;
+
+use super::{
+    betza::{self, Step},
+    fen, PieceType,
+};
+
+/// A piece as written in the config file.
+#[derive(Debug, Clone, Deserialize)]
+pub struct PieceDefinition {
+    pub letter: char,
+    pub name: String,
+    /// How the piece moves in Betza's notation, see `betza`
+    pub betza: String,
+    /// Its material value in pawns
+    pub value: isize,
+}
+
+struct Defined {
+    steps: Arc<[Step]>,
+    value: isize,
+}
+
+lazy_static! {
+    static ref DEFINED: RwLock<HashMap<char, Defined>> = RwLock::new(HashMap::new());
+}
+
+/// The part of the config file the model reads, everything else is left to the UI.
+#[derive(Debug, Default, Deserialize)]
+struct PieceConfig {
+    #[serde(default)]
+    pieces: Vec<PieceDefinition>,
+}
+
+/// Defines the pieces in the config file, logging any that can't be.
+pub fn load() {
+    let Some(path) = dirs::config_dir().map(|dir| dir.join("eden_chess").join("config.toml"))
+    else {
+        return;
+    };
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return;
+    };
+    let defined = toml::from_str::<PieceConfig>(&contents)
+        .map_err(|e| e.to_string())
+        .and_then(|config| define(&config.pieces));
+    if let Err(e) = defined {
+        error!("No custom pieces loaded from {}: {}", path.display(), e);
+    }
+}
+
+/// Defines every piece, or none of them if any is invalid.
+pub fn define(definitions: &[PieceDefinition]) -> Result<(), String> {
+    let mut pieces = Vec::new();
+    for definition in definitions {
+        let letter = definition.letter.to_ascii_uppercase();
+        if !letter.is_ascii_alphabetic() {
+            return Err(format!(
+                "{} needs a letter, not {:?}",
+                definition.name, letter
+            ));
+        }
+        if let Some((_, piece_type)) = fen::piece_from_char(letter) {
+            if !matches!(piece_type, PieceType::Custom(_)) {
+                return Err(format!(
+                    "{} can't take the letter {} of the {:?}",
+                    definition.name, letter, piece_type
+                ));
+            }
+        }
+        let steps = betza::parse(&definition.betza)
+            .map_err(|e| format!("{} has invalid movement: {}", definition.name, e))?;
+        pieces.push((letter, definition, steps));
+    }
+    let mut defined = DEFINED.write().unwrap_or_else(|e| e.into_inner());
+    for (letter, definition, steps) in pieces {
+        info!(
+            "Defined the {} as {} moving {}",
+            definition.name, letter, definition.betza
+        );
+        defined.insert(
+            letter,
+            Defined {
+                steps: steps.into(),
+                value: definition.value,
+            },
+        );
+    }
+    Ok(())
+}
+
+pub fn is_defined(letter: char) -> bool {
+    DEFINED
+        .read()
+        .unwrap_or_else(|e| e.into_inner())
+        .contains_key(&letter)
+}
+
+pub(super) fn steps(letter: char) -> Option<Arc<[Step]>> {
+    let defined = DEFINED.read().unwrap_or_else(|e| e.into_inner());
+    defined.get(&letter).map(|piece| piece.steps.clone())
+}
+
+pub(super) fn value(letter: char) -> Option<isize> {
+    let defined = DEFINED.read().unwrap_or_else(|e| e.into_inner());
+    defined.get(&letter).map(|piece| piece.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{legal_moves, Side};
+
+    #[test]
+    fn plays_a_defined_piece() {
+        let config: PieceConfig = toml::from_str(
+            r#"
+            [[pieces]]
+            letter = "g"
+            name = "Gnu"
+            betza = "NC"
+            value = 5
+            "#,
+        )
+        .unwrap();
+        define(&config.pieces).unwrap();
+        assert_eq!(PieceType::Custom('G').value(), 5);
+        assert_eq!(PieceType::Custom('G').to_string(), "G");
+
+        let position = fen::parse("4k3/8/8/8/3G4/8/8/4K3 w - - 0 1").unwrap();
+        let gnu = legal_moves(&position.board, Side::White)
+            .into_iter()
+            .filter(|(from, _)| *from == "d4".into())
+            .count();
+        // Every knight leap and every camel leap but the one onto its own king
+        assert_eq!(gnu, 15);
+        assert_eq!(
+            fen::to_fen(&position.board, Side::White, 0),
+            "4k3/8/8/8/3G4/8/8/4K3 w - - 0 1"
+        );
+
+        let queen = PieceDefinition {
+            letter: 'q',
+            name: "Queen".to_string(),
+            betza: "Q".to_string(),
+            value: 9,
+        };
+        assert!(define(&[queen]).is_err());
+        let bad = PieceDefinition {
+            letter: 'x',
+            name: "Bad".to_string(),
+            betza: "fmW".to_string(),
+            value: 1,
+        };
+        assert!(define(&[bad]).is_err());
+        assert!(!is_defined('X'));
+    }
+}
@@ -0,0 +1,405 @@
+//! Endgame drills. Each drill sets up a textbook position for the trainee to convert, or to
+//! hold in the Philidor position. In the king and queen, rook or pawn endings the computer
+//! plays the other side perfectly from the tables of `tablebase`. In the Lucena and Philidor
+//! positions it does so from Syzygy tables when a directory with them is given, and otherwise
+//! defends with the built-in engine, or a UCI engine if one is given, neither of which is
+//! perfect, and no move count is given.
+
+use std::sync::Arc;
+
+use log::info;
+
+use super::{
+    engine::{self, MAX_LEVEL},
+    fen,
+    player::{self, Player, Turn},
+    syzygy::{self, Syzygy},
+    tablebase::{Table, Tablebase, Wdl},
+    variant::Variant,
+    Board, Material, Model, PieceType, Side,
+};
+use crate::control::{
+    Endgame, EndgameGoal, EndgameSetup, EndgameState, EndgameStatus, GameResult, GameSetup,
+    ModelMsg, Move, StartPosition,
+};
+
+/// How many moves the trainee has to hold the Philidor position for.
+pub const HOLD_MOVES: usize = 15;
+
+/// Plays by a table, and by the built-in engine once the board is out of it.
+pub struct Perfect {
+    table: Arc<dyn Tablebase>,
+}
+
+impl Player for Perfect {
+    fn choose_move(&mut self, turn: &Turn) -> Result<Option<Move>, String> {
+        self.table
+            .best_move(turn.board, turn.side, turn.rules)
+            .or_else(|| {
+                engine::choose_move(turn.board, turn.side, MAX_LEVEL, turn.rules, turn.history)
+            })
+            .map(|(from, to)| Some(Move::Board(from, to)))
+            .ok_or("No legal moves".to_string())
+    }
+}
+
+/// The piece besides the kings in the drill's endgame, when the tables solve it.
+fn table_piece(endgame: Endgame) -> Option<PieceType> {
+    match endgame {
+        Endgame::QueenMate => Some(PieceType::Queen),
+        Endgame::RookMate => Some(PieceType::Rook),
+        Endgame::KingAndPawn => Some(PieceType::Pawn),
+        Endgame::Lucena | Endgame::Philidor => None,
+    }
+}
+
+/// Whether `side` has queened a pawn on its last rank where the other side can't take the
+/// queen. The side with the pawn starts the rook drills without a queen, so any queen there
+/// is a promoted pawn.
+fn pawn_through(board: &Board, side: Side, rules: &dyn Variant) -> bool {
+    let last_row = match side {
+        Side::White => rules.geometry().ranks as usize,
+        Side::Black => 1,
+    };
+    let replies = rules.legal_moves(board, side.flipped());
+    board.values().flatten().any(|p| {
+        p.side == side
+            && p.piece_type == PieceType::Queen
+            && p.current_pos.row == last_row
+            && !replies.iter().any(|(_, to)| *to == p.current_pos)
+    })
+}
+
+/// A directory or command from the setup, unless it was left blank.
+fn given(text: Option<String>) -> Option<String> {
+    text.filter(|text| !text.trim().is_empty())
+}
+
+/// The Syzygy tables for the drill's endgame in the directories `dirs`, if they are there.
+fn open_syzygy(endgame: Endgame, dirs: &str) -> Option<Syzygy> {
+    let board = fen::parse(endgame.fen()).ok()?.board;
+    let syzygy = Syzygy::open(dirs, &board);
+    if syzygy.is_none() {
+        info!(
+            "No {} tables in {}",
+            syzygy::syzygy_name(&board).unwrap_or_default(),
+            dirs
+        );
+    }
+    syzygy
+}
+
+/// The material `side` is ahead by, in pawns.
+fn lead(board: &Board, side: Side) -> isize {
+    let difference = Material::difference(board);
+    match side {
+        Side::White => difference,
+        Side::Black => -difference,
+    }
+}
+
+pub struct Session {
+    endgame: Endgame,
+    table: Option<Arc<dyn Tablebase>>,
+    tablebases: Option<String>,
+    engine: Option<String>,
+    /// The trainee's moves since the drill was set up
+    moves: usize,
+    optimal: Option<u32>,
+    perfect: bool,
+    state: EndgameState,
+}
+
+impl Session {
+    /// Solves the drill's endgame if the tables can, which takes a moment, or looks for its
+    /// Syzygy tables otherwise.
+    pub fn new(setup: EndgameSetup) -> Session {
+        let tablebases = given(setup.tablebases);
+        let table: Option<Arc<dyn Tablebase>> = match (table_piece(setup.endgame), &tablebases) {
+            (Some(piece), _) => {
+                info!("Solving the {} endgame", setup.endgame);
+                Some(Arc::new(Table::solve(piece)))
+            }
+            (None, Some(dirs)) => {
+                open_syzygy(setup.endgame, dirs).map(|syzygy| Arc::new(syzygy) as _)
+            }
+            (None, None) => None,
+        };
+        Session {
+            endgame: setup.endgame,
+            table,
+            tablebases,
+            engine: given(setup.engine),
+            moves: 0,
+            optimal: None,
+            perfect: false,
+            state: EndgameState::Playing,
+        }
+    }
+
+    /// The player for the computer's side, and whether it defends perfectly.
+    fn defender(&self) -> (Box<dyn Player>, bool) {
+        if let Some(table) = &self.table {
+            return (
+                Box::new(Perfect {
+                    table: table.clone(),
+                }),
+                true,
+            );
+        }
+        match &self.engine {
+            Some(command) => (Box::new(player::External::new(command)), false),
+            None => (Box::new(player::Engine { level: MAX_LEVEL }), false),
+        }
+    }
+
+    /// Judges the position after a move by `mover`.
+    fn judge(
+        &mut self,
+        board: &Board,
+        rules: &dyn Variant,
+        mover: Side,
+        result: Option<GameResult>,
+        san: &str,
+    ) {
+        let trainee = self.endgame.trainee();
+        if mover == trainee {
+            self.moves += 1;
+        }
+        self.state = match self.endgame.goal() {
+            EndgameGoal::Hold => match result {
+                Some(GameResult::Checkmate { .. }) => EndgameState::Failed {
+                    reason: "you were mated".to_string(),
+                },
+                Some(_) => EndgameState::Succeeded,
+                None if pawn_through(board, trainee.flipped(), rules) => EndgameState::Failed {
+                    reason: "the pawn got through".to_string(),
+                },
+                None if lead(board, trainee.flipped()) <= 0 || self.moves >= HOLD_MOVES => {
+                    EndgameState::Succeeded
+                }
+                None => EndgameState::Playing,
+            },
+            goal => match result {
+                Some(GameResult::Checkmate { winner }) if winner == trainee => {
+                    EndgameState::Succeeded
+                }
+                Some(result) => EndgameState::Failed {
+                    reason: result.to_string().to_lowercase(),
+                },
+                None if goal == EndgameGoal::Promote && pawn_through(board, trainee, rules) => {
+                    EndgameState::Succeeded
+                }
+                None if lead(board, trainee) <= 0 => EndgameState::Failed {
+                    reason: "the extra material is gone".to_string(),
+                },
+                None if mover == trainee && !self.still_won(board) => EndgameState::Failed {
+                    reason: format!("{} lets the win slip away", san),
+                },
+                None => EndgameState::Playing,
+            },
+        };
+    }
+
+    /// Whether the trainee's win still stands after their move, as far as the tables know.
+    fn still_won(&self, board: &Board) -> bool {
+        let defender = self.endgame.trainee().flipped();
+        match self
+            .table
+            .as_ref()
+            .and_then(|table| table.probe(board, defender))
+        {
+            Some(probe) => probe.wdl == Wdl::Loss,
+            None => true,
+        }
+    }
+
+    pub fn status(&self) -> EndgameStatus {
+        EndgameStatus {
+            endgame: self.endgame,
+            side: self.endgame.trainee(),
+            moves: self.moves,
+            optimal: self.optimal,
+            perfect: self.perfect,
+            state: self.state.clone(),
+        }
+    }
+}
+
+impl Model {
+    /// Sets up the drill of `setup`, keeping the tables of the last drill if it is the same
+    /// with the same Syzygy directories.
+    pub(super) fn start_endgame(&mut self, setup: EndgameSetup) {
+        let session = match self.endgame.take() {
+            Some(mut session)
+                if session.endgame == setup.endgame
+                    && session.tablebases == given(setup.tablebases.clone()) =>
+            {
+                session.engine = given(setup.engine);
+                session
+            }
+            _ => Session::new(setup),
+        };
+        self.endgame = Some(session);
+        self.retry_endgame();
+    }
+
+    /// Sets the drill up from its starting position with the computer defending.
+    pub(super) fn retry_endgame(&mut self) {
+        let Some(mut session) = self.endgame.take() else {
+            let _ = self
+                .ui_sender
+                .send(ModelMsg::Error("No endgame drill is set up".to_string()));
+            return;
+        };
+        let setup = GameSetup {
+            start: StartPosition::Fen(session.endgame.fen().to_string()),
+            ..GameSetup::default()
+        };
+        if let Err(e) = self.start_game(&setup, &[]) {
+            let _ = self.ui_sender.send(ModelMsg::Error(format!(
+                "The {} drill can't be set up: {}",
+                session.endgame, e
+            )));
+            return;
+        }
+        let board = &self.game.board;
+        let (defender, perfect) = session.defender();
+        session.perfect = perfect;
+        session.optimal = session
+            .table
+            .as_ref()
+            .and_then(|table| table.probe(board, self.game.which_turn))
+            .filter(|probe| probe.wdl != Wdl::Draw)
+            .map(|probe| probe.moves());
+        session.moves = 0;
+        session.state = EndgameState::Playing;
+        self.players
+            .insert(session.endgame.trainee().flipped(), defender);
+        self.endgame = Some(session);
+        let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+    }
+
+    /// Whether the drill on the board is over, so no more moves are played in it.
+    pub(super) fn endgame_over(&self) -> bool {
+        self.endgame
+            .as_ref()
+            .is_some_and(|session| session.state != EndgameState::Playing)
+    }
+
+    /// Checks the move just played against the drill's goal.
+    pub(super) fn check_endgame_move(&mut self) {
+        let Some(session) = &mut self.endgame else {
+            return;
+        };
+        if session.state != EndgameState::Playing {
+            return;
+        }
+        let san = self.game.move_history.last().cloned().unwrap_or_default();
+        session.judge(
+            &self.game.board,
+            self.game.rules(),
+            self.game.which_turn.flipped(),
+            self.game.result,
+            &san,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc::channel, time::Instant};
+
+    use super::*;
+    use crate::model::{fen, variant::Standard, ENGINE_MOVE_DELAY};
+
+    fn drill(endgame: Endgame) -> EndgameSetup {
+        EndgameSetup {
+            endgame,
+            tablebases: None,
+            engine: None,
+        }
+    }
+
+    #[test]
+    fn converts_king_and_pawn() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let defender_moves = |model: &mut Model| {
+            model.last_move_time = Instant::now() - ENGINE_MOVE_DELAY;
+            model.play_turn();
+        };
+        let status = |model: &Model| model.status().endgame.unwrap();
+
+        model.start_endgame(drill(Endgame::KingAndPawn));
+        let start = status(&model);
+        assert!(start.perfect);
+        let optimal = start.optimal.unwrap();
+        assert!(optimal > 0);
+
+        // Play the table's own moves for the trainee, which convert as fast as possible
+        let table = model.endgame.as_ref().unwrap().table.clone().unwrap();
+        while status(&model).state == EndgameState::Playing {
+            let (from, to) = table
+                .best_move(&model.game.board, Side::White, model.game.rules())
+                .unwrap();
+            model.try_move(Move::Board(from, to)).unwrap();
+            if status(&model).state == EndgameState::Playing {
+                defender_moves(&mut model);
+            }
+        }
+        let done = status(&model);
+        assert_eq!(done.state, EndgameState::Succeeded);
+        assert_eq!(done.moves as u32, optimal);
+        assert_eq!(done.within_optimal(), Some(true));
+        assert!(!model.takes_ui_moves());
+
+        // A move that lets the defending king in front of the pawn throws the win away
+        model.retry_endgame();
+        assert_eq!(status(&model).moves, 0);
+        let board = model.game.board.clone();
+        let (from, to) = Standard
+            .legal_moves(&board, Side::White)
+            .into_iter()
+            .find(|(from, to)| {
+                let mut after = board.clone();
+                Standard.make_move(&mut after, *from, *to);
+                table.probe(&after, Side::Black).unwrap().wdl == Wdl::Draw
+            })
+            .unwrap();
+        model.try_move(Move::Board(from, to)).unwrap();
+        assert!(matches!(status(&model).state, EndgameState::Failed { .. }));
+    }
+
+    #[test]
+    fn holds_the_philidor() {
+        let mut session = Session::new(drill(Endgame::Philidor));
+        assert!(session.table.is_none());
+        let board = fen::parse(Endgame::Philidor.fen()).unwrap().board;
+        let (_, perfect) = session.defender();
+        assert!(!perfect, "there are no tables for rook endings");
+        // Nor in a Syzygy directory without them
+        let dir = std::env::temp_dir().join("eden_chess_no_syzygy");
+        std::fs::create_dir_all(&dir).unwrap();
+        let without = Session::new(EndgameSetup {
+            tablebases: Some(dir.to_string_lossy().into_owned()),
+            ..drill(Endgame::Philidor)
+        });
+        assert!(!without.defender().1);
+        session.judge(
+            &board,
+            &Standard,
+            Side::White,
+            Some(GameResult::Stalemate),
+            "Kf6",
+        );
+        assert_eq!(session.state, EndgameState::Succeeded);
+
+        // A black pawn queened on the first rank fails the drill
+        let through = fen::parse("8/8/8/8/8/8/8/K3q2k w - - 0 1").unwrap();
+        assert!(pawn_through(&through.board, Side::Black, &Standard));
+        let taken = fen::parse("8/8/8/8/8/8/8/3Kq2k w - - 0 1").unwrap();
+        assert!(!pawn_through(&taken.board, Side::Black, &Standard));
+    }
+}
@@ -0,0 +1,151 @@
+use rand::seq::SliceRandom;
+
+use super::{notation, variant::Variant, Board, Material, Side};
+use crate::control::{CBPosition, GameResult};
+
+pub const MAX_LEVEL: u8 = 3;
+
+const MATE_SCORE: isize = 1000;
+
+/// Picks a move for `side` with the built-in engine, or `None` if it has no legal moves.
+/// `history` is every move of the game so far in algebraic notation, which variants such as
+/// three-check decide the result from.
+///
+/// Level 1 plays at random, level 2 grabs the most material it can this move and level 3
+/// also looks at the opponent's best reply, which lets it see mate in one.
+pub fn choose_move(
+    board: &Board,
+    side: Side,
+    level: u8,
+    rules: &dyn Variant,
+    history: &[String],
+) -> Option<(CBPosition, CBPosition)> {
+    let mut moves = rules.legal_moves(board, side);
+    // Shuffling first picks randomly between equally good moves
+    moves.shuffle(&mut rand::thread_rng());
+    if level <= 1 {
+        return moves.first().copied();
+    }
+    let depth = if level >= 3 { 1 } else { 0 };
+    let mut history = history.to_vec();
+    moves
+        .into_iter()
+        .max_by_key(|mv| -score_move(board, *mv, side, depth, rules, &mut history))
+}
+
+/// The score for `side` to move, looking `depth` plies further ahead, where `history` ends
+/// with the move that led here.
+fn search(
+    board: &Board,
+    side: Side,
+    depth: u8,
+    rules: &dyn Variant,
+    history: &mut Vec<String>,
+) -> isize {
+    if depth == 0 {
+        return evaluate(board, side);
+    }
+    match rules.result(board, side.flipped(), history) {
+        Some(GameResult::Checkmate { winner } | GameResult::VariantWin { winner, .. }) => {
+            return if winner == side {
+                MATE_SCORE
+            } else {
+                -MATE_SCORE
+            }
+        }
+        Some(_) => return 0,
+        None => {}
+    }
+    rules
+        .legal_moves(board, side)
+        .into_iter()
+        .map(|mv| -score_move(board, mv, side, depth - 1, rules, history))
+        .max()
+        .unwrap_or(0)
+}
+
+/// The score for the opponent after `side` plays `mv`, searching `depth` plies further.
+fn score_move(
+    board: &Board,
+    (from, to): (CBPosition, CBPosition),
+    side: Side,
+    depth: u8,
+    rules: &dyn Variant,
+    history: &mut Vec<String>,
+) -> isize {
+    let mut new_board = board.clone();
+    rules.make_move(&mut new_board, from, to);
+    let gives_check = rules.in_check(&new_board, side.flipped());
+    history.push(notation::algebraic(board, from, to, gives_check, false));
+    let score = search(&new_board, side.flipped(), depth, rules, history);
+    history.pop();
+    score
+}
+
+fn evaluate(board: &Board, side: Side) -> isize {
+    let difference = Material::difference(board);
+    match side {
+        Side::White => difference,
+        Side::Black => -difference,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{
+        empty_board, insert_piece,
+        variant::{Standard, ThreeCheck},
+        PieceType::*,
+        Side::*,
+    };
+
+    #[test]
+    fn takes_free_material() {
+        let mut board = empty_board();
+        insert_piece(&mut board, 1, 'a', White, King);
+        insert_piece(&mut board, 8, 'h', Black, King);
+        insert_piece(&mut board, 4, 'd', White, Rook);
+        insert_piece(&mut board, 4, 'g', Black, Queen);
+        assert_eq!(
+            choose_move(&board, White, 2, &Standard, &[]),
+            Some(("d4".into(), "g4".into()))
+        );
+    }
+
+    #[test]
+    fn finds_mate_in_one() {
+        // Back rank mate with Ra1-a8
+        let mut board = empty_board();
+        insert_piece(&mut board, 1, 'g', White, King);
+        insert_piece(&mut board, 1, 'a', White, Rook);
+        insert_piece(&mut board, 8, 'g', Black, King);
+        insert_piece(&mut board, 7, 'f', Black, Pawn);
+        insert_piece(&mut board, 7, 'g', Black, Pawn);
+        insert_piece(&mut board, 7, 'h', Black, Pawn);
+        assert_eq!(
+            choose_move(&board, White, 3, &Standard, &[]),
+            Some(("a1".into(), "a8".into()))
+        );
+    }
+
+    #[test]
+    fn counts_earlier_checks() {
+        // Nxc3 wins the queen, but Ra8+ is the third check and wins the game
+        let mut board = empty_board();
+        insert_piece(&mut board, 1, 'g', White, King);
+        insert_piece(&mut board, 1, 'a', White, Rook);
+        insert_piece(&mut board, 1, 'd', White, Knight);
+        insert_piece(&mut board, 8, 'h', Black, King);
+        insert_piece(&mut board, 3, 'c', Black, Queen);
+        assert_eq!(
+            choose_move(&board, White, 3, &ThreeCheck, &[]),
+            Some(("d1".into(), "c3".into()))
+        );
+        let history = ["e4", "e5", "Qh5", "Nc6", "Qxf7+", "Kxf7", "Bc4+", "Kg6"].map(String::from);
+        assert_eq!(
+            choose_move(&board, White, 3, &ThreeCheck, &history),
+            Some(("a1".into(), "a8".into()))
+        );
+    }
+}
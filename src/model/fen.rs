@@ -0,0 +1,451 @@
+use super::{castling_rooks, custom, empty_board_of, Board, Piece, PieceType, Side};
+use crate::control::{CBPosition, Geometry, Pockets};
+
+pub const STANDARD_START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+/// A position read from Forsyth-Edwards Notation.
+#[derive(Debug, Clone)]
+pub struct FenPosition {
+    pub board: Board,
+    pub which_turn: Side,
+    /// Plies played before this position, from the full move number
+    pub turn_count: usize,
+    /// Pieces in hand for Crazyhouse, written in brackets after the placement like `[Nq]`
+    pub pockets: Pockets,
+}
+
+/// Parses a FEN string. En passant targets and the halfmove clock are accepted but not
+/// used, castling rights are kept in the `has_moved` flags of the kings and rooks.
+pub fn parse(fen: &str) -> Result<FenPosition, String> {
+    parse_with(fen, Geometry::STANDARD)
+}
+
+/// Parses a FEN string for a board of another shape, such as 10x8 for Capablanca.
+pub fn parse_with(fen: &str, geometry: Geometry) -> Result<FenPosition, String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 2 {
+        return Err(format!(
+            "FEN needs at least the placement and side to move, got {:?}",
+            fen
+        ));
+    }
+    let (placement, pockets) = match fields[0].split_once('[') {
+        Some((placement, hand)) => (placement, parse_pockets(hand)?),
+        None => (fields[0], Pockets::default()),
+    };
+    let mut board = parse_placement(placement, geometry)?;
+
+    let which_turn = match fields[1] {
+        "w" => Side::White,
+        "b" => Side::Black,
+        other => return Err(format!("Side to move should be w or b, got {:?}", other)),
+    };
+
+    let castling = fields.get(2).copied().unwrap_or("-");
+    let is_right =
+        |c: char| "KQkq".contains(c) || geometry.files().contains(&c.to_ascii_lowercase());
+    if castling != "-" && !castling.chars().all(is_right) {
+        return Err(format!("Invalid castling rights {:?}", castling));
+    }
+    apply_castling_rights(&mut board, castling);
+
+    let full_moves: usize = match fields.get(5) {
+        Some(n) => n
+            .parse()
+            .map_err(|_| format!("Full move number should be a number, got {:?}", n))?,
+        None => 1,
+    };
+    let turn_count = full_moves.saturating_sub(1) * 2
+        + match which_turn {
+            Side::White => 0,
+            Side::Black => 1,
+        };
+
+    Ok(FenPosition {
+        board,
+        which_turn,
+        turn_count,
+        pockets,
+    })
+}
+
+/// Reads the pieces in hand, what follows the `[` after the placement.
+fn parse_pockets(hand: &str) -> Result<Pockets, String> {
+    let hand = hand
+        .strip_suffix(']')
+        .ok_or_else(|| format!("Pieces in hand should end with ], got {:?}", hand))?;
+    let mut pockets = Pockets::default();
+    for c in hand.chars() {
+        match piece_from_char(c) {
+            Some((_, PieceType::King)) | None => {
+                return Err(format!("{:?} can't be a piece in hand", c))
+            }
+            Some((side, piece_type)) => pockets.of_mut(side).push(piece_type),
+        }
+    }
+    Ok(pockets)
+}
+
+/// Reads the pieces on each rank, from the last rank down. Empty squares may be counted in
+/// more than one digit on boards wider than nine files.
+fn parse_placement(placement: &str, geometry: Geometry) -> Result<Board, String> {
+    let ranks: Vec<&str> = placement.split('/').collect();
+    if ranks.len() != geometry.ranks as usize {
+        return Err(format!(
+            "FEN should have {} ranks, got {}",
+            geometry.ranks,
+            ranks.len()
+        ));
+    }
+    let mut board = empty_board_of(geometry);
+    for (rank, row) in ranks.iter().zip(geometry.ranks().rev()) {
+        let mut squares: Vec<Option<char>> = Vec::new();
+        let mut chars = rank.chars().peekable();
+        while let Some(c) = chars.next() {
+            if let Some(digit) = c.to_digit(10) {
+                let mut empty = digit as usize;
+                while let Some(digit) = chars.peek().and_then(|c| c.to_digit(10)) {
+                    empty = empty * 10 + digit as usize;
+                    chars.next();
+                }
+                squares.extend(std::iter::repeat_n(None, empty));
+            } else {
+                squares.push(Some(c));
+            }
+        }
+        if squares.len() != geometry.files as usize {
+            return Err(format!("Rank {} should have {} squares", row, geometry.files));
+        }
+        for (c, col) in squares.into_iter().zip(geometry.files()) {
+            let Some(c) = c else { continue };
+            let (side, piece_type) = piece_from_char(c)
+                .ok_or_else(|| format!("Unknown piece {:?} on rank {}", c, row))?;
+            let pos = CBPosition { col, row };
+            let mut piece = Piece::new(side, piece_type, pos);
+            // Pawns past their starting rank have used their double step. Only Horde has
+            // pawns behind it, which may still step twice.
+            piece.has_moved = match (piece_type, side) {
+                (PieceType::Pawn, Side::White) => row > 2,
+                (PieceType::Pawn, Side::Black) => row < geometry.ranks as usize - 1,
+                _ => false,
+            };
+            board.insert(pos, Some(piece));
+        }
+    }
+    // A side may have no king in some variants, such as white in Horde
+    let mut all_kings = 0;
+    for side in [Side::White, Side::Black] {
+        let kings = board
+            .values()
+            .flatten()
+            .filter(|p| p.side == side && p.piece_type == PieceType::King)
+            .count();
+        if kings > 1 {
+            return Err(format!(
+                "{} should have one king, found {}",
+                String::from(side),
+                kings
+            ));
+        }
+        all_kings += kings;
+    }
+    if all_kings == 0 {
+        return Err("The position has no kings".to_string());
+    }
+    Ok(board)
+}
+
+/// Kings and rooks without any castling rights are marked as moved. Rights are read as
+/// X-FEN, where `K` and `Q` are the outermost rook on each side of the king, or as
+/// Shredder-FEN, where the file of the rook is given, like `HAha`.
+fn apply_castling_rights(board: &mut Board, castling: &str) {
+    let geometry = Geometry::of(board);
+    for (side, home_row) in [(Side::White, 1), (Side::Black, geometry.ranks as usize)] {
+        // Without a king there is nothing to castle with
+        let Some(king) = board
+            .values()
+            .flatten()
+            .find(|p| p.side == side && p.piece_type == PieceType::King)
+            .map(|p| p.current_pos)
+        else {
+            continue;
+        };
+        let rooks: Vec<char> = geometry
+            .files()
+            .filter(|col| {
+                board
+                    .get(&CBPosition {
+                        col: *col,
+                        row: home_row,
+                    })
+                    .copied()
+                    .flatten()
+                    .is_some_and(|p| p.side == side && p.piece_type == PieceType::Rook)
+            })
+            .collect();
+        let granted: Vec<char> = castling
+            .chars()
+            .filter(|c| c.is_ascii_uppercase() == (side == Side::White))
+            .filter_map(|c| match c.to_ascii_lowercase() {
+                'k' => rooks.iter().copied().filter(|col| *col > king.col).max(),
+                'q' => rooks.iter().copied().filter(|col| *col < king.col).min(),
+                file => Some(file),
+            })
+            .filter(|_| king.row == home_row)
+            .collect();
+        for piece in board.values_mut().flatten().filter(|p| p.side == side) {
+            let pos = piece.current_pos;
+            match piece.piece_type {
+                PieceType::King => piece.has_moved = granted.is_empty(),
+                PieceType::Rook => {
+                    piece.has_moved = !(pos.row == home_row && granted.contains(&pos.col))
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+/// Writes the position as FEN. The en passant square and halfmove clock are not tracked, so
+/// they are always written as `-` and `0`.
+pub fn to_fen(board: &Board, which_turn: Side, turn_count: usize) -> String {
+    let mut placement = String::new();
+    let geometry = Geometry::of(board);
+    for row in geometry.ranks().rev() {
+        let mut empty = 0;
+        for col in geometry.files() {
+            match board.get(&CBPosition { col, row }).copied().flatten() {
+                Some(piece) => {
+                    if empty > 0 {
+                        placement.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    placement.push(piece_to_char(&piece));
+                }
+                None => empty += 1,
+            }
+        }
+        if empty > 0 {
+            placement.push_str(&empty.to_string());
+        }
+        if row > 1 {
+            placement.push('/');
+        }
+    }
+    let side = match which_turn {
+        Side::White => "w",
+        Side::Black => "b",
+    };
+    format!(
+        "{} {} {} - 0 {}",
+        placement,
+        side,
+        castling_rights(board),
+        turn_count / 2 + 1
+    )
+}
+
+/// The castling rights as X-FEN, which is the usual `KQkq` in standard chess. A rook
+/// with another rook further out on the same side is given by its file instead.
+fn castling_rights(board: &Board) -> String {
+    let mut rights = String::new();
+    for side in [Side::White, Side::Black] {
+        let Some(king) = board
+            .values()
+            .flatten()
+            .find(|p| p.side == side && p.piece_type == PieceType::King)
+        else {
+            continue;
+        };
+        let rooks: Vec<char> = board
+            .values()
+            .flatten()
+            .filter(|p| {
+                p.side == side
+                    && p.piece_type == PieceType::Rook
+                    && p.current_pos.row == king.current_pos.row
+            })
+            .map(|p| p.current_pos.col)
+            .collect();
+        let castling = castling_rooks(board, king);
+        for king_side in [true, false] {
+            let on_side = |col: &char| (*col > king.current_pos.col) == king_side;
+            let outermost = if king_side {
+                rooks.iter().filter(|col| on_side(col)).max()
+            } else {
+                rooks.iter().filter(|col| on_side(col)).min()
+            };
+            for rook in castling.iter().filter(|pos| on_side(&pos.col)) {
+                let right = match (Some(&rook.col) == outermost, king_side) {
+                    (true, true) => 'k',
+                    (true, false) => 'q',
+                    (false, _) => rook.col,
+                };
+                rights.push(match side {
+                    Side::White => right.to_ascii_uppercase(),
+                    Side::Black => right,
+                });
+            }
+        }
+    }
+    if rights.is_empty() {
+        rights.push('-');
+    }
+    rights
+}
+
+/// Writes the position as FEN with the pieces in hand after the placement, as Crazyhouse
+/// positions are written.
+pub fn to_fen_with_pockets(
+    board: &Board,
+    which_turn: Side,
+    turn_count: usize,
+    pockets: &Pockets,
+) -> String {
+    let fen = to_fen(board, which_turn, turn_count);
+    let mut hand = String::from("[");
+    for side in [Side::White, Side::Black] {
+        for piece_type in pockets.of(side) {
+            let letter = piece_type.to_string();
+            hand.push_str(&match side {
+                Side::White => letter,
+                Side::Black => letter.to_ascii_lowercase(),
+            });
+        }
+    }
+    hand.push(']');
+    fen.replacen(' ', &format!("{} ", hand), 1)
+}
+
+pub fn piece_from_char(c: char) -> Option<(Side, PieceType)> {
+    let side = if c.is_ascii_uppercase() {
+        Side::White
+    } else {
+        Side::Black
+    };
+    let piece_type = match c.to_ascii_lowercase() {
+        'k' => PieceType::King,
+        'q' => PieceType::Queen,
+        'r' => PieceType::Rook,
+        'b' => PieceType::Bishop,
+        'n' => PieceType::Knight,
+        'p' => PieceType::Pawn,
+        'a' => PieceType::Archbishop,
+        'c' => PieceType::Chancellor,
+        'm' => PieceType::Amazon,
+        'l' => PieceType::Camel,
+        'z' => PieceType::Zebra,
+        'h' => PieceType::Nightrider,
+        _ if custom::is_defined(c.to_ascii_uppercase()) => {
+            PieceType::Custom(c.to_ascii_uppercase())
+        }
+        _ => return None,
+    };
+    Some((side, piece_type))
+}
+
+pub fn piece_to_char(piece: &Piece) -> char {
+    let c = piece.piece_type.to_string().chars().next().unwrap_or('?');
+    match piece.side {
+        Side::White => c,
+        Side::Black => c.to_ascii_lowercase(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::board_setup;
+
+    #[test]
+    fn round_trip() {
+        let start = parse(STANDARD_START).unwrap();
+        assert_eq!(start.board, board_setup());
+        assert_eq!(
+            to_fen(&start.board, start.which_turn, start.turn_count),
+            STANDARD_START
+        );
+
+        let fen = "r3k2r/pp3ppp/8/3n4/8/8/PP3PPP/R3K2R b Kq - 0 14";
+        let position = parse(fen).unwrap();
+        assert_eq!(position.which_turn, Side::Black);
+        assert_eq!(position.turn_count, 27);
+        assert_eq!(
+            to_fen(&position.board, position.which_turn, position.turn_count),
+            fen
+        );
+    }
+
+    #[test]
+    fn chess960_castling_rights() {
+        let shredder = parse("1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w GBgb - 0 1").unwrap();
+        assert_eq!(
+            to_fen(&shredder.board, shredder.which_turn, shredder.turn_count),
+            "1r2k1r1/pppppppp/8/8/8/8/PPPPPPPP/1R2K1R1 w KQkq - 0 1"
+        );
+        // Only the inner of the two rooks can castle
+        let fen = "4k3/8/8/8/8/8/8/4KR1R w F - 0 1";
+        let inner = parse(fen).unwrap();
+        assert_eq!(
+            to_fen(&inner.board, inner.which_turn, inner.turn_count),
+            fen
+        );
+    }
+
+    #[test]
+    fn crazyhouse_pockets() {
+        let fen = "r1bqkb1r/pppp1ppp/2n5/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R[Nnp] w KQkq - 0 5";
+        let position = parse(fen).unwrap();
+        assert_eq!(position.pockets.white, [PieceType::Knight]);
+        assert_eq!(
+            position.pockets.black,
+            [PieceType::Knight, PieceType::Pawn]
+        );
+        assert_eq!(
+            to_fen_with_pockets(
+                &position.board,
+                position.which_turn,
+                position.turn_count,
+                &position.pockets
+            ),
+            fen
+        );
+        assert!(parse("4k3/8/8/8/8/8/8/4K3[K] w - - 0 1").is_err());
+    }
+
+    #[test]
+    fn other_geometries() {
+        let capablanca = "rnabqkbcnr/pppppppppp/10/10/10/10/PPPPPPPPPP/RNABQKBCNR w KQkq - 0 1";
+        let ten_by_eight = Geometry {
+            files: 10,
+            ranks: 8,
+        };
+        let position = parse_with(capablanca, ten_by_eight).unwrap();
+        assert_eq!(Geometry::of(&position.board), ten_by_eight);
+        assert_eq!(
+            to_fen(&position.board, position.which_turn, position.turn_count),
+            capablanca
+        );
+        assert!(parse(capablanca).is_err());
+
+        let fen = "4k5/10/10/10/10/10/10/10/p9/R3K4R b Q - 0 30";
+        let ten_by_ten = Geometry {
+            files: 10,
+            ranks: 10,
+        };
+        let position = parse_with(fen, ten_by_ten).unwrap();
+        assert_eq!(position.board[&"e10".into()].unwrap().piece_type, PieceType::King);
+        assert_eq!(
+            to_fen(&position.board, position.which_turn, position.turn_count),
+            fen
+        );
+    }
+
+    #[test]
+    fn rejects_bad_fen() {
+        assert!(parse("8/8/8/8/8/8/8/8 w - - 0 1").is_err());
+        assert!(parse("rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1").is_err());
+        assert!(parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq - 0 1").is_err());
+        assert!(parse("rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq - 0 1").is_err());
+    }
+}
@@ -0,0 +1,63 @@
+//! Odds games, where the stronger player starts without some of their pieces. The pieces
+//! given up are the traditional ones: the f-pawn, the queen's knight and the queen's rook.
+
+use super::{Board, PieceType, Side};
+use crate::control::{CBPosition, Geometry, Odds};
+
+/// The piece given up and the square it starts on, for white.
+fn given_up(odds: Odds) -> (PieceType, CBPosition) {
+    match odds {
+        Odds::Pawn | Odds::PawnAndMove => (PieceType::Pawn, "f2".into()),
+        Odds::Knight => (PieceType::Knight, "b1".into()),
+        Odds::Rook => (PieceType::Rook, "a1".into()),
+        Odds::Queen => (PieceType::Queen, "d1".into()),
+    }
+}
+
+/// Takes the odds off the starting board of `giver`.
+pub fn give_odds(board: &mut Board, odds: Odds, giver: Side) -> Result<(), String> {
+    let (piece_type, mut square) = given_up(odds);
+    if giver == Side::Black {
+        square.row = Geometry::of(board).ranks as usize + 1 - square.row;
+    }
+    match board.get(&square).copied().flatten() {
+        Some(piece) if piece.side == giver && piece.piece_type == piece_type => {
+            board.insert(square, None);
+            Ok(())
+        }
+        _ => Err(format!(
+            "{} needs {}'s {:?} on {:?}",
+            odds,
+            String::from(giver),
+            piece_type,
+            square
+        )),
+    }
+}
+
+/// The side that moves first, which is the receiver when the move is given too.
+pub fn first_to_move(odds: Odds, giver: Side) -> Side {
+    match odds {
+        Odds::PawnAndMove => giver.flipped(),
+        _ => Side::White,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::board_setup;
+
+    #[test]
+    fn takes_off_the_odds() {
+        let mut board = board_setup();
+        give_odds(&mut board, Odds::Knight, Side::White).unwrap();
+        assert!(board[&"b1".into()].is_none());
+        assert_eq!(board.values().flatten().count(), 31);
+
+        give_odds(&mut board, Odds::PawnAndMove, Side::Black).unwrap();
+        assert!(board[&"f7".into()].is_none());
+        assert_eq!(first_to_move(Odds::PawnAndMove, Side::Black), Side::White);
+        assert!(give_odds(&mut board, Odds::Knight, Side::White).is_err());
+    }
+}
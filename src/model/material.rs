@@ -0,0 +1,109 @@
+use super::{Board, PieceType, Side};
+
+/// The pieces each side has captured, worked out from the positions the game went through
+/// so the pieces it started without, as in odds games, don't count as taken.
+#[derive(Debug, Clone, PartialEq, Default)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+pub struct Material {
+    /// Black pieces taken by white, most valuable first
+    pub captured_by_white: Vec<PieceType>,
+    /// White pieces taken by black, most valuable first
+    pub captured_by_black: Vec<PieceType>,
+    /// White's material minus black's, in pawns
+    pub difference: isize,
+}
+
+impl Material {
+    /// The material of a game whose positions run from its start to now, `first_to_move`
+    /// making the first move. A move takes whatever pieces of the other side it removes,
+    /// so drops and promotions add nothing and explosions count the pieces they destroy.
+    pub fn from_positions(positions: &[Board], first_to_move: Side) -> Material {
+        let mut captured_by_white = Vec::new();
+        let mut captured_by_black = Vec::new();
+        let mut mover = first_to_move;
+        for pair in positions.windows(2) {
+            let (before, after) = (
+                pieces_of(&pair[0], mover.flipped()),
+                pieces_of(&pair[1], mover.flipped()),
+            );
+            let captured = match mover {
+                Side::White => &mut captured_by_white,
+                Side::Black => &mut captured_by_black,
+            };
+            let mut left = after;
+            for piece_type in before {
+                match left.iter().position(|p| *p == piece_type) {
+                    Some(i) => {
+                        left.swap_remove(i);
+                    }
+                    None => captured.push(piece_type),
+                }
+            }
+            mover.flip();
+        }
+        for captured in [&mut captured_by_white, &mut captured_by_black] {
+            captured.sort_by_key(|p| std::cmp::Reverse(p.value()));
+        }
+        Material {
+            captured_by_white,
+            captured_by_black,
+            difference: positions.last().map_or(0, Material::difference),
+        }
+    }
+
+    /// White's material minus black's on the board, in pawns.
+    pub fn difference(board: &Board) -> isize {
+        board
+            .values()
+            .flatten()
+            .map(|p| match p.side {
+                Side::White => p.piece_type.value(),
+                Side::Black => -p.piece_type.value(),
+            })
+            .sum()
+    }
+}
+
+fn pieces_of(board: &Board, side: Side) -> Vec<PieceType> {
+    board
+        .values()
+        .flatten()
+        .filter(|p| p.side == side)
+        .map(|p| p.piece_type)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{board_setup, move_piece};
+
+    #[test]
+    fn material_after_captures() {
+        let mut board = board_setup();
+        let mut positions = vec![board.clone()];
+        assert_eq!(
+            Material::from_positions(&positions, Side::White),
+            Material::default()
+        );
+
+        // Nxd7 then Qxd7, trading white's knight for a pawn
+        move_piece(&mut board, "b1".into(), "d7".into());
+        positions.push(board.clone());
+        move_piece(&mut board, "d8".into(), "d7".into());
+        positions.push(board.clone());
+        let material = Material::from_positions(&positions, Side::White);
+        assert_eq!(material.captured_by_white, vec![PieceType::Pawn]);
+        assert_eq!(material.captured_by_black, vec![PieceType::Knight]);
+        assert_eq!(material.difference, -2);
+    }
+
+    #[test]
+    fn odds_pieces_are_not_captured() {
+        let mut board = board_setup();
+        board.insert("d1".into(), None);
+        let material = Material::from_positions(&[board], Side::White);
+        assert!(material.captured_by_black.is_empty());
+        assert_eq!(material.difference, -9);
+    }
+}
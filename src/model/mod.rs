@@ -2,24 +2,81 @@
 
 use std::{
     collections::{HashMap, HashSet},
+    path::PathBuf,
     fmt::{Debug, Display},
     iter::repeat,
-    sync::mpsc::{channel, Receiver, Sender},
-    thread,
-    time::Duration,
+    sync::{
+        mpsc::{Receiver, RecvTimeoutError, Sender, TryRecvError},
+        Arc,
+    },
+    time::{Duration, Instant},
     usize,
 };
 
-use log::{debug, error, info, warn};
+use lazy_static::lazy_static;
+use log::{debug, error, info, trace, warn};
 use ratatui::text::Text;
 
 use crate::control::*;
 
+pub use self::material::Material;
+use self::{
+    betza::Step,
+    net::Peer,
+    player::{Player, Turn},
+    variant::Variant,
+};
+
+pub mod arena;
+pub mod autosave;
+mod betza;
+pub mod chess960;
+pub mod custom;
+mod endgame;
+pub mod engine;
+mod fen;
+mod handicap;
+mod material;
+pub mod net;
+mod notation;
+mod pgn;
+pub mod player;
+mod puzzle;
+mod repertoire;
+mod syzygy;
+mod tablebase;
+pub(crate) mod uci;
+pub mod variant;
+
 type MoveList<'a> = Vec<(Piece, CBPosition)>;
+
+/// How long the model waits for a message before checking whether an engine should move.
+const ENGINE_POLL: Duration = Duration::from_millis(50);
+/// The least time between engine moves, so engine games can be followed on the board.
+const ENGINE_MOVE_DELAY: Duration = Duration::from_millis(400);
+
 struct Model {
     ui_sender: Sender<ModelMsg>,
     ui_reciever: Receiver<UiMsg>,
     game: Game,
+    /// Where the moves of each side come from, made from the game's players when first
+    /// asked for a move
+    players: HashMap<Side, Box<dyn Player>>,
+    last_move_time: Instant,
+    /// Where the game is saved after every move, `None` to not save it
+    autosave_path: Option<PathBuf>,
+    /// The other player of a network game
+    peer: Option<Peer>,
+    /// The puzzles being solved in puzzle mode
+    puzzles: Option<puzzle::Session>,
+    /// Where the puzzle rating is kept, `None` to not keep it
+    puzzle_record_path: Option<PathBuf>,
+    /// The repertoire being drilled by the opening trainer
+    repertoire: Option<repertoire::Session>,
+    /// Where the repertoire's review schedule is kept, `None` to not keep it
+    repertoire_schedule_path: Option<PathBuf>,
+    /// The endgame being drilled
+    endgame: Option<endgame::Session>,
 }
 impl Model {
     fn new(send: Sender<ModelMsg>, recv: Receiver<UiMsg>) -> Self {
@@ -27,6 +84,15 @@ impl Model {
             ui_sender: send,
             ui_reciever: recv,
             game: Game::new(),
+            players: HashMap::new(),
+            last_move_time: Instant::now(),
+            autosave_path: None,
+            peer: None,
+            puzzles: None,
+            puzzle_record_path: None,
+            repertoire: None,
+            repertoire_schedule_path: None,
+            endgame: None,
         }
     }
 
@@ -40,11 +106,20 @@ impl Model {
             ui_sender: send,
             ui_reciever: recv,
             game: Game::from_board_state(board, turn),
+            players: HashMap::new(),
+            last_move_time: Instant::now(),
+            autosave_path: None,
+            peer: None,
+            puzzles: None,
+            puzzle_record_path: None,
+            repertoire: None,
+            repertoire_schedule_path: None,
+            endgame: None,
         }
     }
     fn model_loop(&mut self) {
         loop {
-            match self.ui_reciever.recv() {
+            match self.ui_reciever.recv_timeout(ENGINE_POLL) {
                 Ok(m) => {
                     if let UiMsg::Quit = m {
                         info!("Quit message recieved");
@@ -52,14 +127,22 @@ impl Model {
                     }
                     self.handle_message(m)
                 }
+                Err(RecvTimeoutError::Timeout) => {}
                 Err(e) => {
                     error!("{}", e);
                     break;
                 }
             }
+            self.update();
         }
         info!("Model Loop Broken!")
     }
+    /// Lets the game move on without a message: network moves, clocks and engines.
+    fn update(&mut self) {
+        self.handle_network();
+        self.check_clock();
+        self.play_turn();
+    }
     fn handle_message(&mut self, msg: UiMsg) {
         if let UiMsg::GetBoardState = msg {
         } else {
@@ -67,7 +150,18 @@ impl Model {
         }
         match msg {
             UiMsg::Debug(s) => debug!("debug message recieved: {}", s),
-            UiMsg::CheckValidMove((from, to)) => todo!(),
+            UiMsg::CheckValidMove(mv) => {
+                let moves = self
+                    .game
+                    .rules()
+                    .legal_moves(&self.game.board, self.game.which_turn);
+                let reply = if moves.contains(&mv) {
+                    ModelMsg::MoveIsValid
+                } else {
+                    ModelMsg::MoveIsInvalid
+                };
+                let _ = self.ui_sender.send(reply);
+            }
             UiMsg::GetValidMoves(pos) => {
                 let valid_moves = self.game.get_valid_moves(pos);
                 debug!("valid moves : {:?}", valid_moves);
@@ -76,118 +170,622 @@ impl Model {
                 };
             }
             UiMsg::MakeMove((from, to)) => {
-                self.make_move(from, to);
+                if self.takes_ui_moves() {
+                    self.make_move(from, to);
+                } else {
+                    let _ = self.ui_sender.send(ModelMsg::MoveIsInvalid);
+                }
+            }
+            UiMsg::MakeSanMove(san) => {
+                if self.takes_ui_moves() {
+                    self.make_san_move(san.trim());
+                } else {
+                    let _ = self.ui_sender.send(ModelMsg::MoveIsInvalid);
+                }
+            }
+            UiMsg::GetValidDrops(piece_type) => {
+                let drops = self
+                    .game
+                    .legal_drops()
+                    .into_iter()
+                    .filter(|(p, _)| *p == piece_type)
+                    .map(|(_, to)| to)
+                    .collect();
+                let _ = self.ui_sender.send(ModelMsg::Moves(drops));
+            }
+            UiMsg::DropPiece(piece_type, to) => {
+                if self.takes_ui_moves() {
+                    self.make_drop(piece_type, to);
+                } else {
+                    let _ = self.ui_sender.send(ModelMsg::MoveIsInvalid);
+                }
             }
             UiMsg::GetBoardState => {
                 self.ui_sender
                     .send(ModelMsg::BoardState(self.game.board.clone()));
             }
+            UiMsg::GetLegalMoves => {
+                let moves = self
+                    .game
+                    .rules()
+                    .legal_moves(&self.game.board, self.game.which_turn);
+                let _ = self.ui_sender.send(ModelMsg::LegalMoves(moves));
+            }
+            UiMsg::GetGameStatus => {
+                let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+            }
+            UiMsg::GetPositionAt(ply) => match self.game.positions.get(ply) {
+                Some(board) => {
+                    let _ = self
+                        .ui_sender
+                        .send(ModelMsg::PositionAt(ply, board.clone()));
+                }
+                None => warn!("No position after ply {}", ply),
+            },
+            UiMsg::GetPgn => {
+                let _ = self.ui_sender.send(ModelMsg::Pgn(self.pgn()));
+            }
+            UiMsg::NewGame(setup) => self.new_game(setup),
+            UiMsg::SetPosition(fen) => self.set_position(fen),
+            UiMsg::Undo => self.undo(),
+            UiMsg::ResumeAutosave => self.resume_autosave(),
+            UiMsg::HostGame(setup, address) => self.host_game(setup, &address),
+            UiMsg::JoinGame(address, code) => self.join_game(address, code),
+            UiMsg::StartPuzzles(setup) => self.start_puzzles(setup),
+            UiMsg::NextPuzzle => self.next_puzzle(),
+            UiMsg::StartRepertoire(setup) => self.start_repertoire(setup),
+            UiMsg::NextLine => self.next_line(),
+            UiMsg::StartEndgame(setup) => self.start_endgame(setup),
+            UiMsg::RetryEndgame => self.retry_endgame(),
             UiMsg::Quit => unreachable!(),
         }
     }
 
+    /// Whether the player of the side to move plays the moves made in the UI.
+    fn takes_ui_moves(&self) -> bool {
+        if self.training_over() {
+            return false;
+        }
+        let side = self.game.which_turn;
+        let takes_ui_moves = match self.players.get(&side) {
+            Some(player) => player.takes_ui_moves(),
+            None => player::from_kind(self.game.player(side)).takes_ui_moves(),
+        };
+        if !takes_ui_moves {
+            info!("{:?} doesn't take moves from the UI", side);
+        }
+        takes_ui_moves
+    }
+
+    fn new_game(&mut self, setup: GameSetup) {
+        let moves = match &setup.start {
+            StartPosition::Pgn(text) => pgn::parse(text).map(|pgn| pgn.moves),
+            _ => Ok(Vec::new()),
+        };
+        match moves.and_then(|moves| self.start_game(&setup, &moves)) {
+            Ok(()) => {
+                info!("New game: {:?} vs {:?}", setup.white, setup.black);
+                self.autosave();
+                let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+            }
+            Err(e) => {
+                let _ = self.ui_sender.send(ModelMsg::Error(e));
+            }
+        }
+    }
+
+    /// Starts again from a FEN position, keeping the players and time control.
+    fn set_position(&mut self, fen: String) {
+        let setup = GameSetup {
+            white: self.game.white.clone(),
+            black: self.game.black.clone(),
+            time_control: self.game.time_control,
+            start: StartPosition::Fen(fen),
+            variant: self.game.variant,
+            handicap: self.game.handicap,
+        };
+        self.new_game(setup);
+    }
+
+    /// Takes back the last move, leaving the clocks as they are.
+    fn undo(&mut self) {
+        let undone = if self.is_remote_game() {
+            Err("Moves can't be taken back in a network game".to_string())
+        } else if self.in_training() {
+            Err("Moves can't be taken back in a puzzle or drill".to_string())
+        } else if self.game.move_history.is_empty() {
+            Err("There is no move to take back".to_string())
+        } else {
+            let setup = GameSetup {
+                white: self.game.white.clone(),
+                black: self.game.black.clone(),
+                time_control: self.game.time_control,
+                start: StartPosition::Fen(self.start_fen()),
+                variant: self.game.variant,
+                handicap: self.game.handicap,
+            };
+            let clocks = self.game.timer.clocks(self.game.which_turn);
+            let mut moves = self.game.move_history.clone();
+            moves.pop();
+            self.start_game(&setup, &moves).map(|()| {
+                if let Some(clocks) = clocks {
+                    self.game.timer.set_clocks(clocks);
+                }
+            })
+        };
+        match undone {
+            Ok(()) => {
+                self.autosave();
+                let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+            }
+            Err(e) => {
+                let _ = self.ui_sender.send(ModelMsg::Error(e));
+            }
+        }
+    }
+
+    fn resume_autosave(&mut self) {
+        let Some(path) = self.autosave_path.clone() else {
+            return;
+        };
+        let resumed = autosave::load(&path).and_then(|saved| {
+            let saved = saved.ok_or("There is no game to resume")?;
+            self.start_game(&saved.setup()?, &saved.moves)
+                .map_err(|e| format!("Autosave {} can't be resumed: {}", path.display(), e))?;
+            if let Some(clocks) = saved.clocks() {
+                self.game.timer.set_clocks(clocks);
+            }
+            Ok(())
+        });
+        match resumed {
+            Ok(()) => {
+                info!("Resumed game from {}", path.display());
+                let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+            }
+            Err(e) => {
+                let _ = self.ui_sender.send(ModelMsg::Error(e));
+            }
+        }
+    }
+
+    /// Replaces the game with one from `setup` with `moves` played, keeping the old game if
+    /// the setup is invalid or a move is illegal.
+    fn start_game(&mut self, setup: &GameSetup, moves: &[String]) -> Result<(), String> {
+        let game = Game::from_setup(setup)?;
+        let old_game = std::mem::replace(&mut self.game, game);
+        if let Err(e) = self.replay_moves(moves) {
+            self.game = old_game;
+            return Err(e);
+        }
+        self.game.time_control = setup.time_control;
+        self.players.clear();
+        self.peer = None;
+        self.puzzles = None;
+        self.repertoire = None;
+        self.endgame = None;
+        self.game.timer = ChessTimer::new(setup.time_controls());
+        self.game.timer.start();
+        self.last_move_time = Instant::now();
+        Ok(())
+    }
+
+    /// Saves the game if it is still going, or removes the save once it is over.
+    fn autosave(&self) {
+        let Some(path) = &self.autosave_path else {
+            return;
+        };
+        if self.is_remote_game() || self.in_training() {
+            // The network game can't be picked up again without the other player, and a
+            // puzzle or drill isn't a game to pick up
+            return;
+        }
+        let saved = if self.game.result.is_some() {
+            autosave::remove(path)
+        } else {
+            let game = autosave::SavedGame::new(
+                &self.game.white,
+                &self.game.black,
+                self.game.time_control,
+                self.game.timer.clocks(self.game.which_turn),
+                self.start_fen(),
+                self.game.move_history.clone(),
+                self.game.variant,
+            )
+            .with_handicap(self.game.handicap);
+            autosave::save(path, &game)
+        };
+        if let Err(e) = saved {
+            warn!("Could not autosave to {}: {}", path.display(), e);
+        }
+    }
+
+    /// The position before the first move.
+    fn start_fen(&self) -> String {
+        let board = &self.game.positions[0];
+        let side = self.game.starting_side();
+        let turn_count = self.game.turn_count - self.game.move_history.len();
+        if self.game.rules().has_drops() {
+            fen::to_fen_with_pockets(board, side, turn_count, &self.game.pockets[0])
+        } else {
+            fen::to_fen(board, side, turn_count)
+        }
+    }
+
+    /// The game so far as PGN. A game that didn't start from the usual position, such as
+    /// an odds game, has its start in the `SetUp` and `FEN` tags.
+    fn pgn(&self) -> String {
+        let result = self.game.result.map_or("*", |r| r.pgn_result());
+        let mut tags: Vec<(String, String)> = vec![
+            ("Event", "Casual game".to_string()),
+            ("Site", "?".to_string()),
+            ("Date", "????.??.??".to_string()),
+            ("Round", "-".to_string()),
+            ("White", self.game.white.to_string()),
+            ("Black", self.game.black.to_string()),
+            ("Result", result.to_string()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect();
+        if self.game.variant != VariantKind::Standard {
+            tags.push(("Variant".to_string(), self.game.variant.pgn_name().to_string()));
+        }
+        let usual_start = self.game.rules().start_board().unwrap_or_else(board_setup);
+        if self.game.positions[0] != usual_start || self.game.starting_side() != Side::White {
+            tags.push(("SetUp".to_string(), "1".to_string()));
+            tags.push(("FEN".to_string(), self.start_fen()));
+        }
+        if let Some(handicap) = self.game.handicap {
+            tags.push(("Handicap".to_string(), handicap.to_string()));
+        }
+        let setup = GameSetup {
+            time_control: self.game.time_control,
+            handicap: self.game.handicap,
+            ..GameSetup::default()
+        };
+        match setup.time_controls() {
+            Some((white, black)) if white == black => {
+                tags.push(("TimeControl".to_string(), pgn::time_control(white)));
+            }
+            Some((white, black)) => {
+                tags.push(("WhiteTimeControl".to_string(), pgn::time_control(white)));
+                tags.push(("BlackTimeControl".to_string(), pgn::time_control(black)));
+            }
+            None => {}
+        }
+        let first_ply = self.game.turn_count - self.game.move_history.len();
+        let comment = self.game.result.map(|r| r.to_string());
+        pgn::write(
+            &tags,
+            first_ply,
+            &self.game.move_history,
+            result,
+            comment.as_deref(),
+        )
+    }
+
+    fn replay_moves(&mut self, moves: &[String]) -> Result<(), String> {
+        for (i, san) in moves.iter().enumerate() {
+            let mv = self
+                .resolve_san(san)
+                .ok_or_else(|| format!("Move {} ({}) is not legal", i / 2 + 1, san))?;
+            self.play(mv)?;
+        }
+        Ok(())
+    }
+
+    /// Finds the legal move or drop written as `san` in algebraic notation, or as a move in
+    /// UCI's long form.
+    fn resolve_san(&self, san: &str) -> Option<Move> {
+        if let Some(drop) = notation::parse_drop(san) {
+            return self
+                .game
+                .legal_drops()
+                .contains(&drop)
+                .then_some(Move::Drop(drop.0, drop.1));
+        }
+        notation::resolve(
+            &self.game.board,
+            self.game.rules(),
+            self.game.which_turn,
+            san,
+        )
+        .map(|(from, to)| Move::Board(from, to))
+    }
+
+    fn check_clock(&mut self) {
+        // When joined the host's clock decides
+        if self.game.result.is_none()
+            && !self.is_joined()
+            && self.game.timer.flagged(self.game.which_turn)
+        {
+            let loser = self.game.which_turn;
+            info!("{:?} ran out of time", loser);
+            self.game.result = Some(GameResult::OutOfTime { loser });
+            self.send_timeout(loser);
+            self.autosave();
+            let _ = self.ui_sender.send(ModelMsg::OutOfTime(loser));
+            let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+        }
+    }
+
+    /// Asks the player of the side to move for a move and plays it.
+    fn play_turn(&mut self) {
+        if self.game.result.is_some()
+            || self.training_over()
+            || self.last_move_time.elapsed() < ENGINE_MOVE_DELAY
+        {
+            return;
+        }
+        let side = self.game.which_turn;
+        let turn = Turn {
+            board: &self.game.board,
+            side,
+            turn_count: self.game.turn_count,
+            time_left: self.game.timer.remaining(side, side),
+            rules: self.game.rules(),
+            pocket: self.game.pockets().of(side),
+            history: &self.game.move_history,
+            variant: self.game.variant,
+            positions: &self.game.positions,
+            moves: &self.game.moves,
+        };
+        let kind = match side {
+            Side::White => &self.game.white,
+            Side::Black => &self.game.black,
+        };
+        let mv = self
+            .players
+            .entry(side)
+            .or_insert_with(|| player::from_kind(kind))
+            .choose_move(&turn);
+        match mv {
+            Ok(Some(Move::Board(from, to))) => self.make_move(from, to),
+            Ok(Some(Move::Drop(piece_type, to))) => self.make_drop(piece_type, to),
+            Ok(None) => {}
+            Err(e) => {
+                error!("{:?} player failed to move: {}", side, e);
+                // Hand the side over so the game can go on
+                let kind = self.game.player(side).clone();
+                self.set_player(side, PlayerKind::Human);
+                let _ = self.ui_sender.send(ModelMsg::Error(format!(
+                    "{} ({}) failed to move, it is now played by hand: {}",
+                    String::from(side),
+                    kind,
+                    e
+                )));
+                let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+            }
+        }
+    }
+
+    fn set_player(&mut self, side: Side, kind: PlayerKind) {
+        self.players.insert(side, player::from_kind(&kind));
+        self.game.set_player(side, kind);
+    }
+
     fn make_move(&mut self, from: CBPosition, to: CBPosition) {
-        if let Some(_) = self
+        if self.is_joined() {
+            self.request_move(from, to);
+            return;
+        }
+        if let Err(e) = self.try_move(Move::Board(from, to)) {
+            info!("move is invalid: {}", e);
+            let _ = self.ui_sender.send(ModelMsg::MoveIsInvalid);
+            //send a message back to ui to play sound or whatever
+        }
+    }
+
+    /// Plays a move typed in notation, telling the UI when it isn't legal here.
+    fn make_san_move(&mut self, san: &str) {
+        match self.resolve_san(san) {
+            Some(Move::Board(from, to)) => self.make_move(from, to),
+            Some(Move::Drop(piece_type, to)) => self.make_drop(piece_type, to),
+            None => {
+                let _ = self
+                    .ui_sender
+                    .send(ModelMsg::Error(format!("{} is not a legal move", san)));
+            }
+        }
+    }
+
+    fn make_drop(&mut self, piece_type: PieceType, to: CBPosition) {
+        if let Err(e) = self.try_move(Move::Drop(piece_type, to)) {
+            info!("drop is invalid: {}", e);
+            let _ = self.ui_sender.send(ModelMsg::MoveIsInvalid);
+        }
+    }
+
+    /// Plays a move and lets the UI and the other player of a network game know about it.
+    fn try_move(&mut self, mv: Move) -> Result<(), String> {
+        let answers = self.puzzle_answers();
+        let replies = self.repertoire_replies();
+        self.play(mv)?;
+        self.check_puzzle_move(mv, answers);
+        self.check_repertoire_move(mv, replies);
+        self.check_endgame_move();
+        self.announce_result();
+        self.autosave();
+        if let Move::Board(from, to) = mv {
+            // Network games are standard chess, so they never have drops
+            self.send_move(from, to);
+        }
+        let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+        Ok(())
+    }
+
+    fn announce_result(&self) {
+        match self.game.result {
+            Some(GameResult::Checkmate { winner }) => {
+                let _ = self.ui_sender.send(ModelMsg::Checkmate(winner));
+            }
+            Some(GameResult::Stalemate) => {
+                let _ = self.ui_sender.send(ModelMsg::Stalemate);
+            }
+            _ => {}
+        }
+    }
+
+    fn play(&mut self, mv: Move) -> Result<(), String> {
+        match mv {
+            Move::Board(from, to) => self.play_move(from, to),
+            Move::Drop(piece_type, to) => self.play_drop(piece_type, to),
+        }
+    }
+
+    /// Checks a move is legal and plays it, recording it in the game.
+    fn play_move(&mut self, from: CBPosition, to: CBPosition) -> Result<(), String> {
+        if let Some(result) = self.game.result {
+            return Err(format!("The game is over: {}", result));
+        }
+        let Some(piece) = self
             .game
             .board
             .get(&from)
             .expect("From piece pos should be in bounds!")
+        else {
+            return Err(format!("No piece at {:?}", from));
+        };
+        if piece.side != self.game.which_turn {
+            return Err(format!("It is not {}'s turn", String::from(piece.side)));
+        }
+        if !piece.get_valid_moves(&self.game.board).contains(&to) {
+            return Err(format!("{:?} can't move to {:?}", piece, to));
+        }
+        let rules = self.game.rules();
+        let side = self.game.which_turn;
+        if !rules
+            .legal_moves(&self.game.board, side)
+            .contains(&(from, to))
         {
-            info!("making move!");
-            let mut new_board = self.simulate_move(from, to);
-            let all_moves = get_all_moves(&new_board);
-            if Self::check_move_is_valid(
-                Self::get_king(&new_board, self.game.which_turn),
-                &all_moves,
-            ) && to
-                != Self::get_king(&self.game.board, self.game.which_turn.flipped()).current_pos
-            {
-                info!("Move is valid!");
-                if all_moves.len() == 0 {
-                    let _ = self.ui_sender.send(ModelMsg::Stalemate);
-                } else if self.check_for_checkmate(new_board.clone(), all_moves) {
-                    let _ = self
-                        .ui_sender
-                        .send(ModelMsg::Checkmate(self.game.which_turn));
+            return Err(format!("{:?} to {:?} is not legal here", from, to));
+        }
+        info!("Move is valid!");
+        let mut new_board = self.game.board.clone();
+        rules.make_move(&mut new_board, from, to);
+        let gives_check = rules.in_check(&new_board, side.flipped());
+        let notation = notation::algebraic(&self.game.board, from, to, gives_check, false);
+        let mut pockets = self.game.pockets().clone();
+        let captured_promoted = self.game.promoted.remove(&to);
+        if rules.has_drops() && castling(&self.game.board, from, to).is_none() {
+            if let Some(captured) = self.game.board.get(&to).copied().flatten() {
+                let piece_type = if captured_promoted {
+                    PieceType::Pawn
+                } else {
+                    captured.piece_type
                 };
-                self.game.board = new_board;
-            } else {
-                info!("move is invalid!");
-                let _ = self.ui_sender.send(ModelMsg::MoveIsInvalid);
-                //send a message back to ui to play sound or whatever
+                pockets.of_mut(side).push(piece_type);
             }
-        } else {
-            warn!("No piece at from pos despite move request")
         }
+        if self.game.promoted.remove(&from)
+            || (piece.piece_type == PieceType::Pawn && promotes(&self.game.board, side, to))
+        {
+            self.game.promoted.insert(to);
+        }
+        self.record_move(Move::Board(from, to), new_board, pockets, notation);
+        Ok(())
     }
 
-    fn simulate_move(&self, from: CBPosition, to: CBPosition) -> Board {
+    /// Checks a drop from the pocket of the side to move is legal and plays it.
+    fn play_drop(&mut self, piece_type: PieceType, to: CBPosition) -> Result<(), String> {
+        if let Some(result) = self.game.result {
+            return Err(format!("The game is over: {}", result));
+        }
+        let rules = self.game.rules();
+        if !rules.has_drops() {
+            return Err(format!("Pieces can't be dropped in {}", self.game.variant));
+        }
+        if !self.game.legal_drops().contains(&(piece_type, to)) {
+            return Err(format!("{} can't be dropped on {:?}", piece_type, to));
+        }
+        let side = self.game.which_turn;
         let mut new_board = self.game.board.clone();
-
-        move_piece_simulating(&mut new_board, from, to, None);
-
-        new_board
+        variant::drop_piece(&mut new_board, side, piece_type, to);
+        let gives_check = rules.in_check(&new_board, side.flipped());
+        let mut pockets = self.game.pockets().clone();
+        let pocket = pockets.of_mut(side);
+        if let Some(i) = pocket.iter().position(|p| *p == piece_type) {
+            pocket.remove(i);
+        }
+        let notation = notation::drop(piece_type, to, gives_check);
+        self.game.promoted.remove(&to);
+        self.record_move(Move::Drop(piece_type, to), new_board, pockets, notation);
+        Ok(())
     }
 
-    fn check_for_checkmate(&mut self, mut sim_board: Board, all_moves: MoveList) -> bool {
-        debug!("Checking for checkmate");
-        let enemy_turn = self.game.which_turn.flipped();
-        debug!("{}", DebugBoard(&sim_board));
-        if self.can_king_move(&mut sim_board, &all_moves, enemy_turn) {
-            debug!("{}", DebugBoard(&sim_board));
-            debug!("{all_moves:?}");
-            debug!("King can move!");
-            false
-        } else {
-            debug!("King cant move!");
-            for (piece, to) in all_moves.into_iter().filter(|(p, _)| p.side == enemy_turn) {
-                debug!("checking {piece:?} -> {to:?}");
-                let original = piece.current_pos;
-                let old_piece = move_piece_simulating(&mut sim_board, piece.current_pos, to, None);
-                let king = Self::get_king(&sim_board, enemy_turn);
-                let sim_moves = get_all_moves(&sim_board);
-                if !Self::piece_under_attack(king, &sim_moves) {
-                    debug!(
-                        "{:?}",
-                        sim_moves
-                            .into_iter()
-                            .filter(|(p, _)| p.piece_type == PieceType::Pawn)
-                            .collect::<MoveList>()
-                    );
-                    debug!("{}", DebugBoard(&sim_board));
-                    debug!("checkmate disproved!: {piece:?}->{to:?}");
-                    return false;
-                }
-                move_piece_simulating(&mut sim_board, to, original, old_piece);
-            }
-            true
+    /// Records a legal move with the board and pockets after it, and passes the turn on.
+    fn record_move(&mut self, mv: Move, new_board: Board, pockets: Pockets, notation: String) {
+        let rules = self.game.rules();
+        let side = self.game.which_turn;
+        self.game.move_history.push(notation);
+        self.game.result = rules.result(&new_board, side, &self.game.move_history);
+        if matches!(
+            self.game.result,
+            Some(GameResult::Checkmate { .. } | GameResult::Stalemate)
+        ) && !rules
+            .legal_drops(&new_board, side.flipped(), pockets.of(side.flipped()))
+            .is_empty()
+        {
+            // A drop can still block the check or break the stalemate
+            self.game.result = None;
         }
+        if let Some(GameResult::Checkmate { .. }) = self.game.result {
+            let notation = self.game.move_history.last_mut().expect("Just pushed");
+            *notation = format!("{}#", notation.trim_end_matches('+'));
+        }
+        self.game.board = new_board;
+        self.game.moves.push(mv);
+        self.game.positions.push(self.game.board.clone());
+        self.game.pockets.push(pockets);
+        if self.game.result.is_none() {
+            self.game.result = rules
+                .draw(&self.game.positions, &self.game.move_history)
+                .map(|reason| GameResult::Draw { reason });
+        }
+        self.game.last_move = Some(mv.squares());
+        self.game.turn_count += 1;
+        self.game.timer.switch(self.game.which_turn);
+        self.game.which_turn.flip();
+        self.last_move_time = Instant::now();
     }
 
-    fn check_move_is_valid(king: &Piece, all_moves: &MoveList) -> bool {
-        //May add to this later
-        !Self::piece_under_attack(king, all_moves)
+    /// Whether a puzzle, opening drill or endgame drill is on instead of a game.
+    fn in_training(&self) -> bool {
+        self.puzzles.is_some() || self.repertoire.is_some() || self.endgame.is_some()
     }
 
-    fn can_king_move(&self, mut sim_board: &mut Board, all_moves: &MoveList, side: Side) -> bool {
-        debug!("Checking if king can move");
-        let king_pos = Self::get_king(sim_board, side).current_pos;
-        all_moves
-            .iter()
-            .filter(|(p, _)| p.current_pos == king_pos)
-            .filter(|(p, to)| {
-                let old_piece = move_piece_simulating(&mut sim_board, p.current_pos, *to, None);
-                // let new_all_moves = &get_all_moves(sim_board);
-                let new_king = Self::get_king(sim_board, side);
-                debug!("moves for {p:?} -> {to:?}: {:?}", get_all_moves(sim_board));
-                let r = Self::piece_under_attack(new_king, &get_all_moves(sim_board));
-                move_piece_simulating(&mut sim_board, *to, p.current_pos, old_piece);
-                !r
-            })
-            .peekable()
-            .peek()
-            .inspect(|ms| debug!("king moves : {ms:?}"))
-            .is_some()
+    /// Whether the puzzle, repertoire line or endgame drill is over, so no more moves are
+    /// played in it.
+    fn training_over(&self) -> bool {
+        self.puzzle_over() || self.repertoire_line_done() || self.endgame_over()
+    }
+
+    fn status(&self) -> GameStatus {
+        let in_check = self
+            .game
+            .rules()
+            .in_check(&self.game.board, self.game.which_turn)
+            .then_some(self.game.which_turn);
+        GameStatus {
+            which_turn: self.game.which_turn,
+            turn_count: self.game.turn_count,
+            last_move: self.game.last_move,
+            in_check,
+            material: Material::from_positions(&self.game.positions, self.game.starting_side()),
+            move_history: self.game.move_history.clone(),
+            handicap: self.game.handicap,
+            clocks: self.game.timer.clocks(self.game.which_turn),
+            players: (self.game.white.clone(), self.game.black.clone()),
+            result: self.game.result,
+            pockets: self
+                .game
+                .rules()
+                .has_drops()
+                .then(|| self.game.pockets().clone()),
+            puzzle: self.puzzles.as_ref().map(|session| session.status()),
+            repertoire: self.repertoire.as_ref().map(|session| session.status()),
+            endgame: self.endgame.as_ref().map(|session| session.status()),
+        }
     }
 
     fn piece_under_attack(piece: &Piece, all_moves: &MoveList) -> bool {
@@ -196,35 +794,15 @@ impl Model {
             .filter(|(_, pos)| *pos == piece.current_pos)
             .peekable()
             .peek()
-            .inspect(|ms| debug!("{:?} under attack by {:?}", piece.current_pos, ms))
+            .inspect(|ms| trace!("{:?} under attack by {:?}", piece.current_pos, ms))
             .is_some()
     }
-    // this needs to be called for every possible move
-    fn is_state_in_checkmate(
-        board: &Board,
-        all_moves: &Vec<(&Piece, CBPosition)>,
-        side: Side,
-    ) -> bool {
-        let king_pos = Self::get_king(board, side).current_pos;
-        let king_under_attack = all_moves
-            .iter()
-            .filter(|(_, pos)| *pos == king_pos)
-            .next()
-            .is_some();
-        let king_cant_move = all_moves
-            .iter()
-            .filter(|(p, _)| p.current_pos == king_pos)
-            .next()
-            .is_none();
-        king_under_attack && king_cant_move
-    }
-    fn get_king(board: &Board, side: Side) -> &Piece {
+    fn get_king(board: &Board, side: Side) -> Option<&Piece> {
         board
             .iter()
             .map(|(_, p)| p)
             .flatten()
             .find(|p| p.side == side && p.piece_type == PieceType::King)
-            .expect("King should exist")
     }
 }
 
@@ -232,22 +810,155 @@ struct Game {
     board: Board,
     timer: ChessTimer,
     which_turn: Side,
+    turn_count: usize,
+    last_move: Option<(CBPosition, CBPosition)>,
+    move_history: Vec<String>,
+    moves: Vec<Move>,
+    /// The board before the first move and after every move since
+    positions: Vec<Board>,
+    /// The pockets alongside each of the positions, always empty without drops
+    pockets: Vec<Pockets>,
+    /// The squares of pieces promoted from pawns, which go back to a pocket as pawns when
+    /// they are taken
+    promoted: HashSet<CBPosition>,
+    white: PlayerKind,
+    black: PlayerKind,
+    time_control: Option<TimeControl>,
+    variant: VariantKind,
+    handicap: Option<Handicap>,
+    result: Option<GameResult>,
 }
 impl Game {
     fn new() -> Self {
-        let mut board = board_setup();
-
-        Game {
-            board,
-            timer: ChessTimer {},
-            which_turn: Side::White,
-        }
+        Game::from_board_state(board_setup(), Side::White)
     }
     fn from_board_state(board: Board, turn: Side) -> Game {
         Game {
+            positions: vec![board.clone()],
+            pockets: vec![Pockets::default()],
+            promoted: HashSet::new(),
             board,
-            timer: ChessTimer {},
+            timer: ChessTimer::default(),
             which_turn: turn,
+            turn_count: 0,
+            last_move: None,
+            move_history: Vec::new(),
+            moves: Vec::new(),
+            white: PlayerKind::Human,
+            black: PlayerKind::Human,
+            time_control: None,
+            variant: VariantKind::Standard,
+            handicap: None,
+            result: None,
+        }
+    }
+    /// The game at its starting position. The moves of a PGN start are not played yet, and
+    /// its `Variant` tag overrides the variant of the setup.
+    fn from_setup(setup: &GameSetup) -> Result<Game, String> {
+        let pgn = match &setup.start {
+            StartPosition::Pgn(text) => Some(pgn::parse(text)?),
+            _ => None,
+        };
+        let variant = match pgn.as_ref().and_then(|pgn| pgn.tag("Variant")) {
+            Some(name) => VariantKind::from_pgn_name(name)
+                .ok_or_else(|| format!("Unknown variant {:?}", name))?,
+            None => setup.variant,
+        };
+        if let Some(handicap) = setup.handicap {
+            if handicap.time_control.is_some() && setup.time_control.is_none() {
+                return Err("Time odds need a time control for the game".to_string());
+            }
+            if handicap.odds.is_some() && matches!(setup.start, StartPosition::Chess960(_)) {
+                return Err("Odds games start from the standard position".to_string());
+            }
+        }
+        let geometry = variant::rules(variant).geometry();
+        let start = match &setup.start {
+            StartPosition::Standard => None,
+            StartPosition::Fen(fen) => Some(fen::parse_with(fen, geometry)?),
+            StartPosition::Pgn(_) => match pgn.as_ref().and_then(|pgn| pgn.tag("FEN")) {
+                Some(fen) => Some(fen::parse_with(fen, geometry)?),
+                None => None,
+            },
+            StartPosition::Chess960(index) => Some(fen::FenPosition {
+                board: chess960::board(index.unwrap_or_else(chess960::random_index))?,
+                which_turn: Side::White,
+                turn_count: 0,
+                pockets: Pockets::default(),
+            }),
+        };
+        if start
+            .as_ref()
+            .is_some_and(|position| Geometry::of(&position.board) != geometry)
+        {
+            return Err(format!("{} is played on a {} board", variant, geometry));
+        }
+        let mut game = match start {
+            Some(position) => {
+                let mut game = Game::from_board_state(position.board, position.which_turn);
+                game.turn_count = position.turn_count;
+                game.pockets = vec![position.pockets];
+                game
+            }
+            None => {
+                let mut board = variant::rules(variant)
+                    .start_board()
+                    .unwrap_or_else(board_setup);
+                match setup.handicap {
+                    Some(Handicap {
+                        giver,
+                        odds: Some(odds),
+                        ..
+                    }) => {
+                        handicap::give_odds(&mut board, odds, giver)?;
+                        let first = handicap::first_to_move(odds, giver);
+                        let mut game = Game::from_board_state(board, first);
+                        // As when black moves first in a FEN
+                        game.turn_count = if first == Side::Black { 1 } else { 0 };
+                        game
+                    }
+                    _ => Game::from_board_state(board, Side::White),
+                }
+            }
+        };
+        game.white = setup.white.clone();
+        game.black = setup.black.clone();
+        game.variant = variant;
+        game.handicap = setup.handicap;
+        Ok(game)
+    }
+    fn rules(&self) -> &'static dyn Variant {
+        variant::rules(self.variant)
+    }
+    /// The pieces each side can drop now.
+    fn pockets(&self) -> &Pockets {
+        self.pockets.last().expect("There are pockets for every position")
+    }
+    fn legal_drops(&self) -> Vec<(PieceType, CBPosition)> {
+        self.rules().legal_drops(
+            &self.board,
+            self.which_turn,
+            self.pockets().of(self.which_turn),
+        )
+    }
+    fn player(&self, side: Side) -> &PlayerKind {
+        match side {
+            Side::White => &self.white,
+            Side::Black => &self.black,
+        }
+    }
+    /// The side to move in the starting position.
+    fn starting_side(&self) -> Side {
+        if self.move_history.len().is_multiple_of(2) {
+            self.which_turn
+        } else {
+            self.which_turn.flipped()
+        }
+    }
+    fn set_player(&mut self, side: Side, player: PlayerKind) {
+        match side {
+            Side::White => self.white = player,
+            Side::Black => self.black = player,
         }
     }
     fn get_all_pieces_in<'a>(board: &'a Board, positions: &Vec<CBPosition>) -> Vec<&'a Piece> {
@@ -267,7 +978,12 @@ impl Game {
             .get(&moving_piece_pos)
             .expect("Position is out of bounds!")
         {
-            piece.get_valid_moves(&self.board)
+            let moves = self.rules().legal_moves(&self.board, piece.side);
+            piece
+                .get_valid_moves(&self.board)
+                .into_iter()
+                .filter(|to| moves.contains(&(moving_piece_pos, *to)))
+                .collect()
         } else {
             debug!("piece not in hashmap?");
             Vec::new()
@@ -286,7 +1002,9 @@ impl Display for DebugPositions {
     }
 }
 
-#[derive(PartialEq, Eq, Debug, Clone, Copy)]
+#[derive(PartialEq, Eq, Hash, Debug, Clone, Copy)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
 pub enum Side {
     White,
     Black,
@@ -344,9 +1062,10 @@ impl<'a> Display for DebugBoard<'a> {
         let fourty_one_underscores: String = repeat("_").take(41).collect();
         fmt_string.push_str(&fourty_one_underscores);
         fmt_string.push_str("\n");
-        for row in (1..=8).rev() {
+        let geometry = Geometry::of(board);
+        for row in geometry.ranks().rev() {
             let mut row_string = String::from("|");
-            for col in ('a'..='h') {
+            for col in geometry.files() {
                 let pos = CBPosition { col, row };
                 let piece = self.0.get(&pos).expect("Pos should be in bounds!");
                 if let Some(p) = piece {
@@ -376,9 +1095,25 @@ impl<'a> Display for DebugBoard<'a> {
 pub type Board = HashMap<CBPosition, Option<Piece>>;
 
 fn move_piece(board: &mut Board, from: CBPosition, to: CBPosition) {
+    if let Some(castle) = castling(board, from, to) {
+        // Both come off first, as either may land where the other stood
+        let king = board.insert(from, None).flatten();
+        let rook = board.insert(castle.rook, None).flatten();
+        for (piece, to) in [(king, castle.king_to), (rook, castle.rook_to)] {
+            if let Some(mut piece) = piece {
+                piece.current_pos = to;
+                piece.has_moved = true;
+                board.insert(to, Some(piece));
+            }
+        }
+        return;
+    }
     if let Some(Some(mut from_piece)) = board.insert(from, None) {
         from_piece.current_pos = to;
         from_piece.has_moved = true;
+        if from_piece.piece_type == PieceType::Pawn && promotes(board, from_piece.side, to) {
+            from_piece.piece_type = PieceType::Queen;
+        }
         board.insert(to, Some(from_piece));
         // debug!("{}", DebugBoard(board));
     } else {
@@ -386,21 +1121,101 @@ fn move_piece(board: &mut Board, from: CBPosition, to: CBPosition) {
     }
 }
 
-fn move_piece_simulating(
-    board: &mut Board,
-    from: CBPosition,
-    to: CBPosition,
-    replacement: Option<Piece>,
-) -> Option<Piece> {
-    let mut from_piece = board
-        .insert(from, replacement)
-        .expect("already checked")
-        .expect("already checked"); //Evil
-    from_piece.current_pos = to;
+/// Whether a pawn of `side` reaching `to` is on its last rank, where it becomes a queen.
+/// Pawns can't promote to anything else.
+fn promotes(board: &Board, side: Side, to: CBPosition) -> bool {
+    let last_rank = match side {
+        Side::White => Geometry::of(board).ranks as usize,
+        Side::Black => 1,
+    };
+    to.row == last_rank
+}
+
+/// Where the king and rook go when castling.
+struct Castling {
+    rook: CBPosition,
+    king_to: CBPosition,
+    rook_to: CBPosition,
+    king_side: bool,
+}
+impl Castling {
+    /// The king lands on the file next to the last on the king side, as on g1 in the
+    /// standard game, and on the c file on the queen side.
+    fn new(geometry: Geometry, king: CBPosition, rook: CBPosition) -> Castling {
+        let king_side = rook.col > king.col;
+        let last = geometry.last_file();
+        let (king_col, rook_col) = if king_side {
+            (char_sub(last, 1), char_sub(last, 2))
+        } else {
+            ('c', 'd')
+        };
+        Castling {
+            rook,
+            king_to: CBPosition {
+                col: king_col,
+                row: king.row,
+            },
+            rook_to: CBPosition {
+                col: rook_col,
+                row: king.row,
+            },
+            king_side,
+        }
+    }
+}
+
+/// The castling that moving `from` to `to` would be. The king either moves onto one of its
+/// rooks, or two or more files to its landing square, which castles with the outermost rook
+/// on that side.
+fn castling(board: &Board, from: CBPosition, to: CBPosition) -> Option<Castling> {
+    let king = board.get(&from).copied().flatten()?;
+    if king.piece_type != PieceType::King || king.has_moved || to.row != from.row {
+        return None;
+    }
+    let geometry = Geometry::of(board);
+    let rooks = castling_rooks(board, &king);
+    if rooks.contains(&to) {
+        return Some(Castling::new(geometry, from, to));
+    }
+    if (from.col as u8).abs_diff(to.col as u8) < 2 {
+        return None;
+    }
+    let outermost = if to.col == char_sub(geometry.last_file(), 1) {
+        rooks.iter().filter(|r| r.col > from.col).max_by_key(|r| r.col)
+    } else if to.col == 'c' {
+        rooks.iter().filter(|r| r.col < from.col).min_by_key(|r| r.col)
+    } else {
+        None
+    };
+    outermost.map(|rook| Castling::new(geometry, from, *rook))
+}
+
+/// The unmoved rooks on the rank of an unmoved king on its back rank.
+fn castling_rooks(board: &Board, king: &Piece) -> Vec<CBPosition> {
+    let geometry = Geometry::of(board);
+    let home_row = match king.side {
+        Side::White => 1,
+        Side::Black => geometry.ranks as usize,
+    };
+    if king.has_moved || king.current_pos.row != home_row {
+        return Vec::new();
+    }
+    geometry
+        .files()
+        .map(|col| CBPosition { col, row: home_row })
+        .filter(|pos| {
+            board.get(pos).copied().flatten().is_some_and(|p| {
+                p.side == king.side && p.piece_type == PieceType::Rook && !p.has_moved
+            })
+        })
+        .collect()
+}
 
+fn square_attacked(board: &Board, pos: CBPosition, by: Side) -> bool {
     board
-        .insert(to, Some(from_piece))
-        .expect("To should be in bounds!")
+        .values()
+        .flatten()
+        .any(|p| p.side == by && p.attacks(board).contains(&pos))
 }
 
 fn get_all_moves(board: &Board) -> MoveList {
@@ -417,61 +1232,33 @@ fn get_all_moves(board: &Board) -> MoveList {
 }
 
 fn board_setup() -> Board {
-    let mut board = Board::new();
-    for col in 'a'..='h' {
-        for row in 1..=8 {
-            board.insert(
-                CBPosition {
-                    col,
-                    row: row as usize,
-                },
-                None,
-            );
-        }
-    }
-    //Insertion of predetermined pieces
-    insert_piece(&mut board, 1, 'a', Side::White, PieceType::Rook);
-    insert_piece(&mut board, 1, 'b', Side::White, PieceType::Knight);
-    insert_piece(&mut board, 1, 'c', Side::White, PieceType::Bishop);
-    insert_piece(&mut board, 1, 'd', Side::White, PieceType::Queen);
-    insert_piece(&mut board, 1, 'e', Side::White, PieceType::King);
-    insert_piece(&mut board, 1, 'f', Side::White, PieceType::Bishop);
-    insert_piece(&mut board, 1, 'g', Side::White, PieceType::Knight);
-    insert_piece(&mut board, 1, 'h', Side::White, PieceType::Rook);
-
-    insert_piece(&mut board, 8, 'a', Side::Black, PieceType::Rook);
-    insert_piece(&mut board, 8, 'b', Side::Black, PieceType::Knight);
-    insert_piece(&mut board, 8, 'c', Side::Black, PieceType::Bishop);
-    insert_piece(&mut board, 8, 'd', Side::Black, PieceType::Queen);
-    insert_piece(&mut board, 8, 'e', Side::Black, PieceType::King);
-    insert_piece(&mut board, 8, 'f', Side::Black, PieceType::Bishop);
-    insert_piece(&mut board, 8, 'g', Side::Black, PieceType::Knight);
-    insert_piece(&mut board, 8, 'h', Side::Black, PieceType::Rook);
-
-    //insertion of pawns
-
-    for row in 'a'..='h' {
-        insert_piece(&mut board, 2, row, Side::White, PieceType::Pawn);
-        insert_piece(&mut board, 7, row, Side::Black, PieceType::Pawn);
-    }
+    use PieceType::*;
+    board_setup_of(&[Rook, Knight, Bishop, Queen, King, Bishop, Knight, Rook])
+}
 
+/// A board of eight ranks as wide as `back_rank`, with the pieces on the first and last
+/// ranks and pawns in front of them.
+fn board_setup_of(back_rank: &[PieceType]) -> Board {
+    let geometry = Geometry {
+        files: back_rank.len() as u8,
+        ranks: 8,
+    };
+    let mut board = empty_board_of(geometry);
+    for (col, piece_type) in geometry.files().zip(back_rank) {
+        insert_piece(&mut board, 1, col, Side::White, *piece_type);
+        insert_piece(&mut board, 2, col, Side::White, PieceType::Pawn);
+        insert_piece(&mut board, 7, col, Side::Black, PieceType::Pawn);
+        insert_piece(&mut board, 8, col, Side::Black, *piece_type);
+    }
     board
 }
 
 fn empty_board() -> Board {
-    let mut board = Board::new();
-    for col in 'a'..='h' {
-        for row in 1..=8 {
-            board.insert(
-                CBPosition {
-                    col,
-                    row: row as usize,
-                },
-                None,
-            );
-        }
-    }
-    board
+    empty_board_of(Geometry::STANDARD)
+}
+
+fn empty_board_of(geometry: Geometry) -> Board {
+    geometry.squares().into_iter().map(|pos| (pos, None)).collect()
 }
 
 fn insert_piece(board: &mut Board, row: usize, col: char, side: Side, piece_type: PieceType) {
@@ -480,14 +1267,105 @@ fn insert_piece(board: &mut Board, row: usize, col: char, side: Side, piece_type
     board.insert(pos, Some(piece));
 }
 
+/// Every legal move for `side`, as from and to squares.
+fn legal_moves(board: &Board, side: Side) -> Vec<(CBPosition, CBPosition)> {
+    get_all_moves(board)
+        .into_iter()
+        .filter(|(piece, to)| {
+            piece.side == side
+                && !matches!(board.get(to), Some(Some(p)) if p.piece_type == PieceType::King)
+        })
+        .map(|(piece, to)| (piece.current_pos, to))
+        .filter(|(from, to)| {
+            let mut sim = board.clone();
+            move_piece(&mut sim, *from, *to);
+            !in_check(&sim, side)
+        })
+        .collect()
+}
+
+/// Whether the king of `side` is attacked. A side without a king is never in check.
+fn in_check(board: &Board, side: Side) -> bool {
+    Model::get_king(board, side)
+        .is_some_and(|king| Model::piece_under_attack(king, &get_all_moves(board)))
+}
+
+/// The clocks of a timed game. Untimed games have no time control and never run out.
 #[derive(Default)]
-struct ChessTimer {}
+struct ChessTimer {
+    /// The time controls of white and black, which differ in a game with time odds
+    time_controls: Option<(TimeControl, TimeControl)>,
+    white: Duration,
+    black: Duration,
+    /// When the side to move started thinking, `None` until the game starts
+    turn_started: Option<Instant>,
+}
+impl ChessTimer {
+    fn new(time_controls: Option<(TimeControl, TimeControl)>) -> ChessTimer {
+        let (white, black) = time_controls
+            .map(|(white, black)| (white.base, black.base))
+            .unwrap_or_default();
+        ChessTimer {
+            time_controls,
+            white,
+            black,
+            turn_started: None,
+        }
+    }
+    fn start(&mut self) {
+        self.turn_started = Some(Instant::now());
+    }
+    /// Sets the time left for white and black, as when resuming a game.
+    fn set_clocks(&mut self, (white, black): (Duration, Duration)) {
+        self.white = white;
+        self.black = black;
+    }
+    /// Stops the clock of the side that just moved and adds its increment.
+    fn switch(&mut self, moved: Side) {
+        let Some((white_control, black_control)) = self.time_controls else {
+            return;
+        };
+        let elapsed = self.turn_started.map(|t| t.elapsed()).unwrap_or_default();
+        let (clock, time_control) = match moved {
+            Side::White => (&mut self.white, white_control),
+            Side::Black => (&mut self.black, black_control),
+        };
+        *clock = clock.saturating_sub(elapsed) + time_control.increment;
+        self.turn_started = Some(Instant::now());
+    }
+    /// Time left for `side` right now, given whose turn it is.
+    fn remaining(&self, side: Side, to_move: Side) -> Option<Duration> {
+        self.time_controls?;
+        let clock = match side {
+            Side::White => self.white,
+            Side::Black => self.black,
+        };
+        match self.turn_started {
+            Some(started) if side == to_move => Some(clock.saturating_sub(started.elapsed())),
+            _ => Some(clock),
+        }
+    }
+    fn flagged(&self, to_move: Side) -> bool {
+        self.remaining(to_move, to_move)
+            .is_some_and(|left| left.is_zero())
+    }
+    fn clocks(&self, to_move: Side) -> Option<(Duration, Duration)> {
+        Some((
+            self.remaining(Side::White, to_move)?,
+            self.remaining(Side::Black, to_move)?,
+        ))
+    }
+}
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
 pub struct Piece {
     pub side: Side,
+    #[cfg_attr(feature = "serialize", serde(rename = "type"))]
     pub piece_type: PieceType,
+    #[cfg_attr(feature = "serialize", serde(rename = "square"))]
     current_pos: CBPosition,
+    #[cfg_attr(feature = "serialize", serde(rename = "moved"))]
     has_moved: bool,
 }
 impl Debug for Piece {
@@ -513,20 +1391,12 @@ impl Debug for Piece {
 }
 impl Display for Piece {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let display_str = match self.piece_type {
-            PieceType::King => "K",
-            PieceType::Queen => "Q",
-            PieceType::Rook => "R",
-            PieceType::Bishop => "B",
-            PieceType::Knight => "N",
-            PieceType::Pawn => "P",
-        };
-        write!(f, "{}", display_str)
+        write!(f, "{}", self.piece_type)
     }
 }
 
 impl Piece {
-    fn new(side: Side, piece_type: PieceType, current_pos: CBPosition) -> Piece {
+    pub fn new(side: Side, piece_type: PieceType, current_pos: CBPosition) -> Piece {
         Piece {
             side,
             piece_type,
@@ -536,30 +1406,40 @@ impl Piece {
     }
 
     fn get_valid_moves(&self, board: &Board) -> Vec<CBPosition> {
-        let moves = match self.piece_type {
-            PieceType::King => {
-                let mut mvs = self.current_pos.get_adjacents();
-                mvs.append(&mut self.get_available_castle_moves(board));
-                mvs
-            }
-            PieceType::Queen => {
-                let mut mvs = self.current_pos.get_horizontal();
-                mvs.append(&mut self.current_pos.get_vertical());
-                mvs.append(&mut self.current_pos.get_diagonals());
-                mvs
-            }
+        if self.piece_type == PieceType::Pawn {
+            let mut moves = self.filter_blocked_moves(board, self.get_pawn_moves(board));
+            moves.retain(|to| board.contains_key(to));
+            return moves;
+        }
+        let mut moves = self.leaps_and_rides(board);
+        if self.piece_type == PieceType::King {
+            moves.append(&mut self.get_available_castle_moves(board));
+        }
+        moves
+    }
 
-            PieceType::Rook => {
-                let mut mvs = self.current_pos.get_horizontal();
-                mvs.append(&mut self.current_pos.get_vertical());
-                mvs
-            }
-            PieceType::Bishop => self.current_pos.get_diagonals(),
-            PieceType::Knight => self.current_pos.get_knight_moves(),
-            PieceType::Pawn => self.get_pawn_moves(board),
+    /// The moves of a piece other than a pawn, from its Betza definition.
+    fn leaps_and_rides(&self, board: &Board) -> Vec<CBPosition> {
+        let Some(steps) = self.piece_type.steps() else {
+            return Vec::new();
         };
-        let moves = self.filter_blocked_moves(board, moves);
-        moves
+        betza::moves(&steps, board, self.current_pos, self.side)
+    }
+
+    /// The squares the piece attacks, which leaves out pawn pushes and castling.
+    fn attacks(&self, board: &Board) -> Vec<CBPosition> {
+        match self.piece_type {
+            PieceType::Pawn => {
+                let mut attacks = match self.side {
+                    Side::White => self.current_pos.get_offsets(vec![(1, 1), (1, -1)]),
+                    Side::Black => self.current_pos.get_offsets(vec![(-1, 1), (-1, -1)]),
+                };
+                attacks.retain(|to| board.contains_key(to));
+                attacks
+            }
+            PieceType::King => self.leaps_and_rides(board),
+            _ => self.get_valid_moves(board),
+        }
     }
     fn filter_blocked_moves(&self, board: &Board, mut moves: Vec<CBPosition>) -> Vec<CBPosition> {
         let pieces = Game::get_all_pieces_in(board, &moves);
@@ -570,9 +1450,6 @@ impl Piece {
             .collect();
 
         let mut moves = match self.piece_type {
-            PieceType::Queen => self.filter_queen_moves(pieces, friendly_piece_positions, moves),
-            PieceType::Rook => self.filter_rook_moves(board, moves, friendly_piece_positions),
-            PieceType::Bishop => self.filter_bishop_moves(board, moves, friendly_piece_positions),
             PieceType::Pawn => self.filter_pawn_moves(board, moves),
             _ => {
                 moves.retain(|x| !friendly_piece_positions.contains(x));
@@ -601,14 +1478,17 @@ impl Piece {
         };
 
         let mut vert_moves = self.current_pos.get_offsets(offsets);
-        vert_moves.retain(|x| !occupied.contains(x));
+        // The double step can't jump over a piece
+        if let Some(blocked) = vert_moves.iter().position(|x| occupied.contains(x)) {
+            vert_moves.truncate(blocked);
+        }
         moves.retain(|x| vert_moves.contains(x));
         let diag_squares = match self.side {
             Side::White => self.current_pos.get_offsets(vec![(1, 1), (1, -1)]),
             Side::Black => self.current_pos.get_offsets(vec![(-1, 1), (-1, -1)]),
         };
         for square in diag_squares {
-            if let Some(p) = board.get(&square).expect("Pos should exist in board!") {
+            if let Some(Some(p)) = board.get(&square) {
                 if p.side != self.side {
                     moves.push(square)
                 }
@@ -617,116 +1497,51 @@ impl Piece {
         moves
     }
 
-    fn filter_bishop_moves(
-        &self,
-        board: &HashMap<CBPosition, Option<Piece>>,
-        mut moves: Vec<CBPosition>,
-        friendly_piece_positions: Vec<CBPosition>,
-    ) -> Vec<CBPosition> {
-        let pieces = Game::get_all_pieces_in(board, &moves);
-
-        let north_west_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::NorthWest);
-
-        let north_east_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::NorthEast);
-
-        let south_east_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::SouthEast);
-
-        let south_west_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::SouthWest);
-
-        let moves_to_remove = [
-            north_east_blocked,
-            south_east_blocked,
-            south_west_blocked,
-            north_west_blocked,
-            friendly_piece_positions,
-        ]
-        .concat();
-        let moves_to_remove: HashSet<&CBPosition> = moves_to_remove.iter().collect();
-
-        moves.retain(|m| !moves_to_remove.contains(m));
-        moves
-    }
-
-    fn filter_rook_moves(
-        &self,
-        board: &HashMap<CBPosition, Option<Piece>>,
-        mut moves: Vec<CBPosition>,
-        friendly_piece_positions: Vec<CBPosition>,
-    ) -> Vec<CBPosition> {
-        let pieces = Game::get_all_pieces_in(board, &moves);
-
-        let north_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::North);
-
-        let east_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::East);
-
-        let south_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::South);
-
-        let west_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::West);
-
-        let moves_to_remove = [
-            north_blocked,
-            east_blocked,
-            south_blocked,
-            west_blocked,
-            friendly_piece_positions,
-        ]
-        .concat();
-
-        let moves_to_remove: HashSet<&CBPosition> = moves_to_remove.iter().collect();
-
-        moves.retain(|m| !moves_to_remove.contains(m));
-        moves
-    }
-
-    fn filter_queen_moves(
-        &self,
-        pieces: Vec<&Piece>,
-        friendly_piece_positions: Vec<CBPosition>,
-        mut moves: Vec<CBPosition>,
-    ) -> Vec<CBPosition> {
-        let north_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::North);
-
-        let east_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::East);
-
-        let south_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::South);
-
-        let west_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::West);
-
-        let north_west_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::NorthWest);
-
-        let north_east_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::NorthEast);
-
-        let south_east_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::SouthEast);
-
-        let south_west_blocked: Vec<CBPosition> = self.blocked_in(&pieces, Direction::SouthWest);
-
-        let moves_to_remove = [
-            north_blocked,
-            north_east_blocked,
-            east_blocked,
-            south_east_blocked,
-            south_blocked,
-            south_west_blocked,
-            west_blocked,
-            north_west_blocked,
-            friendly_piece_positions,
-        ]
-        .concat();
-
-        let moves_to_remove: HashSet<&CBPosition> = moves_to_remove.iter().collect();
-
-        moves.retain(|m| !moves_to_remove.contains(m));
-        moves
-    }
+    /// Castling moves by the Chess960 rules, which the standard game follows too. Each is
+    /// given as the king moving onto its rook, and also as the king moving to its landing
+    /// square when that is two or more files away.
     fn get_available_castle_moves(&self, board: &Board) -> Vec<CBPosition> {
-        Vec::new()
+        let mut moves = Vec::new();
+        if self.has_moved {
+            return moves;
+        }
+        let from = self.current_pos;
+        let geometry = Geometry::of(board);
+        for rook in castling_rooks(board, self) {
+            let castle = Castling::new(geometry, from, rook);
+            let span = |a: char, b: char| {
+                (a.min(b)..=a.max(b)).map(move |col| CBPosition { col, row: from.row })
+            };
+            let clear = span(from.col, castle.king_to.col)
+                .chain(span(rook.col, castle.rook_to.col))
+                .all(|pos| pos == from || pos == rook || board.get(&pos) == Some(&None));
+            if !clear {
+                continue;
+            }
+            // The king may not castle out of, through or into check
+            let mut without = board.clone();
+            without.insert(from, None);
+            without.insert(rook, None);
+            if span(from.col, castle.king_to.col)
+                .any(|pos| square_attacked(&without, pos, self.side.flipped()))
+            {
+                continue;
+            }
+            moves.push(rook);
+            if (from.col as u8).abs_diff(castle.king_to.col as u8) >= 2
+                && castling(board, from, castle.king_to).is_some_and(|c| c.rook == rook)
+            {
+                moves.push(castle.king_to);
+            }
+        }
+        moves
     }
     fn can_move_to(&self, to_pos: CBPosition, board: &Board) -> bool {
         self.get_valid_moves(board).contains(&to_pos)
     }
 
     fn get_pawn_moves(&self, board: &Board) -> Vec<CBPosition> {
-        debug!("pawn moves board: {}", DebugBoard(board));
+        trace!("pawn moves board: {}", DebugBoard(board));
         let mut positions: Vec<CBPosition> = Vec::new();
         match self.side {
             Side::White => {
@@ -740,7 +1555,7 @@ impl Piece {
                 if let Some(up_right) = self.current_pos.get_offset(1, 1) {
                     CBPosition::push_if_occupied(&mut positions, up_right, board)
                 }
-                debug!("pawn moves: {:?} -> {positions:?}", self.current_pos);
+                trace!("pawn moves: {:?} -> {positions:?}", self.current_pos);
                 positions
             }
             Side::Black => {
@@ -759,25 +1574,11 @@ impl Piece {
             } // she en on my passant til i yeah
         }
     }
-    fn blocked_in(&self, pieces: &Vec<&Piece>, dir: Direction) -> Vec<CBPosition> {
-        let closest_pos = pieces
-            .iter()
-            .filter(|x| self.current_pos.beyond(dir).contains(&x.current_pos))
-            .map(|x| x.current_pos)
-            .min_by(|x, y| {
-                self.current_pos
-                    .positional_difference(x)
-                    .cmp(&self.current_pos.positional_difference(y))
-            });
-        let o = match closest_pos {
-            Some(p) => p.beyond(dir),
-            None => Vec::<CBPosition>::new(),
-        };
-        o
-    }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serialize", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serialize", serde(rename_all = "snake_case"))]
 pub enum PieceType {
     King,
     Queen,
@@ -785,12 +1586,146 @@ pub enum PieceType {
     Bishop,
     Knight,
     Pawn,
+    /// Bishop and knight
+    Archbishop,
+    /// Rook and knight
+    Chancellor,
+    /// Queen and knight
+    Amazon,
+    /// Leaps (3, 1)
+    Camel,
+    /// Leaps (3, 2)
+    Zebra,
+    /// Rides the knight's leap in a line
+    Nightrider,
+    /// A piece from the config file, by its letter, see `custom`
+    Custom(char),
+}
+impl Display for PieceType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let display_str = match self {
+            PieceType::King => "K",
+            PieceType::Queen => "Q",
+            PieceType::Rook => "R",
+            PieceType::Bishop => "B",
+            PieceType::Knight => "N",
+            PieceType::Pawn => "P",
+            PieceType::Archbishop => "A",
+            PieceType::Chancellor => "C",
+            PieceType::Amazon => "M",
+            PieceType::Camel => "L",
+            PieceType::Zebra => "Z",
+            PieceType::Nightrider => "H",
+            PieceType::Custom(letter) => return write!(f, "{}", letter),
+        };
+        write!(f, "{}", display_str)
+    }
+}
+impl PieceType {
+    /// The usual material value in pawns, the king counting for nothing.
+    pub fn value(&self) -> isize {
+        match self {
+            PieceType::King => 0,
+            PieceType::Queen => 9,
+            PieceType::Rook => 5,
+            PieceType::Bishop => 3,
+            PieceType::Knight => 3,
+            PieceType::Pawn => 1,
+            PieceType::Archbishop => 7,
+            PieceType::Chancellor => 8,
+            PieceType::Amazon => 12,
+            PieceType::Camel => 2,
+            PieceType::Zebra => 2,
+            PieceType::Nightrider => 4,
+            PieceType::Custom(letter) => custom::value(*letter).unwrap_or(0),
+        }
+    }
+
+    /// How a built-in piece moves in Betza notation, see `betza`. Pawns have rules of their
+    /// own and custom pieces keep theirs in `custom`.
+    pub fn movement(&self) -> Option<&'static str> {
+        match self {
+            PieceType::King => Some("K"),
+            PieceType::Queen => Some("Q"),
+            PieceType::Rook => Some("R"),
+            PieceType::Bishop => Some("B"),
+            PieceType::Knight => Some("N"),
+            PieceType::Pawn => None,
+            PieceType::Archbishop => Some("BN"),
+            PieceType::Chancellor => Some("RN"),
+            PieceType::Amazon => Some("QN"),
+            PieceType::Camel => Some("C"),
+            PieceType::Zebra => Some("Z"),
+            PieceType::Nightrider => Some("NN"),
+            PieceType::Custom(_) => None,
+        }
+    }
+
+    /// The piece's movement, read from Betza once rather than on every move.
+    fn steps(&self) -> Option<Arc<[Step]>> {
+        lazy_static! {
+            static ref BUILT_IN: HashMap<PieceType, Arc<[Step]>> = [
+                PieceType::King,
+                PieceType::Queen,
+                PieceType::Rook,
+                PieceType::Bishop,
+                PieceType::Knight,
+                PieceType::Archbishop,
+                PieceType::Chancellor,
+                PieceType::Amazon,
+                PieceType::Camel,
+                PieceType::Zebra,
+                PieceType::Nightrider,
+            ]
+            .into_iter()
+            .filter_map(|piece_type| {
+                let movement = piece_type.movement()?;
+                let steps = betza::parse(movement).expect("Piece definitions are valid Betza");
+                Some((piece_type, steps.into()))
+            })
+            .collect();
+        }
+        match self {
+            PieceType::Custom(letter) => custom::steps(*letter),
+            piece_type => BUILT_IN.get(piece_type).cloned(),
+        }
+    }
+}
+
+/// A model without a thread of its own, moved on by calling `tick`, so one pool of threads
+/// can run many games. It doesn't autosave.
+pub struct SteppedModel(Model);
+impl SteppedModel {
+    pub fn new(send: Sender<ModelMsg>, recv: Receiver<UiMsg>) -> SteppedModel {
+        SteppedModel(Model::new(send, recv))
+    }
+    /// Handles every waiting message, then lets engines and clocks move on. Returns false
+    /// once the other side has quit or gone away.
+    pub fn tick(&mut self) -> bool {
+        loop {
+            match self.0.ui_reciever.try_recv() {
+                Ok(UiMsg::Quit) | Err(TryRecvError::Disconnected) => return false,
+                Ok(m) => self.0.handle_message(m),
+                Err(TryRecvError::Empty) => break,
+            }
+        }
+        self.0.update();
+        true
+    }
+}
+
+/// Runs a model that doesn't autosave, for driving games from scripts.
+pub fn init_headless_model(send: Sender<ModelMsg>, recv: Receiver<UiMsg>) {
+    Model::new(send, recv).model_loop();
 }
-impl PieceType {}
 
 pub fn init_model(send: Sender<ModelMsg>, recv: Receiver<UiMsg>) {
+    custom::load();
     let mut model = Model::new(send, recv);
-    if let Err(e) = model.ui_sender.send(ModelMsg::Debug("Started")) {
+    model.autosave_path = autosave::path();
+    model.puzzle_record_path = puzzle::record_path();
+    model.repertoire_schedule_path = repertoire::schedule_path();
+    if let Err(e) = model.ui_sender.send(ModelMsg::Debug("Started".to_string())) {
         error!("{}", e)
     };
     model.model_loop();
@@ -802,9 +1737,10 @@ pub fn init_model(send: Sender<ModelMsg>, recv: Receiver<UiMsg>) {
 mod tests {
 
     use core::panic;
-    use std::{future, thread::JoinHandle};
-
-    use lazy_static::lazy_static;
+    use std::{
+        sync::mpsc::channel,
+        thread::{self, JoinHandle},
+    };
 
     use crate::model::*;
     lazy_static! {
@@ -890,4 +1826,314 @@ mod tests {
             .map(|_| ())
             .ok_or(name)
     }
+
+    #[test]
+    fn status_after_check() {
+        use crate::model::{PieceType::*, Side::*};
+        let mut board = empty_board();
+        insert_piece(&mut board, 1, 'a', White, King);
+        insert_piece(&mut board, 2, 'b', White, Rook);
+        insert_piece(&mut board, 8, 'e', Black, King);
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::from_board_state(model_send, ui_recv, board, White);
+
+        model.make_move(CBPosition::from("b2"), CBPosition::from("e2"));
+
+        let status = model.status();
+        assert_eq!(status.which_turn, Black);
+        assert_eq!(status.turn_count, 1);
+        assert_eq!(
+            status.last_move,
+            Some((CBPosition::from("b2"), CBPosition::from("e2")))
+        );
+        assert_eq!(status.in_check, Some(Black));
+    }
+
+    #[test]
+    fn new_game_from_pgn() {
+        let (model_send, model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+
+        model.new_game(GameSetup {
+            start: StartPosition::Pgn("1. e4 e5 2. Nf3 Nc6 3. Bb5".to_string()),
+            ..GameSetup::default()
+        });
+        let status = model.status();
+        assert_eq!(status.move_history, vec!["e4", "e5", "Nf3", "Nc6", "Bb5"]);
+        assert_eq!(status.which_turn, Side::Black);
+
+        model.new_game(GameSetup {
+            start: StartPosition::Pgn("1. e4 e5 2. Ke3".to_string()),
+            ..GameSetup::default()
+        });
+        assert_eq!(model.status().move_history.len(), 5);
+        assert!(model_recv
+            .try_iter()
+            .any(|msg| matches!(msg, ModelMsg::Error(_))));
+    }
+
+    #[test]
+    fn odds_game() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let setup = GameSetup {
+            handicap: Some(Handicap {
+                giver: Side::Black,
+                odds: Some(Odds::PawnAndMove),
+                time_control: None,
+            }),
+            ..GameSetup::default()
+        };
+        model.start_game(&setup, &[]).unwrap();
+        assert!(model.game.board[&"f7".into()].is_none());
+        assert_eq!(model.game.which_turn, Side::White);
+        model.play_move("e2".into(), "e4".into()).unwrap();
+        let pgn = model.pgn();
+        assert!(pgn.contains("[SetUp \"1\"]"), "{pgn}");
+        assert!(pgn.contains("rnbqkbnr/ppppp1pp/8/8/8/8/PPPPPPPP/RNBQKBNR"), "{pgn}");
+        assert!(pgn.contains("1. e4"), "{pgn}");
+
+        let setup = GameSetup {
+            handicap: Some(Handicap {
+                giver: Side::White,
+                odds: None,
+                time_control: Some(TimeControl::new(5, 0)),
+            }),
+            ..GameSetup::default()
+        };
+        assert!(model.start_game(&setup, &[]).is_err(), "time odds need a clock");
+    }
+
+    #[test]
+    fn castles_by_chess960_rules() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let setup = GameSetup {
+            start: StartPosition::Fen("rk4r1/pppppppp/8/8/8/8/PPPPPPPP/RK4R1 w KQkq - 0 1".into()),
+            ..GameSetup::default()
+        };
+        model.start_game(&setup, &[]).unwrap();
+        let king_moves = model.game.get_valid_moves("b1".into());
+        assert!(king_moves.contains(&"g1".into()));
+        assert!(king_moves.contains(&"a1".into()));
+        // Onto the rook, or to the king's landing square
+        model.play_move("b1".into(), "g1".into()).unwrap();
+        model.play_move("b8".into(), "a8".into()).unwrap();
+        assert_eq!(model.game.move_history, ["O-O", "O-O-O"]);
+        assert_eq!(
+            model.start_fen().split(' ').nth(2),
+            Some("KQkq"),
+            "the start keeps its rights"
+        );
+        assert_eq!(
+            fen::to_fen(&model.game.board, model.game.which_turn, 2),
+            "2kr2r1/pppppppp/8/8/8/8/PPPPPPPP/R4RK1 w - - 0 2"
+        );
+
+        let setup = GameSetup {
+            start: StartPosition::Fen("4k3/8/8/8/8/8/8/R3K2r w Q - 0 1".into()),
+            ..GameSetup::default()
+        };
+        model.start_game(&setup, &[]).unwrap();
+        assert!(model.play_move("e1".into(), "c1".into()).is_err(), "in check");
+        let setup = GameSetup {
+            start: StartPosition::Chess960(Some(518)),
+            ..GameSetup::default()
+        };
+        let moves = ["e4", "e5", "Nf3", "Nc6", "Bc4", "Bc5", "O-O"].map(String::from);
+        model.start_game(&setup, &moves).unwrap();
+        assert!(model.game.board[&"f1".into()].is_some_and(|p| p.piece_type == PieceType::Rook));
+    }
+
+    #[test]
+    fn variant_from_pgn_tag() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let text = "[Variant \"King of the Hill\"]\n\n1. d4 e5 2. Kd2 Ke7 3. Kd3 Ke6 4. Ke4";
+        model.new_game(GameSetup {
+            start: StartPosition::Pgn(text.to_string()),
+            ..GameSetup::default()
+        });
+        assert_eq!(model.game.variant, VariantKind::KingOfTheHill);
+        assert!(matches!(
+            model.game.result,
+            Some(GameResult::VariantWin { winner: Side::White, .. })
+        ));
+        assert!(model.play_move("e6".into(), "d6".into()).is_err());
+        assert!(model.pgn().contains("[Variant \"King of the Hill\"]"));
+
+        model.new_game(GameSetup {
+            variant: VariantKind::Horde,
+            ..GameSetup::default()
+        });
+        model.play_move("d4".into(), "d5".into()).unwrap();
+        model.undo();
+        assert_eq!(model.game.variant, VariantKind::Horde);
+        assert_eq!(model.status().in_check, None);
+    }
+
+    #[test]
+    fn draws_by_repetition() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let moves = ["Nf3", "Nf6", "Ng1", "Ng8", "Nf3", "Nf6", "Ng1"].map(String::from);
+        model.start_game(&GameSetup::default(), &moves).unwrap();
+        assert_eq!(model.game.result, None);
+        model.play_move("f6".into(), "g8".into()).unwrap();
+        assert_eq!(
+            model.game.result,
+            Some(GameResult::Draw {
+                reason: DrawReason::Repetition
+            })
+        );
+
+        // A lone king can still win three-check, but not standard chess
+        let setup = GameSetup {
+            start: StartPosition::Fen("4k3/8/8/8/8/8/3q4/4K3 w - - 0 1".to_string()),
+            ..GameSetup::default()
+        };
+        model.start_game(&setup, &["Kxd2".to_string()]).unwrap();
+        assert_eq!(
+            model.game.result,
+            Some(GameResult::Draw {
+                reason: DrawReason::InsufficientMaterial
+            })
+        );
+        let setup = GameSetup {
+            variant: VariantKind::ThreeCheck,
+            ..setup
+        };
+        model.start_game(&setup, &["Kxd2".to_string()]).unwrap();
+        assert_eq!(model.game.result, None);
+    }
+
+    #[test]
+    fn crazyhouse_drops() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let setup = GameSetup {
+            variant: VariantKind::Crazyhouse,
+            ..GameSetup::default()
+        };
+        let moves = ["e4", "d5", "exd5", "Qxd5"].map(String::from);
+        model.start_game(&setup, &moves).unwrap();
+        let pockets = model.status().pockets.unwrap();
+        assert_eq!(pockets.white, [PieceType::Pawn]);
+        assert_eq!(pockets.black, [PieceType::Pawn]);
+
+        model.play_drop(PieceType::Pawn, "c4".into()).unwrap();
+        assert_eq!(model.game.move_history.last().unwrap(), "P@c4");
+        assert_eq!(model.game.last_move, Some(("c4".into(), "c4".into())));
+        assert!(model.status().pockets.unwrap().white.is_empty());
+        assert!(model.play_drop(PieceType::Pawn, "d8".into()).is_err());
+        assert!(model.play_drop(PieceType::Knight, "f6".into()).is_err());
+
+        model.undo();
+        assert_eq!(model.status().pockets.unwrap().white, [PieceType::Pawn]);
+        assert!(model.resolve_san("P@c4").is_some());
+        assert_eq!(model.status().move_history.len(), 4);
+    }
+
+    #[test]
+    fn crazyhouse_pockets_promoted_pieces_as_pawns() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let setup = GameSetup {
+            variant: VariantKind::Crazyhouse,
+            start: StartPosition::Fen("r3k3/1P6/8/8/8/8/8/4K3 w - - 0 1".to_string()),
+            ..GameSetup::default()
+        };
+        let moves = ["b8=Q+", "Rxb8"].map(String::from);
+        model.start_game(&setup, &moves).unwrap();
+        assert_eq!(model.status().pockets.unwrap().black, [PieceType::Pawn]);
+        // The rook that took it wasn't promoted
+        assert!(model.game.promoted.is_empty());
+    }
+
+    #[test]
+    fn players_move_for_their_side() {
+        let (model_send, model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        model.new_game(GameSetup {
+            white: PlayerKind::Random,
+            black: PlayerKind::Engine(2),
+            ..GameSetup::default()
+        });
+
+        model.handle_message(UiMsg::MakeMove(("e2".into(), "e4".into())));
+        assert!(model_recv.try_iter().any(|msg| msg == ModelMsg::MoveIsInvalid));
+        for _ in 0..4 {
+            model.last_move_time = Instant::now() - ENGINE_MOVE_DELAY;
+            model.play_turn();
+        }
+        assert_eq!(model.status().move_history.len(), 4);
+    }
+
+    #[test]
+    fn plays_moves_in_notation() {
+        let (model_send, model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+
+        model.handle_message(UiMsg::MakeSanMove("e4".to_string()));
+        model.handle_message(UiMsg::MakeSanMove("e7e5".to_string()));
+        model.handle_message(UiMsg::MakeSanMove("Nf6".to_string()));
+        assert_eq!(model.status().move_history, ["e4", "e5"]);
+        assert!(model_recv
+            .try_iter()
+            .any(|msg| msg == ModelMsg::Error("Nf6 is not a legal move".to_string())));
+    }
+
+    #[test]
+    fn rejects_moves_out_of_turn() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+
+        assert!(model
+            .play_move(CBPosition::from("e7"), CBPosition::from("e5"))
+            .is_err());
+        assert!(model
+            .play_move(CBPosition::from("e2"), CBPosition::from("e5"))
+            .is_err());
+        assert!(model
+            .play_move(CBPosition::from("e2"), CBPosition::from("e4"))
+            .is_ok());
+    }
+
+    #[test]
+    fn resumes_autosave() {
+        let path = std::env::temp_dir().join("eden_chess_test_autosave.toml");
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        model.autosave_path = Some(path.clone());
+        model.new_game(GameSetup {
+            black: PlayerKind::Engine(2),
+            time_control: Some(TimeControl::new(5, 0)),
+            ..GameSetup::default()
+        });
+        model.make_move(CBPosition::from("e2"), CBPosition::from("e4"));
+
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut resumed = Model::new(model_send, ui_recv);
+        resumed.autosave_path = Some(path.clone());
+        resumed.resume_autosave();
+        let _ = std::fs::remove_file(&path);
+
+        let status = resumed.status();
+        assert_eq!(status.move_history, vec!["e4"]);
+        assert_eq!(status.players, (PlayerKind::Human, PlayerKind::Engine(2)));
+        assert!(status.clocks.is_some());
+    }
 }
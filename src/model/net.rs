@@ -0,0 +1,846 @@
+//! Two player games over TCP. The host runs the authoritative `Model` and the joining
+//! side runs a replica that only plays the moves the host sends it.
+//!
+//! The protocol is one command per line, mirroring the messages between the UI and model:
+//!
+//! | Direction     | Line                              | Meaning                                   |
+//! |---------------|-----------------------------------|-------------------------------------------|
+//! | join → host   | `HELLO eden_chess <version> <id>` | handshake, with the session id of the game |
+//! | host → join   | `WELCOME <version> <id> <w\|b>`   | accepted, playing the given side          |
+//! | host → join   | `REFUSED <reason>`                | handshake failed, the connection closes   |
+//! | host → join   | `GAME <w\|b> <base ms\|-> <increment ms\|-> <fen>` | a game from this position |
+//! | both          | `MOVE <from><to>`                 | `UiMsg::MakeMove` / a move was played     |
+//! | host → join   | `REJECT <reason>`                 | `ModelMsg::MoveIsInvalid`                 |
+//! | host → join   | `CLOCK <white ms> <black ms>`     | the clocks after a move                   |
+//! | host → join   | `TIMEOUT <w\|b>`                  | `ModelMsg::OutOfTime`                     |
+//! | join → host   | `SYNC`                            | asks for the game again from the start    |
+//!
+//! The session id is the game code the host shows, which the joining player types in to take
+//! their seat. The host sends `GAME`, every `MOVE` so far and `CLOCK` after each handshake,
+//! so a player that drops out can join again and carry on. Lines longer than
+//! `MAX_LINE_LENGTH` close the connection.
+
+use std::{
+    io::{self, BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+    thread,
+    time::Duration,
+};
+
+use log::{debug, error, info, warn};
+
+use super::{fen, legal_moves, player::Remote, uci, ChessTimer, Game, Model, Side};
+use crate::control::{
+    GameResult, GameSetup, ModelMsg, Move, PlayerKind, StartPosition, TimeControl, VariantKind,
+};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const DEFAULT_PORT: u16 = 7878;
+
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+const RECONNECT_DELAY: Duration = Duration::from_secs(2);
+const ACCEPT_POLL: Duration = Duration::from_millis(100);
+/// The longest line either end accepts, well over the longest `GAME` line
+pub const MAX_LINE_LENGTH: usize = 1024;
+
+#[derive(Debug)]
+pub(super) enum NetEvent {
+    /// A connection was made, with the first line it sent if we are hosting
+    Connected {
+        id: usize,
+        stream: TcpStream,
+        hello: Option<String>,
+    },
+    Line {
+        id: usize,
+        line: String,
+    },
+    Disconnected {
+        id: usize,
+    },
+    /// The first connection to the host could not be made
+    Failed(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Role {
+    Host,
+    Join,
+}
+
+/// The other player of a network game and the connection to them.
+pub(super) struct Peer {
+    role: Role,
+    /// The side played by the other end
+    side: Side,
+    events: Receiver<NetEvent>,
+    /// The current connection and its id, `None` while disconnected
+    writer: Option<(usize, TcpStream)>,
+    /// Passes the other player's moves on to their `Remote` player, once one is seated
+    moves: Option<Sender<Move>>,
+    /// The ply of a move from the other player waiting to be played, so they can't send two
+    queued_ply: Option<usize>,
+    /// Identifies the game so a dropped player can rejoin it
+    session: String,
+    pub(super) port: u16,
+    /// Tells the listening or reconnecting thread to stop once the game is left
+    closed: Arc<AtomicBool>,
+}
+
+impl Drop for Peer {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        if let Some((_, stream)) = &self.writer {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+impl Peer {
+    fn send(&mut self, line: &str) {
+        let Some((_, stream)) = &mut self.writer else {
+            return;
+        };
+        debug!("net send: {}", line);
+        if let Err(e) = writeln!(stream, "{}", line) {
+            warn!("Could not send {:?}: {}", line, e);
+            self.writer = None;
+        }
+    }
+
+    fn is_current(&self, id: usize) -> bool {
+        self.writer
+            .as_ref()
+            .is_some_and(|(current, _)| *current == id)
+    }
+}
+
+fn side_to_str(side: Side) -> &'static str {
+    match side {
+        Side::White => "w",
+        Side::Black => "b",
+    }
+}
+
+fn side_from_str(s: &str) -> Result<Side, String> {
+    match s {
+        "w" => Ok(Side::White),
+        "b" => Ok(Side::Black),
+        other => Err(format!("Invalid side {:?}", other)),
+    }
+}
+
+/// Reads a line without its ending, `None` at the end of the stream. A line longer than
+/// `MAX_LINE_LENGTH` is an error rather than being read whole.
+pub fn read_line(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut line = String::new();
+    let read = reader
+        .take(MAX_LINE_LENGTH as u64 + 1)
+        .read_line(&mut line)?;
+    if read == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && read > MAX_LINE_LENGTH {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("line longer than {} bytes", MAX_LINE_LENGTH),
+        ));
+    }
+    Ok(Some(line.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+/// Accepts connections on `address` until `closed` is set, reading the handshake line of
+/// each.
+fn listen(address: &str, closed: Arc<AtomicBool>) -> Result<(Receiver<NetEvent>, u16), String> {
+    let listener =
+        TcpListener::bind(address).map_err(|e| format!("Can't listen on {}: {}", address, e))?;
+    let port = listener.local_addr().map_err(|e| e.to_string())?.port();
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let (send, recv) = channel();
+    thread::spawn(move || {
+        let mut id = 0;
+        while !closed.load(Ordering::Relaxed) {
+            let stream = match listener.accept() {
+                Ok((stream, addr)) => {
+                    info!("Connection {} from {}", id, addr);
+                    stream
+                }
+                Err(e) if e.kind() == std::io::ErrorKind::WouldBlock => {
+                    thread::sleep(ACCEPT_POLL);
+                    continue;
+                }
+                Err(e) => {
+                    warn!("Failed connection: {}", e);
+                    continue;
+                }
+            };
+            let send = send.clone();
+            thread::spawn(move || {
+                let _ = stream.set_nonblocking(false);
+                let _ = stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT));
+                let mut reader = BufReader::new(stream);
+                let Ok(Some(hello)) = read_line(&mut reader) else {
+                    return;
+                };
+                let _ = reader.get_ref().set_read_timeout(None);
+                let hello = Some(hello.trim().to_string());
+                read_connection(id, reader, hello, &send, |_| {});
+            });
+            id += 1;
+        }
+        info!("Stopped listening on port {}", port);
+    });
+    info!("Hosting on port {}", port);
+    Ok((recv, port))
+}
+
+/// Forwards the lines from one connection until it closes, showing each to `inspect`
+/// first. Returns false if the model has gone away.
+fn read_connection(
+    id: usize,
+    mut reader: BufReader<TcpStream>,
+    hello: Option<String>,
+    send: &Sender<NetEvent>,
+    mut inspect: impl FnMut(&str),
+) -> bool {
+    let Ok(stream) = reader.get_ref().try_clone() else {
+        return true;
+    };
+    if send
+        .send(NetEvent::Connected { id, stream, hello })
+        .is_err()
+    {
+        return false;
+    }
+    loop {
+        match read_line(&mut reader) {
+            Ok(Some(line)) => {
+                debug!("net recv: {}", line);
+                inspect(&line);
+                if send.send(NetEvent::Line { id, line }).is_err() {
+                    return false;
+                }
+            }
+            Ok(None) => break,
+            Err(e) => {
+                info!("Connection {} closed: {}", id, e);
+                let _ = reader.get_ref().shutdown(std::net::Shutdown::Both);
+                break;
+            }
+        }
+    }
+    send.send(NetEvent::Disconnected { id }).is_ok()
+}
+
+/// Connects to a host with the game's session id, connecting again whenever the connection
+/// drops.
+fn join(address: String, session: String, closed: Arc<AtomicBool>) -> Receiver<NetEvent> {
+    let (send, recv) = channel();
+    thread::spawn(move || {
+        let mut refused = false;
+        for id in 0.. {
+            match TcpStream::connect(&address) {
+                Ok(mut stream) => {
+                    let hello = format!("HELLO eden_chess {} {}", PROTOCOL_VERSION, session);
+                    if writeln!(stream, "{}", hello).is_ok() {
+                        let inspect = |line: &str| {
+                            if line.starts_with("REFUSED") {
+                                refused = true;
+                            }
+                        };
+                        if !read_connection(id, BufReader::new(stream), None, &send, inspect) {
+                            return;
+                        }
+                    }
+                }
+                Err(e) if id == 0 => {
+                    let _ = send.send(NetEvent::Failed(format!(
+                        "Could not connect to {}: {}",
+                        address, e
+                    )));
+                    return;
+                }
+                Err(e) => info!("Reconnecting to {} failed: {}", address, e),
+            }
+            if refused || closed.load(Ordering::Relaxed) {
+                return;
+            }
+            thread::sleep(RECONNECT_DELAY);
+        }
+    });
+    recv
+}
+
+impl Model {
+    /// Starts a game with `setup`, one of whose players is `PlayerKind::Remote`, and waits
+    /// for them to join at `address`.
+    pub(super) fn host_game(&mut self, setup: GameSetup, address: &str) {
+        let remote_side = match (&setup.white, &setup.black) {
+            (PlayerKind::Remote, PlayerKind::Remote) => None,
+            (PlayerKind::Remote, _) => Some(Side::White),
+            (_, PlayerKind::Remote) => Some(Side::Black),
+            _ => None,
+        };
+        let Some(remote_side) = remote_side else {
+            let _ = self.ui_sender.send(ModelMsg::Error(
+                "One side of a network game must be the remote player".to_string(),
+            ));
+            return;
+        };
+        // The protocol has no way to tell the other side the rules yet
+        if setup.variant != VariantKind::Standard {
+            let _ = self.ui_sender.send(ModelMsg::Error(
+                "Network games can only be standard chess".to_string(),
+            ));
+            return;
+        }
+        let closed = Arc::new(AtomicBool::new(false));
+        let (events, port) = match listen(address, closed.clone()) {
+            Ok(listening) => listening,
+            Err(e) => {
+                let _ = self.ui_sender.send(ModelMsg::Error(e));
+                return;
+            }
+        };
+        if let Err(e) = self.start_game(&setup, &[]) {
+            closed.store(true, Ordering::Relaxed);
+            let _ = self.ui_sender.send(ModelMsg::Error(e));
+            return;
+        }
+        self.peer = Some(Peer {
+            role: Role::Host,
+            side: remote_side,
+            events,
+            writer: None,
+            moves: None,
+            queued_ply: None,
+            session: format!("{:08x}", rand::random::<u32>()),
+            port,
+            closed,
+        });
+        self.seat_remote();
+        let session = self.peer.as_ref().map(|peer| peer.session.clone());
+        let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+        let _ = self.ui_sender.send(ModelMsg::Network(format!(
+            "Waiting for the other player to join on port {} with game code {}",
+            port,
+            session.unwrap_or_default()
+        )));
+    }
+
+    /// Joins the game hosted at `address` with the game code the host gave out, the game
+    /// starts once the host sends it.
+    pub(super) fn join_game(&mut self, address: String, session: String) {
+        let port = address
+            .rsplit_once(':')
+            .and_then(|(_, port)| port.parse().ok())
+            .unwrap_or(DEFAULT_PORT);
+        let closed = Arc::new(AtomicBool::new(false));
+        self.peer = Some(Peer {
+            role: Role::Join,
+            side: Side::White,
+            events: join(address, session.clone(), closed.clone()),
+            writer: None,
+            moves: None,
+            queued_ply: None,
+            session,
+            port,
+            closed,
+        });
+    }
+
+    /// Makes the other side's player one that plays the moves passed on from the connection.
+    /// When joined the host's moves come as part of its game, which is played as it arrives,
+    /// so nothing is passed on.
+    fn seat_remote(&mut self) {
+        let Some(peer) = &mut self.peer else {
+            return;
+        };
+        let (moves, remote) = channel();
+        peer.moves = Some(moves);
+        peer.queued_ply = None;
+        self.players
+            .insert(peer.side, Box::new(Remote::new(remote)));
+    }
+
+    pub(super) fn is_remote_game(&self) -> bool {
+        self.peer.is_some()
+    }
+
+    /// Whether this model follows a host instead of deciding the game itself.
+    pub(super) fn is_joined(&self) -> bool {
+        self.peer
+            .as_ref()
+            .is_some_and(|peer| peer.role == Role::Join)
+    }
+
+    /// Handles everything that arrived from the other player since the last call.
+    pub(super) fn handle_network(&mut self) {
+        loop {
+            let Some(peer) = &self.peer else {
+                return;
+            };
+            let event = match peer.events.try_recv() {
+                Ok(event) => event,
+                Err(_) => return,
+            };
+            let role = peer.role;
+            let result = match (role, event) {
+                (_, NetEvent::Failed(e)) => {
+                    self.peer = None;
+                    Err(e)
+                }
+                (Role::Host, NetEvent::Connected { id, stream, hello }) => {
+                    self.accept(id, stream, hello.unwrap_or_default());
+                    Ok(())
+                }
+                (Role::Join, NetEvent::Connected { id, stream, .. }) => {
+                    if let Some(peer) = &mut self.peer {
+                        peer.writer = Some((id, stream));
+                    }
+                    Ok(())
+                }
+                (_, NetEvent::Line { id, line })
+                    if self.peer.as_ref().is_some_and(|p| p.is_current(id)) =>
+                {
+                    match role {
+                        Role::Host => self.handle_join_line(&line),
+                        Role::Join => self.handle_host_line(&line),
+                    }
+                }
+                (_, NetEvent::Line { .. }) => Ok(()),
+                (_, NetEvent::Disconnected { id }) => {
+                    if let Some(peer) = self.peer.as_mut().filter(|p| p.is_current(id)) {
+                        peer.writer = None;
+                        let notice = match role {
+                            Role::Host => {
+                                "The other player disconnected, waiting for them to rejoin"
+                            }
+                            Role::Join => "Lost the connection to the host, reconnecting",
+                        };
+                        let _ = self.ui_sender.send(ModelMsg::Network(notice.to_string()));
+                    }
+                    Ok(())
+                }
+            };
+            if let Err(e) = result {
+                warn!("{}", e);
+                let _ = self.ui_sender.send(ModelMsg::Error(e));
+            }
+        }
+    }
+
+    /// Checks the handshake of a new connection to the host.
+    fn accept(&mut self, id: usize, mut stream: TcpStream, hello: String) {
+        let Some(peer) = &mut self.peer else {
+            return;
+        };
+        let mut words = hello.split_whitespace();
+        let refusal = match (words.next(), words.next(), words.next(), words.next()) {
+            (Some("HELLO"), Some("eden_chess"), Some(version), session) => {
+                if version != PROTOCOL_VERSION.to_string() {
+                    Some(format!(
+                        "protocol version {} is not supported, the host uses version {}",
+                        version, PROTOCOL_VERSION
+                    ))
+                } else if session != Some(peer.session.as_str()) {
+                    Some("wrong game code".to_string())
+                } else {
+                    None
+                }
+            }
+            _ => Some("not an eden_chess client".to_string()),
+        };
+        if let Some(reason) = refusal {
+            info!("Refused connection {}: {}", id, reason);
+            let _ = writeln!(stream, "REFUSED {}", reason);
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+            return;
+        }
+        peer.writer = Some((id, stream));
+        let welcome = format!(
+            "WELCOME {} {} {}",
+            PROTOCOL_VERSION,
+            peer.session,
+            side_to_str(peer.side)
+        );
+        peer.send(&welcome);
+        self.send_sync();
+        let _ = self
+            .ui_sender
+            .send(ModelMsg::Network("The other player joined".to_string()));
+    }
+
+    /// Sends the whole game so the other player can rebuild it.
+    fn send_sync(&mut self) {
+        let start_fen = fen::to_fen(
+            &self.game.positions[0],
+            self.game.starting_side(),
+            self.game.turn_count - self.game.move_history.len(),
+        );
+        let (base, increment) = match self.game.time_control {
+            Some(tc) => (
+                tc.base.as_millis().to_string(),
+                tc.increment.as_millis().to_string(),
+            ),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let mut lines = Vec::new();
+        if let Some(peer) = &self.peer {
+            lines.push(format!(
+                "GAME {} {} {} {}",
+                side_to_str(peer.side),
+                base,
+                increment,
+                start_fen
+            ));
+        }
+        for mv in self.game.moves.iter() {
+            if let Move::Board(from, to) = mv {
+                lines.push(format!("MOVE {}", uci::format_move((*from, *to))));
+            }
+        }
+        lines.extend(self.clock_line());
+        if let Some(GameResult::OutOfTime { loser }) = self.game.result {
+            lines.push(format!("TIMEOUT {}", side_to_str(loser)));
+        }
+        if let Some(peer) = &mut self.peer {
+            for line in lines {
+                peer.send(&line);
+            }
+        }
+    }
+
+    fn clock_line(&self) -> Option<String> {
+        let (white, black) = self.game.timer.clocks(self.game.which_turn)?;
+        Some(format!("CLOCK {} {}", white.as_millis(), black.as_millis()))
+    }
+
+    /// A line from the joining player, read by the host.
+    fn handle_join_line(&mut self, line: &str) -> Result<(), String> {
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        match command {
+            "MOVE" => {
+                let ply = self.game.move_history.len();
+                let legal = legal_moves(&self.game.board, self.game.which_turn);
+                let Some(peer) = &mut self.peer else {
+                    return Ok(());
+                };
+                let their_turn = self.game.which_turn == peer.side && peer.queued_ply != Some(ply);
+                let checked = if !their_turn {
+                    Err("it is not your turn".to_string())
+                } else {
+                    uci::parse_move(args.trim()).and_then(|(from, to)| {
+                        if legal.contains(&(from, to)) {
+                            Ok(Move::Board(from, to))
+                        } else {
+                            Err(format!("{} is not a legal move", args.trim()))
+                        }
+                    })
+                };
+                // Their player plays the move on its turn, which sends it back to them
+                match (checked, &peer.moves) {
+                    (Ok(mv), Some(moves)) if moves.send(mv).is_ok() => {
+                        peer.queued_ply = Some(ply);
+                    }
+                    (Ok(_), _) => warn!("No remote player to play {}", args),
+                    (Err(reason), _) => {
+                        info!("Rejected move {} from the other player: {}", args, reason);
+                        peer.send(&format!("REJECT {}", reason));
+                    }
+                }
+                Ok(())
+            }
+            "SYNC" => {
+                self.send_sync();
+                Ok(())
+            }
+            _ => Err(format!("Unknown message from the other player: {:?}", line)),
+        }
+    }
+
+    /// A line from the host, read by the joining player.
+    fn handle_host_line(&mut self, line: &str) -> Result<(), String> {
+        let (command, args) = line.split_once(' ').unwrap_or((line, ""));
+        let words: Vec<&str> = args.split_whitespace().collect();
+        match command {
+            "WELCOME" => {
+                let session = words.get(1).ok_or("WELCOME without a session id")?;
+                if let Some(peer) = &mut self.peer {
+                    peer.session = session.to_string();
+                }
+                let _ = self
+                    .ui_sender
+                    .send(ModelMsg::Network("Connected to the host".to_string()));
+            }
+            "REFUSED" => {
+                self.peer = None;
+                return Err(format!("The host refused the connection: {}", args));
+            }
+            "GAME" => {
+                let [side, base, increment, ..] = words[..] else {
+                    return Err(format!("Malformed GAME line {:?}", line));
+                };
+                let fen = words[3..].join(" ");
+                let own_side = side_from_str(side)?;
+                let time_control = match (base, increment) {
+                    ("-", _) => None,
+                    (base, increment) => Some(TimeControl {
+                        base: Duration::from_millis(base.parse().map_err(|_| "Invalid clock")?),
+                        increment: Duration::from_millis(
+                            increment.parse().map_err(|_| "Invalid clock")?,
+                        ),
+                    }),
+                };
+                let (white, black) = match own_side {
+                    Side::White => (PlayerKind::Human, PlayerKind::Remote),
+                    Side::Black => (PlayerKind::Remote, PlayerKind::Human),
+                };
+                let setup = GameSetup {
+                    white,
+                    black,
+                    time_control,
+                    start: StartPosition::Fen(fen),
+                    variant: VariantKind::Standard,
+                    handicap: None,
+                };
+                self.game = Game::from_setup(&setup)?;
+                self.players.clear();
+                self.game.time_control = time_control;
+                // The host's clock decides, including any time odds
+                self.game.timer = ChessTimer::new(time_control.map(|tc| (tc, tc)));
+                self.game.timer.start();
+                if let Some(peer) = &mut self.peer {
+                    peer.side = own_side.flipped();
+                }
+                self.seat_remote();
+            }
+            "MOVE" => {
+                let (from, to) = uci::parse_move(args.trim())?;
+                if let Err(e) = self.play_move(from, to) {
+                    // We are out of step with the host, start again from its game
+                    warn!("Move {} from the host failed: {}, resyncing", args, e);
+                    if let Some(peer) = &mut self.peer {
+                        peer.send("SYNC");
+                    }
+                    return Ok(());
+                }
+                self.announce_result();
+            }
+            "CLOCK" => {
+                let [white, black] = words[..] else {
+                    return Err(format!("Malformed CLOCK line {:?}", line));
+                };
+                let ms = |s: &str| {
+                    s.parse()
+                        .map(Duration::from_millis)
+                        .map_err(|_| "Invalid clock")
+                };
+                self.game.timer.set_clocks((ms(white)?, ms(black)?));
+                self.game.timer.start();
+            }
+            "TIMEOUT" => {
+                let loser = side_from_str(args.trim())?;
+                self.game.result = Some(GameResult::OutOfTime { loser });
+                let _ = self.ui_sender.send(ModelMsg::OutOfTime(loser));
+            }
+            "REJECT" => {
+                let _ = self.ui_sender.send(ModelMsg::MoveIsInvalid);
+                return Err(format!("The host rejected the move: {}", args));
+            }
+            _ => return Err(format!("Unknown message from the host: {:?}", line)),
+        }
+        let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+        Ok(())
+    }
+
+    /// Sends a move made on this side to the other player, and the clocks if hosting.
+    pub(super) fn send_move(&mut self, from: super::CBPosition, to: super::CBPosition) {
+        let clock = self.clock_line();
+        let Some(peer) = &mut self.peer else {
+            return;
+        };
+        peer.send(&format!("MOVE {}", uci::format_move((from, to))));
+        if peer.role == Role::Host {
+            if let Some(clock) = clock {
+                peer.send(&clock);
+            }
+        }
+    }
+
+    /// Tells the other player the host's clock ran out.
+    pub(super) fn send_timeout(&mut self, loser: Side) {
+        if let Some(peer) = self.peer.as_mut().filter(|p| p.role == Role::Host) {
+            peer.send(&format!("TIMEOUT {}", side_to_str(loser)));
+        }
+    }
+
+    /// Asks the host to play a move, it is only made once the host sends it back.
+    pub(super) fn request_move(&mut self, from: super::CBPosition, to: super::CBPosition) {
+        if !legal_moves(&self.game.board, self.game.which_turn).contains(&(from, to)) {
+            let _ = self.ui_sender.send(ModelMsg::MoveIsInvalid);
+            return;
+        }
+        match &mut self.peer {
+            Some(peer) if peer.writer.is_some() => {
+                peer.send(&format!("MOVE {}", uci::format_move((from, to))))
+            }
+            _ => {
+                error!("Not connected to the host");
+                let _ = self.ui_sender.send(ModelMsg::MoveIsInvalid);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::{control::CBPosition, model::Model};
+
+    fn model() -> Model {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        Model::new(model_send, ui_recv)
+    }
+
+    /// Lets both models handle the network and play their turns until `done` or a timeout.
+    fn pump(host: &mut Model, join: &mut Model, done: impl Fn(&Model, &Model) -> bool) {
+        let start = Instant::now();
+        while !done(host, join) {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            host.update();
+            join.update();
+            thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    fn hosted_game() -> (Model, u16) {
+        let mut host = model();
+        host.host_game(
+            GameSetup {
+                black: PlayerKind::Remote,
+                ..GameSetup::default()
+            },
+            "127.0.0.1:0",
+        );
+        let port = host.peer.as_ref().unwrap().port;
+        (host, port)
+    }
+
+    #[test]
+    fn plays_over_loopback() {
+        let (mut host, port) = hosted_game();
+        let mut join = model();
+        let session = host.peer.as_ref().unwrap().session.clone();
+        join.join_game(format!("127.0.0.1:{}", port), session);
+        pump(&mut host, &mut join, |_, j| {
+            j.game.player(Side::Black) == &PlayerKind::Human
+        });
+
+        host.make_move(CBPosition::from("e2"), CBPosition::from("e4"));
+        pump(&mut host, &mut join, |_, j| j.game.move_history.len() == 1);
+        join.make_move(CBPosition::from("e7"), CBPosition::from("e5"));
+        pump(&mut host, &mut join, |h, j| {
+            h.game.move_history.len() == 2 && j.game.move_history.len() == 2
+        });
+        assert_eq!(host.game.move_history, vec!["e4", "e5"]);
+        assert_eq!(join.game.move_history, host.game.move_history);
+
+        // Dropping the connection and rejoining gets the game back
+        let session = join.peer.as_ref().unwrap().session.clone();
+        let (_, stream) = join.peer.as_mut().unwrap().writer.take().unwrap();
+        stream.shutdown(std::net::Shutdown::Both).unwrap();
+        join.game = Game::new();
+        pump(&mut host, &mut join, |_, j| j.game.move_history.len() == 2);
+        assert_eq!(join.peer.as_ref().unwrap().session, session);
+    }
+
+    /// Connects to the host as a raw client and sends the handshake.
+    fn raw_client(port: u16, hello: &str) -> BufReader<TcpStream> {
+        let mut stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_millis(20)))
+            .unwrap();
+        writeln!(stream, "{}", hello).unwrap();
+        BufReader::new(stream)
+    }
+
+    /// Reads the next line from the host, letting it handle the network meanwhile.
+    fn next_line(host: &mut Model, client: &mut BufReader<TcpStream>) -> String {
+        let start = Instant::now();
+        let mut line = String::new();
+        loop {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            host.handle_network();
+            if client.read_line(&mut line).is_ok() && line.ends_with('\n') {
+                return line;
+            }
+        }
+    }
+
+    #[test]
+    fn rejects_bad_clients_and_moves() {
+        let (mut host, port) = hosted_game();
+
+        let mut old = raw_client(port, "HELLO eden_chess 0");
+        assert!(next_line(&mut host, &mut old).starts_with("REFUSED protocol version 0"));
+
+        // A seat needs the game code, even while it is empty
+        let mut stranger = raw_client(port, &format!("HELLO eden_chess {}", PROTOCOL_VERSION));
+        assert!(next_line(&mut host, &mut stranger).starts_with("REFUSED wrong game code"));
+
+        let session = host.peer.as_ref().unwrap().session.clone();
+        let hello = format!("HELLO eden_chess {} {}", PROTOCOL_VERSION, session);
+        let mut client = raw_client(port, &hello);
+        assert!(next_line(&mut host, &mut client).starts_with("WELCOME 1"));
+        // Black moving first is out of turn
+        writeln!(client.get_mut(), "MOVE e7e5").unwrap();
+        let mut line = String::new();
+        while !line.starts_with("REJECT") {
+            line = next_line(&mut host, &mut client);
+        }
+        assert!(line.contains("not your turn"));
+
+        let mut third = raw_client(port, &format!("HELLO eden_chess {} 0", PROTOCOL_VERSION));
+        assert!(next_line(&mut host, &mut third).starts_with("REFUSED wrong game code"));
+
+        host.make_move(CBPosition::from("e2"), CBPosition::from("e4"));
+        // The knight can't reach e5
+        writeln!(client.get_mut(), "MOVE g8e5").unwrap();
+        while !line.starts_with("REJECT") || line.contains("not your turn") {
+            line = next_line(&mut host, &mut client);
+        }
+        assert_eq!(host.game.move_history, vec!["e4"]);
+
+        // The remote player plays the move on its turn, and a second one sent before then is refused
+        writeln!(client.get_mut(), "MOVE e7e5").unwrap();
+        writeln!(client.get_mut(), "MOVE d7d5").unwrap();
+        line.clear();
+        while !line.starts_with("REJECT it is not your turn") {
+            line = next_line(&mut host, &mut client);
+        }
+        let start = Instant::now();
+        while host.game.move_history.len() < 2 {
+            assert!(start.elapsed() < Duration::from_secs(5), "timed out");
+            host.update();
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(host.game.move_history, vec!["e4", "e5"]);
+    }
+
+    #[test]
+    fn refuses_long_lines() {
+        let text = format!("short\n{}\n", "x".repeat(MAX_LINE_LENGTH * 2));
+        let mut reader = BufReader::new(text.as_bytes());
+        assert_eq!(read_line(&mut reader).unwrap(), Some("short".to_string()));
+        assert!(read_line(&mut reader).is_err());
+    }
+}
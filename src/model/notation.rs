@@ -0,0 +1,195 @@
+use super::{castling, fen, promotes, uci, variant::Variant, Board, PieceType, Side};
+use crate::control::CBPosition;
+
+/// Writes a move in standard algebraic notation, using the board from before the move.
+pub fn algebraic(
+    board: &Board,
+    from: CBPosition,
+    to: CBPosition,
+    gives_check: bool,
+    is_mate: bool,
+) -> String {
+    let piece = board
+        .get(&from)
+        .copied()
+        .flatten()
+        .expect("Moving piece should exist");
+    let castle = castling(board, from, to);
+    let captures = castle.is_none() && board.get(&to).is_some_and(|p| p.is_some());
+    let mut notation = String::new();
+
+    if let Some(castle) = &castle {
+        notation.push_str(if castle.king_side { "O-O" } else { "O-O-O" });
+    } else if piece.piece_type == PieceType::Pawn {
+        if captures {
+            notation.push(from.col);
+        }
+    } else {
+        notation.push_str(&piece.piece_type.to_string());
+        // Other pieces of the same kind that could also reach the square
+        let rivals: Vec<CBPosition> = board
+            .values()
+            .flatten()
+            .filter(|p| {
+                p.side == piece.side
+                    && p.piece_type == piece.piece_type
+                    && p.current_pos != from
+                    && p.get_valid_moves(board).contains(&to)
+            })
+            .map(|p| p.current_pos)
+            .collect();
+        if !rivals.is_empty() {
+            if rivals.iter().all(|p| p.col != from.col) {
+                notation.push(from.col);
+            } else if rivals.iter().all(|p| p.row != from.row) {
+                notation.push_str(&from.row.to_string());
+            } else {
+                notation.push_str(&format!("{:?}", from));
+            }
+        }
+    }
+    if captures {
+        notation.push('x');
+    }
+    if castle.is_none() {
+        notation.push_str(&format!("{:?}", to));
+    }
+    if piece.piece_type == PieceType::Pawn && promotes(board, piece.side, to) {
+        notation.push_str("=Q");
+    }
+    if is_mate {
+        notation.push('#');
+    } else if gives_check {
+        notation.push('+');
+    }
+    notation
+}
+
+/// Writes a drop from the pocket, such as `N@f3`. Pawn drops are written `P@e4`.
+pub fn drop(piece_type: PieceType, to: CBPosition, gives_check: bool) -> String {
+    format!(
+        "{}@{:?}{}",
+        piece_type,
+        to,
+        if gives_check { "+" } else { "" }
+    )
+}
+
+/// Reads a drop written as `N@f3`, where a pawn drop may also leave out the `P`.
+pub fn parse_drop(san: &str) -> Option<(PieceType, CBPosition)> {
+    let (piece, square) = san.trim_end_matches(['+', '#']).split_once('@')?;
+    let piece_type = match piece {
+        "" => PieceType::Pawn,
+        _ if piece.len() == 1 && piece.chars().all(|c| c.is_ascii_uppercase()) => {
+            fen::piece_from_char(piece.chars().next()?)?.1
+        }
+        _ => return None,
+    };
+    Some((piece_type, square.parse().ok()?))
+}
+
+/// Whether a move in either form promotes to something other than a queen, which pawns
+/// can't do here.
+pub fn underpromotes(text: &str) -> bool {
+    let text = text.trim_end_matches(['+', '#', '!', '?']);
+    match text.split_once('=') {
+        Some((_, piece)) => piece != "Q",
+        None => text
+            .strip_suffix(['r', 'b', 'n'])
+            .is_some_and(|rest| rest.ends_with(|c: char| c.is_ascii_digit())),
+    }
+}
+
+/// Finds the legal move of `side` written as `text`, in algebraic notation or in UCI's
+/// long form such as `e2e4`. A promotion may leave out the queen, but naming any other
+/// piece finds no move.
+pub fn resolve(
+    board: &Board,
+    rules: &dyn Variant,
+    side: Side,
+    text: &str,
+) -> Option<(CBPosition, CBPosition)> {
+    if underpromotes(text) {
+        return None;
+    }
+    let legal = rules.legal_moves(board, side);
+    if let Ok(mv) = uci::parse_move(text) {
+        return legal.contains(&mv).then_some(mv);
+    }
+    let san = text.trim_end_matches(['+', '#', '!', '?']);
+    let san = san.strip_suffix("=Q").unwrap_or(san);
+    legal.into_iter().find(|(from, to)| {
+        let written = algebraic(board, *from, *to, false, false);
+        written.trim_end_matches("=Q") == san
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{board_setup, empty_board, insert_piece, PieceType::*, Side::*};
+
+    #[test]
+    fn pawn_and_piece_moves() {
+        let mut board = board_setup();
+        assert_eq!(algebraic(&board, "e2".into(), "e4".into(), false, false), "e4");
+        assert_eq!(algebraic(&board, "g1".into(), "f3".into(), false, false), "Nf3");
+        insert_piece(&mut board, 3, 'd', Black, Pawn);
+        assert_eq!(algebraic(&board, "e2".into(), "d3".into(), true, false), "exd3+");
+    }
+
+    #[test]
+    fn disambiguation() {
+        let mut board = empty_board();
+        insert_piece(&mut board, 1, 'a', White, Rook);
+        insert_piece(&mut board, 1, 'h', White, Rook);
+        insert_piece(&mut board, 5, 'a', White, Rook);
+        insert_piece(&mut board, 8, 'e', Black, Queen);
+        assert_eq!(algebraic(&board, "h1".into(), "d1".into(), false, false), "Rhd1");
+        assert_eq!(algebraic(&board, "a5".into(), "a3".into(), false, false), "R5a3");
+        assert_eq!(algebraic(&board, "a1".into(), "e1".into(), false, true), "Rae1#");
+    }
+
+    #[test]
+    fn drops() {
+        assert_eq!(drop(Knight, "f3".into(), false), "N@f3");
+        assert_eq!(drop(Pawn, "e6".into(), true), "P@e6+");
+        assert_eq!(parse_drop("N@f3"), Some((Knight, "f3".into())));
+        assert_eq!(parse_drop("@e4#"), Some((Pawn, "e4".into())));
+        assert_eq!(parse_drop("Nf3"), None);
+        assert_eq!(parse_drop("K@i0"), None);
+    }
+
+    #[test]
+    fn resolves_either_form() {
+        let board = board_setup();
+        let rules = crate::model::variant::rules(crate::control::VariantKind::Standard);
+        let knight = Some(("g1".into(), "f3".into()));
+        assert_eq!(resolve(&board, rules, White, "Nf3"), knight);
+        assert_eq!(resolve(&board, rules, White, "g1f3"), knight);
+        assert_eq!(resolve(&board, rules, White, "Nf6"), None);
+        assert_eq!(resolve(&board, rules, Black, "e2e4"), None);
+    }
+
+    #[test]
+    fn promotes_to_a_queen() {
+        let mut board = empty_board();
+        insert_piece(&mut board, 1, 'a', White, King);
+        insert_piece(&mut board, 8, 'a', Black, King);
+        insert_piece(&mut board, 7, 'e', White, Pawn);
+        let rules = crate::model::variant::rules(crate::control::VariantKind::Standard);
+        let push = Some(("e7".into(), "e8".into()));
+        let written = algebraic(&board, "e7".into(), "e8".into(), true, false);
+        assert_eq!(written, "e8=Q+");
+        for text in ["e8=Q", "e8=Q+", "e8", "e7e8q", "e7e8"] {
+            assert_eq!(resolve(&board, rules, White, text), push, "{}", text);
+        }
+        for text in ["e8=N", "e7e8n", "e7e8r"] {
+            assert_eq!(resolve(&board, rules, White, text), None, "{}", text);
+        }
+
+        rules.make_move(&mut board, "e7".into(), "e8".into());
+        let queen = board.get(&"e8".into()).copied().flatten().unwrap();
+        assert_eq!(queen.piece_type, Queen);
+    }
+}
@@ -0,0 +1,317 @@
+use crate::control::TimeControl;
+
+/// The tags and main line of a game in Portable Game Notation.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Pgn {
+    pub tags: Vec<(String, String)>,
+    /// Moves of the main line in algebraic notation, without annotations
+    pub moves: Vec<String>,
+}
+
+impl Pgn {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+const RESULTS: [&str; 4] = ["1-0", "0-1", "1/2-1/2", "*"];
+
+/// Reads the first game of a PGN file. Comments, variations and numeric annotations are
+/// skipped, and `!`/`?` suffixes are stripped from the moves.
+pub fn parse(text: &str) -> Result<Pgn, String> {
+    let (tags, movetext) = split_game(text)?;
+    let mut pgn = Pgn {
+        tags,
+        moves: Vec::new(),
+    };
+    let mut depth = 0;
+    for token in tokens(&movetext)? {
+        match token.as_str() {
+            "(" => depth += 1,
+            ")" => depth -= 1,
+            _ if depth > 0 => {}
+            result if RESULTS.contains(&result) => break,
+            _ => pgn.moves.push(token),
+        }
+    }
+    Ok(pgn)
+}
+
+/// Reads every line of the first game of a PGN file, the main line and each variation, as
+/// the moves from the start to where it ends. A variation replaces the move before it, as
+/// in `1. e4 e5 (1... c5) 2. Nf3`, whose lines are `e4 c5` and `e4 e5 Nf3`.
+pub fn parse_lines(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let (_, movetext) = split_game(text)?;
+    let mut lines = Vec::new();
+    let mut line: Vec<String> = Vec::new();
+    // The lines the open variations branch from
+    let mut branched: Vec<Vec<String>> = Vec::new();
+    for token in tokens(&movetext)? {
+        match token.as_str() {
+            "(" => {
+                branched.push(line.clone());
+                if line.pop().is_none() {
+                    return Err("A variation in the PGN has no move to replace".to_string());
+                }
+            }
+            ")" => {
+                let parent = branched.pop().expect("Tokens have matched brackets");
+                lines.push(std::mem::replace(&mut line, parent));
+            }
+            result if RESULTS.contains(&result) => {
+                if branched.is_empty() {
+                    break;
+                }
+            }
+            _ => line.push(token),
+        }
+    }
+    lines.push(line);
+    lines.retain(|line| !line.is_empty());
+    Ok(lines)
+}
+
+/// Splits the first game of a PGN file into its tags and movetext.
+fn split_game(text: &str) -> Result<(Vec<(String, String)>, String), String> {
+    let mut tags = Vec::new();
+    let mut movetext = String::new();
+    for line in text.lines() {
+        let line = line.trim();
+        if line.starts_with('[') {
+            if !movetext.trim().is_empty() {
+                // The tags of the next game
+                break;
+            }
+            tags.push(parse_tag(line)?);
+        } else if !line.starts_with('%') {
+            movetext.push_str(line);
+            movetext.push('\n');
+        }
+    }
+    Ok((tags, movetext))
+}
+
+/// The moves, variation brackets and results of movetext, without comments, move numbers,
+/// numeric annotations or `!`/`?` suffixes.
+fn tokens(movetext: &str) -> Result<Vec<String>, String> {
+    let mut depth = 0;
+    let mut in_brace_comment = false;
+    let mut in_line_comment = false;
+    let mut cleaned = String::new();
+    for c in movetext.chars() {
+        if in_line_comment {
+            if c == '\n' {
+                in_line_comment = false;
+                cleaned.push(' ');
+            }
+            continue;
+        }
+        if in_brace_comment {
+            if c == '}' {
+                in_brace_comment = false;
+                cleaned.push(' ');
+            }
+            continue;
+        }
+        match c {
+            '{' => in_brace_comment = true,
+            ';' => in_line_comment = true,
+            '(' => {
+                depth += 1;
+                cleaned.push_str(" ( ");
+            }
+            ')' if depth == 0 => return Err("Unmatched ) in PGN".to_string()),
+            ')' => {
+                depth -= 1;
+                cleaned.push_str(" ) ");
+            }
+            _ => cleaned.push(c),
+        }
+    }
+    if in_brace_comment || depth > 0 {
+        return Err("Unterminated comment or variation in PGN".to_string());
+    }
+
+    let mut tokens = Vec::new();
+    for token in cleaned.split_whitespace() {
+        if token.starts_with('$') {
+            continue;
+        }
+        if RESULTS.contains(&token) {
+            tokens.push(token.to_string());
+            continue;
+        }
+        // Move numbers can be stuck to the move, as in "1.e4"
+        let token = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        let token = token.trim_end_matches(['!', '?']);
+        if !token.is_empty() {
+            tokens.push(token.to_string());
+        }
+    }
+    Ok(tokens)
+}
+
+/// Reads every game of a PGN file, such as an opening suite.
+pub fn parse_all(text: &str) -> Result<Vec<Pgn>, String> {
+    let mut games = Vec::new();
+    let mut game = String::new();
+    let mut in_movetext = false;
+    for line in text.lines() {
+        let is_tag = line.trim().starts_with('[');
+        if is_tag && in_movetext {
+            games.push(parse(&game)?);
+            game.clear();
+            in_movetext = false;
+        }
+        in_movetext |= !is_tag && !line.trim().is_empty();
+        game.push_str(line);
+        game.push('\n');
+    }
+    if !game.trim().is_empty() {
+        games.push(parse(&game)?);
+    }
+    Ok(games)
+}
+
+/// A time control as in the `TimeControl` tag, seconds and increment like `300+3`.
+pub fn time_control(time_control: TimeControl) -> String {
+    format!(
+        "{}+{}",
+        time_control.base.as_secs(),
+        time_control.increment.as_secs()
+    )
+}
+
+/// Writes a game. `first_ply` is the number of plies played before the first move, which
+/// sets the move numbers.
+pub fn write(
+    tags: &[(String, String)],
+    first_ply: usize,
+    moves: &[String],
+    result: &str,
+    comment: Option<&str>,
+) -> String {
+    let mut text = String::new();
+    for (name, value) in tags {
+        text.push_str(&format!("[{} \"{}\"]\n", name, value.replace('"', "\\\"")));
+    }
+    text.push('\n');
+
+    let mut line = String::new();
+    let mut push = |token: String, text: &mut String| {
+        if !line.is_empty() && line.len() + token.len() >= 80 {
+            text.push_str(&line);
+            text.push('\n');
+            line.clear();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(&token);
+    };
+    for (i, san) in moves.iter().enumerate() {
+        let ply = first_ply + i;
+        if ply.is_multiple_of(2) {
+            push(format!("{}. {}", ply / 2 + 1, san), &mut text);
+        } else if i == 0 {
+            push(format!("{}... {}", ply / 2 + 1, san), &mut text);
+        } else {
+            push(san.clone(), &mut text);
+        }
+    }
+    if let Some(comment) = comment {
+        push(format!("{{{}}}", comment.replace('}', ")")), &mut text);
+    }
+    push(result.to_string(), &mut text);
+    text.push_str(&line);
+    text.push_str("\n\n");
+    text
+}
+
+fn parse_tag(line: &str) -> Result<(String, String), String> {
+    let inner = line
+        .strip_prefix('[')
+        .and_then(|l| l.strip_suffix(']'))
+        .ok_or_else(|| format!("Malformed PGN tag {}", line))?;
+    let (name, value) = inner
+        .split_once(' ')
+        .ok_or_else(|| format!("Malformed PGN tag {}", line))?;
+    let value = value.trim();
+    let value = value
+        .strip_prefix('"')
+        .and_then(|v| v.strip_suffix('"'))
+        .unwrap_or(value)
+        .replace("\\\"", "\"");
+    Ok((name.to_string(), value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_movetext() {
+        let pgn = parse(
+            r#"[Event "Club night"]
+[White "A"]
+[Black "B"]
+
+1. e4 {best by test} e5 2.Nf3 (2. f4 exf4) Nc6!? 3. Bb5 $1 a6
+; a line comment
+4. Ba4 1-0
+
+[Event "Second game"]
+1. d4 *"#,
+        )
+        .unwrap();
+        assert_eq!(pgn.tag("Event"), Some("Club night"));
+        assert_eq!(pgn.tag("White"), Some("A"));
+        assert_eq!(
+            pgn.moves,
+            vec!["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4"]
+        );
+    }
+
+    #[test]
+    fn writes_and_reads_back() {
+        let tags = vec![
+            ("White".to_string(), "Engine \"A\"".to_string()),
+            ("Result".to_string(), "0-1".to_string()),
+        ];
+        let moves: Vec<String> = ["e5", "Nf3", "Nc6"].map(String::from).to_vec();
+        let text = write(&tags, 1, &moves, "0-1", Some("adjudicated"));
+        assert!(text.contains("1... e5 2. Nf3 Nc6 {adjudicated} 0-1"));
+
+        let games = parse_all(&format!("{}{}", text, text)).unwrap();
+        assert_eq!(games.len(), 2);
+        assert_eq!(games[1].tag("White"), Some("Engine \"A\""));
+        assert_eq!(games[1].moves, moves);
+    }
+
+    #[test]
+    fn reads_every_line() {
+        let lines = parse_lines(
+            "1. e4 e5 (1... c5 2. Nf3 (2. c3) d6) 2. Nf3 Nc6 (2... d6 {Philidor}) 3. Bb5 *",
+        )
+        .unwrap();
+        let lines: Vec<String> = lines.iter().map(|line| line.join(" ")).collect();
+        assert_eq!(
+            lines,
+            [
+                "e4 c5 c3",
+                "e4 c5 Nf3 d6",
+                "e4 e5 Nf3 d6",
+                "e4 e5 Nf3 Nc6 Bb5"
+            ]
+        );
+        assert!(parse_lines("(1. d4) 1. e4").is_err());
+    }
+
+    #[test]
+    fn rejects_unterminated_comment() {
+        assert!(parse("1. e4 { oops").is_err());
+    }
+}
@@ -0,0 +1,257 @@
+use std::{
+    collections::VecDeque,
+    iter::zip,
+    sync::mpsc::{channel, Receiver, TryRecvError},
+    time::Duration,
+};
+
+use rand::seq::SliceRandom;
+
+use super::{
+    engine, fen, notation,
+    uci::{self, Score, UciEngine},
+    variant::Variant,
+    Board, PieceType, Side,
+};
+use crate::control::{Move, PlayerKind, VariantKind};
+
+/// What a player is shown when asked for a move.
+pub struct Turn<'a> {
+    pub board: &'a Board,
+    pub side: Side,
+    pub turn_count: usize,
+    /// Time left on the side's clock, if the game is timed
+    pub time_left: Option<Duration>,
+    pub rules: &'a dyn Variant,
+    /// The pieces the side can drop, empty in variants without drops
+    pub pocket: &'a [PieceType],
+    /// Every move of the game so far in algebraic notation
+    pub history: &'a [String],
+    /// The variant played, for players that have to be told the rules
+    pub variant: VariantKind,
+    /// The board before the first move and after every move since
+    pub positions: &'a [Board],
+    /// Every move of the game so far
+    pub moves: &'a [Move],
+}
+
+/// Where the moves for one side come from. The model asks the player of the side to move
+/// whenever it has nothing else to do.
+pub trait Player: Send {
+    /// The move to play, or `None` if there isn't one yet, such as while waiting for the UI
+    /// or the network. An error hands the side over to a human.
+    fn choose_move(&mut self, turn: &Turn) -> Result<Option<Move>, String>;
+
+    /// Whether moves made on the board in the UI are played for this side.
+    fn takes_ui_moves(&self) -> bool {
+        false
+    }
+
+    /// The player's evaluation after its last move, from its own point of view, if it
+    /// gives one.
+    fn score(&self) -> Option<Score> {
+        None
+    }
+}
+
+/// Someone at the terminal, whose moves come from the UI.
+pub struct Human;
+
+impl Player for Human {
+    fn choose_move(&mut self, _: &Turn) -> Result<Option<Move>, String> {
+        Ok(None)
+    }
+
+    fn takes_ui_moves(&self) -> bool {
+        true
+    }
+}
+
+/// The built-in engine.
+pub struct Engine {
+    pub level: u8,
+}
+
+impl Player for Engine {
+    fn choose_move(&mut self, turn: &Turn) -> Result<Option<Move>, String> {
+        // The search doesn't look at drops, so the engine only drops when it can't move
+        engine::choose_move(turn.board, turn.side, self.level, turn.rules, turn.history)
+            .map(|(from, to)| Move::Board(from, to))
+            .or_else(|| {
+                let drops = turn.rules.legal_drops(turn.board, turn.side, turn.pocket);
+                drops
+                    .choose(&mut rand::thread_rng())
+                    .map(|(piece_type, to)| Move::Drop(*piece_type, *to))
+            })
+            .map(Some)
+            .ok_or("No legal moves".to_string())
+    }
+}
+
+/// A UCI engine run as a child process, started when it is first asked for a move.
+pub struct External {
+    command: String,
+    engine: Option<UciEngine>,
+    /// Time to think about each move, worked out from the clock when not set
+    movetime: Option<Duration>,
+    score: Option<Score>,
+}
+
+impl External {
+    pub fn new(command: &str) -> External {
+        External {
+            command: command.to_string(),
+            engine: None,
+            movetime: None,
+            score: None,
+        }
+    }
+
+    pub fn with_movetime(mut self, movetime: Duration) -> External {
+        self.movetime = Some(movetime);
+        self
+    }
+}
+
+impl Player for External {
+    fn choose_move(&mut self, turn: &Turn) -> Result<Option<Move>, String> {
+        let engine = match &mut self.engine {
+            Some(engine) => engine,
+            None => {
+                let mut engine = UciEngine::start(&self.command)?;
+                set_rules(&mut engine, turn.variant)?;
+                self.engine.insert(engine)
+            }
+        };
+        let movetime = match (self.movetime, turn.time_left) {
+            (Some(movetime), _) => movetime,
+            (None, Some(left)) => {
+                (left / 30).clamp(Duration::from_millis(100), Duration::from_secs(5))
+            }
+            (None, None) => Duration::from_secs(1),
+        };
+        // The engine is given the moves so it can see repetitions
+        let start_side = if turn.moves.len().is_multiple_of(2) {
+            turn.side
+        } else {
+            turn.side.flipped()
+        };
+        let start_fen = fen::to_fen(
+            &turn.positions[0],
+            start_side,
+            turn.turn_count - turn.moves.len(),
+        );
+        let chess960 = engine.has_option("UCI_Chess960");
+        let moves: Vec<String> = zip(turn.positions, turn.moves)
+            .map(|(board, mv)| uci::format_game_move(board, *mv, chess960))
+            .collect();
+        let ((from, to), score) = engine.search(&start_fen, &moves, movetime)?;
+        self.score = score;
+        Ok(Some(Move::Board(from, to)))
+    }
+
+    fn score(&self) -> Option<Score> {
+        self.score
+    }
+}
+
+/// Tells the engine the rules of the game. The game castles by Chess960's rules from any
+/// start, so engines that can are told to as well.
+fn set_rules(engine: &mut UciEngine, variant: VariantKind) -> Result<(), String> {
+    if engine.has_option("UCI_Chess960") {
+        engine.set_option("UCI_Chess960", "true")?;
+    }
+    if variant == VariantKind::Standard {
+        return Ok(());
+    }
+    if !engine.allows("UCI_Variant", variant.uci_name()) {
+        return Err(format!(
+            "{} doesn't play {}",
+            engine.name(),
+            variant.pgn_name()
+        ));
+    }
+    engine.set_option("UCI_Variant", variant.uci_name())
+}
+
+/// Plays any legal move.
+pub struct Random;
+
+impl Player for Random {
+    fn choose_move(&mut self, turn: &Turn) -> Result<Option<Move>, String> {
+        let mut moves: Vec<Move> = turn
+            .rules
+            .legal_moves(turn.board, turn.side)
+            .into_iter()
+            .map(|(from, to)| Move::Board(from, to))
+            .collect();
+        moves.extend(
+            turn.rules
+                .legal_drops(turn.board, turn.side, turn.pocket)
+                .into_iter()
+                .map(|(piece_type, to)| Move::Drop(piece_type, to)),
+        );
+        moves
+            .choose(&mut rand::thread_rng())
+            .copied()
+            .map(Some)
+            .ok_or("No legal moves".to_string())
+    }
+}
+
+/// The other player of a network game, playing the moves that arrive over the connection.
+/// `net` checks each move is theirs to make and legal before passing it on.
+pub struct Remote {
+    moves: Receiver<Move>,
+}
+
+impl Remote {
+    pub fn new(moves: Receiver<Move>) -> Remote {
+        Remote { moves }
+    }
+}
+
+impl Player for Remote {
+    fn choose_move(&mut self, _: &Turn) -> Result<Option<Move>, String> {
+        match self.moves.try_recv() {
+            Ok(mv) => Ok(Some(mv)),
+            Err(TryRecvError::Empty) => Ok(None),
+            Err(TryRecvError::Disconnected) => Err("Not connected to anyone".to_string()),
+        }
+    }
+}
+
+/// Plays a fixed line of moves in algebraic notation or UCI's long form, such as the
+/// replies in a puzzle. A move that isn't legal, or running out of moves, is an error.
+pub struct Scripted {
+    moves: VecDeque<String>,
+}
+
+impl Scripted {
+    pub fn new(moves: Vec<String>) -> Scripted {
+        Scripted {
+            moves: moves.into(),
+        }
+    }
+}
+
+impl Player for Scripted {
+    fn choose_move(&mut self, turn: &Turn) -> Result<Option<Move>, String> {
+        let text = self.moves.pop_front().ok_or("The line has no more moves")?;
+        notation::resolve(turn.board, turn.rules, turn.side, &text)
+            .map(|(from, to)| Some(Move::Board(from, to)))
+            .ok_or_else(|| format!("{} is not a legal move", text))
+    }
+}
+
+/// The player for `kind`. A remote player made here isn't connected to anyone, `net` seats
+/// one that is for network games.
+pub fn from_kind(kind: &PlayerKind) -> Box<dyn Player> {
+    match kind {
+        PlayerKind::Human => Box::new(Human),
+        PlayerKind::Engine(level) => Box::new(Engine { level: *level }),
+        PlayerKind::External(command) => Box::new(External::new(command)),
+        PlayerKind::Random => Box::new(Random),
+        PlayerKind::Remote => Box::new(Remote::new(channel().1)),
+    }
+}
@@ -0,0 +1,572 @@
+//! Puzzle mode. Puzzles come from a Lichess puzzle CSV, whose moves start with the
+//! opponent's move that sets the puzzle, or from EPD records naming the best move with
+//! `bm`. The solver's rating is kept in `eden_chess/puzzles.toml` in the platform data
+//! directory and moves like an Elo rating, with each puzzle's rating as the opponent's.
+
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use log::{info, warn};
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use super::{fen, notation, player, Model, Side};
+use crate::control::{
+    CBPosition, GameResult, GameSetup, ModelMsg, Move, PuzzleSetup, PuzzleState, PuzzleStatus,
+    StartPosition,
+};
+
+/// The rating of a new solver, and of EPD puzzles, which have none.
+pub const DEFAULT_RATING: u32 = 1500;
+/// The most one puzzle can move the solver's rating.
+const K_FACTOR: f64 = 32.0;
+/// How many of the unplayed puzzles closest to the solver's rating the next is picked from.
+const CANDIDATES: usize = 5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Puzzle {
+    pub id: String,
+    pub fen: String,
+    /// The line from `fen`, in UCI's long form or algebraic notation
+    pub moves: Vec<String>,
+    /// Whether the first move is the opponent's, played to set the puzzle
+    pub opponent_starts: bool,
+    /// Other moves accepted as the solver's first, from an EPD `bm` naming several
+    pub also_best: Vec<String>,
+    pub rating: u32,
+    pub themes: Vec<String>,
+}
+
+impl Puzzle {
+    /// The side that has to find the moves.
+    pub fn solver(&self) -> Side {
+        let to_move = match self.fen.split_whitespace().nth(1) {
+            Some("b") => Side::Black,
+            _ => Side::White,
+        };
+        if self.opponent_starts {
+            to_move.flipped()
+        } else {
+            to_move
+        }
+    }
+
+    /// Whether the move after `ply` moves of the line is the solver's.
+    fn solvers_ply(&self, ply: usize) -> bool {
+        ply.is_multiple_of(2) != self.opponent_starts
+    }
+
+    fn opponent_moves(&self) -> Vec<String> {
+        self.moves
+            .iter()
+            .enumerate()
+            .filter(|(ply, _)| !self.solvers_ply(*ply))
+            .map(|(_, mv)| mv.clone())
+            .collect()
+    }
+}
+
+/// Reads the Lichess puzzle database format, with or without its header:
+/// `PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags`.
+pub fn parse_csv(text: &str) -> Result<Vec<Puzzle>, String> {
+    let mut puzzles = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("PuzzleId") {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(',').collect();
+        if fields.len() < 4 {
+            return Err(format!(
+                "Line {} is not a Lichess puzzle: {:?}",
+                i + 1,
+                line
+            ));
+        }
+        let rating = fields[3]
+            .parse()
+            .map_err(|_| format!("Line {}: {:?} is not a rating", i + 1, fields[3]))?;
+        let themes = fields.get(7).map_or(Vec::new(), |themes| {
+            themes.split_whitespace().map(String::from).collect()
+        });
+        puzzles.push(Puzzle {
+            id: fields[0].to_string(),
+            fen: fields[1].to_string(),
+            moves: fields[2].split_whitespace().map(String::from).collect(),
+            opponent_starts: true,
+            also_best: Vec::new(),
+            rating,
+            themes,
+        });
+    }
+    Ok(puzzles)
+}
+
+/// Reads EPD records such as `<placement> w - - bm Qxf7+; id "WAC.001";`. The solver plays
+/// the side to move and only has to find the best move.
+pub fn parse_epd(text: &str) -> Result<Vec<Puzzle>, String> {
+    let mut puzzles = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        // Placement, side, castling and en passant, then the operations
+        let mut rest = line;
+        let mut position = Vec::new();
+        for _ in 0..4 {
+            let (field, after) = rest
+                .split_once(char::is_whitespace)
+                .ok_or_else(|| format!("Line {} is not an EPD record: {:?}", i + 1, line))?;
+            position.push(field);
+            rest = after.trim_start();
+        }
+        let mut best = Vec::new();
+        let mut id = format!("#{}", i + 1);
+        for operation in rest.split(';') {
+            match operation.trim().split_once(char::is_whitespace) {
+                Some(("bm", moves)) => best = moves.split_whitespace().map(String::from).collect(),
+                Some(("id", name)) => id = name.trim().trim_matches('"').to_string(),
+                _ => {}
+            }
+        }
+        if best.is_empty() {
+            return Err(format!("Line {} has no best move (bm)", i + 1));
+        }
+        puzzles.push(Puzzle {
+            id,
+            fen: format!("{} 0 1", position.join(" ")),
+            moves: best.drain(..1).collect(),
+            opponent_starts: false,
+            also_best: best,
+            rating: DEFAULT_RATING,
+            themes: Vec::new(),
+        });
+    }
+    Ok(puzzles)
+}
+
+/// Reads a puzzle file, as CSV if its name ends in `.csv` and as EPD otherwise. Puzzles
+/// whose position can't be read are left out, and so are those promoting to anything but a
+/// queen, which pawns can't do here.
+pub fn load(path: &Path) -> Result<Vec<Puzzle>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    let is_csv = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("csv"));
+    let puzzles = if is_csv {
+        parse_csv(&text)?
+    } else {
+        parse_epd(&text)?
+    };
+    let puzzles: Vec<Puzzle> = puzzles
+        .into_iter()
+        .filter(|puzzle| match fen::parse(&puzzle.fen) {
+            Ok(_) => true,
+            Err(e) => {
+                warn!("Skipping puzzle {}: {}", puzzle.id, e);
+                false
+            }
+        })
+        .filter(|puzzle| {
+            let underpromotes = puzzle
+                .moves
+                .iter()
+                .chain(&puzzle.also_best)
+                .any(|mv| notation::underpromotes(mv));
+            if underpromotes {
+                warn!(
+                    "Skipping puzzle {}: it promotes to a piece other than a queen",
+                    puzzle.id
+                );
+            }
+            !underpromotes
+        })
+        .collect();
+    if puzzles.is_empty() {
+        return Err(format!("{} has no puzzles", path.display()));
+    }
+    Ok(puzzles)
+}
+
+/// The solver's rating and how many puzzles they have solved and failed.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct PuzzleRecord {
+    pub rating: u32,
+    pub solved: u32,
+    pub failed: u32,
+}
+
+impl Default for PuzzleRecord {
+    fn default() -> Self {
+        PuzzleRecord {
+            rating: DEFAULT_RATING,
+            solved: 0,
+            failed: 0,
+        }
+    }
+}
+
+impl PuzzleRecord {
+    /// The record after a puzzle rated `rating`. The rating moves more the more surprising
+    /// the result was.
+    pub fn after(self, rating: u32, solved: bool) -> PuzzleRecord {
+        let expected = 1.0 / (1.0 + 10f64.powf((rating as f64 - self.rating as f64) / 400.0));
+        let score = if solved { 1.0 } else { 0.0 };
+        let new_rating = self.rating as f64 + K_FACTOR * (score - expected);
+        PuzzleRecord {
+            rating: new_rating.round().max(100.0) as u32,
+            solved: self.solved + solved as u32,
+            failed: self.failed + !solved as u32,
+        }
+    }
+}
+
+pub fn record_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("eden_chess").join("puzzles.toml"))
+}
+
+/// Reads the solver's record, starting a new one if there is none or it can't be read.
+pub fn load_record(path: &Path) -> PuzzleRecord {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return PuzzleRecord::default();
+    };
+    toml::from_str(&contents).unwrap_or_else(|e| {
+        warn!("Puzzle record {} can't be read: {}", path.display(), e);
+        PuzzleRecord::default()
+    })
+}
+
+pub fn save_record(path: &Path, record: &PuzzleRecord) -> Result<(), String> {
+    let contents = toml::to_string(record).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, contents).map_err(|e| e.to_string())
+}
+
+/// The puzzles of a file and the solver's way through them.
+pub struct Session {
+    puzzles: Vec<Puzzle>,
+    /// The puzzles already given, so none comes up again until all have
+    played: HashSet<usize>,
+    current: usize,
+    /// How many moves of the current puzzle's line have been played
+    ply: usize,
+    state: PuzzleState,
+    record: PuzzleRecord,
+    /// Where the record is saved after every puzzle, `None` to not save it
+    record_path: Option<PathBuf>,
+}
+
+impl Session {
+    /// Starts on the puzzles with `theme`, or all of them.
+    pub fn new(
+        puzzles: Vec<Puzzle>,
+        theme: Option<&str>,
+        record_path: Option<PathBuf>,
+    ) -> Result<Session, String> {
+        let puzzles: Vec<Puzzle> = match theme {
+            Some(theme) => puzzles
+                .into_iter()
+                .filter(|p| p.themes.iter().any(|t| t.eq_ignore_ascii_case(theme)))
+                .collect(),
+            None => puzzles,
+        };
+        if puzzles.is_empty() {
+            return Err(format!(
+                "No puzzles have the theme {:?}",
+                theme.unwrap_or_default()
+            ));
+        }
+        let record = record_path.as_deref().map(load_record).unwrap_or_default();
+        Ok(Session {
+            puzzles,
+            played: HashSet::new(),
+            current: 0,
+            ply: 0,
+            state: PuzzleState::Solving,
+            record,
+            record_path,
+        })
+    }
+
+    pub fn puzzle(&self) -> &Puzzle {
+        &self.puzzles[self.current]
+    }
+
+    /// Moves on to an unplayed puzzle close to the solver's rating.
+    fn next(&mut self) -> &Puzzle {
+        if self.played.len() == self.puzzles.len() {
+            self.played.clear();
+        }
+        let mut candidates: Vec<usize> = (0..self.puzzles.len())
+            .filter(|i| !self.played.contains(i))
+            .collect();
+        candidates.sort_by_key(|i| self.puzzles[*i].rating.abs_diff(self.record.rating));
+        candidates.truncate(CANDIDATES);
+        self.current = *candidates
+            .choose(&mut rand::thread_rng())
+            .expect("Some puzzle is unplayed");
+        self.ply = 0;
+        self.state = PuzzleState::Solving;
+        self.puzzle()
+    }
+
+    /// The moves written in the line that are right for the solver now, empty while it is
+    /// the opponent's move or once the puzzle is over.
+    fn answers(&self) -> Vec<&String> {
+        let puzzle = self.puzzle();
+        if self.state != PuzzleState::Solving || !puzzle.solvers_ply(self.ply) {
+            return Vec::new();
+        }
+        let mut answers: Vec<&String> = puzzle.moves.get(self.ply).into_iter().collect();
+        if self.ply == puzzle.opponent_starts as usize {
+            answers.extend(&puzzle.also_best);
+        }
+        answers
+    }
+
+    /// Goes on along the line after a right move, which solves the puzzle at its end.
+    fn advance(&mut self) {
+        self.ply += 1;
+        if self.ply >= self.puzzle().moves.len() {
+            self.finish(PuzzleState::Solved);
+        }
+    }
+
+    fn finish(&mut self, state: PuzzleState) {
+        let solved = state == PuzzleState::Solved;
+        self.record = self.record.after(self.puzzle().rating, solved);
+        self.played.insert(self.current);
+        self.state = state;
+        info!(
+            "Puzzle {} {}, rating now {}",
+            self.puzzle().id,
+            if solved { "solved" } else { "failed" },
+            self.record.rating
+        );
+        if let Some(path) = &self.record_path {
+            if let Err(e) = save_record(path, &self.record) {
+                warn!(
+                    "Could not save the puzzle record to {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    pub fn status(&self) -> PuzzleStatus {
+        let puzzle = self.puzzle();
+        PuzzleStatus {
+            id: puzzle.id.clone(),
+            rating: puzzle.rating,
+            themes: puzzle.themes.clone(),
+            side: puzzle.solver(),
+            state: self.state.clone(),
+            solver_rating: self.record.rating,
+        }
+    }
+}
+
+impl Model {
+    /// Loads the puzzle file of `setup` and sets up the first puzzle.
+    pub(super) fn start_puzzles(&mut self, setup: PuzzleSetup) {
+        let session = load(Path::new(setup.path.trim())).and_then(|puzzles| {
+            Session::new(
+                puzzles,
+                setup.theme.as_deref(),
+                self.puzzle_record_path.clone(),
+            )
+        });
+        match session {
+            Ok(session) => {
+                self.puzzles = Some(session);
+                self.next_puzzle();
+            }
+            Err(e) => {
+                let _ = self.ui_sender.send(ModelMsg::Error(e));
+            }
+        }
+    }
+
+    /// Sets up another puzzle. Moving on from one that isn't over counts as failing it.
+    pub(super) fn next_puzzle(&mut self) {
+        let answer = self.puzzle_answers().first().map(|(_, san)| san.clone());
+        let Some(mut session) = self.puzzles.take() else {
+            let _ = self
+                .ui_sender
+                .send(ModelMsg::Error("No puzzles are loaded".to_string()));
+            return;
+        };
+        if session.state == PuzzleState::Solving {
+            let answer = answer.unwrap_or_else(|| session.puzzle().moves[session.ply].clone());
+            session.finish(PuzzleState::Failed { answer });
+        }
+        let puzzle = session.next().clone();
+        let setup = GameSetup {
+            start: StartPosition::Fen(puzzle.fen.clone()),
+            ..GameSetup::default()
+        };
+        if let Err(e) = self.start_game(&setup, &[]) {
+            let _ = self.ui_sender.send(ModelMsg::Error(format!(
+                "Puzzle {} can't be set up: {}",
+                puzzle.id, e
+            )));
+            self.puzzles = Some(session);
+            return;
+        }
+        self.players.insert(
+            puzzle.solver().flipped(),
+            Box::new(player::Scripted::new(puzzle.opponent_moves())),
+        );
+        self.puzzles = Some(session);
+        let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+    }
+
+    /// Whether a puzzle is on the board and already solved or failed, so no more moves are
+    /// played.
+    pub(super) fn puzzle_over(&self) -> bool {
+        self.puzzles
+            .as_ref()
+            .is_some_and(|session| session.state != PuzzleState::Solving)
+    }
+
+    /// The moves that are right for the solver in the position on the board, with how they
+    /// are written. Empty unless the solver of a puzzle is to move.
+    pub(super) fn puzzle_answers(&self) -> Vec<(Move, String)> {
+        let Some(session) = &self.puzzles else {
+            return Vec::new();
+        };
+        let board = &self.game.board;
+        session
+            .answers()
+            .into_iter()
+            .filter_map(|text| {
+                notation::resolve(board, self.game.rules(), self.game.which_turn, text)
+            })
+            .map(|(from, to): (CBPosition, CBPosition)| {
+                let san = notation::algebraic(board, from, to, false, false);
+                (Move::Board(from, to), san)
+            })
+            .collect()
+    }
+
+    /// Checks a move just played against the puzzle's line, given the `answers` that were
+    /// right before it. Any mate solves the puzzle, whether or not it is the one in the line.
+    pub(super) fn check_puzzle_move(&mut self, mv: Move, answers: Vec<(Move, String)>) {
+        let solver_gave_mate = matches!(
+            self.game.result,
+            Some(GameResult::Checkmate { winner }) if self.puzzles.as_ref().is_some_and(|s| s.puzzle().solver() == winner)
+        );
+        let Some(session) = &mut self.puzzles else {
+            return;
+        };
+        if session.state != PuzzleState::Solving {
+            return;
+        }
+        if !session.puzzle().solvers_ply(session.ply) || answers.iter().any(|(a, _)| *a == mv) {
+            session.advance();
+        } else if solver_gave_mate {
+            session.finish(PuzzleState::Solved);
+        } else {
+            let answer = match answers.into_iter().next() {
+                Some((_, san)) => san,
+                None => session.puzzle().moves[session.ply].clone(),
+            };
+            session.finish(PuzzleState::Failed { answer });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc::channel, time::Instant};
+
+    use super::*;
+    use crate::model::ENGINE_MOVE_DELAY;
+
+    const CSV: &str = "\
+PuzzleId,FEN,Moves,Rating,RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags
+00sHx,q3k1nr/1pp1nQpp/3p4/1P2p3/4P3/B1PP1b2/B5PP/5K2 b k - 0 17,e8d7 a2e6 d7d8 f7f8,1760,80,83,72,mate mateIn2 middlegame short,https://lichess.org/yyznGmXs/black#34,Italian_Game
+";
+
+    #[test]
+    fn reads_puzzle_files() {
+        let puzzles = parse_csv(CSV).unwrap();
+        assert_eq!(puzzles.len(), 1);
+        let puzzle = &puzzles[0];
+        assert_eq!(puzzle.rating, 1760);
+        assert_eq!(puzzle.solver(), Side::White);
+        assert_eq!(puzzle.opponent_moves(), ["e8d7", "d7d8"]);
+        assert!(puzzle.themes.contains(&"mateIn2".to_string()));
+
+        let epd = "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - bm Qxf7# Qxf7+; id \"scholar\";";
+        let puzzles = parse_epd(epd).unwrap();
+        assert_eq!(puzzles[0].id, "scholar");
+        assert_eq!(puzzles[0].moves, ["Qxf7#"]);
+        assert_eq!(puzzles[0].also_best, ["Qxf7+"]);
+        assert_eq!(puzzles[0].solver(), Side::White);
+        assert!(parse_epd("8/8/8/8/8/8/8/K6k w - - id \"none\";").is_err());
+    }
+
+    #[test]
+    fn rating_moves_with_surprise() {
+        let record = PuzzleRecord::default();
+        let even = record.after(DEFAULT_RATING, true);
+        assert_eq!(even.rating, DEFAULT_RATING + 16);
+        assert_eq!(even.solved, 1);
+        assert!(record.after(2000, true).rating > even.rating);
+        assert!(record.after(1000, false).rating < DEFAULT_RATING - 16);
+
+        let puzzles = parse_csv(CSV).unwrap();
+        assert!(Session::new(puzzles.clone(), Some("MATEIN2"), None).is_ok());
+        assert!(Session::new(puzzles, Some("endgame"), None).is_err());
+    }
+
+    #[test]
+    fn plays_the_line() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let opponent_moves = |model: &mut Model| {
+            model.last_move_time = Instant::now() - ENGINE_MOVE_DELAY;
+            model.play_turn();
+        };
+        let state = |model: &Model| model.status().puzzle.unwrap().state;
+
+        model.puzzles = Some(Session::new(parse_csv(CSV).unwrap(), None, None).unwrap());
+        model.next_puzzle();
+        opponent_moves(&mut model);
+        assert_eq!(model.game.move_history, ["Kd7"]);
+        model
+            .try_move(Move::Board("a2".into(), "e6".into()))
+            .unwrap();
+        opponent_moves(&mut model);
+        assert_eq!(state(&model), PuzzleState::Solving);
+        model
+            .try_move(Move::Board("f7".into(), "f8".into()))
+            .unwrap();
+        assert_eq!(state(&model), PuzzleState::Solved);
+
+        // The only puzzle comes round again once all have been played
+        model.next_puzzle();
+        opponent_moves(&mut model);
+        model
+            .try_move(Move::Board("f7".into(), "g7".into()))
+            .unwrap();
+        assert_eq!(
+            state(&model),
+            PuzzleState::Failed {
+                answer: "Be6".to_string()
+            }
+        );
+        assert!(!model.takes_ui_moves());
+        assert!(model.status().puzzle.unwrap().solver_rating < DEFAULT_RATING + 16);
+    }
+}
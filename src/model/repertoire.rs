@@ -0,0 +1,466 @@
+//! The opening trainer. A repertoire is a PGN whose main line and variations are the moves
+//! prepared for one side, from the standard start. The trainer plays the other side's moves
+//! along a line and tests the replies, scheduling each position with spaced repetition in
+//! Leitner boxes: a right reply moves the position to a box reviewed less often, a wrong one
+//! back to the first. The schedule is kept in `eden_chess/repertoire.toml` in the platform
+//! data directory.
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::channel,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+
+use super::{notation, pgn, player, Model, Side};
+use crate::control::{CBPosition, GameSetup, ModelMsg, Move, RepertoireSetup, RepertoireStatus};
+
+/// Days until a position in each box is due again.
+const INTERVAL_DAYS: [u64; 6] = [0, 1, 3, 7, 16, 35];
+const DAY: u64 = 24 * 60 * 60;
+
+/// Which box a position is in and when it is next due.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct Card {
+    pub level: usize,
+    /// Seconds since the Unix epoch
+    pub due: u64,
+}
+
+impl Card {
+    fn reviewed(self, now: u64, right: bool) -> Card {
+        let level = if right {
+            (self.level + 1).min(INTERVAL_DAYS.len() - 1)
+        } else {
+            0
+        };
+        Card {
+            level,
+            due: now + INTERVAL_DAYS[level] * DAY,
+        }
+    }
+}
+
+/// The cards of every position reviewed so far, by `position_key`.
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Schedule {
+    #[serde(default)]
+    pub cards: BTreeMap<String, Card>,
+}
+
+impl Schedule {
+    /// When the position is due, a position never reviewed being due from the start.
+    fn due(&self, key: &str) -> u64 {
+        self.cards.get(key).map_or(0, |card| card.due)
+    }
+}
+
+pub fn schedule_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("eden_chess").join("repertoire.toml"))
+}
+
+/// Reads the schedule, starting a new one if there is none or it can't be read.
+pub fn load_schedule(path: &Path) -> Schedule {
+    let Ok(contents) = fs::read_to_string(path) else {
+        return Schedule::default();
+    };
+    toml::from_str(&contents).unwrap_or_else(|e| {
+        warn!(
+            "Repertoire schedule {} can't be read: {}",
+            path.display(),
+            e
+        );
+        Schedule::default()
+    })
+}
+
+pub fn save_schedule(path: &Path, schedule: &Schedule) -> Result<(), String> {
+    let contents = toml::to_string(schedule).map_err(|e| e.to_string())?;
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    fs::write(path, contents).map_err(|e| e.to_string())
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs())
+}
+
+/// A move without its check or mate mark, which PGN files don't always agree on.
+fn bare(san: &str) -> &str {
+    san.trim_end_matches(['+', '#'])
+}
+
+/// Names the position after `moves` for `side`, such as `white: e4 e5 Nf3 Nc6`.
+fn position_key(side: Side, moves: &[String]) -> String {
+    let moves: Vec<&str> = moves.iter().map(|mv| bare(mv)).collect();
+    format!("{}: {}", String::from(side).to_lowercase(), moves.join(" "))
+}
+
+pub struct Repertoire {
+    pub side: Side,
+    /// Every line from the start to where it ends
+    pub lines: Vec<Vec<String>>,
+}
+
+impl Repertoire {
+    /// Whether the move after `ply` moves is the repertoire side's.
+    fn sides_ply(&self, ply: usize) -> bool {
+        ply.is_multiple_of(2) == (self.side == Side::White)
+    }
+
+    fn goes_through(line: &[String], moves: &[String]) -> bool {
+        line.len() >= moves.len() && line.iter().zip(moves).all(|(a, b)| bare(a) == bare(b))
+    }
+
+    /// The side's moves in the repertoire after `moves`.
+    fn replies(&self, moves: &[String]) -> Vec<&String> {
+        if !self.sides_ply(moves.len()) {
+            return Vec::new();
+        }
+        let mut replies: Vec<&String> = Vec::new();
+        for line in &self.lines {
+            if let Some(reply) = line
+                .get(moves.len())
+                .filter(|_| Repertoire::goes_through(line, moves))
+            {
+                if !replies.iter().any(|r| bare(r) == bare(reply)) {
+                    replies.push(reply);
+                }
+            }
+        }
+        replies
+    }
+
+    /// The keys of the positions along `line` where the side is to move.
+    fn positions_of(&self, line: &[String]) -> Vec<String> {
+        (0..line.len())
+            .filter(|ply| self.sides_ply(*ply))
+            .map(|ply| position_key(self.side, &line[..ply]))
+            .collect()
+    }
+
+    fn positions(&self) -> BTreeSet<String> {
+        self.lines
+            .iter()
+            .flat_map(|line| self.positions_of(line))
+            .collect()
+    }
+}
+
+/// A repertoire being drilled, one line at a time.
+pub struct Session {
+    repertoire: Repertoire,
+    schedule: Schedule,
+    /// Where the schedule is saved after every review, `None` to not save it
+    schedule_path: Option<PathBuf>,
+    /// The line being played, which changes when the side picks another repertoire move
+    line: usize,
+    mistakes: usize,
+    expected: Option<(CBPosition, CBPosition)>,
+    line_done: bool,
+}
+
+impl Session {
+    pub fn new(repertoire: Repertoire, schedule_path: Option<PathBuf>) -> Session {
+        let schedule = schedule_path
+            .as_deref()
+            .map(load_schedule)
+            .unwrap_or_default();
+        Session {
+            repertoire,
+            schedule,
+            schedule_path,
+            line: 0,
+            mistakes: 0,
+            expected: None,
+            line_done: false,
+        }
+    }
+
+    /// Starts on the line whose positions are most overdue.
+    fn next_line(&mut self) {
+        let line_due = |line: &Vec<String>| {
+            self.repertoire
+                .positions_of(line)
+                .iter()
+                .map(|key| self.schedule.due(key))
+                .min()
+                .unwrap_or(u64::MAX)
+        };
+        self.line = (0..self.repertoire.lines.len())
+            .min_by_key(|i| line_due(&self.repertoire.lines[*i]))
+            .unwrap_or(0);
+        self.mistakes = 0;
+        self.expected = None;
+        self.line_done = false;
+    }
+
+    /// Follows the line through `moves`, switching to another if the side chose a different
+    /// repertoire move, and gives the other side's moves left in it.
+    fn follow(&mut self, moves: &[String]) -> Vec<String> {
+        let lines = &self.repertoire.lines;
+        if !Repertoire::goes_through(&lines[self.line], moves) {
+            match lines
+                .iter()
+                .position(|line| Repertoire::goes_through(line, moves))
+            {
+                Some(line) => self.line = line,
+                None => {
+                    self.line_done = true;
+                    return Vec::new();
+                }
+            }
+        }
+        let line = &self.repertoire.lines[self.line];
+        self.line_done = moves.len() >= line.len();
+        (moves.len()..line.len())
+            .filter(|ply| !self.repertoire.sides_ply(*ply))
+            .map(|ply| line[ply].clone())
+            .collect()
+    }
+
+    /// Records a reply in the position after `moves`. A right reply to a position that
+    /// isn't due yet leaves its card as it is.
+    fn review(&mut self, moves: &[String], right: bool) {
+        let key = position_key(self.repertoire.side, moves);
+        let now = now();
+        if right && self.schedule.due(&key) > now {
+            return;
+        }
+        let card = self.schedule.cards.get(&key).copied().unwrap_or_default();
+        self.schedule.cards.insert(key, card.reviewed(now, right));
+        if let Some(path) = &self.schedule_path {
+            if let Err(e) = save_schedule(path, &self.schedule) {
+                warn!(
+                    "Could not save the repertoire schedule to {}: {}",
+                    path.display(),
+                    e
+                );
+            }
+        }
+    }
+
+    pub fn status(&self) -> RepertoireStatus {
+        let positions = self.repertoire.positions();
+        let now = now();
+        RepertoireStatus {
+            side: self.repertoire.side,
+            due: positions
+                .iter()
+                .filter(|key| self.schedule.due(key) <= now)
+                .count(),
+            positions: positions.len(),
+            mistakes: self.mistakes,
+            expected: self.expected,
+            line_done: self.line_done,
+        }
+    }
+}
+
+impl Model {
+    /// Loads the repertoire of `setup` and starts on the line most due for review.
+    pub(super) fn start_repertoire(&mut self, setup: RepertoireSetup) {
+        let path = setup.path.trim();
+        let lines = fs::read_to_string(path)
+            .map_err(|e| format!("Could not read {}: {}", path, e))
+            .and_then(|text| pgn::parse_lines(&text))
+            .and_then(|lines| {
+                if lines.is_empty() {
+                    return Err(format!("{} has no moves", path));
+                }
+                // A model of its own checks every line is legal without touching the game
+                let (send, _) = channel();
+                let (_, recv) = channel();
+                let mut scratch = Model::new(send, recv);
+                for line in &lines {
+                    scratch
+                        .start_game(&GameSetup::default(), line)
+                        .map_err(|e| format!("{}: {}", path, e))?;
+                }
+                Ok(lines)
+            });
+        match lines {
+            Ok(lines) => {
+                let repertoire = Repertoire {
+                    side: setup.side,
+                    lines,
+                };
+                self.repertoire = Some(Session::new(
+                    repertoire,
+                    self.repertoire_schedule_path.clone(),
+                ));
+                self.next_line();
+            }
+            Err(e) => {
+                let _ = self.ui_sender.send(ModelMsg::Error(e));
+            }
+        }
+    }
+
+    /// Starts again from the beginning on the line most due for review.
+    pub(super) fn next_line(&mut self) {
+        let Some(mut session) = self.repertoire.take() else {
+            let _ = self
+                .ui_sender
+                .send(ModelMsg::Error("No repertoire is loaded".to_string()));
+            return;
+        };
+        session.next_line();
+        if let Err(e) = self.start_game(&GameSetup::default(), &[]) {
+            error!("Could not start a repertoire line: {}", e);
+        }
+        self.repertoire = Some(session);
+        self.script_repertoire_line();
+        let _ = self.ui_sender.send(ModelMsg::GameStatus(self.status()));
+    }
+
+    /// Gives the other side the rest of its moves along the line being drilled.
+    fn script_repertoire_line(&mut self) {
+        let history = self.game.move_history.clone();
+        let Some(session) = &mut self.repertoire else {
+            return;
+        };
+        let moves = session.follow(&history);
+        let side = session.repertoire.side.flipped();
+        self.players
+            .insert(side, Box::new(player::Scripted::new(moves)));
+    }
+
+    pub(super) fn repertoire_line_done(&self) -> bool {
+        self.repertoire
+            .as_ref()
+            .is_some_and(|session| session.line_done)
+    }
+
+    /// The repertoire moves in the position on the board, empty unless the repertoire's side
+    /// is to move in a line being drilled.
+    pub(super) fn repertoire_replies(&self) -> Vec<Move> {
+        let Some(session) = &self.repertoire else {
+            return Vec::new();
+        };
+        let board = &self.game.board;
+        session
+            .repertoire
+            .replies(&self.game.move_history)
+            .into_iter()
+            .filter_map(|san| {
+                notation::resolve(board, self.game.rules(), self.game.which_turn, san)
+            })
+            .map(|(from, to)| Move::Board(from, to))
+            .collect()
+    }
+
+    /// Checks a move just played against the repertoire, given its `replies` from before the
+    /// move. A wrong move is taken back and the wanted one shown, to be played instead.
+    pub(super) fn check_repertoire_move(&mut self, mv: Move, replies: Vec<Move>) {
+        let mut before = self.game.move_history.clone();
+        before.pop();
+        let Some(session) = &mut self.repertoire else {
+            return;
+        };
+        if session.line_done {
+            return;
+        }
+        if !session.repertoire.sides_ply(before.len()) {
+            // The other side's move from the script
+            session.follow(&self.game.move_history);
+            return;
+        }
+        if replies.contains(&mv) {
+            // After a mistake the wanted move was already counted as wrong
+            if session.expected.take().is_none() {
+                session.review(&before, true);
+            }
+            self.script_repertoire_line();
+            return;
+        }
+        session.review(&before, false);
+        session.mistakes += 1;
+        session.expected = replies.first().map(|reply| reply.squares());
+        let session = self.repertoire.take();
+        if let Err(e) = self.start_game(&GameSetup::default(), &before) {
+            error!("Could not take back a move off the repertoire: {}", e);
+        }
+        self.repertoire = session;
+        self.script_repertoire_line();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::model::ENGINE_MOVE_DELAY;
+
+    const REPERTOIRE: &str = "1. e4 e5 (1... c5 2. Nf3) 2. Nf3 Nc6 3. Bb5 *";
+
+    #[test]
+    fn drills_lines() {
+        let (model_send, _model_recv) = channel();
+        let (_ui_send, ui_recv) = channel();
+        let mut model = Model::new(model_send, ui_recv);
+        let opponent_moves = |model: &mut Model| {
+            model.last_move_time = Instant::now() - ENGINE_MOVE_DELAY;
+            model.play_turn();
+        };
+        let status = |model: &Model| model.status().repertoire.unwrap();
+
+        let repertoire = Repertoire {
+            side: Side::White,
+            lines: pgn::parse_lines(REPERTOIRE).unwrap(),
+        };
+        assert_eq!(repertoire.positions().len(), 4);
+        model.repertoire = Some(Session::new(repertoire, None));
+        model.next_line();
+        assert_eq!(status(&model).due, 4);
+
+        model
+            .try_move(Move::Board("d2".into(), "d4".into()))
+            .unwrap();
+        assert!(model.game.move_history.is_empty(), "taken back");
+        assert_eq!(
+            status(&model).expected,
+            Some(("e2".into(), "e4".into())),
+            "the wanted move is shown"
+        );
+        model
+            .try_move(Move::Board("e2".into(), "e4".into()))
+            .unwrap();
+        assert_eq!(status(&model).expected, None);
+        opponent_moves(&mut model);
+        // The first line is the one with 1... c5
+        assert_eq!(model.game.move_history, ["e4", "c5"]);
+        model
+            .try_move(Move::Board("g1".into(), "f3".into()))
+            .unwrap();
+        let done = status(&model);
+        assert!(done.line_done);
+        assert_eq!(done.mistakes, 1);
+        assert_eq!(
+            done.due, 3,
+            "only the position after 1. e4 c5 was right first time"
+        );
+        assert!(!model.takes_ui_moves());
+
+        model.next_line();
+        assert!(!status(&model).line_done);
+        opponent_moves(&mut model);
+        assert!(model.game.move_history.is_empty(), "white moves first");
+    }
+
+    #[test]
+    fn boxes_space_out_reviews() {
+        let card = Card::default().reviewed(0, true).reviewed(DAY, true);
+        assert_eq!(card.level, 2);
+        assert_eq!(card.due, DAY + 3 * DAY);
+        assert_eq!(card.reviewed(10, false), Card { level: 0, due: 10 });
+    }
+}
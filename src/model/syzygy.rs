@@ -0,0 +1,1146 @@
+//! Syzygy endgame tables, read from a local directory: the `.rtbw` files of wins, draws and
+//! losses and the `.rtbz` files of the distance to zeroing, the plies until the winning side
+//! next captures or moves a pawn. Positions are turned into indices into the compressed
+//! tables the way the tables' generator does it. The tables know underpromotions and en
+//! passant, which boards here never have, so only the moves of this crate's own rules are
+//! looked at.
+
+use std::{
+    collections::HashMap,
+    env, fs,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use lazy_static::lazy_static;
+use log::{info, warn};
+
+use super::{
+    tablebase::{Probe, Tablebase, Wdl},
+    variant::{Standard, Variant},
+    Board, PieceType, Side,
+};
+use crate::control::CBPosition;
+
+/// The most pieces on the board the tables go up to.
+const MAX_PIECES: usize = 7;
+
+// Flags of each compressed table
+const STM: u8 = 1;
+const MAPPED: u8 = 2;
+const WIN_PLIES: u8 = 4;
+const LOSS_PLIES: u8 = 8;
+const WIDE: u8 = 16;
+const SINGLE_VALUE: u8 = 128;
+
+// Results as the tables store them, for the side to move
+const LOSS: i32 = -2;
+const BLESSED_LOSS: i32 = -1;
+const DRAW: i32 = 0;
+const CURSED_WIN: i32 = 1;
+const WIN: i32 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Wdl,
+    Dtz,
+}
+
+impl Kind {
+    fn extension(&self) -> &'static str {
+        match self {
+            Kind::Wdl => "rtbw",
+            Kind::Dtz => "rtbz",
+        }
+    }
+
+    fn magic(&self) -> [u8; 4] {
+        match self {
+            Kind::Wdl => [0x71, 0xE8, 0x23, 0x5D],
+            Kind::Dtz => [0xD7, 0x66, 0x0C, 0xA5],
+        }
+    }
+}
+
+fn rank(square: usize) -> usize {
+    square / 8
+}
+
+fn file(square: usize) -> usize {
+    square % 8
+}
+
+/// How far `square` is above the a1-h8 diagonal, negative below it.
+fn off_diagonal(square: usize) -> isize {
+    rank(square) as isize - file(square) as isize
+}
+
+fn touching(a: usize, b: usize) -> bool {
+    rank(a).abs_diff(rank(b)) <= 1 && file(a).abs_diff(file(b)) <= 1
+}
+
+/// The numbering of squares and groups of pieces the indices are built from.
+struct Encoding {
+    /// The squares below the a1-h8 diagonal, from 0 to 27
+    b1h1h7: [u64; 64],
+    /// The squares of the a1-d1-d4 triangle, from 0 to 9 with the diagonal last
+    a1d1d4: [u64; 64],
+    /// The 462 placings of two kings with the first in the a1-d1-d4 triangle, by the first
+    /// king's `a1d1d4` and the second king's square
+    kings: [[u64; 64]; 10],
+    /// The ways to pick `k` of `n`, by `k` and `n`
+    binomial: [[u64; 64]; MAX_PIECES],
+    /// The squares pawns can stand on, from 47 down, with the one nearest the edge and
+    /// lowest leading the others
+    pawns: [u64; 64],
+    /// Where the leading pawns' indices start, by the number of them and the leader's square
+    lead_pawn_index: [[u64; 64]; MAX_PIECES],
+    /// The number of leading pawn placings, by the number of them and the leader's file
+    lead_pawn_placings: [[u64; 4]; MAX_PIECES],
+}
+
+impl Encoding {
+    fn new() -> Encoding {
+        let mut encoding = Encoding {
+            b1h1h7: [0; 64],
+            a1d1d4: [0; 64],
+            kings: [[0; 64]; 10],
+            binomial: [[0; 64]; MAX_PIECES],
+            pawns: [0; 64],
+            lead_pawn_index: [[0; 64]; MAX_PIECES],
+            lead_pawn_placings: [[0; 4]; MAX_PIECES],
+        };
+        let mut code = 0;
+        for square in (0..64).filter(|s| off_diagonal(*s) < 0) {
+            encoding.b1h1h7[square] = code;
+            code += 1;
+        }
+
+        let mut diagonal = Vec::new();
+        code = 0;
+        for square in [0, 1, 2, 3, 9, 10, 11, 18, 19, 27] {
+            if off_diagonal(square) < 0 {
+                encoding.a1d1d4[square] = code;
+                code += 1;
+            } else {
+                diagonal.push(square);
+            }
+        }
+        for square in diagonal {
+            encoding.a1d1d4[square] = code;
+            code += 1;
+        }
+
+        // A first king on the diagonal keeps the second on or below it, and placings with
+        // both on the diagonal come last
+        let mut both_on_diagonal = Vec::new();
+        code = 0;
+        for index in 0..10 {
+            // Squares outside the triangle are 0 as well as b1
+            for first in (0..28).filter(|s| encoding.a1d1d4[*s] == index && (index > 0 || *s == 1))
+            {
+                for second in 0..64 {
+                    if touching(first, second)
+                        || (off_diagonal(first) == 0 && off_diagonal(second) > 0)
+                    {
+                        continue;
+                    }
+                    if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                        both_on_diagonal.push((index as usize, second));
+                    } else {
+                        encoding.kings[index as usize][second] = code;
+                        code += 1;
+                    }
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            encoding.kings[index][second] = code;
+            code += 1;
+        }
+
+        encoding.binomial[0][0] = 1;
+        for n in 1..64 {
+            for k in 0..MAX_PIECES.min(n + 1) {
+                encoding.binomial[k][n] = if k > 0 {
+                    encoding.binomial[k - 1][n - 1]
+                } else {
+                    0
+                } + if k < n {
+                    encoding.binomial[k][n - 1]
+                } else {
+                    0
+                };
+            }
+        }
+
+        let mut available = 48;
+        for leading in 1..MAX_PIECES {
+            for file in 0..4 {
+                let mut index = 0;
+                for rank in 1..7 {
+                    let square = rank * 8 + file;
+                    if leading == 1 {
+                        encoding.pawns[square] = available - 1;
+                        encoding.pawns[square ^ 7] = available - 2;
+                        available -= 2;
+                    }
+                    encoding.lead_pawn_index[leading][square] = index;
+                    index += encoding.binomial[leading - 1][encoding.pawns[square] as usize];
+                }
+                encoding.lead_pawn_placings[leading][file] = index;
+            }
+        }
+        encoding
+    }
+}
+
+lazy_static! {
+    static ref ENCODING: Encoding = Encoding::new();
+}
+
+/// The table's code for a piece, white's from 1 to 6 and black's 8 more.
+fn piece_code(side: Side, piece_type: PieceType) -> Option<u8> {
+    let code = match piece_type {
+        PieceType::Pawn => 1,
+        PieceType::Knight => 2,
+        PieceType::Bishop => 3,
+        PieceType::Rook => 4,
+        PieceType::Queen => 5,
+        PieceType::King => 6,
+        _ => return None,
+    };
+    Some(match side {
+        Side::White => code,
+        Side::Black => code + 8,
+    })
+}
+
+/// The pieces on a board of the size the tables know, by square counting from a1 along the
+/// ranks, with the letters of each side's pieces as in table names.
+struct Position {
+    pieces: Vec<(usize, u8)>,
+    white: String,
+    black: String,
+}
+
+impl Position {
+    fn new(board: &Board) -> Option<Position> {
+        if board.len() != 64 {
+            return None;
+        }
+        let mut pieces = Vec::new();
+        for piece in board.values().flatten() {
+            let pos = piece.current_pos;
+            let square = (pos.row - 1) * 8 + (pos.col as u8 - b'a') as usize;
+            pieces.push((square, piece_code(piece.side, piece.piece_type)?));
+        }
+        if pieces.len() > MAX_PIECES {
+            return None;
+        }
+        pieces.sort_unstable();
+        // Table names go from the king down to the pawns
+        let letters = |colour: u8| -> String {
+            let mut codes: Vec<u8> = pieces
+                .iter()
+                .map(|(_, code)| *code)
+                .filter(|code| code & 8 == colour)
+                .collect();
+            codes.sort_unstable_by(|a, b| b.cmp(a));
+            codes
+                .iter()
+                .map(|code| b" PNBRQK"[(code & 7) as usize] as char)
+                .collect()
+        };
+        Some(Position {
+            white: letters(0),
+            black: letters(8),
+            pieces,
+        })
+    }
+}
+
+/// The name Syzygy gives the endgame on the board, such as `KRPvKR`, with the side with more
+/// material first, or `None` if there can't be a table for it.
+pub fn syzygy_name(board: &Board) -> Option<String> {
+    let position = Position::new(board)?;
+    let material = |side: Side| -> isize {
+        board
+            .values()
+            .flatten()
+            .filter(|p| p.side == side)
+            .map(|p| p.piece_type.value())
+            .sum()
+    };
+    Some(if material(Side::Black) > material(Side::White) {
+        format!("{}v{}", position.black, position.white)
+    } else {
+        format!("{}v{}", position.white, position.black)
+    })
+}
+
+/// A value read from a table.
+enum Stored {
+    Value(i32),
+    /// DTZ tables only store one side to move, and this is the other
+    OtherSide,
+}
+
+/// How one side to move, and with pawns one file of the leading pawn, is compressed.
+#[derive(Default)]
+struct Pairs {
+    flags: u8,
+    min_sym_len: u8,
+    block_size: usize,
+    /// Values between the entries of the sparse index
+    span: u64,
+    num_blocks: usize,
+    block_length_size: usize,
+    sparse_index_size: usize,
+    // Offsets into the file
+    lowest_sym: usize,
+    btree: usize,
+    sparse_index: usize,
+    block_length: usize,
+    data: usize,
+    /// The lowest code of each Huffman code length, padded to 64 bits
+    base64: Vec<u64>,
+    /// The number of values each symbol stands for, less one
+    symlen: Vec<u8>,
+    /// The pieces in the order they are grouped in
+    pieces: [u8; MAX_PIECES],
+    /// What each group's index is multiplied by
+    group_idx: [u64; MAX_PIECES + 1],
+    /// The pieces in each group, ended by 0
+    group_len: [usize; MAX_PIECES + 1],
+    /// Where the DTZ values of each result start in the value map
+    map_idx: [usize; 4],
+}
+
+/// One table file, read whole.
+struct Table {
+    data: Vec<u8>,
+    kind: Kind,
+    pieces: usize,
+    has_pawns: bool,
+    /// Whether either side has a piece other than the king that it has only one of
+    unique: bool,
+    /// The pawns of the leading side, the side with fewer if both have some, and the other's
+    pawns: [usize; 2],
+    /// Both sides have the same pieces
+    symmetric: bool,
+    /// By side to move and, with pawns, the leading pawn's file
+    pairs: Vec<Vec<Pairs>>,
+    /// Where the DTZ value map starts
+    map: usize,
+}
+
+impl Table {
+    fn byte(&self, at: usize) -> Option<u8> {
+        self.data.get(at).copied()
+    }
+
+    fn u16_le(&self, at: usize) -> Option<u16> {
+        Some(u16::from_le_bytes(
+            self.data.get(at..at + 2)?.try_into().ok()?,
+        ))
+    }
+
+    fn u32_le(&self, at: usize) -> Option<u32> {
+        Some(u32::from_le_bytes(
+            self.data.get(at..at + 4)?.try_into().ok()?,
+        ))
+    }
+
+    fn u32_be(&self, at: usize) -> Option<u32> {
+        Some(u32::from_be_bytes(
+            self.data.get(at..at + 4)?.try_into().ok()?,
+        ))
+    }
+
+    fn u64_be(&self, at: usize) -> Option<u64> {
+        Some(u64::from_be_bytes(
+            self.data.get(at..at + 8)?.try_into().ok()?,
+        ))
+    }
+
+    /// The two symbols a symbol of the Huffman tree pairs up.
+    fn children(&self, btree: usize, sym: usize) -> Option<(usize, usize)> {
+        let at = btree + 3 * sym;
+        let (a, b, c) = (self.byte(at)?, self.byte(at + 1)?, self.byte(at + 2)?);
+        let left = ((b as usize & 0xF) << 8) | a as usize;
+        let right = ((c as usize) << 4) | (b as usize >> 4);
+        Some((left, right))
+    }
+
+    /// Reads the table `name` of `path`, or `None` if it isn't a table of that kind.
+    fn read(path: &Path, name: &str, kind: Kind) -> Option<Table> {
+        let data = fs::read(path).ok()?;
+        if data.get(..4)? != kind.magic() {
+            warn!("{} is not a Syzygy table", path.display());
+            return None;
+        }
+        let (white, black) = name.split_once('v')?;
+        let count = |side: &str, letter: char| side.chars().filter(|c| *c == letter).count();
+        let (white_pawns, black_pawns) = (count(white, 'P'), count(black, 'P'));
+        let lead_white = black_pawns == 0 || (white_pawns > 0 && black_pawns >= white_pawns);
+        let mut table = Table {
+            data,
+            kind,
+            pieces: white.len() + black.len(),
+            has_pawns: white_pawns + black_pawns > 0,
+            unique: [white, black]
+                .iter()
+                .any(|side| "QRBNP".chars().any(|letter| count(side, letter) == 1)),
+            pawns: if lead_white {
+                [white_pawns, black_pawns]
+            } else {
+                [black_pawns, white_pawns]
+            },
+            symmetric: white == black,
+            pairs: Vec::new(),
+            map: 0,
+        };
+        if table.set_up().is_none() {
+            warn!(
+                "{} is cut short or not a table for {}",
+                path.display(),
+                name
+            );
+            return None;
+        }
+        Some(table)
+    }
+
+    /// Reads the table's layout from the header.
+    fn set_up(&mut self) -> Option<()> {
+        let flags = self.byte(4)?;
+        if (flags & 2 != 0) != self.has_pawns || (flags & 1 == 0) != self.symmetric {
+            return None;
+        }
+        let sides = if self.kind == Kind::Wdl && !self.symmetric {
+            2
+        } else {
+            1
+        };
+        let files = if self.has_pawns { 4 } else { 1 };
+        let both_pawns = self.has_pawns && self.pawns[1] > 0;
+        let mut pairs: Vec<Vec<Pairs>> = (0..sides)
+            .map(|_| (0..files).map(|_| Pairs::default()).collect())
+            .collect();
+
+        let mut at = 5;
+        for file in 0..files {
+            let first = self.byte(at)?;
+            let second = if both_pawns { self.byte(at + 1)? } else { 0xFF };
+            let order = [
+                [(first & 0xF) as usize, (second & 0xF) as usize],
+                [(first >> 4) as usize, (second >> 4) as usize],
+            ];
+            at += 1 + both_pawns as usize;
+            for k in 0..self.pieces {
+                let byte = self.byte(at)?;
+                for (side, pairs) in pairs.iter_mut().enumerate() {
+                    pairs[file].pieces[k] = if side == 1 { byte >> 4 } else { byte & 0xF };
+                }
+                at += 1;
+            }
+            for (side, pairs) in pairs.iter_mut().enumerate() {
+                self.set_groups(&mut pairs[file], order[side], file);
+            }
+        }
+        at += at & 1;
+
+        for file in 0..files {
+            for side in &mut pairs {
+                at = self.set_sizes(&mut side[file], at)?;
+            }
+        }
+
+        if self.kind == Kind::Dtz {
+            self.map = at;
+            for pairs in &mut pairs[0] {
+                if pairs.flags & MAPPED == 0 {
+                    continue;
+                }
+                if pairs.flags & WIDE != 0 {
+                    at += at & 1;
+                    for i in 0..4 {
+                        pairs.map_idx[i] = (at - self.map) / 2 + 1;
+                        at += 2 * self.u16_le(at)? as usize + 2;
+                    }
+                } else {
+                    for i in 0..4 {
+                        pairs.map_idx[i] = at - self.map + 1;
+                        at += self.byte(at)? as usize + 1;
+                    }
+                }
+            }
+            at += at & 1;
+        }
+
+        for file in 0..files {
+            for side in &mut pairs {
+                side[file].sparse_index = at;
+                at += side[file].sparse_index_size * 6;
+            }
+        }
+        for file in 0..files {
+            for side in &mut pairs {
+                side[file].block_length = at;
+                at += side[file].block_length_size * 2;
+            }
+        }
+        for file in 0..files {
+            for side in &mut pairs {
+                at = (at + 0x3F) & !0x3F;
+                side[file].data = at;
+                at += side[file].num_blocks * side[file].block_size;
+            }
+        }
+        if at > self.data.len() {
+            return None;
+        }
+        self.pairs = pairs;
+        Some(())
+    }
+
+    /// Groups the pieces the way they are encoded together, and works out what each group's
+    /// index is multiplied by. Pieces of the same kind make a group, but the first group is
+    /// the leading pawns, or without pawns three unique pieces or else the two kings. The
+    /// groups' order, given by `order` for the first group and the other side's pawns, is
+    /// the table's own.
+    fn set_groups(&self, pairs: &mut Pairs, order: [usize; 2], file: usize) {
+        let encoding = &*ENCODING;
+        let mut n = 0;
+        let mut first_len: isize = if self.has_pawns {
+            0
+        } else if self.unique {
+            3
+        } else {
+            2
+        };
+        pairs.group_len[0] = 1;
+        for i in 1..self.pieces {
+            first_len -= 1;
+            if first_len > 0 || pairs.pieces[i] == pairs.pieces[i - 1] {
+                pairs.group_len[n] += 1;
+            } else {
+                n += 1;
+                pairs.group_len[n] = 1;
+            }
+        }
+        n += 1;
+        pairs.group_len[n] = 0;
+
+        let both_pawns = self.has_pawns && self.pawns[1] > 0;
+        let mut next = if both_pawns { 2 } else { 1 };
+        let mut free = 64 - pairs.group_len[0] - if both_pawns { pairs.group_len[1] } else { 0 };
+        let mut idx: u64 = 1;
+        let mut k = 0;
+        while next < n || k == order[0] || k == order[1] {
+            if k == order[0] {
+                pairs.group_idx[0] = idx;
+                idx *= if self.has_pawns {
+                    encoding.lead_pawn_placings[pairs.group_len[0]][file]
+                } else if self.unique {
+                    31332
+                } else {
+                    462
+                };
+            } else if k == order[1] {
+                pairs.group_idx[1] = idx;
+                idx *= encoding.binomial[pairs.group_len[1]][48 - pairs.group_len[0]];
+            } else {
+                pairs.group_idx[next] = idx;
+                idx *= encoding.binomial[pairs.group_len[next]][free];
+                free -= pairs.group_len[next];
+                next += 1;
+            }
+            k += 1;
+        }
+        pairs.group_idx[n] = idx;
+    }
+
+    /// Reads the sizes of the compressed data and its Huffman code from `at`, returning
+    /// where they end.
+    fn set_sizes(&self, pairs: &mut Pairs, mut at: usize) -> Option<usize> {
+        pairs.flags = self.byte(at)?;
+        at += 1;
+        if pairs.flags & SINGLE_VALUE != 0 {
+            // The one value every position has
+            pairs.min_sym_len = self.byte(at)?;
+            return Some(at + 1);
+        }
+        let groups = pairs.group_len.iter().position(|len| *len == 0)?;
+        let size = pairs.group_idx[groups];
+        pairs.block_size = 1 << self.byte(at)?;
+        pairs.span = 1 << self.byte(at + 1)?;
+        pairs.sparse_index_size = size.div_ceil(pairs.span) as usize;
+        let padding = self.byte(at + 2)? as usize;
+        pairs.num_blocks = self.u32_le(at + 3)? as usize;
+        pairs.block_length_size = pairs.num_blocks + padding;
+        let max_sym_len = self.byte(at + 7)?;
+        pairs.min_sym_len = self.byte(at + 8)?;
+        at += 9;
+        if pairs.min_sym_len == 0 || max_sym_len < pairs.min_sym_len || max_sym_len > 64 {
+            return None;
+        }
+        pairs.lowest_sym = at;
+
+        // Canonical Huffman codes are longer the lower they are, so the lowest code of each
+        // length padded to 64 bits falls as the length grows
+        let lengths = (max_sym_len - pairs.min_sym_len) as usize + 1;
+        pairs.base64 = vec![0; lengths];
+        for i in (0..lengths - 1).rev() {
+            let lowest = self.u16_le(at + 2 * i)? as u64;
+            let next_lowest = self.u16_le(at + 2 * (i + 1))? as u64;
+            pairs.base64[i] = (pairs.base64[i + 1] + lowest).wrapping_sub(next_lowest) / 2;
+        }
+        for (i, base) in pairs.base64.iter_mut().enumerate() {
+            *base = base
+                .checked_shl(64 - i as u32 - pairs.min_sym_len as u32)
+                .unwrap_or(0);
+        }
+        at += 2 * lengths;
+
+        // Each symbol stands for a pair of symbols, down to the values themselves
+        let symbols = self.u16_le(at)? as usize;
+        at += 2;
+        pairs.btree = at;
+        pairs.symlen = vec![0; symbols];
+        let mut visited = vec![false; symbols];
+        for sym in 0..symbols {
+            if !visited[sym] {
+                let len = self.symlen(pairs, sym, &mut visited)?;
+                pairs.symlen[sym] = len;
+            }
+        }
+        Some(at + 3 * symbols + (symbols & 1))
+    }
+
+    fn symlen(&self, pairs: &mut Pairs, sym: usize, visited: &mut [bool]) -> Option<u8> {
+        visited[sym] = true;
+        let (left, right) = self.children(pairs.btree, sym)?;
+        if right == 0xFFF {
+            return Some(0);
+        }
+        for child in [left, right] {
+            if !*visited.get(child)? {
+                let len = self.symlen(pairs, child, visited)?;
+                pairs.symlen[child] = len;
+            }
+        }
+        Some(
+            pairs.symlen[left]
+                .wrapping_add(pairs.symlen[right])
+                .wrapping_add(1),
+        )
+    }
+
+    /// The value stored at `idx`.
+    fn decompress(&self, pairs: &Pairs, idx: u64) -> Option<i32> {
+        if pairs.flags & SINGLE_VALUE != 0 {
+            return Some(pairs.min_sym_len as i32);
+        }
+        // The sparse index points near the block holding `idx`, and the block lengths lead
+        // the rest of the way
+        let k = (idx / pairs.span) as usize;
+        let entry = pairs.sparse_index + 6 * k;
+        let mut block = self.u32_le(entry)? as usize;
+        let mut offset =
+            self.u16_le(entry + 4)? as i64 + (idx % pairs.span) as i64 - (pairs.span / 2) as i64;
+        let block_length = |block: usize| -> Option<i64> {
+            Some(self.u16_le(pairs.block_length + 2 * block)? as i64)
+        };
+        while offset < 0 {
+            block = block.checked_sub(1)?;
+            offset += block_length(block)? + 1;
+        }
+        while offset > block_length(block)? {
+            offset -= block_length(block)? + 1;
+            block += 1;
+        }
+
+        // Read symbols from the start of the block until the one spanning the offset
+        let mut at = pairs.data + block * pairs.block_size;
+        let mut buffer = self.u64_be(at)?;
+        at += 8;
+        let mut buffered: usize = 64;
+        let min_len = pairs.min_sym_len as usize;
+        let mut sym;
+        loop {
+            let mut len = 0;
+            while buffer < *pairs.base64.get(len)? {
+                len += 1;
+            }
+            sym = ((buffer - pairs.base64[len]) >> (64 - len - min_len)) as u16;
+            sym = sym.wrapping_add(self.u16_le(pairs.lowest_sym + 2 * len)?);
+            let values = *pairs.symlen.get(sym as usize)? as i64 + 1;
+            if offset < values {
+                break;
+            }
+            offset -= values;
+            len += min_len;
+            buffer = buffer.checked_shl(len as u32).unwrap_or(0);
+            buffered = buffered.checked_sub(len)?;
+            if buffered <= 32 {
+                buffered += 32;
+                buffer |= (self.u32_be(at)? as u64) << (64 - buffered);
+                at += 4;
+            }
+        }
+
+        // Then down the pairs to the value itself
+        let mut sym = sym as usize;
+        while *pairs.symlen.get(sym)? != 0 {
+            let (left, right) = self.children(pairs.btree, sym)?;
+            let values = *pairs.symlen.get(left)? as i64 + 1;
+            if offset < values {
+                sym = left;
+            } else {
+                offset -= values;
+                sym = right;
+            }
+        }
+        Some(self.children(pairs.btree, sym)?.0 as i32)
+    }
+
+    /// The value for `position` with `side` to move, the side's result in a WDL table and
+    /// the plies to zeroing in a DTZ table, given the result `wdl`. `flipped` is whether
+    /// black has the pieces the table's name puts first.
+    fn probe(&self, position: &Position, side: Side, flipped: bool, wdl: i32) -> Option<Stored> {
+        let encoding = &*ENCODING;
+        // The tables are for white to move with the first side's pieces, or in symmetric
+        // endgames for white to move, so the board is turned around to match
+        let flip = flipped || (self.symmetric && side == Side::Black);
+        let (flip_colour, flip_squares) = if flip { (8, 56) } else { (0, 0) };
+        let stm = flip as usize ^ (side == Side::Black) as usize;
+
+        let mut squares = [0usize; MAX_PIECES];
+        let mut pieces = [0u8; MAX_PIECES];
+        let mut size = 0;
+        let mut file = 0;
+        let lead_colour = self.pairs[0][0].pieces[0] ^ flip_colour;
+        if self.has_pawns {
+            for (square, code) in &position.pieces {
+                if *code == lead_colour {
+                    squares[size] = square ^ flip_squares;
+                    size += 1;
+                }
+            }
+            // The leading pawn is the one nearest the edge and lowest
+            let lead = (0..size).max_by_key(|i| encoding.pawns[squares[*i]])?;
+            squares.swap(0, lead);
+            file = self::file(squares[0]).min(7 - self::file(squares[0]));
+        }
+        let leading = size;
+
+        if self.kind == Kind::Dtz
+            && (self.pairs[0][file].flags & STM) as usize != stm
+            && (!self.symmetric || self.has_pawns)
+        {
+            return Some(Stored::OtherSide);
+        }
+
+        for (square, code) in &position.pieces {
+            if self.has_pawns && *code == lead_colour {
+                continue;
+            }
+            squares[size] = square ^ flip_squares;
+            pieces[size] = code ^ flip_colour;
+            size += 1;
+        }
+        if size < 2 || size != self.pieces {
+            return None;
+        }
+        let pairs = &self.pairs[stm % self.pairs.len()][file];
+
+        // Put the pieces in the table's order
+        for i in leading..size - 1 {
+            for j in i + 1..size {
+                if pairs.pieces[i] == pieces[j] {
+                    pieces.swap(i, j);
+                    squares.swap(i, j);
+                    break;
+                }
+            }
+        }
+
+        // The leading piece goes on the queenside
+        if self::file(squares[0]) > 3 {
+            for square in &mut squares[..size] {
+                *square ^= 7;
+            }
+        }
+
+        let mut idx;
+        if self.has_pawns {
+            idx = encoding.lead_pawn_index[leading][squares[0]];
+            squares[1..leading].sort_by_key(|square| encoding.pawns[*square]);
+            for (i, square) in squares.iter().enumerate().take(leading).skip(1) {
+                idx += encoding.binomial[i][encoding.pawns[*square] as usize];
+            }
+        } else {
+            // And without pawns below the fifth rank, then below the a1-h8 diagonal
+            if rank(squares[0]) > 3 {
+                for square in &mut squares[..size] {
+                    *square ^= 56;
+                }
+            }
+            for i in 0..pairs.group_len[0] {
+                let off = off_diagonal(squares[i]);
+                if off == 0 {
+                    continue;
+                }
+                if off > 0 {
+                    for square in &mut squares[i..size] {
+                        *square = ((*square >> 3) | (*square << 3)) & 63;
+                    }
+                }
+                break;
+            }
+
+            if self.unique {
+                let [a, b, c] = [squares[0], squares[1], squares[2]];
+                let adjust1 = (b > a) as u64;
+                let adjust2 = (c > a) as u64 + (c > b) as u64;
+                let (a, b, c) = (a as u64, b as u64, c as u64);
+                let rank = |square: u64| square / 8;
+                let below = |square: u64| encoding.b1h1h7[square as usize];
+                idx = if off_diagonal(a as usize) != 0 {
+                    (encoding.a1d1d4[a as usize] * 63 + b - adjust1) * 62 + c - adjust2
+                } else if off_diagonal(b as usize) != 0 {
+                    (6 * 63 + rank(a) * 28 + below(b)) * 62 + c - adjust2
+                } else if off_diagonal(c as usize) != 0 {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + rank(a) * 7 * 28
+                        + (rank(b) - adjust1) * 28
+                        + below(c)
+                } else {
+                    6 * 63 * 62
+                        + 4 * 28 * 62
+                        + 4 * 7 * 28
+                        + rank(a) * 7 * 6
+                        + (rank(b) - adjust1) * 6
+                        + (rank(c) - adjust2)
+                };
+            } else {
+                idx = encoding.kings[encoding.a1d1d4[squares[0]] as usize][squares[1]];
+            }
+        }
+
+        // The other groups each take the squares the groups before them left free
+        idx *= pairs.group_idx[0];
+        let mut start = pairs.group_len[0];
+        let mut other_pawns = self.has_pawns && self.pawns[1] > 0;
+        let mut next = 1;
+        while pairs.group_len[next] != 0 {
+            let len = pairs.group_len[next];
+            squares[start..start + len].sort_unstable();
+            let mut n = 0;
+            for i in 0..len {
+                let square = squares[start + i];
+                let taken = squares[..start].iter().filter(|s| square > **s).count();
+                let free = square - taken - if other_pawns { 8 } else { 0 };
+                n += encoding.binomial[i + 1][free];
+            }
+            other_pawns = false;
+            idx += n * pairs.group_idx[next];
+            start += len;
+            next += 1;
+        }
+
+        let value = self.decompress(pairs, idx)?;
+        Some(Stored::Value(match self.kind {
+            Kind::Wdl => value - 2,
+            Kind::Dtz => self.plies(file, value, wdl)?,
+        }))
+    }
+
+    /// Turns a stored DTZ value into plies. Values are numbered by how common they are for
+    /// each result, and some tables count moves rather than plies.
+    fn plies(&self, file: usize, value: i32, wdl: i32) -> Option<i32> {
+        let pairs = &self.pairs[0][file];
+        let mut value = value;
+        if pairs.flags & MAPPED != 0 {
+            let start = pairs.map_idx[[1, 3, 0, 2, 0][(wdl + 2) as usize]];
+            let at = start + value as usize;
+            value = if pairs.flags & WIDE != 0 {
+                self.u16_le(self.map + 2 * at)? as i32
+            } else {
+                self.byte(self.map + at)? as i32
+            };
+        }
+        if (wdl == WIN && pairs.flags & WIN_PLIES == 0)
+            || (wdl == LOSS && pairs.flags & LOSS_PLIES == 0)
+            || wdl == CURSED_WIN
+            || wdl == BLESSED_LOSS
+        {
+            value *= 2;
+        }
+        Some(value + 1)
+    }
+}
+
+/// The plies to zeroing just before a capture or pawn move that leaves `wdl`.
+fn dtz_before_zeroing(wdl: i32) -> i32 {
+    match wdl {
+        WIN => 1,
+        CURSED_WIN => 101,
+        BLESSED_LOSS => -101,
+        LOSS => -1,
+        _ => 0,
+    }
+}
+
+fn zeroing(board: &Board, from: CBPosition, to: CBPosition) -> bool {
+    board.get(&to).is_some_and(|p| p.is_some())
+        || board
+            .get(&from)
+            .copied()
+            .flatten()
+            .is_some_and(|p| p.piece_type == PieceType::Pawn)
+}
+
+fn after(board: &Board, from: CBPosition, to: CBPosition) -> Board {
+    let mut after = board.clone();
+    Standard.make_move(&mut after, from, to);
+    after
+}
+
+/// The Syzygy tables of some directories, read as probes reach them.
+pub struct Syzygy {
+    dirs: Vec<PathBuf>,
+    /// The tables read so far by file name, `None` where there is no such file
+    tables: Mutex<HashMap<String, Option<Arc<Table>>>>,
+}
+
+impl Syzygy {
+    /// The tables of the directories in `path`, separated as in `PATH`, if they have both
+    /// the WDL and DTZ tables for the endgame on `board`.
+    pub fn open(path: &str, board: &Board) -> Option<Syzygy> {
+        let syzygy = Syzygy {
+            dirs: env::split_paths(path).collect(),
+            tables: Mutex::new(HashMap::new()),
+        };
+        let position = Position::new(board)?;
+        if position.pieces.len() > 2
+            && (syzygy.table(Kind::Wdl, &position).is_none()
+                || syzygy.table(Kind::Dtz, &position).is_none())
+        {
+            return None;
+        }
+        info!("Probing {} Syzygy tables in {}", syzygy_name(board)?, path);
+        Some(syzygy)
+    }
+
+    /// The table of `kind` for the position's pieces, and whether it has black's pieces
+    /// first.
+    fn table(&self, kind: Kind, position: &Position) -> Option<(Arc<Table>, bool)> {
+        let white_first = format!("{}v{}", position.white, position.black);
+        let black_first = format!("{}v{}", position.black, position.white);
+        let mut tables = self.tables.lock().ok()?;
+        for (name, flipped) in [(white_first, false), (black_first, true)] {
+            let file = format!("{}.{}", name, kind.extension());
+            let table = tables
+                .entry(file)
+                .or_insert_with_key(|file| {
+                    let path = self
+                        .dirs
+                        .iter()
+                        .map(|dir| dir.join(file))
+                        .find(|p| p.is_file())?;
+                    Table::read(&path, &name, kind).map(Arc::new)
+                })
+                .clone();
+            if let Some(table) = table {
+                return Some((table, flipped));
+            }
+        }
+        None
+    }
+
+    /// The result stored for `side` to move, from -2 for a loss to 2 for a win.
+    fn stored_wdl(&self, board: &Board, side: Side) -> Option<i32> {
+        let position = Position::new(board)?;
+        if position.pieces.len() == 2 {
+            return Some(DRAW);
+        }
+        let (table, flipped) = self.table(Kind::Wdl, &position)?;
+        match table.probe(&position, side, flipped, DRAW)? {
+            Stored::Value(wdl) => Some(wdl),
+            Stored::OtherSide => None,
+        }
+    }
+
+    fn stored_dtz(&self, board: &Board, side: Side, wdl: i32) -> Option<Stored> {
+        let position = Position::new(board)?;
+        let (table, flipped) = self.table(Kind::Dtz, &position)?;
+        table.probe(&position, side, flipped, wdl)
+    }
+
+    /// The result for `side` to move, and whether the best move zeroes so the DTZ tables
+    /// can't be trusted. Tables may store any value where a capture wins, and a draw where
+    /// a capture draws but the position is lost, so captures are looked at too, and with
+    /// `pawn_moves` pawn moves as well.
+    fn search(&self, board: &Board, side: Side, pawn_moves: bool) -> Option<(i32, bool)> {
+        let moves = Standard.legal_moves(board, side);
+        let mut best = LOSS;
+        let mut searched = 0;
+        for (from, to) in &moves {
+            let looked_at = if pawn_moves {
+                zeroing(board, *from, *to)
+            } else {
+                board.get(to).is_some_and(|p| p.is_some())
+            };
+            if !looked_at {
+                continue;
+            }
+            searched += 1;
+            let (value, _) = self.search(&after(board, *from, *to), side.flipped(), false)?;
+            let value = -value;
+            if value > best {
+                best = value;
+                if value >= WIN {
+                    return Some((value, true));
+                }
+            }
+        }
+        let every_move = searched > 0 && searched == moves.len();
+        let value = if every_move {
+            best
+        } else {
+            self.stored_wdl(board, side)?
+        };
+        if best >= value {
+            return Some((best, best > DRAW || every_move));
+        }
+        Some((value, false))
+    }
+
+    /// The plies to zeroing for `side` to move, positive when winning and negative when
+    /// losing, counting past 100 where the fifty-move rule saves the losing side.
+    fn dtz(&self, board: &Board, side: Side) -> Option<i32> {
+        let (wdl, zeroes) = self.search(board, side, true)?;
+        if wdl == DRAW {
+            return Some(0);
+        }
+        if zeroes {
+            return Some(dtz_before_zeroing(wdl));
+        }
+        if let Stored::Value(dtz) = self.stored_dtz(board, side, wdl)? {
+            let cursed = if wdl.abs() == 1 { 100 } else { 0 };
+            return Some((dtz + cursed) * wdl.signum());
+        }
+        // The table has the other side to move, so look one move further
+        let mut best = None;
+        for (from, to) in Standard.legal_moves(board, side) {
+            let dtz = self.move_dtz(board, side, from, to)?;
+            if dtz.signum() == wdl.signum() && best.is_none_or(|best| dtz < best) {
+                best = Some(dtz);
+            }
+        }
+        Some(best.unwrap_or(-1))
+    }
+
+    /// The plies to zeroing for `side` playing the move from `from` to `to`.
+    fn move_dtz(&self, board: &Board, side: Side, from: CBPosition, to: CBPosition) -> Option<i32> {
+        let after = after(board, from, to);
+        let reply = side.flipped();
+        if zeroing(board, from, to) {
+            let (wdl, _) = self.search(&after, reply, false)?;
+            return Some(-dtz_before_zeroing(wdl));
+        }
+        let dtz = -self.dtz(&after, reply)?;
+        if dtz == 1
+            && Standard.in_check(&after, reply)
+            && Standard.legal_moves(&after, reply).is_empty()
+        {
+            return Some(1);
+        }
+        Some(dtz + dtz.signum())
+    }
+}
+
+impl Tablebase for Syzygy {
+    /// The result for `side` to move, with the plies counted to zeroing rather than to mate.
+    /// Wins the fifty-move rule turns into draws count as draws.
+    fn probe(&self, board: &Board, side: Side) -> Option<Probe> {
+        let dtz = self.dtz(board, side)?;
+        let wdl = match dtz {
+            1..=100 => Wdl::Win,
+            -100..=-1 => Wdl::Loss,
+            _ => return Some(Probe::DRAW),
+        };
+        Some(Probe {
+            wdl,
+            plies: dtz.unsigned_abs() as u16,
+        })
+    }
+
+    fn best_move(
+        &self,
+        board: &Board,
+        side: Side,
+        _rules: &dyn Variant,
+    ) -> Option<(CBPosition, CBPosition)> {
+        let mut best = None;
+        for (from, to) in Standard.legal_moves(board, side) {
+            let dtz = self.move_dtz(board, side, from, to)?;
+            let rank = match dtz {
+                1.. => (2, -dtz),
+                0 => (1, 0),
+                _ => (0, -dtz),
+            };
+            if best.is_none_or(|(best, _)| rank > best) {
+                best = Some((rank, (from, to)));
+            }
+        }
+        best.map(|(_, mv)| mv)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::fen;
+
+    #[test]
+    fn encodes_like_the_generator() {
+        let encoding = &*ENCODING;
+        // 462 placings of the kings, numbered without gaps
+        let mut codes: Vec<u64> = (0..10)
+            .flat_map(|index| {
+                (0..64).filter_map(move |square| {
+                    let first =
+                        (0..28).find(|s| encoding.a1d1d4[*s] == index && (index > 0 || *s == 1))?;
+                    let legal = !touching(first, square)
+                        && !(off_diagonal(first) == 0 && off_diagonal(square) > 0);
+                    legal.then_some(encoding.kings[index as usize][square])
+                })
+            })
+            .collect();
+        codes.sort_unstable();
+        assert_eq!(codes, (0..462).collect::<Vec<_>>());
+        // a2 leads every other pawn and e7 none
+        assert_eq!(encoding.pawns[8], 47);
+        assert_eq!(encoding.pawns[52], 0);
+        // A lone leading pawn is any of the 6 squares up its file
+        assert_eq!(encoding.lead_pawn_placings[1], [6; 4]);
+        assert_eq!(encoding.binomial[2][62], 62 * 61 / 2);
+    }
+
+    #[test]
+    fn finds_tables_by_name() {
+        let lucena = fen::parse("1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1").unwrap();
+        assert_eq!(syzygy_name(&lucena.board).unwrap(), "KRPvKR");
+        let black_ahead = fen::parse("8/8/8/8/8/8/1q6/K1k5 w - - 0 1").unwrap();
+        assert_eq!(syzygy_name(&black_ahead.board).unwrap(), "KQvK");
+
+        let dir = env::temp_dir().join("eden_chess_no_syzygy");
+        fs::create_dir_all(&dir).unwrap();
+        assert!(Syzygy::open(dir.to_str().unwrap(), &lucena.board).is_none());
+    }
+}
@@ -0,0 +1,569 @@
+//! Endgame tablebases. Endgames of the two kings and one more queen, rook or pawn are solved
+//! in memory by retrograde analysis under this crate's own rules, the pawn's going on in the
+//! queen's once it promotes. Bigger endgames are read from Syzygy tables by `syzygy`.
+
+use super::{variant::Variant, Board, Piece, PieceType, Side};
+use crate::control::{CBPosition, Geometry};
+
+/// The side to move, both kings and the extra piece, each on one of 64 squares.
+const SIZE: usize = 2 * 64 * 64 * 64;
+
+const KING_STEPS: [(i8, i8); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+const ROOK_LINES: [(i8, i8); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Wdl {
+    Win,
+    Draw,
+    Loss,
+}
+
+/// A position's result for the side to move.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Probe {
+    pub wdl: Wdl,
+    /// Plies until the winning side mates, or with Syzygy tables until it next captures or
+    /// moves a pawn, 0 in a draw
+    pub plies: u16,
+}
+
+impl Probe {
+    pub const DRAW: Probe = Probe {
+        wdl: Wdl::Draw,
+        plies: 0,
+    };
+
+    /// The winning side's moves until it mates, or with Syzygy tables captures or moves a
+    /// pawn, counting that move.
+    pub fn moves(&self) -> u32 {
+        match self.wdl {
+            Wdl::Win => (self.plies as u32).div_ceil(2),
+            Wdl::Loss => self.plies as u32 / 2,
+            Wdl::Draw => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Entry {
+    /// Pieces on the same square, kings touching, the side not to move in check or a pawn
+    /// on the first or last rank
+    Invalid,
+    /// Not won or lost yet, a draw once the analysis is done
+    Unknown,
+    Draw,
+    Win(u16),
+    Loss(u16),
+}
+
+/// A position with the side that has the extra piece moving up the board, whatever its
+/// colour. Squares count from a1 along the ranks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Position {
+    strong_to_move: bool,
+    strong_king: u8,
+    weak_king: u8,
+    piece: u8,
+}
+
+impl Position {
+    fn index(&self) -> usize {
+        (((self.strong_to_move as usize * 64 + self.strong_king as usize) * 64
+            + self.weak_king as usize)
+            * 64)
+            + self.piece as usize
+    }
+
+    fn from_index(index: usize) -> Position {
+        Position {
+            strong_to_move: index / (64 * 64 * 64) == 1,
+            strong_king: (index / (64 * 64) % 64) as u8,
+            weak_king: (index / 64 % 64) as u8,
+            piece: (index % 64) as u8,
+        }
+    }
+}
+
+/// Where a move leads: a position of the table, a bare king each after the piece is taken,
+/// or a position of the queen's table after the pawn promotes.
+enum Next {
+    Position(usize),
+    BareKings,
+    Promotion(usize),
+}
+
+fn rank(square: u8) -> i8 {
+    (square / 8) as i8
+}
+
+fn file(square: u8) -> i8 {
+    (square % 8) as i8
+}
+
+fn offset(square: u8, (ranks, files): (i8, i8)) -> Option<u8> {
+    let (rank, file) = (rank(square) + ranks, file(square) + files);
+    ((0..8).contains(&rank) && (0..8).contains(&file)).then(|| (rank * 8 + file) as u8)
+}
+
+fn touching(a: u8, b: u8) -> bool {
+    a != b && (rank(a) - rank(b)).abs() <= 1 && (file(a) - file(b)).abs() <= 1
+}
+
+/// Tables of perfect play, solved here or read from files.
+pub trait Tablebase: Send + Sync {
+    /// The result for `side` to move, or `None` if the board isn't in the tables.
+    fn probe(&self, board: &Board, side: Side) -> Option<Probe>;
+
+    /// The tables' choice of move for `side`: the quickest conversion when winning, the
+    /// longest resistance when losing and any move that keeps the draw otherwise. `None` if
+    /// the board isn't in the tables or the side can't move.
+    fn best_move(
+        &self,
+        board: &Board,
+        side: Side,
+        rules: &dyn Variant,
+    ) -> Option<(CBPosition, CBPosition)>;
+}
+
+/// The solved endgame of a king and one more piece against a bare king.
+pub struct Table {
+    piece: PieceType,
+    entries: Vec<Entry>,
+    /// The queen's table a pawn's table goes on in once the pawn promotes
+    promoted: Option<Box<Table>>,
+}
+
+impl Table {
+    /// Whether endgames with this extra piece can be solved.
+    pub fn solves(piece: PieceType) -> bool {
+        matches!(piece, PieceType::Queen | PieceType::Rook | PieceType::Pawn)
+    }
+
+    /// Works out every position with `piece` by retrograde analysis: mates are found first,
+    /// along with the quickest promotion to a won queen ending, then each step back wins where
+    /// one move reaches a lost position and loses where every move reaches a won one. What is
+    /// never reached is drawn.
+    pub fn solve(piece: PieceType) -> Table {
+        let promoted = (piece == PieceType::Pawn).then(|| Box::new(Table::solve(PieceType::Queen)));
+        let mut table = Table {
+            piece,
+            entries: vec![Entry::Unknown; SIZE],
+            promoted,
+        };
+        // Moves left to try in each position of the side without the piece, before it is lost
+        let mut unresolved = vec![0u8; SIZE];
+        // Positions to work back from, by the plies to their result. A promotion only wins in
+        // as many plies as its queen ending, so it is seeded there and left if a quicker win
+        // is found first.
+        let mut levels: Vec<Vec<(usize, bool)>> = Vec::new();
+        let push = |levels: &mut Vec<Vec<(usize, bool)>>, plies: u16, index, seed| {
+            let plies = plies as usize;
+            if levels.len() <= plies {
+                levels.resize_with(plies + 1, Vec::new);
+            }
+            levels[plies].push((index, seed));
+        };
+        let mut next = Vec::new();
+        for (index, moves_left) in unresolved.iter_mut().enumerate() {
+            let position = Position::from_index(index);
+            if !table.is_valid(position) {
+                table.entries[index] = Entry::Invalid;
+                continue;
+            }
+            table.moves(position, &mut next);
+            let entry = if next.is_empty() {
+                if !position.strong_to_move && table.attacks(position, position.weak_king) {
+                    Entry::Loss(0)
+                } else {
+                    Entry::Draw
+                }
+            } else if next.iter().any(|n| matches!(n, Next::BareKings)) {
+                Entry::Draw
+            } else {
+                *moves_left = next.len() as u8;
+                Entry::Unknown
+            };
+            if let Entry::Loss(plies) = entry {
+                push(&mut levels, plies, index, false);
+            }
+            if let Some(plies) = table.quickest_promotion(&next) {
+                push(&mut levels, plies, index, true);
+            }
+            table.entries[index] = entry;
+        }
+
+        // Every move backwards, by where it leads
+        let mut starts = vec![0u32; SIZE + 1];
+        table.for_each_move(&mut next, |_, to| starts[to + 1] += 1);
+        for i in 0..SIZE {
+            starts[i + 1] += starts[i];
+        }
+        let mut filled = starts.clone();
+        let mut from = vec![0u32; starts[SIZE] as usize];
+        table.for_each_move(&mut next, |index, to| {
+            from[filled[to] as usize] = index as u32;
+            filled[to] += 1;
+        });
+
+        let mut plies = 0;
+        while plies < levels.len() {
+            for (index, seed) in std::mem::take(&mut levels[plies]) {
+                if seed {
+                    if table.entries[index] != Entry::Unknown {
+                        continue;
+                    }
+                    table.entries[index] = Entry::Win(plies as u16);
+                }
+                let entry = table.entries[index];
+                for &before in &from[starts[index] as usize..starts[index + 1] as usize] {
+                    let before = before as usize;
+                    if table.entries[before] != Entry::Unknown {
+                        continue;
+                    }
+                    match entry {
+                        Entry::Loss(plies) => {
+                            table.entries[before] = Entry::Win(plies + 1);
+                            push(&mut levels, plies + 1, before, false);
+                        }
+                        Entry::Win(plies) => {
+                            unresolved[before] -= 1;
+                            if unresolved[before] == 0 {
+                                table.entries[before] = Entry::Loss(plies + 1);
+                                push(&mut levels, plies + 1, before, false);
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            plies += 1;
+        }
+        table
+    }
+
+    /// The plies to mate after the quickest of the `next` moves that promotes into a won
+    /// queen ending.
+    fn quickest_promotion(&self, next: &[Next]) -> Option<u16> {
+        let promoted = self.promoted.as_ref()?;
+        next.iter()
+            .filter_map(|next| match next {
+                Next::Promotion(to) => match promoted.entries[*to] {
+                    Entry::Loss(plies) => Some(plies + 1),
+                    _ => None,
+                },
+                _ => None,
+            })
+            .min()
+    }
+
+    /// Calls `f` with every move between positions still being worked out.
+    fn for_each_move(&self, next: &mut Vec<Next>, mut f: impl FnMut(usize, usize)) {
+        for index in 0..SIZE {
+            if self.entries[index] != Entry::Unknown {
+                continue;
+            }
+            self.moves(Position::from_index(index), next);
+            for to in next.iter() {
+                if let Next::Position(to) = to {
+                    f(index, *to);
+                }
+            }
+        }
+    }
+
+    fn is_valid(&self, position: Position) -> bool {
+        let Position {
+            strong_to_move,
+            strong_king,
+            weak_king,
+            piece,
+        } = position;
+        if strong_king == weak_king || piece == strong_king || piece == weak_king {
+            return false;
+        }
+        if touching(strong_king, weak_king) {
+            return false;
+        }
+        if self.piece == PieceType::Pawn && (rank(piece) == 0 || rank(piece) == 7) {
+            return false;
+        }
+        !strong_to_move || !self.attacks(position, weak_king)
+    }
+
+    /// Whether the extra piece attacks `target`, with the strong king in the way.
+    fn attacks(&self, position: Position, target: u8) -> bool {
+        let lines: &[(i8, i8)] = match self.piece {
+            PieceType::Queen => &KING_STEPS,
+            PieceType::Rook => &ROOK_LINES,
+            _ => {
+                return [(1, -1), (1, 1)]
+                    .into_iter()
+                    .any(|step| offset(position.piece, step) == Some(target))
+            }
+        };
+        lines.iter().any(|step| {
+            let mut square = position.piece;
+            while let Some(next) = offset(square, *step) {
+                if next == target {
+                    return true;
+                }
+                if next == position.strong_king {
+                    return false;
+                }
+                square = next;
+            }
+            false
+        })
+    }
+
+    /// Fills `next` with where every legal move of the side to move leads.
+    fn moves(&self, position: Position, next: &mut Vec<Next>) {
+        next.clear();
+        let Position {
+            strong_king,
+            weak_king,
+            piece,
+            ..
+        } = position;
+        let to_index = |strong_king, weak_king, piece| {
+            Position {
+                strong_to_move: !position.strong_to_move,
+                strong_king,
+                weak_king,
+                piece,
+            }
+            .index()
+        };
+        if !position.strong_to_move {
+            for step in KING_STEPS {
+                let Some(to) = offset(weak_king, step) else {
+                    continue;
+                };
+                if touching(to, strong_king) {
+                    continue;
+                }
+                if to == piece {
+                    next.push(Next::BareKings);
+                } else if !self.attacks(position, to) {
+                    next.push(Next::Position(to_index(strong_king, to, piece)));
+                }
+            }
+            return;
+        }
+        for step in KING_STEPS {
+            if let Some(to) = offset(strong_king, step) {
+                if to != piece && to != weak_king && !touching(to, weak_king) {
+                    next.push(Next::Position(to_index(to, weak_king, piece)));
+                }
+            }
+        }
+        let empty = |square: u8| square != strong_king && square != weak_king;
+        match self.piece {
+            PieceType::Pawn => {
+                if let Some(to) = offset(piece, (1, 0)).filter(|to| empty(*to)) {
+                    let index = to_index(strong_king, weak_king, to);
+                    next.push(if rank(to) == 7 {
+                        Next::Promotion(index)
+                    } else {
+                        Next::Position(index)
+                    });
+                    if let Some(two) = offset(piece, (2, 0)).filter(|to| empty(*to)) {
+                        if rank(piece) == 1 {
+                            next.push(Next::Position(to_index(strong_king, weak_king, two)));
+                        }
+                    }
+                }
+            }
+            _ => {
+                let lines: &[(i8, i8)] = if self.piece == PieceType::Queen {
+                    &KING_STEPS
+                } else {
+                    &ROOK_LINES
+                };
+                for step in lines {
+                    let mut square = piece;
+                    while let Some(to) = offset(square, *step).filter(|to| empty(*to)) {
+                        next.push(Next::Position(to_index(strong_king, weak_king, to)));
+                        square = to;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Finds the table's position on the board, with the side to move next.
+    fn position(&self, board: &Board, side: Side) -> Option<Position> {
+        if Geometry::of(board) != Geometry::STANDARD {
+            return None;
+        }
+        let pieces: Vec<&Piece> = board.values().flatten().collect();
+        let extra = match pieces[..] {
+            [a, b, c] => [a, b, c]
+                .into_iter()
+                .filter(|p| p.piece_type != PieceType::King)
+                .collect::<Vec<_>>(),
+            _ => return None,
+        };
+        let [extra] = extra[..] else {
+            return None;
+        };
+        if extra.piece_type != self.piece {
+            return None;
+        }
+        let strong = extra.side;
+        let square = |pos: CBPosition| {
+            let square = (pos.row as u8 - 1) * 8 + (pos.col as u8 - b'a');
+            // The black side's pieces are seen from its own end of the board
+            match strong {
+                Side::White => square,
+                Side::Black => square ^ 56,
+            }
+        };
+        let king = |side: Side| {
+            pieces
+                .iter()
+                .find(|p| p.side == side && p.piece_type == PieceType::King)
+                .map(|p| square(p.current_pos))
+        };
+        Some(Position {
+            strong_to_move: side == strong,
+            strong_king: king(strong)?,
+            weak_king: king(strong.flipped())?,
+            piece: square(extra.current_pos),
+        })
+    }
+}
+
+impl Tablebase for Table {
+    /// The result for `side` to move, or `None` if the board isn't this endgame or, for a
+    /// pawn, the queen ending it promotes into.
+    fn probe(&self, board: &Board, side: Side) -> Option<Probe> {
+        let Some(position) = self.position(board, side) else {
+            return self.promoted.as_ref()?.probe(board, side);
+        };
+        match self.entries[position.index()] {
+            Entry::Invalid => None,
+            Entry::Unknown | Entry::Draw => Some(Probe::DRAW),
+            Entry::Win(plies) => Some(Probe {
+                wdl: Wdl::Win,
+                plies,
+            }),
+            Entry::Loss(plies) => Some(Probe {
+                wdl: Wdl::Loss,
+                plies,
+            }),
+        }
+    }
+
+    fn best_move(
+        &self,
+        board: &Board,
+        side: Side,
+        rules: &dyn Variant,
+    ) -> Option<(CBPosition, CBPosition)> {
+        self.probe(board, side)?;
+        rules
+            .legal_moves(board, side)
+            .into_iter()
+            .max_by_key(|(from, to)| {
+                let mut after = board.clone();
+                rules.make_move(&mut after, *from, *to);
+                // Taking the piece leaves two bare kings, which is a draw
+                let reply = self.probe(&after, side.flipped()).unwrap_or(Probe::DRAW);
+                let plies = reply.plies as i32;
+                match reply.wdl {
+                    Wdl::Loss => (2, -plies),
+                    Wdl::Draw => (1, 0),
+                    Wdl::Win => (0, plies),
+                }
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{fen, variant::Standard};
+
+    fn probe(table: &Table, fen: &str) -> Probe {
+        let position = fen::parse(fen).unwrap();
+        table.probe(&position.board, position.which_turn).unwrap()
+    }
+
+    #[test]
+    fn longest_mates() {
+        // The longest mates with a queen and with a rook are 10 and 16 moves
+        for (piece, longest) in [(PieceType::Queen, 10), (PieceType::Rook, 16)] {
+            let table = Table::solve(piece);
+            let most = (0..SIZE)
+                .filter_map(|index| match table.entries[index] {
+                    Entry::Win(plies) => Some(plies),
+                    _ => None,
+                })
+                .max()
+                .unwrap();
+            assert_eq!(most.div_ceil(2), longest, "{:?}", piece);
+        }
+    }
+
+    #[test]
+    fn king_and_pawn() {
+        let table = Table::solve(PieceType::Pawn);
+        // The king in front of its pawn on the sixth rank wins whoever is to move
+        for fen in [
+            "4k3/8/4K3/4P3/8/8/8/8 w - - 0 1",
+            "4k3/8/4K3/4P3/8/8/8/8 b - - 0 1",
+        ] {
+            assert_ne!(probe(&table, fen).wdl, Wdl::Draw, "{}", fen);
+        }
+        // A rook's pawn can't get past a king in the corner
+        assert_eq!(probe(&table, "k7/8/8/8/8/8/P7/K7 w - - 0 1"), Probe::DRAW);
+        // Seen from black's end too
+        assert_eq!(
+            probe(&table, "8/8/8/8/4p3/4k3/8/4K3 b - - 0 1").wdl,
+            Wdl::Win
+        );
+
+        // Queening goes on to mate in the queen's table
+        let queening = probe(&table, "8/4P3/8/8/8/2k5/8/4K3 w - - 0 1");
+        assert_eq!(queening.wdl, Wdl::Win);
+        let queened = probe(&table, "4Q3/8/8/8/8/2k5/8/4K3 b - - 0 1");
+        assert_eq!(queened.wdl, Wdl::Loss);
+        assert_eq!(queening.plies, queened.plies + 1);
+        // Unless the king in the corner of the promotion square takes the queen
+        assert_eq!(probe(&table, "8/2kP4/8/8/8/8/8/4K3 w - - 0 1"), Probe::DRAW);
+    }
+
+    #[test]
+    fn defends_longest() {
+        let table = Table::solve(PieceType::Rook);
+        // Black takes the undefended rook
+        let hanging = fen::parse("8/8/8/8/8/8/1k6/R3K3 b - - 0 1").unwrap();
+        assert_eq!(
+            table.best_move(&hanging.board, Side::Black, &Standard),
+            Some(("b2".into(), "a1".into()))
+        );
+        assert_eq!(probe(&table, "8/8/8/8/8/8/1k6/R3K3 b - - 0 1"), Probe::DRAW);
+
+        let position = fen::parse("8/8/8/8/4k3/8/8/R3K3 b - - 0 1").unwrap();
+        let before = table.probe(&position.board, Side::Black).unwrap();
+        assert_eq!(before.wdl, Wdl::Loss);
+        let (from, to) = table
+            .best_move(&position.board, Side::Black, &Standard)
+            .unwrap();
+        let mut after = position.board.clone();
+        Standard.make_move(&mut after, from, to);
+        let reply = table.probe(&after, Side::White).unwrap();
+        assert_eq!(reply.wdl, Wdl::Win);
+        assert_eq!(reply.plies + 1, before.plies);
+    }
+}
@@ -0,0 +1,339 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    process::{Child, ChildStdin, Command, Stdio},
+    sync::mpsc::{channel, Receiver, RecvTimeoutError},
+    thread,
+    time::{Duration, Instant},
+};
+
+use log::{debug, info};
+
+use super::{castling, promotes, Board, PieceType};
+use crate::control::{CBPosition, Move};
+
+/// How long an engine has to answer anything but a search
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An external engine driven over the Universal Chess Interface.
+pub struct UciEngine {
+    name: String,
+    /// The options the engine listed, by name, with the rest of their `option` line
+    options: Vec<(String, String)>,
+    process: Child,
+    stdin: ChildStdin,
+    /// The engine's output, read line by line on a thread of its own so a silent engine
+    /// can't hold up the caller past a deadline
+    lines: Receiver<String>,
+}
+
+impl UciEngine {
+    /// Starts the engine from a command line and waits for it to be ready.
+    pub fn start(command: &str) -> Result<UciEngine, String> {
+        let mut engine = UciEngine::spawn(command)?;
+        engine.send("uci")?;
+        for line in engine.read_until("uciok", ANSWER_TIMEOUT)? {
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.to_string();
+            }
+            if let Some(option) = line.strip_prefix("option name ") {
+                let (name, rest) = option.split_once(" type ").unwrap_or((option, ""));
+                engine.options.push((name.to_string(), rest.to_string()));
+            }
+        }
+        engine.send("isready")?;
+        engine.read_until("readyok", ANSWER_TIMEOUT)?;
+        info!("Started UCI engine {}", engine.name);
+        Ok(engine)
+    }
+
+    fn spawn(command: &str) -> Result<UciEngine, String> {
+        let mut parts = command.split_whitespace();
+        let program = parts.next().ok_or("The engine command is empty")?;
+        let mut process = Command::new(program)
+            .args(parts)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| format!("Could not start engine {:?}: {}", command, e))?;
+        let stdin = process.stdin.take().ok_or("Engine has no stdin")?;
+        let stdout = BufReader::new(process.stdout.take().ok_or("Engine has no stdout")?);
+        let (send, lines) = channel();
+        thread::spawn(move || {
+            for line in stdout.lines() {
+                let Ok(line) = line else { break };
+                if send.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(UciEngine {
+            name: command.to_string(),
+            options: Vec::new(),
+            process,
+            stdin,
+            lines,
+        })
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Whether the engine listed the option `name` when it started.
+    pub fn has_option(&self, name: &str) -> bool {
+        self.options.iter().any(|(option, _)| option == name)
+    }
+
+    /// Whether `value` is one of the choices the engine listed for a combo option, such as
+    /// a variant of `UCI_Variant`.
+    pub fn allows(&self, name: &str, value: &str) -> bool {
+        self.options
+            .iter()
+            .filter(|(option, _)| option == name)
+            .any(|(_, rest)| rest.split(" var ").skip(1).any(|var| var.trim() == value))
+    }
+
+    /// Sets one of the options the engine listed, such as `UCI_Variant`.
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), String> {
+        self.send(&format!("setoption name {} value {}", name, value))?;
+        self.send("isready")?;
+        self.read_until("readyok", ANSWER_TIMEOUT)?;
+        Ok(())
+    }
+
+    /// Asks for the best move in the position, giving the engine `movetime` to think.
+    pub fn best_move(
+        &mut self,
+        fen: &str,
+        movetime: Duration,
+    ) -> Result<(CBPosition, CBPosition), String> {
+        self.search(fen, &[], movetime).map(|(mv, _)| mv)
+    }
+
+    /// Like `best_move` for the position after `moves` in UCI's long form are played from
+    /// `fen`, also giving the last score the engine reported, from the point of view of the
+    /// side to move.
+    pub fn search(
+        &mut self,
+        fen: &str,
+        moves: &[String],
+        movetime: Duration,
+    ) -> Result<((CBPosition, CBPosition), Option<Score>), String> {
+        if moves.is_empty() {
+            self.send(&format!("position fen {}", fen))?;
+        } else {
+            self.send(&format!("position fen {} moves {}", fen, moves.join(" ")))?;
+        }
+        self.send(&format!("go movetime {}", movetime.as_millis()))?;
+        let lines = self.read_until("bestmove", movetime + ANSWER_TIMEOUT)?;
+        let best = lines
+            .last()
+            .and_then(|line| line.split_whitespace().nth(1))
+            .ok_or_else(|| format!("{} sent no best move", self.name))?;
+        let score = lines.iter().rev().find_map(|line| parse_score(line));
+        Ok((parse_move(best)?, score))
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), String> {
+        debug!("to {}: {}", self.name, command);
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| format!("Could not write to {}: {}", self.name, e))
+    }
+
+    /// Reads lines up to and including the first that starts with `prefix`, giving up if it
+    /// doesn't come within `timeout`.
+    fn read_until(&mut self, prefix: &str, timeout: Duration) -> Result<Vec<String>, String> {
+        let deadline = Instant::now() + timeout;
+        let mut lines = Vec::new();
+        loop {
+            let left = deadline.saturating_duration_since(Instant::now());
+            let line = match self.lines.recv_timeout(left) {
+                Ok(line) => line.trim().to_string(),
+                Err(RecvTimeoutError::Timeout) => {
+                    return Err(format!(
+                        "{} sent no {} within {}s",
+                        self.name,
+                        prefix,
+                        timeout.as_secs()
+                    ))
+                }
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(format!("{} exited while waiting for {}", self.name, prefix))
+                }
+            };
+            debug!("from {}: {}", self.name, line);
+            let done = line.starts_with(prefix);
+            lines.push(line);
+            if done {
+                return Ok(lines);
+            }
+        }
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        // An engine that is stuck won't act on quit, so it is killed either way
+        let _ = self.send("quit");
+        let _ = self.process.kill();
+        let _ = self.process.wait();
+    }
+}
+
+/// An engine's evaluation of a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Score {
+    Centipawns(i32),
+    /// Mate in this many moves, negative when the engine is being mated
+    Mate(i32),
+}
+
+impl Score {
+    /// The score in centipawns, mates counting as more than any material.
+    pub fn centipawns(&self) -> i32 {
+        match *self {
+            Score::Centipawns(cp) => cp,
+            Score::Mate(moves) if moves > 0 => 100_000 - moves,
+            Score::Mate(moves) => -100_000 - moves,
+        }
+    }
+}
+
+/// Reads the score from an `info` line such as `info depth 12 score cp 31 pv e2e4`.
+fn parse_score(line: &str) -> Option<Score> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if words.first() != Some(&"info") {
+        return None;
+    }
+    let at = words.iter().position(|w| *w == "score")?;
+    let value = words.get(at + 2)?.parse().ok()?;
+    match *words.get(at + 1)? {
+        "cp" => Some(Score::Centipawns(value)),
+        "mate" => Some(Score::Mate(value)),
+        _ => None,
+    }
+}
+
+/// Reads a move in UCI long algebraic form such as `e2e4`. Ranks past the ninth take two
+/// digits, as in `a10a9`, and a promotion suffix as in `e7e8q` is ignored.
+pub fn parse_move(mv: &str) -> Result<(CBPosition, CBPosition), String> {
+    let squares = match mv.strip_suffix(['q', 'r', 'b', 'n']) {
+        Some(squares) if squares.ends_with(|c: char| c.is_ascii_digit()) => squares,
+        _ => mv,
+    };
+    let to_start = squares
+        .char_indices()
+        .skip(1)
+        .find(|(_, c)| c.is_ascii_lowercase())
+        .map(|(i, _)| i);
+    match to_start {
+        Some(i) if squares.len() >= 4 && squares.is_ascii() => {
+            Ok((squares[..i].parse()?, squares[i..].parse()?))
+        }
+        _ => Err(format!("Invalid UCI move {:?}", mv)),
+    }
+}
+
+pub fn format_move((from, to): (CBPosition, CBPosition)) -> String {
+    format!("{:?}{:?}", from, to)
+}
+
+/// A move of a game as sent to an engine, from the board it was played on. Castling is the
+/// king taking its rook for engines playing by Chess960's rules and the king's own move
+/// otherwise, promotions are always to a queen and drops are written like `P@e4`.
+pub fn format_game_move(board: &Board, mv: Move, chess960: bool) -> String {
+    let (from, to) = match mv {
+        Move::Board(from, to) => (from, to),
+        Move::Drop(piece_type, to) => return format!("{}@{:?}", piece_type, to),
+    };
+    let to = match castling(board, from, to) {
+        Some(castling) if chess960 => castling.rook,
+        Some(castling) => castling.king_to,
+        None => to,
+    };
+    match board.get(&from).copied().flatten() {
+        Some(pawn) if pawn.piece_type == PieceType::Pawn && promotes(board, pawn.side, to) => {
+            format!("{}q", format_move((from, to)))
+        }
+        _ => format_move((from, to)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::fen;
+
+    #[test]
+    fn parses_moves() {
+        assert_eq!(parse_move("e2e4"), Ok(("e2".into(), "e4".into())));
+        assert_eq!(parse_move("e7e8q"), Ok(("e7".into(), "e8".into())));
+        assert_eq!(
+            parse_move("a10a9"),
+            Ok((
+                CBPosition { col: 'a', row: 10 },
+                CBPosition { col: 'a', row: 9 }
+            ))
+        );
+        assert!(parse_move("e7e").is_err());
+        assert!(parse_move("e2e4x").is_err());
+    }
+
+    #[test]
+    fn formats_game_moves() {
+        let board = fen::parse("r3k2r/4P3/8/8/8/8/8/R3K2R w KQkq - 0 1")
+            .unwrap()
+            .board;
+        let castle = |to: &'static str, chess960| {
+            format_game_move(&board, Move::Board("e1".into(), to.into()), chess960)
+        };
+        assert_eq!(castle("g1", false), "e1g1");
+        assert_eq!(castle("h1", false), "e1g1");
+        assert_eq!(castle("g1", true), "e1h1");
+        assert_eq!(castle("a1", true), "e1a1");
+        let promotion = Move::Board("e7".into(), "d8".into());
+        assert_eq!(format_game_move(&board, promotion, false), "e7d8q");
+        let drop = Move::Drop(PieceType::Knight, "f3".into());
+        assert_eq!(format_game_move(&board, drop, false), "N@f3");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn reads_the_options_listed() {
+        let script = std::env::temp_dir().join(format!("fake_engine_{}.sh", std::process::id()));
+        std::fs::write(
+            &script,
+            "while read line; do case $line in \
+             uci) echo 'id name Fake'; \
+             echo 'option name UCI_Variant type combo default chess var chess var atomic'; \
+             echo 'option name Skill Level type spin default 20 min 0 max 20'; echo uciok;; \
+             isready) echo readyok;; go*) echo 'bestmove e7e5';; esac; done",
+        )
+        .unwrap();
+        let mut engine = UciEngine::start(&format!("sh {}", script.display())).unwrap();
+        let _ = std::fs::remove_file(&script);
+        assert_eq!(engine.name(), "Fake");
+        assert!(engine.has_option("Skill Level") && !engine.has_option("UCI_Chess960"));
+        assert!(engine.allows("UCI_Variant", "atomic"));
+        assert!(!engine.allows("UCI_Variant", "horde"));
+        let moves = ["e2e4".to_string()];
+        let (best, _) = engine
+            .search(fen::STANDARD_START, &moves, Duration::from_millis(10))
+            .unwrap();
+        assert_eq!(best, ("e7".into(), "e5".into()));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn gives_up_on_a_silent_engine() {
+        let mut engine = UciEngine::spawn("sleep 60").unwrap();
+        let started = Instant::now();
+        assert!(engine
+            .read_until("uciok", Duration::from_millis(100))
+            .is_err());
+        drop(engine);
+        assert!(started.elapsed() < Duration::from_secs(5));
+    }
+}
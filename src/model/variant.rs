@@ -0,0 +1,506 @@
+//! The rules of each variant. Everything not overridden plays as standard chess, so a
+//! variant only has to describe how it differs.
+
+use super::{
+    board_setup, board_setup_of, castling, empty_board, fen, get_all_moves, in_check, insert_piece,
+    legal_moves, move_piece, Board, Piece, PieceType, Side,
+};
+use crate::control::{CBPosition, DrawReason, GameResult, Geometry, VariantKind};
+
+pub trait Variant: Sync {
+    /// The shape of the board the variant is played on.
+    fn geometry(&self) -> Geometry {
+        Geometry::STANDARD
+    }
+
+    /// The starting position, when the variant has its own.
+    fn start_board(&self) -> Option<Board> {
+        None
+    }
+
+    /// Every legal move for `side`.
+    fn legal_moves(&self, board: &Board, side: Side) -> Vec<(CBPosition, CBPosition)> {
+        legal_moves(board, side)
+    }
+
+    /// Plays a legal move on the board.
+    fn make_move(&self, board: &mut Board, from: CBPosition, to: CBPosition) {
+        move_piece(board, from, to)
+    }
+
+    /// Whether `side` is in check, as shown on the board and marked in the notation.
+    fn in_check(&self, board: &Board, side: Side) -> bool {
+        in_check(board, side)
+    }
+
+    /// The result once `mover` has moved, from the board after the move and every move of
+    /// the game so far in algebraic notation, this one included.
+    fn result(&self, board: &Board, mover: Side, _history: &[String]) -> Option<GameResult> {
+        checkmate_or_stalemate(self, board, mover)
+    }
+
+    /// Whether the game is drawn once a move has been played that didn't end it, from every
+    /// position of the game so far and every move in algebraic notation, the last included.
+    fn draw(&self, positions: &[Board], history: &[String]) -> Option<DrawReason> {
+        repetition_or_fifty_moves(positions, history).or_else(|| {
+            insufficient_material(positions.last()?).then_some(DrawReason::InsufficientMaterial)
+        })
+    }
+
+    /// Whether captured pieces go to the capturer's pocket to be dropped later.
+    fn has_drops(&self) -> bool {
+        false
+    }
+
+    /// Every legal drop for `side` of the pieces in its pocket.
+    fn legal_drops(
+        &self,
+        _board: &Board,
+        _side: Side,
+        _pocket: &[PieceType],
+    ) -> Vec<(PieceType, CBPosition)> {
+        Vec::new()
+    }
+}
+
+pub fn rules(kind: VariantKind) -> &'static dyn Variant {
+    match kind {
+        VariantKind::Standard => &Standard,
+        VariantKind::KingOfTheHill => &KingOfTheHill,
+        VariantKind::ThreeCheck => &ThreeCheck,
+        VariantKind::Antichess => &Antichess,
+        VariantKind::Atomic => &Atomic,
+        VariantKind::Horde => &Horde,
+        VariantKind::Crazyhouse => &Crazyhouse,
+        VariantKind::Capablanca => &Capablanca,
+    }
+}
+
+/// Puts a piece from the pocket on the board. A pawn dropped on its starting rank may still
+/// step twice, a dropped rook can't castle.
+pub fn drop_piece(board: &mut Board, side: Side, piece_type: PieceType, to: CBPosition) {
+    let mut piece = Piece::new(side, piece_type, to);
+    piece.has_moved = match (piece_type, side) {
+        (PieceType::Pawn, Side::White) => to.row != 2,
+        (PieceType::Pawn, Side::Black) => to.row != Geometry::of(board).ranks as usize - 1,
+        _ => true,
+    };
+    board.insert(to, Some(piece));
+}
+
+/// Ends the game when the side to move has no legal moves.
+fn checkmate_or_stalemate<V: Variant + ?Sized>(
+    rules: &V,
+    board: &Board,
+    mover: Side,
+) -> Option<GameResult> {
+    let side = mover.flipped();
+    if !rules.legal_moves(board, side).is_empty() {
+        None
+    } else if rules.in_check(board, side) {
+        Some(GameResult::Checkmate { winner: mover })
+    } else {
+        Some(GameResult::Stalemate)
+    }
+}
+
+/// The draws every variant has: the same position with the same side to move for the third
+/// time, or a hundred plies without a capture or a pawn move.
+fn repetition_or_fifty_moves(positions: &[Board], history: &[String]) -> Option<DrawReason> {
+    // Boards also tell whether pieces have moved, which only counts in the castling rights
+    let position = |board: &Board| fen::to_fen(board, Side::White, 0);
+    let last = position(positions.last()?);
+    let repetitions = positions
+        .iter()
+        .rev()
+        .step_by(2)
+        .filter(|board| position(board) == last)
+        .count();
+    if repetitions >= 3 {
+        return Some(DrawReason::Repetition);
+    }
+    // Captures and pawn moves are the only moves whose notation starts with a file or has an x
+    let quiet_plies = history
+        .iter()
+        .rev()
+        .take_while(|san| !san.contains('x') && !san.starts_with(|c: char| c.is_ascii_lowercase()))
+        .count();
+    (quiet_plies >= 100).then_some(DrawReason::FiftyMoves)
+}
+
+/// Whether only the kings are left, or the kings and a single bishop or knight.
+fn insufficient_material(board: &Board) -> bool {
+    let mut pieces = board
+        .values()
+        .flatten()
+        .filter(|p| p.piece_type != PieceType::King);
+    match (pieces.next(), pieces.next()) {
+        (None, _) => true,
+        (Some(piece), None) => matches!(piece.piece_type, PieceType::Bishop | PieceType::Knight),
+        _ => false,
+    }
+}
+
+fn king(board: &Board, side: Side) -> Option<&Piece> {
+    board
+        .values()
+        .flatten()
+        .find(|p| p.side == side && p.piece_type == PieceType::King)
+}
+
+pub struct Standard;
+
+impl Variant for Standard {}
+
+/// Bringing the king to one of the four centre squares wins.
+pub struct KingOfTheHill;
+
+impl Variant for KingOfTheHill {
+    fn draw(&self, positions: &[Board], history: &[String]) -> Option<DrawReason> {
+        // A lone king can still walk to the hill
+        repetition_or_fifty_moves(positions, history)
+    }
+
+    fn result(&self, board: &Board, mover: Side, _: &[String]) -> Option<GameResult> {
+        let hill = ["d4", "e4", "d5", "e5"].map(CBPosition::from);
+        if king(board, mover).is_some_and(|k| hill.contains(&k.current_pos)) {
+            return Some(GameResult::VariantWin {
+                winner: mover,
+                variant: VariantKind::KingOfTheHill,
+            });
+        }
+        checkmate_or_stalemate(self, board, mover)
+    }
+}
+
+/// Giving check for the third time wins.
+pub struct ThreeCheck;
+
+impl Variant for ThreeCheck {
+    fn draw(&self, positions: &[Board], history: &[String]) -> Option<DrawReason> {
+        // A lone minor piece can still give the checks
+        repetition_or_fifty_moves(positions, history)
+    }
+
+    fn result(&self, board: &Board, mover: Side, history: &[String]) -> Option<GameResult> {
+        let checks = history
+            .iter()
+            .rev()
+            .step_by(2)
+            .filter(|mv| mv.ends_with(['+', '#']))
+            .count();
+        if checks >= 3 {
+            return Some(GameResult::VariantWin {
+                winner: mover,
+                variant: VariantKind::ThreeCheck,
+            });
+        }
+        checkmate_or_stalemate(self, board, mover)
+    }
+}
+
+/// Captures are compulsory and the king is an ordinary piece. Losing every piece, or
+/// having no move, wins.
+pub struct Antichess;
+
+impl Variant for Antichess {
+    fn draw(&self, positions: &[Board], history: &[String]) -> Option<DrawReason> {
+        // The king is an ordinary piece, so no material is too little
+        repetition_or_fifty_moves(positions, history)
+    }
+
+    fn legal_moves(&self, board: &Board, side: Side) -> Vec<(CBPosition, CBPosition)> {
+        let moves: Vec<(CBPosition, CBPosition)> = get_all_moves(board)
+            .into_iter()
+            .filter(|(p, to)| p.side == side && castling(board, p.current_pos, *to).is_none())
+            .map(|(p, to)| (p.current_pos, to))
+            .collect();
+        let captures: Vec<(CBPosition, CBPosition)> = moves
+            .iter()
+            .copied()
+            .filter(|(_, to)| board.get(to).is_some_and(|p| p.is_some()))
+            .collect();
+        if captures.is_empty() {
+            moves
+        } else {
+            captures
+        }
+    }
+
+    fn in_check(&self, _: &Board, _: Side) -> bool {
+        false
+    }
+
+    fn result(&self, board: &Board, mover: Side, _: &[String]) -> Option<GameResult> {
+        let side = mover.flipped();
+        self.legal_moves(board, side)
+            .is_empty()
+            .then_some(GameResult::VariantWin {
+                winner: side,
+                variant: VariantKind::Antichess,
+            })
+    }
+}
+
+/// A capture explodes, removing the capturing piece and every piece but pawns next to the
+/// square. Blowing up the enemy king wins, and kings can't capture.
+pub struct Atomic;
+
+impl Atomic {
+    fn kings_touch(board: &Board) -> bool {
+        match (king(board, Side::White), king(board, Side::Black)) {
+            (Some(white), Some(black)) => white
+                .current_pos
+                .get_adjacents()
+                .contains(&black.current_pos),
+            _ => false,
+        }
+    }
+}
+
+impl Variant for Atomic {
+    fn legal_moves(&self, board: &Board, side: Side) -> Vec<(CBPosition, CBPosition)> {
+        get_all_moves(board)
+            .into_iter()
+            .filter(|(p, to)| {
+                p.side == side
+                    && !(p.piece_type == PieceType::King
+                        && board.get(to).is_some_and(|p| p.is_some())
+                        && castling(board, p.current_pos, *to).is_none())
+            })
+            .map(|(p, to)| (p.current_pos, to))
+            .filter(|(from, to)| {
+                let mut sim = board.clone();
+                self.make_move(&mut sim, *from, *to);
+                match (king(&sim, side), king(&sim, side.flipped())) {
+                    (None, _) => false,
+                    (Some(_), None) => true,
+                    _ => !self.in_check(&sim, side),
+                }
+            })
+            .collect()
+    }
+
+    fn make_move(&self, board: &mut Board, from: CBPosition, to: CBPosition) {
+        let captures =
+            board.get(&to).is_some_and(|p| p.is_some()) && castling(board, from, to).is_none();
+        move_piece(board, from, to);
+        if captures {
+            board.insert(to, None);
+            for pos in to.get_adjacents() {
+                if board
+                    .get(&pos)
+                    .copied()
+                    .flatten()
+                    .is_some_and(|p| p.piece_type != PieceType::Pawn)
+                {
+                    board.insert(pos, None);
+                }
+            }
+        }
+    }
+
+    /// Touching kings can't give check, as taking one would blow up both.
+    fn in_check(&self, board: &Board, side: Side) -> bool {
+        !Atomic::kings_touch(board) && in_check(board, side)
+    }
+
+    fn result(&self, board: &Board, mover: Side, _: &[String]) -> Option<GameResult> {
+        if king(board, mover.flipped()).is_none() {
+            return Some(GameResult::VariantWin {
+                winner: mover,
+                variant: VariantKind::Atomic,
+            });
+        }
+        checkmate_or_stalemate(self, board, mover)
+    }
+}
+
+/// White has 36 pawns and no king against a standard black army. Black wins by capturing
+/// them all, white by checkmate.
+pub struct Horde;
+
+impl Variant for Horde {
+    fn start_board(&self) -> Option<Board> {
+        let mut board = empty_board();
+        for (pos, piece) in board_setup() {
+            if piece.is_some_and(|p| p.side == Side::Black) {
+                board.insert(pos, piece);
+            }
+        }
+        for col in 'a'..='h' {
+            for row in 1..=4 {
+                insert_piece(&mut board, row, col, Side::White, PieceType::Pawn);
+            }
+        }
+        for col in ['b', 'c', 'f', 'g'] {
+            insert_piece(&mut board, 5, col, Side::White, PieceType::Pawn);
+        }
+        // Pawns on the first and second ranks may still step twice
+        for piece in board.values_mut().flatten() {
+            piece.has_moved = piece.side == Side::White && piece.current_pos.row > 2;
+        }
+        Some(board)
+    }
+
+    fn result(&self, board: &Board, mover: Side, _: &[String]) -> Option<GameResult> {
+        if !board.values().flatten().any(|p| p.side == Side::White) {
+            return Some(GameResult::VariantWin {
+                winner: Side::Black,
+                variant: VariantKind::Horde,
+            });
+        }
+        checkmate_or_stalemate(self, board, mover)
+    }
+}
+
+/// Captured pieces change sides and can be dropped on any empty square instead of moving,
+/// though pawns not on the first or last rank.
+pub struct Crazyhouse;
+
+impl Variant for Crazyhouse {
+    fn draw(&self, positions: &[Board], history: &[String]) -> Option<DrawReason> {
+        // Captured pieces come back as drops, so material never runs out
+        repetition_or_fifty_moves(positions, history)
+    }
+
+    fn has_drops(&self) -> bool {
+        true
+    }
+
+    fn legal_drops(
+        &self,
+        board: &Board,
+        side: Side,
+        pocket: &[PieceType],
+    ) -> Vec<(PieceType, CBPosition)> {
+        let mut piece_types: Vec<PieceType> = Vec::new();
+        for piece_type in pocket {
+            if !piece_types.contains(piece_type) {
+                piece_types.push(*piece_type);
+            }
+        }
+        let mut empty: Vec<CBPosition> = board
+            .iter()
+            .filter(|(_, piece)| piece.is_none())
+            .map(|(pos, _)| *pos)
+            .collect();
+        empty.sort_by_key(|pos| (pos.row, pos.col));
+        let last_rank = Geometry::of(board).ranks as usize;
+        let mut drops = Vec::new();
+        for piece_type in piece_types {
+            for to in empty.iter().copied() {
+                if piece_type == PieceType::Pawn && (to.row == 1 || to.row == last_rank) {
+                    continue;
+                }
+                let mut sim = board.clone();
+                drop_piece(&mut sim, side, piece_type, to);
+                if !self.in_check(&sim, side) {
+                    drops.push((piece_type, to));
+                }
+            }
+        }
+        drops
+    }
+}
+
+/// Played on a board of ten files with an archbishop and a chancellor beside the usual
+/// pieces. Castling on the king side takes the king to the i file, next to the j rook.
+pub struct Capablanca;
+
+impl Variant for Capablanca {
+    fn geometry(&self) -> Geometry {
+        Geometry {
+            files: 10,
+            ranks: 8,
+        }
+    }
+
+    fn start_board(&self) -> Option<Board> {
+        use PieceType::*;
+        Some(board_setup_of(&[
+            Rook, Knight, Archbishop, Bishop, Queen, King, Bishop, Chancellor, Knight, Rook,
+        ]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::{fen, PieceType::*, Side::*};
+
+    fn play(rules: &dyn Variant, board: &mut Board, from: &'static str, to: &'static str) {
+        let side = board[&from.into()].unwrap().side;
+        assert!(rules
+            .legal_moves(board, side)
+            .contains(&(from.into(), to.into())));
+        rules.make_move(board, from.into(), to.into());
+    }
+
+    #[test]
+    fn variant_rules() {
+        let mut board = empty_board();
+        insert_piece(&mut board, 3, 'e', White, King);
+        insert_piece(&mut board, 8, 'a', Black, King);
+        play(&KingOfTheHill, &mut board, "e3", "e4");
+        assert!(matches!(
+            KingOfTheHill.result(&board, White, &[]),
+            Some(GameResult::VariantWin { winner: White, .. })
+        ));
+
+        let history = [
+            "e4", "e5", "Bb5+", "c6", "Bxc6+", "Nxc6", "Qh5", "Nf6", "Qxf7+",
+        ]
+        .map(String::from);
+        assert!(ThreeCheck.result(&board_setup(), White, &history).is_some());
+        assert!(ThreeCheck
+            .result(&board_setup(), White, &history[..7])
+            .is_none());
+
+        // After 1. e3 b5 white has to take
+        let mut board = board_setup();
+        play(&Antichess, &mut board, "e2", "e3");
+        play(&Antichess, &mut board, "b7", "b5");
+        assert_eq!(
+            Antichess.legal_moves(&board, White),
+            [("f1".into(), "b5".into())]
+        );
+
+        // Taking on f7 blows up the king next to it
+        let mut board = fen::parse("4k3/5p2/8/8/8/8/8/4KQ2 w - - 0 1")
+            .unwrap()
+            .board;
+        play(&Atomic, &mut board, "f1", "f7");
+        assert!(board[&"e8".into()].is_none() && board[&"f7".into()].is_none());
+        assert!(matches!(
+            Atomic.result(&board, White, &[]),
+            Some(GameResult::VariantWin { winner: White, .. })
+        ));
+
+        let horde = Horde.start_board().unwrap();
+        assert_eq!(
+            horde.values().flatten().filter(|p| p.side == White).count(),
+            36
+        );
+        assert!(Horde
+            .legal_moves(&horde, White)
+            .contains(&("a4".into(), "a5".into())));
+    }
+
+    #[test]
+    fn capablanca() {
+        let mut board = Capablanca.start_board().unwrap();
+        assert_eq!(board.len(), 80);
+        assert_eq!(Capablanca.legal_moves(&board, White).len(), 28);
+        assert_eq!(board[&"h1".into()].unwrap().piece_type, Chancellor);
+
+        // The king castles to the i file, its rook landing beside it on h
+        for square in ["g1", "h1", "i1"] {
+            board.insert(square.into(), None);
+        }
+        play(&Capablanca, &mut board, "f1", "i1");
+        assert_eq!(board[&"i1".into()].unwrap().piece_type, King);
+        assert_eq!(board[&"h1".into()].unwrap().piece_type, Rook);
+        assert!(board[&"j1".into()].is_none());
+    }
+}
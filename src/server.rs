@@ -0,0 +1,720 @@
+//! A headless server running many games at once, built with the `executor` feature.
+//!
+//! Clients speak one command per line over TCP:
+//!
+//! | Client sends                      | Server answers                                      |
+//! |-----------------------------------|-----------------------------------------------------|
+//! | `HELLO eden_chess_server <version>` | `WELCOME <version> <client id>` or `REFUSED <reason>` |
+//! | `LIST`                            | `GAME <id> <white> <black> <spectators> <plies>` per game, then `END` |
+//! | `CREATE [w\|b] [<minutes>+<increment>]` | `JOINED <id> <w\|b>` then `STATE`           |
+//! | `JOIN <id> [w\|b]`                | `JOINED <id> <w\|b>` then `STATE`                   |
+//! | `WATCH <id>`                      | `WATCHING <id>` then `STATE`, read only             |
+//! | `MOVE <id> <from><to>`            | `MOVE` to everyone in the game, or `REJECT <id> <reason>` |
+//! | `LEAVE <id>`                      | `LEFT <id>`                                         |
+//! | `QUIT`                            | the connection closes                               |
+//!
+//! Players and spectators of a game are sent `MOVE <id> <from><to> <san>` after every move
+//! and `RESULT <id> <result>` when it ends. `STATE <id> <w|b> <white ms|-> <black ms|->
+//! <san>...` gives the side to move, the clocks and every move so far. Anything that goes
+//! wrong is answered with `ERROR <reason>`. A player whose connection drops frees their
+//! side, so they can `JOIN` again to carry on. A line longer than `MAX_LINE_LENGTH` closes
+//! the connection.
+
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        mpsc::{channel, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use log::{debug, info, warn};
+
+use crate::{
+    control::{GameSetup, GameStatus, ModelMsg, StartPosition, TimeControl, UiMsg},
+    executor::Executor,
+    model::{uci, Side, SteppedModel},
+};
+
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const DEFAULT_PORT: u16 = 7879;
+
+/// How often every game and connection is looked at.
+const TICK: Duration = Duration::from_millis(20);
+/// The longest command a client may send, longer ones close the connection
+const MAX_LINE_LENGTH: usize = 1024;
+
+type ClientId = usize;
+type GameId = usize;
+
+/// One game and everyone taking part in it.
+struct ServerGame {
+    id: GameId,
+    model: SteppedModel,
+    to_model: Sender<UiMsg>,
+    from_model: Receiver<ModelMsg>,
+    white: Option<ClientId>,
+    black: Option<ClientId>,
+    spectators: Vec<ClientId>,
+    /// The last status from the model, `None` until the game has started
+    status: Option<GameStatus>,
+    /// The player whose move the model is checking
+    pending_mover: Option<ClientId>,
+}
+
+impl ServerGame {
+    fn new(id: GameId, setup: GameSetup) -> ServerGame {
+        let (to_model, model_recv) = channel();
+        let (model_send, from_model) = channel();
+        let _ = to_model.send(UiMsg::NewGame(setup));
+        ServerGame {
+            id,
+            model: SteppedModel::new(model_send, model_recv),
+            to_model,
+            from_model,
+            white: None,
+            black: None,
+            spectators: Vec::new(),
+            status: None,
+            pending_mover: None,
+        }
+    }
+
+    fn seat(&mut self, side: Side) -> &mut Option<ClientId> {
+        match side {
+            Side::White => &mut self.white,
+            Side::Black => &mut self.black,
+        }
+    }
+
+    fn side_of(&self, client: ClientId) -> Option<Side> {
+        if self.white == Some(client) {
+            Some(Side::White)
+        } else if self.black == Some(client) {
+            Some(Side::Black)
+        } else {
+            None
+        }
+    }
+
+    fn participants(&self) -> impl Iterator<Item = ClientId> + '_ {
+        self.white
+            .into_iter()
+            .chain(self.black)
+            .chain(self.spectators.iter().copied())
+    }
+
+    fn remove(&mut self, client: ClientId) {
+        if self.white == Some(client) {
+            self.white = None;
+        }
+        if self.black == Some(client) {
+            self.black = None;
+        }
+        self.spectators.retain(|c| *c != client);
+    }
+
+    fn state_line(&self) -> String {
+        let Some(status) = &self.status else {
+            return format!("STATE {} w - -", self.id);
+        };
+        let (white, black) = match status.clocks {
+            Some((white, black)) => (white.as_millis().to_string(), black.as_millis().to_string()),
+            None => ("-".to_string(), "-".to_string()),
+        };
+        let mut line = format!(
+            "STATE {} {} {} {}",
+            self.id,
+            side_to_str(status.which_turn),
+            white,
+            black
+        );
+        for san in status.move_history.iter() {
+            line.push(' ');
+            line.push_str(san);
+        }
+        line
+    }
+}
+
+/// What the server keeps about a connection.
+struct Client {
+    id: ClientId,
+    stream: TcpStream,
+    /// Lines sent to this client from games, written out by the client's own job
+    outbox: Receiver<String>,
+    /// Bytes read that don't make a whole line yet
+    partial: Vec<u8>,
+    greeted: bool,
+    closed: bool,
+}
+
+/// The state shared by the jobs of every game and client.
+#[derive(Default)]
+struct Shared {
+    games: Mutex<HashMap<GameId, Arc<Mutex<ServerGame>>>>,
+    outboxes: Mutex<HashMap<ClientId, Sender<String>>>,
+    next_game: AtomicUsize,
+}
+
+impl Shared {
+    fn send_to(&self, client: ClientId, line: String) {
+        let outboxes = self.outboxes.lock().expect("Outboxes poisoned");
+        if let Some(outbox) = outboxes.get(&client) {
+            let _ = outbox.send(line);
+        }
+    }
+
+    fn game(&self, id: GameId) -> Result<Arc<Mutex<ServerGame>>, String> {
+        self.games
+            .lock()
+            .expect("Games poisoned")
+            .get(&id)
+            .cloned()
+            .ok_or_else(|| format!("No game {}", id))
+    }
+}
+
+pub struct Server {
+    listener: TcpListener,
+    executor: Executor,
+    shared: Arc<Shared>,
+}
+
+impl Server {
+    pub fn bind(port: u16, threads: usize) -> Result<Server, String> {
+        let listener = TcpListener::bind(("0.0.0.0", port))
+            .map_err(|e| format!("Can't listen on port {}: {}", port, e))?;
+        listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+        Ok(Server {
+            listener,
+            executor: Executor::new(threads),
+            shared: Arc::new(Shared::default()),
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener
+            .local_addr()
+            .map(|addr| addr.port())
+            .unwrap_or_default()
+    }
+
+    /// Serves until `stop` is set. Every tick each game and connection gets a job on the
+    /// executor, skipping any whose last job is still running.
+    pub fn run(&self, stop: &AtomicBool) {
+        info!(
+            "Serving on port {} with {} threads",
+            self.port(),
+            self.executor.threads()
+        );
+        let mut clients: Vec<Arc<Mutex<Client>>> = Vec::new();
+        let mut next_client = 0;
+        while !stop.load(Ordering::Relaxed) {
+            loop {
+                match self.listener.accept() {
+                    Ok((stream, addr)) => {
+                        info!("Client {} connected from {}", next_client, addr);
+                        if let Some(client) = self.add_client(next_client, stream) {
+                            clients.push(client);
+                        }
+                        next_client += 1;
+                    }
+                    Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                    Err(e) => {
+                        warn!("Failed connection: {}", e);
+                        break;
+                    }
+                }
+            }
+
+            clients.retain(|client| match client.try_lock() {
+                Ok(client) if client.closed => {
+                    self.remove_client(client.id);
+                    false
+                }
+                _ => true,
+            });
+            for client in clients.iter() {
+                let (client, shared) = (client.clone(), self.shared.clone());
+                self.executor.spawn(move || {
+                    if let Ok(mut client) = client.try_lock() {
+                        poll_client(&mut client, &shared);
+                    }
+                });
+            }
+
+            let games: Vec<_> = {
+                let mut games = self.shared.games.lock().expect("Games poisoned");
+                // Games nobody is in any more are over
+                games.retain(|id, game| {
+                    let empty = game
+                        .try_lock()
+                        .is_ok_and(|game| game.participants().next().is_none());
+                    if empty {
+                        info!("Closing game {}", id);
+                    }
+                    !empty
+                });
+                games.values().cloned().collect()
+            };
+            for game in games {
+                let shared = self.shared.clone();
+                self.executor.spawn(move || {
+                    if let Ok(mut game) = game.try_lock() {
+                        tick_game(&mut game, &shared);
+                    }
+                });
+            }
+            thread::sleep(TICK);
+        }
+        info!("Server stopped");
+    }
+
+    fn add_client(&self, id: ClientId, stream: TcpStream) -> Option<Arc<Mutex<Client>>> {
+        if let Err(e) = stream.set_nonblocking(true) {
+            warn!("Dropping client {}: {}", id, e);
+            return None;
+        }
+        let (send, outbox) = channel();
+        self.shared
+            .outboxes
+            .lock()
+            .expect("Outboxes poisoned")
+            .insert(id, send);
+        Some(Arc::new(Mutex::new(Client {
+            id,
+            stream,
+            outbox,
+            partial: Vec::new(),
+            greeted: false,
+            closed: false,
+        })))
+    }
+
+    fn remove_client(&self, id: ClientId) {
+        info!("Client {} disconnected", id);
+        self.shared
+            .outboxes
+            .lock()
+            .expect("Outboxes poisoned")
+            .remove(&id);
+        let games: Vec<_> = self
+            .shared
+            .games
+            .lock()
+            .expect("Games poisoned")
+            .values()
+            .cloned()
+            .collect();
+        for game in games {
+            game.lock().expect("Game poisoned").remove(id);
+        }
+    }
+}
+
+/// Sends a client what games have for it and handles the commands it sent.
+fn poll_client(client: &mut Client, shared: &Shared) {
+    while let Ok(line) = client.outbox.try_recv() {
+        write_line(client, &line);
+    }
+    let mut buf = [0; 1024];
+    loop {
+        match client.stream.read(&mut buf) {
+            Ok(0) => {
+                client.closed = true;
+                return;
+            }
+            Ok(n) => client.partial.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+            Err(e) => {
+                debug!("Client {} read failed: {}", client.id, e);
+                client.closed = true;
+                return;
+            }
+        }
+    }
+    if client.partial.len() > MAX_LINE_LENGTH && !client.partial.contains(&b'\n') {
+        write_line(client, "ERROR line too long");
+        client.closed = true;
+        return;
+    }
+    while let Some(end) = client.partial.iter().position(|b| *b == b'\n') {
+        let line: Vec<u8> = client.partial.drain(..=end).collect();
+        let line = String::from_utf8_lossy(&line).trim().to_string();
+        if line.is_empty() {
+            continue;
+        }
+        debug!("Client {}: {}", client.id, line);
+        let reply = handle_command(client, shared, &line);
+        if let Err(e) = reply {
+            write_line(client, &format!("ERROR {}", e));
+        }
+        if client.closed {
+            return;
+        }
+    }
+}
+
+fn write_line(client: &mut Client, line: &str) {
+    if let Err(e) = writeln!(client.stream, "{}", line) {
+        // A client too slow to keep up is dropped rather than holding up a worker
+        debug!("Client {} write failed: {}", client.id, e);
+        client.closed = true;
+    }
+}
+
+fn handle_command(client: &mut Client, shared: &Shared, line: &str) -> Result<(), String> {
+    let words: Vec<&str> = line.split_whitespace().collect();
+    if !client.greeted {
+        return match words[..] {
+            ["HELLO", "eden_chess_server", version] if version == PROTOCOL_VERSION.to_string() => {
+                client.greeted = true;
+                let welcome = format!("WELCOME {} {}", PROTOCOL_VERSION, client.id);
+                write_line(client, &welcome);
+                Ok(())
+            }
+            ["HELLO", "eden_chess_server", version] => {
+                refuse(
+                    client,
+                    &format!(
+                        "protocol version {} is not supported, the server uses version {}",
+                        version, PROTOCOL_VERSION
+                    ),
+                );
+                Ok(())
+            }
+            _ => {
+                refuse(client, "expected HELLO");
+                Ok(())
+            }
+        };
+    }
+    match words[..] {
+        ["LIST"] => {
+            let games: Vec<_> = shared
+                .games
+                .lock()
+                .expect("Games poisoned")
+                .values()
+                .cloned()
+                .collect();
+            let mut lines: Vec<(GameId, String)> = games
+                .iter()
+                .map(|game| {
+                    let game = game.lock().expect("Game poisoned");
+                    let seat = |seat: Option<ClientId>| match seat {
+                        Some(_) => "taken",
+                        None => "open",
+                    };
+                    let plies = game.status.as_ref().map_or(0, |s| s.move_history.len());
+                    let line = format!(
+                        "GAME {} {} {} {} {}",
+                        game.id,
+                        seat(game.white),
+                        seat(game.black),
+                        game.spectators.len(),
+                        plies
+                    );
+                    (game.id, line)
+                })
+                .collect();
+            lines.sort();
+            for (_, line) in lines {
+                write_line(client, &line);
+            }
+            write_line(client, "END");
+        }
+        ["CREATE", ref args @ ..] => {
+            let mut side = Side::White;
+            let mut time_control = None;
+            for arg in args {
+                match *arg {
+                    "w" | "b" => side = side_from_str(arg)?,
+                    tc => time_control = Some(parse_time_control(tc)?),
+                }
+            }
+            let id = shared.next_game.fetch_add(1, Ordering::Relaxed);
+            let mut game = ServerGame::new(
+                id,
+                GameSetup {
+                    time_control,
+                    start: StartPosition::Standard,
+                    ..GameSetup::default()
+                },
+            );
+            *game.seat(side) = Some(client.id);
+            info!("Client {} created game {}", client.id, id);
+            // Start the game now so the first move isn't refused while it sets up
+            tick_game(&mut game, shared);
+            write_line(client, &format!("JOINED {} {}", id, side_to_str(side)));
+            write_line(client, &game.state_line());
+            shared
+                .games
+                .lock()
+                .expect("Games poisoned")
+                .insert(id, Arc::new(Mutex::new(game)));
+        }
+        ["JOIN", id, ref side @ ..] => {
+            let game = shared.game(parse_id(id)?)?;
+            let mut game = game.lock().expect("Game poisoned");
+            if game.side_of(client.id).is_some() {
+                return Err(format!("You are already playing game {}", game.id));
+            }
+            let side = match side {
+                [side] => side_from_str(side)?,
+                _ if game.white.is_none() => Side::White,
+                _ => Side::Black,
+            };
+            if game.seat(side).is_some() {
+                return Err(format!(
+                    "{} is already taken in game {}",
+                    String::from(side),
+                    game.id
+                ));
+            }
+            game.spectators.retain(|c| *c != client.id);
+            *game.seat(side) = Some(client.id);
+            write_line(client, &format!("JOINED {} {}", game.id, side_to_str(side)));
+            write_line(client, &game.state_line());
+        }
+        ["WATCH", id] => {
+            let game = shared.game(parse_id(id)?)?;
+            let mut game = game.lock().expect("Game poisoned");
+            if game.side_of(client.id).is_some() {
+                return Err(format!("You are playing game {}", game.id));
+            }
+            if !game.spectators.contains(&client.id) {
+                game.spectators.push(client.id);
+            }
+            write_line(client, &format!("WATCHING {}", game.id));
+            write_line(client, &game.state_line());
+        }
+        ["MOVE", id, mv] => {
+            let game = shared.game(parse_id(id)?)?;
+            let mut game = game.lock().expect("Game poisoned");
+            let reject = |client: &mut Client, reason: &str| {
+                write_line(client, &format!("REJECT {} {}", id, reason));
+            };
+            let Some(side) = game.side_of(client.id) else {
+                let reason = if game.spectators.contains(&client.id) {
+                    "spectators can't move"
+                } else {
+                    "you are not playing this game"
+                };
+                reject(client, reason);
+                return Ok(());
+            };
+            match &game.status {
+                Some(status) if status.result.is_some() => reject(client, "the game is over"),
+                Some(status) if status.which_turn == side => match uci::parse_move(mv) {
+                    Ok(mv) => {
+                        game.pending_mover = Some(client.id);
+                        let _ = game.to_model.send(UiMsg::MakeMove(mv));
+                    }
+                    Err(e) => reject(client, &e),
+                },
+                _ => reject(client, "it is not your turn"),
+            }
+        }
+        ["LEAVE", id] => {
+            let game = shared.game(parse_id(id)?)?;
+            game.lock().expect("Game poisoned").remove(client.id);
+            write_line(client, &format!("LEFT {}", id));
+        }
+        ["QUIT"] => client.closed = true,
+        _ => return Err(format!("Unknown command {:?}", line)),
+    }
+    Ok(())
+}
+
+fn refuse(client: &mut Client, reason: &str) {
+    info!("Refusing client {}: {}", client.id, reason);
+    write_line(client, &format!("REFUSED {}", reason));
+    client.closed = true;
+}
+
+/// Moves the game on and tells everyone in it what happened.
+fn tick_game(game: &mut ServerGame, shared: &Shared) {
+    game.model.tick();
+    while let Ok(msg) = game.from_model.try_recv() {
+        match msg {
+            ModelMsg::GameStatus(status) => {
+                let played = game.status.as_ref().map_or(0, |s| s.move_history.len());
+                let mut lines = Vec::new();
+                if status.move_history.len() == played + 1 {
+                    if let (Some(last_move), Some(san)) =
+                        (status.last_move, status.move_history.last())
+                    {
+                        lines.push(format!(
+                            "MOVE {} {:?}{:?} {}",
+                            game.id, last_move.0, last_move.1, san
+                        ));
+                    }
+                }
+                if let Some(result) = status.result {
+                    if !matches!(&game.status, Some(s) if s.result.is_some()) {
+                        lines.push(format!("RESULT {} {}", game.id, result));
+                    }
+                }
+                game.status = Some(status);
+                for client in game.participants().collect::<Vec<_>>() {
+                    for line in lines.iter() {
+                        shared.send_to(client, line.clone());
+                    }
+                }
+            }
+            ModelMsg::MoveIsInvalid => {
+                if let Some(mover) = game.pending_mover.take() {
+                    shared.send_to(mover, format!("REJECT {} illegal move", game.id));
+                }
+            }
+            ModelMsg::Error(e) => {
+                for client in game.participants().collect::<Vec<_>>() {
+                    shared.send_to(client, format!("ERROR {}", e));
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+fn side_to_str(side: Side) -> &'static str {
+    match side {
+        Side::White => "w",
+        Side::Black => "b",
+    }
+}
+
+fn side_from_str(s: &str) -> Result<Side, String> {
+    match s {
+        "w" => Ok(Side::White),
+        "b" => Ok(Side::Black),
+        other => Err(format!("Invalid side {:?}, expected w or b", other)),
+    }
+}
+
+fn parse_id(id: &str) -> Result<GameId, String> {
+    id.parse().map_err(|_| format!("Invalid game id {:?}", id))
+}
+
+/// Reads a time control written as `<minutes>+<increment seconds>`, such as `5+3`.
+fn parse_time_control(tc: &str) -> Result<TimeControl, String> {
+    let invalid = || format!("Invalid time control {:?}, expected one like 5+3", tc);
+    let (minutes, increment) = tc.split_once('+').ok_or_else(invalid)?;
+    Ok(TimeControl::new(
+        minutes.parse().map_err(|_| invalid())?,
+        increment.parse().map_err(|_| invalid())?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader},
+        time::Instant,
+    };
+
+    use super::*;
+
+    struct TestClient(BufReader<TcpStream>);
+
+    impl TestClient {
+        fn connect(port: u16) -> TestClient {
+            let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(5)))
+                .unwrap();
+            let mut client = TestClient(BufReader::new(stream));
+            client.send(&format!("HELLO eden_chess_server {}", PROTOCOL_VERSION));
+            assert!(client.read().starts_with("WELCOME"));
+            client
+        }
+
+        fn send(&mut self, line: &str) {
+            writeln!(self.0.get_mut(), "{}", line).unwrap();
+        }
+
+        fn read(&mut self) -> String {
+            let mut line = String::new();
+            self.0.read_line(&mut line).unwrap();
+            line.trim().to_string()
+        }
+
+        /// Reads until a line starting with `prefix`.
+        fn read_until(&mut self, prefix: &str) -> String {
+            let start = Instant::now();
+            loop {
+                assert!(start.elapsed() < Duration::from_secs(10), "timed out");
+                let line = self.read();
+                if line.starts_with(prefix) {
+                    return line;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn hosts_games_with_spectators() {
+        let server = Arc::new(Server::bind(0, 2).unwrap());
+        let port = server.port();
+        let stop = Arc::new(AtomicBool::new(false));
+        let running = {
+            let (server, stop) = (server.clone(), stop.clone());
+            thread::spawn(move || server.run(&stop))
+        };
+
+        let mut white = TestClient::connect(port);
+        white.send("CREATE w 5+0");
+        assert_eq!(white.read(), "JOINED 0 w");
+        assert!(white.read().starts_with("STATE 0 w "));
+        let mut other = TestClient::connect(port);
+        other.send("CREATE");
+        assert_eq!(other.read(), "JOINED 1 w");
+        assert_eq!(other.read(), "STATE 1 w - -");
+
+        let mut black = TestClient::connect(port);
+        black.send("JOIN 0");
+        assert_eq!(black.read(), "JOINED 0 b");
+        let mut spectator = TestClient::connect(port);
+        spectator.send("WATCH 0");
+        assert_eq!(spectator.read(), "WATCHING 0");
+
+        black.send("MOVE 0 e7e5");
+        assert_eq!(black.read_until("REJECT"), "REJECT 0 it is not your turn");
+        white.send("MOVE 0 e2e5");
+        assert_eq!(white.read_until("REJECT"), "REJECT 0 illegal move");
+        white.send("MOVE 0 e2e4");
+        assert_eq!(spectator.read_until("MOVE"), "MOVE 0 e2e4 e4");
+        assert_eq!(black.read_until("MOVE"), "MOVE 0 e2e4 e4");
+        spectator.send("MOVE 0 e7e5");
+        assert_eq!(
+            spectator.read_until("REJECT"),
+            "REJECT 0 spectators can't move"
+        );
+
+        other.send("LIST");
+        assert_eq!(other.read(), "GAME 0 taken taken 1 1");
+        assert_eq!(other.read(), "GAME 1 taken open 0 0");
+        assert_eq!(other.read(), "END");
+
+        let mut old = TcpStream::connect(("127.0.0.1", port)).unwrap();
+        writeln!(old, "HELLO eden_chess_server 0").unwrap();
+        let mut line = String::new();
+        BufReader::new(old).read_line(&mut line).unwrap();
+        assert!(line.starts_with("REFUSED protocol version 0"));
+
+        // A line that never ends gets the client dropped instead of buffered
+        let stream = other.0.get_mut();
+        stream.write_all(&[b'x'; 2 * MAX_LINE_LENGTH]).unwrap();
+        assert_eq!(other.read(), "ERROR line too long");
+
+        stop.store(true, Ordering::Relaxed);
+        running.join().unwrap();
+    }
+}
@@ -1,13 +1,14 @@
-use std::iter::repeat;
-use std::iter::zip;
+use std::iter::repeat_n;
+use std::rc::Rc;
 
 use crate::control;
-use crate::control::CBPosition;
+use crate::control::{CBPosition, Geometry};
 use crate::model::Side;
-use crate::model::{Board, Piece};
+use crate::model::{Board, Piece, PieceType};
+use crate::ui::pieces::PieceStyle;
+use crate::ui::theme::Theme;
 use color_eyre::owo_colors::Color as BadColor2;
 use color_eyre::owo_colors::OwoColorize as BadColor;
-use crossterm::style::Color;
 use log::debug;
 use log::info;
 use log::warn;
@@ -16,25 +17,114 @@ use ratatui::widgets as w;
 use ratatui::widgets::Block;
 use ratatui::widgets::Paragraph;
 use ratatui::widgets::Widget;
+use serde::Deserialize;
+
+/// How much of the board is drawn, for playing blindfold with the moves typed in notation.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Blindfold {
+    /// The board is drawn as usual
+    #[default]
+    Off,
+    /// The empty squares and coordinates, without the pieces
+    Grid,
+    /// Nothing at all
+    Hidden,
+}
+
+impl Blindfold {
+    pub fn next(&self) -> Blindfold {
+        match self {
+            Blindfold::Off => Blindfold::Grid,
+            Blindfold::Grid => Blindfold::Hidden,
+            Blindfold::Hidden => Blindfold::Off,
+        }
+    }
+
+    /// The name used for the option in the config file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Blindfold::Off => "off",
+            Blindfold::Grid => "grid",
+            Blindfold::Hidden => "hidden",
+        }
+    }
+
+    pub fn is_on(&self) -> bool {
+        *self != Blindfold::Off
+    }
+}
 
 #[derive(Clone, Copy)]
 pub struct Chessboard<'a> {
     board: &'a Board,
+    geometry: Geometry,
     cursor: CBPosition,
     valid_moves: &'a Vec<CBPosition>,
+    theme: &'a Theme,
+    flipped: bool,
+    piece_style: PieceStyle,
+    last_move: Option<(CBPosition, CBPosition)>,
+    /// A move to show as the one to play, such as in the opening trainer after a mistake
+    expected_move: Option<(CBPosition, CBPosition)>,
+    in_check: Option<Side>,
+    blindfold: Blindfold,
+    /// Whether the files and ranks are labelled
+    coordinates: bool,
 }
 impl<'a> Chessboard<'a> {
     pub fn new(
         board: &'a Board,
         cursor: CBPosition,
         valid_moves: &'a Vec<CBPosition>,
+        theme: &'a Theme,
     ) -> Chessboard<'a> {
         Chessboard {
             board,
+            geometry: Geometry::of(board),
             cursor,
             valid_moves,
+            theme,
+            flipped: false,
+            piece_style: PieceStyle::default(),
+            last_move: None,
+            expected_move: None,
+            in_check: None,
+            blindfold: Blindfold::Off,
+            coordinates: true,
         }
     }
+    pub fn flipped(mut self, flipped: bool) -> Chessboard<'a> {
+        self.flipped = flipped;
+        self
+    }
+    pub fn piece_style(mut self, piece_style: PieceStyle) -> Chessboard<'a> {
+        self.piece_style = piece_style;
+        self
+    }
+    pub fn last_move(mut self, last_move: Option<(CBPosition, CBPosition)>) -> Chessboard<'a> {
+        self.last_move = last_move;
+        self
+    }
+    pub fn expected_move(
+        mut self,
+        expected_move: Option<(CBPosition, CBPosition)>,
+    ) -> Chessboard<'a> {
+        self.expected_move = expected_move;
+        self
+    }
+    pub fn in_check(mut self, in_check: Option<Side>) -> Chessboard<'a> {
+        self.in_check = in_check;
+        self
+    }
+    pub fn blindfold(mut self, blindfold: Blindfold) -> Chessboard<'a> {
+        self.blindfold = blindfold;
+        self
+    }
+    pub fn coordinates(mut self, coordinates: bool) -> Chessboard<'a> {
+        self.coordinates = coordinates;
+        self
+    }
 }
 
 impl<'a> Widget for Chessboard<'a> {
@@ -44,18 +134,67 @@ impl<'a> Widget for Chessboard<'a> {
     {
         // Paragraph::new("Plog").render(area, buf);
 
-        let (board_size_width, board_size_height) = if true || area.width > area.height {
-            ((area.height as f32 * 2.5).floor() as u16, area.height)
-        } else {
-            ((area.height as f32 * 2.5).floor() as u16, area.height)
-            //(area.width, (area.width as f32 * 2.5).floor() as u16)
-        };
-        let square_width = (board_size_width as f32 / 9.0).floor() as u16;
-        let square_height = (board_size_height as f32 / 9.0).floor() as u16;
-        let mut col_constraints = Constraint::from_mins(repeat(square_height).take(9));
-        col_constraints.append(&mut Constraint::from_maxes(repeat(square_height).take(9)));
-        let mut row_constraints = Constraint::from_mins(repeat(square_width).take(9));
-        row_constraints.append(&mut Constraint::from_maxes(repeat(square_height).take(9)));
+        if self.blindfold == Blindfold::Hidden {
+            return;
+        }
+        let geometry = self.geometry;
+        let grid = Self::grid(area, geometry);
+
+        for col_let in geometry.files().filter(|_| self.coordinates) {
+            let pos = CBPosition { col: col_let, row: 1 };
+            let (_, x) = Self::cell_of(pos, self.flipped, geometry);
+            Paragraph::new(String::from(col_let)).render(grid[geometry.ranks as usize][x], buf)
+        }
+        for row in geometry.ranks() {
+            let (y, _) = Self::cell_of(CBPosition { col: 'a', row }, self.flipped, geometry);
+            if self.coordinates {
+                Paragraph::new(row.to_string()).render(grid[y][0], buf);
+            }
+            for col_let in geometry.files() {
+                let pos = CBPosition { col: col_let, row };
+                // Blindfold boards draw every square empty, so nothing gives a piece away
+                let piece = match self.board.get(&pos) {
+                    Some(p) if !self.blindfold.is_on() => p,
+                    _ => &None,
+                };
+                let valid = self.valid_moves.contains(&pos)
+                    || self
+                        .expected_move
+                        .is_some_and(|(from, to)| pos == from || pos == to);
+                let square = ChessboardSquare {
+                    piece,
+                    colour: SquareColour::of(pos),
+                    selected: self.cursor == pos,
+                    valid,
+                    capture: valid && piece.is_some(),
+                    last_move: self
+                        .last_move
+                        .is_some_and(|(from, to)| pos == from || pos == to),
+                    check: piece.is_some_and(|p| {
+                        p.piece_type == PieceType::King && Some(p.side) == self.in_check
+                    }),
+                    piece_style: self.piece_style,
+                    theme: self.theme,
+                };
+                let (y, x) = Self::cell_of(pos, self.flipped, geometry);
+                square.render(grid[y][x], buf);
+            }
+        }
+    }
+}
+impl<'a> Chessboard<'a> {
+    /// Splits the area into the grid of squares and labels, one more each way than the
+    /// board has files and ranks, indexed by row from the top then column from the left. The
+    /// bottom row holds the file labels and the leftmost column the rank labels.
+    fn grid(area: Rect, geometry: Geometry) -> Vec<Rc<[Rect]>> {
+        let (rows, cols) = (geometry.ranks as usize + 1, geometry.files as usize + 1);
+        let (board_size_width, board_size_height) = (Self::width(area, geometry), area.height);
+        let square_width = (board_size_width as f32 / cols as f32).floor() as u16;
+        let square_height = (board_size_height as f32 / rows as f32).floor() as u16;
+        let mut col_constraints = Constraint::from_mins(repeat_n(square_height, rows));
+        col_constraints.append(&mut Constraint::from_maxes(repeat_n(square_height, rows)));
+        let mut row_constraints = Constraint::from_mins(repeat_n(square_width, cols));
+        row_constraints.append(&mut Constraint::from_maxes(repeat_n(square_height, cols)));
 
         let board_rect = Rect::new(area.x, area.y, board_size_width, board_size_height);
 
@@ -64,41 +203,53 @@ impl<'a> Widget for Chessboard<'a> {
             .constraints(col_constraints)
             .split(board_rect);
 
-        for row in (0..=8) {
-            let mut current_colour = if row % 2 == 0 {
-                SquareColour::White
-            } else {
-                SquareColour::Black
-            };
-
-            let row_layout = Layout::default()
-                .direction(Direction::Horizontal)
-                .constraints(row_constraints.clone())
-                .split(whole_board[8 - row]);
-
-            if row == 0 {
-                for (col, col_let) in zip((1..=8).rev(), ('a'..='h').rev()) {
-                    Paragraph::new(String::from(col_let)).render(row_layout[col], buf)
-                }
-            } else {
-                Paragraph::new(row.to_string()).render(row_layout[0], buf);
-                for (col, col_let) in zip((1..=8).rev(), ('a'..='h').rev()) {
-                    let piece = match self.board.get(&CBPosition { col: col_let, row }) {
-                        Some(p) => p,
-                        None => &None,
-                    };
-                    let pos = CBPosition { col: col_let, row };
-                    let square = ChessboardSquare {
-                        piece,
-                        colour: current_colour,
-                        selected: self.cursor == pos,
-                        valid: self.valid_moves.contains(&pos),
-                    };
-                    square.render(row_layout[col], buf);
-                    current_colour.flip();
-                }
+        whole_board
+            .iter()
+            .map(|row_rect| {
+                Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints(row_constraints.clone())
+                    .split(*row_rect)
+            })
+            .collect()
+    }
+
+    /// The (row, column) of the grid cell a square is drawn in. Unflipped boards have rank 1
+    /// at the bottom and the a-file on the left, flipped boards mirror both.
+    fn cell_of(pos: CBPosition, flipped: bool, geometry: Geometry) -> (usize, usize) {
+        let file = (pos.col as u8 - b'a') as usize;
+        if flipped {
+            (pos.row - 1, geometry.files as usize - file)
+        } else {
+            (geometry.ranks as usize - pos.row, file + 1)
+        }
+    }
+
+    /// How wide the board is drawn in the area, which is set by its height so the squares
+    /// keep their shape.
+    pub fn width(area: Rect, geometry: Geometry) -> u16 {
+        let cells_wide = (geometry.files + 1) as f32 / (geometry.ranks + 1) as f32;
+        ((area.height as f32 * 2.5 * cells_wide).floor() as u16).min(area.width)
+    }
+
+    /// Finds the square under a terminal cell, using the same layout as `render`.
+    pub fn position_at(
+        area: Rect,
+        x: u16,
+        y: u16,
+        flipped: bool,
+        geometry: Geometry,
+    ) -> Option<CBPosition> {
+        let grid = Self::grid(area, geometry);
+        for pos in geometry.squares() {
+            let (cell_y, cell_x) = Self::cell_of(pos, flipped, geometry);
+            let square = grid[cell_y][cell_x];
+            if x >= square.left() && x < square.right() && y >= square.top() && y < square.bottom()
+            {
+                return Some(pos);
             }
         }
+        None
     }
 }
 
@@ -107,45 +258,70 @@ struct ChessboardSquare<'a> {
     colour: SquareColour,
     selected: bool,
     valid: bool,
+    capture: bool,
+    last_move: bool,
+    check: bool,
+    piece_style: PieceStyle,
+    theme: &'a Theme,
 }
 impl<'a> Widget for ChessboardSquare<'a> {
     fn render(self, area: Rect, buf: &mut Buffer)
     where
         Self: Sized,
     {
-        let (square_text, fg_color) = match self.piece {
-            Some(p) => match p.piece_type {
-                crate::model::PieceType::King => ("K", p.side.to_color()),
-
-                crate::model::PieceType::Queen => ("Q", p.side.to_color()),
-
-                crate::model::PieceType::Rook => ("R", p.side.to_color()),
-
-                crate::model::PieceType::Bishop => ("B", p.side.to_color()),
-
-                crate::model::PieceType::Knight => ("N", p.side.to_color()),
-
-                crate::model::PieceType::Pawn => ("P", p.side.to_color()),
-            },
-            None => ("", ratatui::prelude::Color::Black), //Block::new().bg(self.colour.to_color().into()),
-        };
-        let mut square_text = square_text.to_string();
-        if self.selected {
-            Paragraph::new(square_text)
-                .fg(fg_color)
-                .bg(self.colour.to_selected_color().into())
-                .render(area, buf);
+        let bg_color = if self.selected {
+            self.colour.to_selected_color(self.theme)
+        } else if self.capture {
+            self.theme.capture_move
         } else if self.valid {
-            Paragraph::new(square_text)
-                .fg(fg_color)
-                .bg(SQUARE_VALID_FOR_MOVE.into())
-                .render(area, buf);
+            self.theme.valid_move
+        } else if self.check {
+            self.theme.check
+        } else if self.last_move {
+            self.theme.last_move
         } else {
-            Paragraph::new(square_text)
-                .fg(fg_color)
-                .bg(self.colour.to_color().into())
-                .render(area, buf);
+            self.colour.to_color(self.theme)
+        };
+        let glyph = match self.piece {
+            Some(p) => self.piece_style.glyph(p, area.width, area.height),
+            None => Vec::new(),
         };
+        // Pad above the piece so it sits in the middle of the square
+        let padding = (area.height as usize).saturating_sub(glyph.len()) / 2;
+        let lines: Vec<Line> = repeat_n("", padding)
+            .chain(glyph)
+            .map(Line::from)
+            .collect();
+        Paragraph::new(lines)
+            .centered()
+            .style(Style::new().fg(contrasting_fg(bg_color)).bg(bg_color))
+            .render(area, buf);
+    }
+}
+
+/// Picks black or white text, whichever reads better on the given background, so pieces of
+/// both sides stay visible on light and dark squares alike. The side is told apart by the
+/// glyph instead of the colour.
+fn contrasting_fg(bg: Color) -> Color {
+    let is_light = match bg {
+        Color::Rgb(r, g, b) => 0.299 * r as f32 + 0.587 * g as f32 + 0.114 * b as f32 > 140.0,
+        Color::White
+        | Color::Gray
+        | Color::Yellow
+        | Color::Green
+        | Color::Cyan
+        | Color::LightRed
+        | Color::LightGreen
+        | Color::LightYellow
+        | Color::LightBlue
+        | Color::LightMagenta
+        | Color::LightCyan => true,
+        _ => false,
+    };
+    if is_light {
+        Color::Black
+    } else {
+        Color::White
     }
 }
 
@@ -155,53 +331,25 @@ pub enum SquareColour {
     White,
 }
 
-static BLACK_SQUARE_COLOUR: Color = Color::Rgb {
-    r: 76,
-    g: 58,
-    b: 46,
-};
-
-static WHITE_SQUARE_COLOUR: Color = Color::Rgb {
-    r: 245,
-    g: 226,
-    b: 183,
-};
-
-static BLACK_SQUARE_COLOUR_SELECTED: Color = Color::Rgb {
-    r: 115,
-    g: 93,
-    b: 78,
-};
-
-static WHITE_SQUARE_COLOUR_SELECTED: Color = Color::Rgb {
-    r: 184,
-    g: 120,
-    b: 134,
-};
-
-static SQUARE_VALID_FOR_MOVE: Color = Color::Rgb {
-    r: 50,
-    g: 255,
-    b: 50,
-};
-
 impl SquareColour {
-    pub fn flip(&mut self) {
-        match self {
-            SquareColour::Black => *self = SquareColour::White,
-            SquareColour::White => *self = SquareColour::Black,
+    /// The colour of a square on the board, a1 being dark.
+    pub fn of(pos: CBPosition) -> SquareColour {
+        if (pos.col as usize - 'a' as usize + pos.row).is_multiple_of(2) {
+            SquareColour::White
+        } else {
+            SquareColour::Black
         }
     }
-    pub fn to_color(&self) -> Color {
+    pub fn to_color(self, theme: &Theme) -> Color {
         match self {
-            SquareColour::Black => BLACK_SQUARE_COLOUR,
-            SquareColour::White => WHITE_SQUARE_COLOUR,
+            SquareColour::Black => theme.dark_square,
+            SquareColour::White => theme.light_square,
         }
     }
-    pub fn to_selected_color(&self) -> Color {
+    pub fn to_selected_color(self, theme: &Theme) -> Color {
         match self {
-            SquareColour::Black => BLACK_SQUARE_COLOUR_SELECTED,
-            SquareColour::White => WHITE_SQUARE_COLOUR_SELECTED,
+            SquareColour::Black => theme.dark_square_selected,
+            SquareColour::White => theme.light_square_selected,
         }
     }
 }
@@ -0,0 +1,186 @@
+use std::{fs, path::PathBuf};
+
+use log::*;
+use serde::{de::Error, Deserialize, Deserializer};
+
+use super::{chessboard::Blindfold, pieces::PieceStyle, theme::Theme};
+
+/// User settings, read from `eden_chess/config.toml` in the platform config directory
+/// (`~/.config/eden_chess/config.toml` on Linux).
+///
+/// ```toml
+/// theme = "ocean"
+/// piece_style = "unicode"
+///
+/// [[themes]]
+/// name = "ocean"
+/// dark_square = "#1f4e79"
+/// light_square = "#dbe9f6"
+/// ```
+///
+/// Pieces of your own can be added with `[[pieces]]`, which the model reads, see
+/// `model::custom`.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// The theme to start with, either a preset or one of `themes`
+    pub theme: Option<String>,
+    pub piece_style: PieceStyle,
+    pub flip_board: bool,
+    pub follow_side_to_move: bool,
+    /// Whether to hide the pieces and take moves typed in notation
+    pub blindfold: Blindfold,
+    /// Custom themes, each with a name. Any colours left out are taken from the classic theme
+    #[serde(deserialize_with = "named_themes")]
+    pub themes: Vec<Theme>,
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("eden_chess").join("config.toml"))
+    }
+
+    /// Loads the config file, falling back to the defaults if it is missing or invalid.
+    pub fn load() -> Config {
+        let Some(path) = Config::path() else {
+            warn!("No config directory on this platform, using default config");
+            return Config::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => Config::parse(&contents).unwrap_or_else(|e| {
+                error!("Invalid config file {}: {}", path.display(), e);
+                Config::default()
+            }),
+            Err(e) => {
+                info!("No config loaded from {}: {}", path.display(), e);
+                Config::default()
+            }
+        }
+    }
+
+    /// Writes the settings chosen in the UI to the config file, keeping the custom themes
+    /// and anything else already in it.
+    pub fn save_settings(&self) -> Result<PathBuf, String> {
+        let path = Config::path().ok_or("No config directory on this platform")?;
+        let mut table = match fs::read_to_string(&path) {
+            Ok(contents) => contents
+                .parse::<toml::Table>()
+                .map_err(|e| format!("Invalid config file {}: {}", path.display(), e))?,
+            Err(_) => toml::Table::new(),
+        };
+        if let Some(theme) = &self.theme {
+            table.insert("theme".to_string(), theme.clone().into());
+        }
+        table.insert(
+            "piece_style".to_string(),
+            self.piece_style.name().to_string().into(),
+        );
+        table.insert("flip_board".to_string(), self.flip_board.into());
+        table.insert(
+            "follow_side_to_move".to_string(),
+            self.follow_side_to_move.into(),
+        );
+        table.insert(
+            "blindfold".to_string(),
+            self.blindfold.name().to_string().into(),
+        );
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(&path, table.to_string()).map_err(|e| e.to_string())?;
+        info!("Saved settings to {}", path.display());
+        Ok(path)
+    }
+
+    fn parse(contents: &str) -> Result<Config, toml::de::Error> {
+        toml::from_str(contents)
+    }
+
+    /// The presets followed by the custom themes. A custom theme sharing a preset's name
+    /// replaces that preset.
+    pub fn themes(&self) -> Vec<Theme> {
+        let mut themes = Theme::presets();
+        for custom in self.themes.iter() {
+            match themes.iter_mut().find(|t| t.name == custom.name) {
+                Some(existing) => *existing = custom.clone(),
+                None => themes.push(custom.clone()),
+            }
+        }
+        themes
+    }
+
+    /// Index into `themes` of the theme named in the config, or the first theme.
+    pub fn starting_theme(&self, themes: &[Theme]) -> usize {
+        let Some(name) = &self.theme else {
+            return 0;
+        };
+        themes
+            .iter()
+            .position(|t| &t.name == name)
+            .unwrap_or_else(|| {
+                warn!("Theme {} not found, using {}", name, themes[0].name);
+                0
+            })
+    }
+}
+
+/// Refuses custom themes without a name, which would otherwise pass for one of the presets.
+fn named_themes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<Theme>, D::Error> {
+    let themes = Vec::<Theme>::deserialize(deserializer)?;
+    match themes.iter().position(|theme| theme.name.is_empty()) {
+        Some(i) => Err(D::Error::custom(format!(
+            "custom theme {} has no name",
+            i + 1
+        ))),
+        None => Ok(themes),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ratatui::style::Color;
+
+    use super::*;
+
+    #[test]
+    fn custom_themes() {
+        let config = Config::parse(
+            r##"
+            theme = "ocean"
+            piece_style = "art"
+            blindfold = "grid"
+
+            [[themes]]
+            name = "ocean"
+            dark_square = "#1f4e79"
+
+            [[themes]]
+            name = "classic"
+            valid_move = "blue"
+            "##,
+        )
+        .unwrap();
+        assert_eq!(config.piece_style, PieceStyle::Art);
+        assert_eq!(config.blindfold, Blindfold::Grid);
+
+        let themes = config.themes();
+        assert_eq!(themes.len(), Theme::presets().len() + 1);
+        let ocean = &themes[config.starting_theme(&themes)];
+        assert_eq!(ocean.name, "ocean");
+        assert_eq!(ocean.dark_square, Color::Rgb(0x1f, 0x4e, 0x79));
+        assert_eq!(ocean.light_square, Theme::classic().light_square);
+        assert_eq!(themes[0].valid_move, Color::Blue);
+    }
+
+    #[test]
+    fn unknown_theme_falls_back() {
+        let config = Config::parse("theme = \"nonexistent\"").unwrap();
+        assert_eq!(config.starting_theme(&config.themes()), 0);
+    }
+
+    #[test]
+    fn custom_theme_needs_a_name() {
+        assert!(Config::parse("[[themes]]\ndark_square = \"red\"").is_err());
+        assert!(Config::parse("[[themes]]\nname = \"\"").is_err());
+    }
+}
@@ -0,0 +1,350 @@
+use std::time::{Duration, Instant};
+
+use crossterm::event::KeyCode;
+use rand::seq::SliceRandom;
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Clear, Paragraph, Widget};
+
+use super::chessboard::Chessboard;
+use super::menu::FormAction;
+use super::theme::Theme;
+use crate::control::{CBPosition, Geometry};
+use crate::model::{Board, Side};
+
+/// How long a round of the coordinate trainer lasts
+pub const ROUND: Duration = Duration::from_secs(30);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CoordinateMode {
+    /// A square is named and found on the board with the cursor or a click
+    Find,
+    /// A square is highlighted and its name typed
+    Name,
+}
+
+impl CoordinateMode {
+    fn next(&self) -> CoordinateMode {
+        match self {
+            CoordinateMode::Find => CoordinateMode::Name,
+            CoordinateMode::Name => CoordinateMode::Find,
+        }
+    }
+}
+
+impl std::fmt::Display for CoordinateMode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            CoordinateMode::Find => "Find the square",
+            CoordinateMode::Name => "Name the square",
+        })
+    }
+}
+
+/// Drills reading the board without its labels, scoring the squares found or named in a
+/// timed round.
+pub struct CoordinateTrainer {
+    mode: CoordinateMode,
+    /// The side whose view of the board is shown
+    side: Side,
+    /// The empty board the squares are shown on
+    board: Board,
+    target: CBPosition,
+    cursor: CBPosition,
+    /// The name typed so far in `Name` mode
+    typed: String,
+    /// When the round began, `None` before the first round
+    started: Option<Instant>,
+    correct: u32,
+    wrong: u32,
+    /// The most found in a finished round of this mode
+    best: u32,
+    /// The last wrong answer, with the square that was asked for
+    mistake: Option<(CBPosition, CBPosition)>,
+}
+
+impl Default for CoordinateTrainer {
+    fn default() -> Self {
+        CoordinateTrainer {
+            mode: CoordinateMode::Find,
+            side: Side::White,
+            board: Geometry::STANDARD
+                .squares()
+                .into_iter()
+                .map(|pos| (pos, None))
+                .collect(),
+            target: CBPosition { col: 'e', row: 4 },
+            cursor: CBPosition { col: 'a', row: 1 },
+            typed: String::new(),
+            started: None,
+            correct: 0,
+            wrong: 0,
+            best: 0,
+            mistake: None,
+        }
+    }
+}
+
+impl CoordinateTrainer {
+    pub fn handle_key(&mut self, key: KeyCode) -> FormAction {
+        if key == KeyCode::Esc {
+            return FormAction::Back;
+        }
+        if !self.running() {
+            match key {
+                KeyCode::Tab => {
+                    self.mode = self.mode.next();
+                    self.best = 0;
+                }
+                KeyCode::Char('f') => self.side.flip(),
+                KeyCode::Enter | KeyCode::Char(' ') => self.start(),
+                _ => {}
+            }
+            return FormAction::None;
+        }
+        let geometry = Geometry::STANDARD;
+        match (self.mode, key) {
+            (CoordinateMode::Find, KeyCode::Left) => {
+                self.cursor.move_cursor_left(self.flipped(), geometry)
+            }
+            (CoordinateMode::Find, KeyCode::Right) => {
+                self.cursor.move_cursor_right(self.flipped(), geometry)
+            }
+            (CoordinateMode::Find, KeyCode::Up) => {
+                self.cursor.move_cursor_up(self.flipped(), geometry)
+            }
+            (CoordinateMode::Find, KeyCode::Down) => {
+                self.cursor.move_cursor_down(self.flipped(), geometry)
+            }
+            (CoordinateMode::Find, KeyCode::Enter | KeyCode::Char(' ')) => self.answer(self.cursor),
+            (CoordinateMode::Name, KeyCode::Backspace) => {
+                self.typed.pop();
+            }
+            (CoordinateMode::Name, KeyCode::Char(c)) => {
+                self.typed.push(c.to_ascii_lowercase());
+                // Every square of the board is a letter and a digit
+                if self.typed.len() == 2 {
+                    let typed = std::mem::take(&mut self.typed);
+                    match typed.parse::<CBPosition>() {
+                        Ok(pos) if geometry.contains(pos) => self.answer(pos),
+                        _ => self.answer_wrong(None),
+                    }
+                }
+            }
+            _ => {}
+        }
+        FormAction::None
+    }
+
+    /// Answers with a clicked square in `Find` mode.
+    pub fn click(&mut self, pos: CBPosition) {
+        if self.running() && self.mode == CoordinateMode::Find {
+            self.cursor = pos;
+            self.answer(pos);
+        }
+    }
+
+    pub fn flipped(&self) -> bool {
+        self.side == Side::Black
+    }
+
+    fn start(&mut self) {
+        self.best = self.best();
+        self.correct = 0;
+        self.wrong = 0;
+        self.typed.clear();
+        self.mistake = None;
+        self.next_target();
+        self.started = Some(Instant::now());
+    }
+
+    fn answer(&mut self, pos: CBPosition) {
+        if pos == self.target {
+            self.correct += 1;
+            self.mistake = None;
+            self.next_target();
+        } else {
+            self.answer_wrong(Some(pos));
+        }
+    }
+
+    fn answer_wrong(&mut self, pos: Option<CBPosition>) {
+        self.wrong += 1;
+        self.mistake = pos.map(|pos| (pos, self.target));
+    }
+
+    /// Picks a square to ask for, never the one just asked for.
+    fn next_target(&mut self) {
+        let squares: Vec<CBPosition> = Geometry::STANDARD
+            .squares()
+            .into_iter()
+            .filter(|pos| *pos != self.target)
+            .collect();
+        if let Some(target) = squares.choose(&mut rand::thread_rng()) {
+            self.target = *target;
+        }
+    }
+
+    pub fn time_left(&self) -> Duration {
+        self.started
+            .map_or(ROUND, |started| ROUND.saturating_sub(started.elapsed()))
+    }
+
+    fn running(&self) -> bool {
+        self.started.is_some() && !self.time_left().is_zero()
+    }
+
+    /// The most squares found in a round of this mode, counting the round just finished.
+    pub fn best(&self) -> u32 {
+        if self.started.is_some() && !self.running() {
+            self.best.max(self.correct)
+        } else {
+            self.best
+        }
+    }
+
+    /// Where the board is drawn in the area the trainer is drawn in, for finding clicks.
+    pub fn board_area(area: Rect) -> Rect {
+        let inner = Block::new().borders(Borders::ALL).inner(area);
+        Layout::default()
+            .constraints([
+                Constraint::Length(2),
+                Constraint::Min(0),
+                Constraint::Length(2),
+            ])
+            .split(inner)[1]
+    }
+
+    fn prompt(&self) -> Line<'static> {
+        if self.started.is_none() {
+            return Line::from(self.mode.to_string()).bold();
+        }
+        if !self.running() {
+            return Line::from(format!(
+                "Time! {} found, {} wrong, best {}",
+                self.correct,
+                self.wrong,
+                self.best()
+            ))
+            .bold();
+        }
+        let ask = match self.mode {
+            CoordinateMode::Find => format!("Find {:?}", self.target),
+            CoordinateMode::Name => format!("Name the highlighted square: {}_", self.typed),
+        };
+        let feedback = match self.mistake {
+            Some((answer, target)) => format!(", that was {:?} not {:?}", answer, target),
+            None => String::new(),
+        };
+        Line::from(format!(
+            "{}   {} found, {} wrong, {}s left{}",
+            ask,
+            self.correct,
+            self.wrong,
+            self.time_left().as_secs(),
+            feedback
+        ))
+        .bold()
+    }
+
+    fn help(&self) -> &'static str {
+        match (self.running(), self.mode) {
+            (false, _) => {
+                "Tab to switch mode, f to flip the board, Enter to start a 30 second round. \
+                 Esc to go back"
+            }
+            (true, CoordinateMode::Find) => {
+                "Move the cursor to the square and press Enter, or click it. Esc to go back"
+            }
+            (true, CoordinateMode::Name) => "Type the square's file and rank. Esc to go back",
+        }
+    }
+}
+
+/// Draws the trainer over the whole screen.
+pub struct CoordinateView<'a> {
+    trainer: &'a CoordinateTrainer,
+    theme: &'a Theme,
+}
+
+impl<'a> CoordinateView<'a> {
+    pub fn new(trainer: &'a CoordinateTrainer, theme: &'a Theme) -> CoordinateView<'a> {
+        CoordinateView { trainer, theme }
+    }
+}
+
+impl<'a> Widget for CoordinateView<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let trainer = self.trainer;
+        Clear.render(area, buf);
+        let block = Block::new()
+            .title("Coordinate trainer")
+            .borders(Borders::ALL);
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let layout = Layout::default()
+            .constraints([
+                Constraint::Length(2),
+                Constraint::Min(0),
+                Constraint::Length(2),
+            ])
+            .split(inner);
+        Paragraph::new(trainer.prompt()).render(layout[0], buf);
+
+        // The square to name is shown as the cursor, the one to find isn't marked at all
+        let cursor = match trainer.mode {
+            CoordinateMode::Name if trainer.running() => trainer.target,
+            _ => trainer.cursor,
+        };
+        let none = Vec::new();
+        Chessboard::new(&trainer.board, cursor, &none, self.theme)
+            .flipped(trainer.flipped())
+            .coordinates(false)
+            .render(CoordinateTrainer::board_area(area), buf);
+        Paragraph::new(trainer.help()).dim().render(layout[2], buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scores_a_round() {
+        let mut trainer = CoordinateTrainer::default();
+        trainer.handle_key(KeyCode::Enter);
+        assert!(trainer.running());
+
+        trainer.click(trainer.target);
+        let target = trainer.target;
+        let wrong = Geometry::STANDARD
+            .squares()
+            .into_iter()
+            .find(|pos| *pos != target)
+            .unwrap();
+        trainer.click(wrong);
+        assert_eq!((trainer.correct, trainer.wrong), (1, 1));
+        assert_eq!(trainer.mistake, Some((wrong, target)));
+
+        // Answers after the time is up don't count
+        trainer.started = Some(Instant::now() - ROUND);
+        trainer.click(trainer.target);
+        assert_eq!(trainer.correct, 1);
+        assert_eq!(trainer.best(), 1);
+    }
+
+    #[test]
+    fn names_squares() {
+        let mut trainer = CoordinateTrainer::default();
+        trainer.handle_key(KeyCode::Tab);
+        trainer.handle_key(KeyCode::Enter);
+        assert_eq!(trainer.mode, CoordinateMode::Name);
+
+        let name = format!("{:?}", trainer.target).to_uppercase();
+        name.chars()
+            .for_each(|c| _ = trainer.handle_key(KeyCode::Char(c)));
+        trainer.handle_key(KeyCode::Char('z'));
+        trainer.handle_key(KeyCode::Char('9'));
+        assert_eq!((trainer.correct, trainer.wrong), (1, 1));
+        assert!(trainer.typed.is_empty());
+    }
+}
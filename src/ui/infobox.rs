@@ -1,33 +1,144 @@
 use ratatui::prelude::*;
-use ratatui::widgets::{Block, Borders, Paragraph};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
 use ratatui::{
     layout::{Constraint, Layout, Rect},
     widgets::Widget,
 };
 
 use super::chessboard::SquareColour;
+use super::theme::Theme;
 use super::GameData;
+use crate::control::{EndgameStatus, PuzzleStatus, RepertoireStatus};
+use crate::model::{Material, PieceType, Side};
 
 pub struct Infobox<'a> {
     game_data: &'a GameData,
+    theme: &'a Theme,
 }
 
 impl<'a> Infobox<'a> {
-    pub fn new(game_data: &GameData) -> Infobox {
-        Infobox { game_data }
+    pub fn new(game_data: &'a GameData, theme: &'a Theme) -> Infobox<'a> {
+        Infobox { game_data, theme }
     }
 
-    fn create_layout(area: Rect) -> (Rect, Rect, Rect) {
+    fn create_layout(area: Rect) -> (Rect, Rect, Rect, Rect, Rect) {
         let temp_layout = Layout::default()
-            .constraints([Constraint::Percentage(10), Constraint::Percentage(90)])
+            .constraints([
+                Constraint::Percentage(10),
+                Constraint::Length(3),
+                Constraint::Length(4),
+                Constraint::Min(0),
+            ])
             .split(area);
-        let (top, move_history_rect) = (temp_layout[0], temp_layout[1]);
+        let (top, players_rect, material_rect, move_history_rect) = (
+            temp_layout[0],
+            temp_layout[1],
+            temp_layout[2],
+            temp_layout[3],
+        );
         let temp_layout = Layout::default()
             .constraints([Constraint::Percentage(50), Constraint::Percentage(50)])
             .direction(Direction::Horizontal)
             .split(top);
         let (turn_count_rect, turn_side_rect) = (temp_layout[0], temp_layout[1]);
-        (turn_count_rect, turn_side_rect, move_history_rect)
+        (
+            turn_count_rect,
+            turn_side_rect,
+            players_rect,
+            material_rect,
+            move_history_rect,
+        )
+    }
+
+    /// One line per side with who is playing it and their clock in a timed game.
+    fn player_lines(game_data: &GameData) -> Vec<Line<'static>> {
+        [Side::White, Side::Black]
+            .into_iter()
+            .map(|side| {
+                let clock = match game_data.clock(side) {
+                    Some(left) => {
+                        format!("{:>2}:{:02}  ", left.as_secs() / 60, left.as_secs() % 60)
+                    }
+                    None => String::new(),
+                };
+                Line::from(format!(
+                    "{:<7}{}{}",
+                    String::from(side),
+                    clock,
+                    game_data.player(side)
+                ))
+            })
+            .collect()
+    }
+
+    /// The side the solver plays with their rating, and the puzzle's themes.
+    fn puzzle_lines(puzzle: &PuzzleStatus) -> Vec<Line<'static>> {
+        vec![
+            Line::from(format!(
+                "{} to play, your rating {}",
+                String::from(puzzle.side),
+                puzzle.solver_rating
+            )),
+            Line::from(puzzle.themes.join(", ")).dim(),
+        ]
+    }
+
+    /// The side being trained, the mistakes in this line and how much of the repertoire is
+    /// due for review.
+    fn repertoire_lines(repertoire: &RepertoireStatus) -> Vec<Line<'static>> {
+        vec![
+            Line::from(format!(
+                "Playing {}, {} mistakes this line",
+                String::from(repertoire.side),
+                repertoire.mistakes
+            )),
+            Line::from(format!(
+                "{} of {} positions due",
+                repertoire.due, repertoire.positions
+            ))
+            .dim(),
+        ]
+    }
+
+    /// The side being trained with the goal, and the moves so far against the fewest
+    /// possible.
+    fn endgame_lines(endgame: &EndgameStatus) -> Vec<Line<'static>> {
+        let optimal = match endgame.optimal {
+            Some(optimal) => format!("{} moves, {} possible", endgame.moves, optimal),
+            None => format!("{} moves", endgame.moves),
+        };
+        let defence = if endgame.perfect {
+            "perfect defence"
+        } else {
+            "engine defence"
+        };
+        vec![
+            Line::from(format!(
+                "Playing {}: {}",
+                String::from(endgame.side),
+                endgame.endgame.goal()
+            )),
+            Line::from(format!("{}, {}", optimal, defence)).dim(),
+        ]
+    }
+
+    /// One line per side listing the pieces it has taken, with the material lead after the
+    /// side that is ahead.
+    fn material_lines(material: &Material) -> Vec<Line<'static>> {
+        let line = |side: &str, captured: &Vec<PieceType>, lead: isize| {
+            let mut text = format!("{:<7}", side);
+            for piece_type in captured {
+                text.push_str(&format!("{} ", piece_type));
+            }
+            if lead > 0 {
+                text.push_str(&format!(" +{}", lead));
+            }
+            Line::from(text)
+        };
+        vec![
+            line("White", &material.captured_by_white, material.difference),
+            line("Black", &material.captured_by_black, -material.difference),
+        ]
     }
 }
 impl<'a> Widget for Infobox<'a> {
@@ -35,7 +146,8 @@ impl<'a> Widget for Infobox<'a> {
     where
         Self: Sized,
     {
-        let (turn_count_rect, turn_side_rect, move_history_rect) = Infobox::create_layout(area);
+        let (turn_count_rect, turn_side_rect, players_rect, material_rect, move_history_rect) =
+            Infobox::create_layout(area);
 
         let turn_count_block = Block::new().title("Turn Count");
         let turn_count_inner = turn_count_block.inner(turn_count_rect);
@@ -46,23 +158,76 @@ impl<'a> Widget for Infobox<'a> {
         let turn_side_block = Block::new().title("Who's Turn").borders(Borders::ALL);
         let turn_side_inner = turn_side_block.inner(turn_side_rect);
         turn_side_block.render(turn_side_rect, buf);
-        let turn_side_para = Paragraph::new(self.game_data.which_turn)
-            .bg(SquareColour::from(self.game_data.which_turn)
-                .to_color()
-                .into())
-            .fg(Color::Black)
-            .centered();
-        turn_side_para.render(turn_side_inner, buf);
+        let turn_side_para = match self.game_data.result {
+            Some(result) => Paragraph::new(result.to_string()).bold(),
+            None => Paragraph::new(self.game_data.which_turn)
+                .bg(SquareColour::from(self.game_data.which_turn).to_color(self.theme))
+                .fg(Color::Black),
+        };
+        turn_side_para.centered().render(turn_side_inner, buf);
+
+        // An odds game says who gives what beside the players, and a puzzle, the opening
+        // trainer or an endgame drill replaces them
+        let data = self.game_data;
+        let (players_title, players_lines) = if let Some(puzzle) = &data.puzzle {
+            (
+                format!("Puzzle {}, rated {}", puzzle.id, puzzle.rating),
+                Infobox::puzzle_lines(puzzle),
+            )
+        } else if let Some(repertoire) = &data.repertoire {
+            (
+                "Opening trainer".to_string(),
+                Infobox::repertoire_lines(repertoire),
+            )
+        } else if let Some(endgame) = &data.endgame {
+            (
+                format!("Endgame drill, {}", endgame.endgame),
+                Infobox::endgame_lines(endgame),
+            )
+        } else if let Some(handicap) = data.handicap {
+            (
+                format!("Players, {}", handicap),
+                Infobox::player_lines(data),
+            )
+        } else {
+            ("Players".to_string(), Infobox::player_lines(data))
+        };
+        let players_block = Block::new().title(players_title);
+        let players_inner = players_block.inner(players_rect);
+        players_block.render(players_rect, buf);
+        Paragraph::new(players_lines).render(players_inner, buf);
+
+        let material_block = Block::new().title("Captured");
+        let material_inner = material_block.inner(material_rect);
+        material_block.render(material_rect, buf);
+        Paragraph::new(Infobox::material_lines(&self.game_data.material))
+            .render(material_inner, buf);
 
-        let move_history_str = self
+        let move_history_title = if self.game_data.viewing_ply.is_some() {
+            "Move History (viewing, End to resume)"
+        } else {
+            "Move History"
+        };
+        let move_history_block = Block::new().title(move_history_title);
+        let move_history_inner = move_history_block.inner(move_history_rect);
+        move_history_block.render(move_history_rect, buf);
+        let moves: Vec<ListItem> = self
             .game_data
             .move_history
             .iter()
-            .fold("".to_owned(), |acc, m| format!("{}\n{}", acc, m));
-        let move_history_block = Block::new().title("Move History");
-        let move_history_inner = move_history_block.inner(move_history_rect);
-        move_history_block.render(move_history_rect, buf);
-        let move_history_para = Paragraph::new(move_history_str);
-        move_history_para.render(move_history_inner, buf);
+            .enumerate()
+            .map(|(i, m)| {
+                let dots = if i % 2 == 0 { "." } else { "..." };
+                ListItem::new(format!("{}{} {}", i / 2 + 1, dots, m))
+            })
+            .collect();
+        // The item for a ply is the move that led to it, so the start position has none
+        let selected = match self.game_data.viewing_ply {
+            Some(ply) => ply.checked_sub(1),
+            None => self.game_data.move_history.len().checked_sub(1),
+        };
+        let mut list_state = ListState::default().with_selected(selected);
+        let move_history_list = List::new(moves).highlight_style(Style::new().reversed());
+        StatefulWidget::render(move_history_list, move_history_inner, buf, &mut list_state);
     }
 }
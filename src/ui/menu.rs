@@ -0,0 +1,941 @@
+use std::{fs, path::Path};
+
+use crossterm::event::KeyCode;
+use ratatui::{
+    prelude::*,
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Wrap},
+};
+
+use crate::{
+    control::{
+        Endgame, EndgameSetup, GameSetup, Handicap, Odds, PlayerKind, PuzzleSetup, RepertoireSetup,
+        StartPosition, TimeControl, VariantKind,
+    },
+    model::{engine::MAX_LEVEL, net::DEFAULT_PORT, Side},
+};
+
+/// A boxed list of rows with one selected, used for every screen outside the game.
+pub struct Menu<'a> {
+    title: &'a str,
+    rows: Vec<Line<'a>>,
+    selected: usize,
+    footer: Option<&'a str>,
+}
+
+impl<'a> Menu<'a> {
+    pub fn new(title: &'a str, rows: Vec<Line<'a>>, selected: usize) -> Menu<'a> {
+        Menu {
+            title,
+            rows,
+            selected,
+            footer: None,
+        }
+    }
+
+    /// A line of help or an error shown under the rows.
+    pub fn footer(mut self, footer: Option<&'a str>) -> Menu<'a> {
+        self.footer = footer;
+        self
+    }
+}
+
+impl<'a> Widget for Menu<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let width = area.width.min(70);
+        let height = (self.rows.len() as u16 + 6).min(area.height);
+        let area = Rect {
+            x: area.x + (area.width - width) / 2,
+            y: area.y + (area.height - height) / 2,
+            width,
+            height,
+        };
+        Clear.render(area, buf);
+        let block = Block::new().title(self.title).borders(Borders::ALL);
+        let inner = block.inner(area);
+        block.render(area, buf);
+        let layout = Layout::default()
+            .constraints([Constraint::Min(0), Constraint::Length(3)])
+            .split(inner);
+
+        let items: Vec<ListItem> = self.rows.into_iter().map(ListItem::new).collect();
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        let list = List::new(items)
+            .highlight_style(Style::new().reversed())
+            .highlight_symbol("> ");
+        StatefulWidget::render(list, layout[0], buf, &mut state);
+        if let Some(footer) = self.footer {
+            Paragraph::new(footer)
+                .wrap(Wrap { trim: true })
+                .dim()
+                .render(layout[1], buf);
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MainMenuItem {
+    NewGame,
+    Load,
+    Puzzles,
+    Openings,
+    Endgames,
+    Coordinates,
+    Resume,
+    Host,
+    Join,
+    Settings,
+    Quit,
+}
+
+impl MainMenuItem {
+    pub const ALL: [MainMenuItem; 11] = [
+        MainMenuItem::NewGame,
+        MainMenuItem::Load,
+        MainMenuItem::Puzzles,
+        MainMenuItem::Openings,
+        MainMenuItem::Endgames,
+        MainMenuItem::Coordinates,
+        MainMenuItem::Resume,
+        MainMenuItem::Host,
+        MainMenuItem::Join,
+        MainMenuItem::Settings,
+        MainMenuItem::Quit,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            MainMenuItem::NewGame => "New game",
+            MainMenuItem::Load => "Load PGN/FEN",
+            MainMenuItem::Puzzles => "Puzzles",
+            MainMenuItem::Openings => "Opening trainer",
+            MainMenuItem::Endgames => "Endgame drills",
+            MainMenuItem::Coordinates => "Coordinate trainer",
+            MainMenuItem::Resume => "Resume autosave",
+            MainMenuItem::Host => "Host LAN game",
+            MainMenuItem::Join => "Join LAN game",
+            MainMenuItem::Settings => "Settings",
+            MainMenuItem::Quit => "Quit",
+        }
+    }
+
+    /// Where the item is in the menu.
+    pub fn index(self) -> usize {
+        MainMenuItem::ALL
+            .iter()
+            .position(|item| *item == self)
+            .expect("Every item is in the menu")
+    }
+}
+
+/// The time controls offered in the new game form, in minutes plus seconds of increment.
+const TIME_CONTROLS: [Option<(u64, u64)>; 7] = [
+    None,
+    Some((1, 0)),
+    Some((3, 2)),
+    Some((5, 0)),
+    Some((10, 5)),
+    Some((15, 10)),
+    Some((30, 0)),
+];
+
+/// The kind of player picked for a side, the command of an external engine is typed
+/// separately.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PlayerChoice {
+    Human,
+    Engine(u8),
+    Random,
+    External,
+    Remote,
+}
+
+impl PlayerChoice {
+    /// The choice after this one, remote players are only offered when hosting.
+    fn next(self, hosting: bool) -> PlayerChoice {
+        match self {
+            PlayerChoice::Human => PlayerChoice::Engine(1),
+            PlayerChoice::Engine(level) if level < MAX_LEVEL => PlayerChoice::Engine(level + 1),
+            PlayerChoice::Engine(_) => PlayerChoice::Random,
+            PlayerChoice::Random => PlayerChoice::External,
+            PlayerChoice::External if hosting => PlayerChoice::Remote,
+            PlayerChoice::External | PlayerChoice::Remote => PlayerChoice::Human,
+        }
+    }
+
+    fn previous(self, hosting: bool) -> PlayerChoice {
+        match self {
+            PlayerChoice::Human if hosting => PlayerChoice::Remote,
+            PlayerChoice::Human => PlayerChoice::External,
+            PlayerChoice::Engine(1) => PlayerChoice::Human,
+            PlayerChoice::Engine(level) => PlayerChoice::Engine(level - 1),
+            PlayerChoice::Random => PlayerChoice::Engine(MAX_LEVEL),
+            PlayerChoice::External => PlayerChoice::Random,
+            PlayerChoice::Remote => PlayerChoice::External,
+        }
+    }
+
+    fn label(self) -> String {
+        match self {
+            PlayerChoice::Human => "Human".to_string(),
+            PlayerChoice::Engine(level) => format!("Engine level {}", level),
+            PlayerChoice::Random => "Random mover".to_string(),
+            PlayerChoice::External => "External engine".to_string(),
+            PlayerChoice::Remote => "Remote player".to_string(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Field {
+    White,
+    WhiteCommand,
+    Black,
+    BlackCommand,
+    TimeControl,
+    Variant,
+    Chess960,
+    Fen,
+    Odds,
+    OddsGiver,
+    GiverTime,
+    /// The address to listen on when hosting
+    Listen,
+    Start,
+}
+
+const FIELDS: [Field; 12] = [
+    Field::White,
+    Field::WhiteCommand,
+    Field::Black,
+    Field::BlackCommand,
+    Field::TimeControl,
+    Field::Variant,
+    Field::Chess960,
+    Field::Fen,
+    Field::Odds,
+    Field::OddsGiver,
+    Field::GiverTime,
+    Field::Start,
+];
+
+const HOST_FIELDS: [Field; 9] = [
+    Field::White,
+    Field::WhiteCommand,
+    Field::Black,
+    Field::BlackCommand,
+    Field::TimeControl,
+    Field::Chess960,
+    Field::Fen,
+    Field::Listen,
+    Field::Start,
+];
+
+pub enum FormAction {
+    None,
+    Back,
+    Start(GameSetup),
+    /// Start the game and wait for the remote player at the address
+    Host(GameSetup, String),
+    /// Load a puzzle file and start on its puzzles
+    Puzzles(PuzzleSetup),
+    /// Load a repertoire and start drilling it
+    Repertoire(RepertoireSetup),
+    /// Set up an endgame drill
+    Endgame(EndgameSetup),
+}
+
+/// The new game dialog, picking the players, time control and starting position.
+pub struct NewGameForm {
+    /// Whether the game is hosted for a player on another machine
+    hosting: bool,
+    selected: usize,
+    white: PlayerChoice,
+    black: PlayerChoice,
+    white_command: String,
+    black_command: String,
+    time_control: usize,
+    /// Index into `VariantKind::ALL`
+    variant: usize,
+    chess960: bool,
+    /// The starting position as FEN, the standard position when empty. For Chess960 it is
+    /// the position number instead, a random one when empty.
+    fen: String,
+    /// Index into `Odds::ALL` plus one, no material odds when 0
+    odds: usize,
+    odds_giver: Side,
+    /// Index into `TIME_CONTROLS` for the giver's clock, the game's own when 0
+    giver_time: usize,
+    listen: String,
+    error: Option<String>,
+}
+
+impl Default for NewGameForm {
+    fn default() -> Self {
+        NewGameForm {
+            hosting: false,
+            selected: FIELDS.len() - 1,
+            white: PlayerChoice::Human,
+            black: PlayerChoice::Human,
+            white_command: String::new(),
+            black_command: String::new(),
+            time_control: 0,
+            variant: 0,
+            chess960: false,
+            fen: String::new(),
+            odds: 0,
+            odds_giver: Side::White,
+            giver_time: 0,
+            listen: format!("0.0.0.0:{}", DEFAULT_PORT),
+            error: None,
+        }
+    }
+}
+
+impl NewGameForm {
+    /// The form for hosting a network game, with the other machine playing black.
+    pub fn host() -> NewGameForm {
+        NewGameForm {
+            hosting: true,
+            selected: HOST_FIELDS.len() - 1,
+            black: PlayerChoice::Remote,
+            ..NewGameForm::default()
+        }
+    }
+
+    fn fields(&self) -> &'static [Field] {
+        if self.hosting {
+            &HOST_FIELDS
+        } else {
+            &FIELDS
+        }
+    }
+
+    pub fn handle_key(&mut self, key: KeyCode) -> FormAction {
+        let fields = self.fields();
+        let field = fields[self.selected];
+        match key {
+            KeyCode::Esc => return FormAction::Back,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => self.selected = (self.selected + 1) % fields.len(),
+            KeyCode::Left => self.cycle(field, false),
+            KeyCode::Right => self.cycle(field, true),
+            KeyCode::Enter if field == Field::Start => match self.action() {
+                Ok(action) => return action,
+                Err(e) => self.error = Some(e),
+            },
+            KeyCode::Enter => self.selected = (self.selected + 1) % fields.len(),
+            KeyCode::Backspace => {
+                if let Some(text) = self.text_mut(field) {
+                    text.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(text) = self.text_mut(field) {
+                    text.push(c);
+                }
+            }
+            _ => {}
+        }
+        FormAction::None
+    }
+
+    fn cycle(&mut self, field: Field, forwards: bool) {
+        let hosting = self.hosting;
+        let step = |choice: PlayerChoice| {
+            if forwards {
+                choice.next(hosting)
+            } else {
+                choice.previous(hosting)
+            }
+        };
+        match field {
+            Field::White => self.white = step(self.white),
+            Field::Black => self.black = step(self.black),
+            Field::TimeControl if forwards => {
+                self.time_control = (self.time_control + 1) % TIME_CONTROLS.len()
+            }
+            Field::TimeControl => {
+                self.time_control =
+                    (self.time_control + TIME_CONTROLS.len() - 1) % TIME_CONTROLS.len()
+            }
+            Field::Variant if forwards => {
+                self.variant = (self.variant + 1) % VariantKind::ALL.len()
+            }
+            Field::Variant => {
+                self.variant = (self.variant + VariantKind::ALL.len() - 1) % VariantKind::ALL.len()
+            }
+            Field::Chess960 => self.chess960 = !self.chess960,
+            Field::Odds if forwards => self.odds = (self.odds + 1) % (Odds::ALL.len() + 1),
+            Field::Odds => self.odds = (self.odds + Odds::ALL.len()) % (Odds::ALL.len() + 1),
+            Field::OddsGiver => self.odds_giver = self.odds_giver.flipped(),
+            Field::GiverTime if forwards => {
+                self.giver_time = (self.giver_time + 1) % TIME_CONTROLS.len()
+            }
+            Field::GiverTime => {
+                self.giver_time = (self.giver_time + TIME_CONTROLS.len() - 1) % TIME_CONTROLS.len()
+            }
+            _ => {}
+        }
+    }
+
+    fn text_mut(&mut self, field: Field) -> Option<&mut String> {
+        match field {
+            Field::WhiteCommand => Some(&mut self.white_command),
+            Field::BlackCommand => Some(&mut self.black_command),
+            Field::Fen => Some(&mut self.fen),
+            Field::Listen => Some(&mut self.listen),
+            _ => None,
+        }
+    }
+
+    fn player(choice: PlayerChoice, command: &str) -> Result<PlayerKind, String> {
+        match choice {
+            PlayerChoice::Human => Ok(PlayerKind::Human),
+            PlayerChoice::Engine(level) => Ok(PlayerKind::Engine(level)),
+            PlayerChoice::Random => Ok(PlayerKind::Random),
+            PlayerChoice::External if command.trim().is_empty() => {
+                Err("Enter the command to start the external engine".to_string())
+            }
+            PlayerChoice::External => Ok(PlayerKind::External(command.trim().to_string())),
+            PlayerChoice::Remote => Ok(PlayerKind::Remote),
+        }
+    }
+
+    fn action(&self) -> Result<FormAction, String> {
+        let setup = self.setup()?;
+        if !self.hosting {
+            return Ok(FormAction::Start(setup));
+        }
+        let remote_players = [&setup.white, &setup.black]
+            .iter()
+            .filter(|p| ***p == PlayerKind::Remote)
+            .count();
+        if remote_players != 1 {
+            return Err("Exactly one side must be the remote player".to_string());
+        }
+        Ok(FormAction::Host(setup, listen_address(&self.listen)?))
+    }
+
+    fn setup(&self) -> Result<GameSetup, String> {
+        let start = if self.chess960 {
+            let number = match self.fen.trim() {
+                "" => None,
+                number => Some(
+                    number
+                        .parse()
+                        .map_err(|_| format!("{:?} is not a Chess960 position number", number))?,
+                ),
+            };
+            StartPosition::Chess960(number)
+        } else if self.fen.trim().is_empty() {
+            StartPosition::Standard
+        } else {
+            StartPosition::Fen(self.fen.trim().to_string())
+        };
+        Ok(GameSetup {
+            white: NewGameForm::player(self.white, &self.white_command)?,
+            black: NewGameForm::player(self.black, &self.black_command)?,
+            time_control: TIME_CONTROLS[self.time_control]
+                .map(|(minutes, increment)| TimeControl::new(minutes, increment)),
+            start,
+            variant: VariantKind::ALL[self.variant],
+            handicap: self.handicap(),
+        })
+    }
+
+    fn handicap(&self) -> Option<Handicap> {
+        let odds = self.odds.checked_sub(1).map(|i| Odds::ALL[i]);
+        let time_control = TIME_CONTROLS[self.giver_time]
+            .map(|(minutes, increment)| TimeControl::new(minutes, increment));
+        (odds.is_some() || time_control.is_some()).then_some(Handicap {
+            giver: self.odds_giver,
+            odds,
+            time_control,
+        })
+    }
+
+    /// Shows an error from the model, such as an invalid FEN.
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    pub fn menu(&self) -> Menu<'_> {
+        let row = |label: &str, value: String, enabled: bool| {
+            let line = Line::from(format!("{:<18}{}", label, value));
+            if enabled {
+                line
+            } else {
+                line.dim()
+            }
+        };
+        let time_control = match TIME_CONTROLS[self.time_control] {
+            Some((minutes, increment)) => TimeControl::new(minutes, increment).to_string(),
+            None => "Untimed".to_string(),
+        };
+        let (fen_label, fen) = match (self.chess960, self.fen.is_empty()) {
+            (true, true) => ("  Position number", "(random)".to_string()),
+            (true, false) => ("  Position number", self.fen.clone()),
+            (false, true) => ("Starting FEN", "(standard)".to_string()),
+            (false, false) => ("Starting FEN", self.fen.clone()),
+        };
+        let mut rows = vec![
+            row("White", format!("< {} >", self.white.label()), true),
+            row(
+                "  Engine command",
+                self.white_command.clone(),
+                self.white == PlayerChoice::External,
+            ),
+            row("Black", format!("< {} >", self.black.label()), true),
+            row(
+                "  Engine command",
+                self.black_command.clone(),
+                self.black == PlayerChoice::External,
+            ),
+            row("Time control", format!("< {} >", time_control), true),
+        ];
+        if !self.hosting {
+            rows.push(row(
+                "Variant",
+                format!("< {} >", VariantKind::ALL[self.variant]),
+                true,
+            ));
+        }
+        rows.extend([
+            row(
+                "Chess960",
+                format!("< {} >", if self.chess960 { "On" } else { "Off" }),
+                true,
+            ),
+            row(fen_label, fen, true),
+        ]);
+        if self.hosting {
+            rows.push(row("Listen on", self.listen.clone(), true));
+        } else {
+            let odds = match self.odds.checked_sub(1) {
+                Some(i) => Odds::ALL[i].to_string(),
+                None => "None".to_string(),
+            };
+            let giver_time = match TIME_CONTROLS[self.giver_time] {
+                Some((minutes, increment)) => TimeControl::new(minutes, increment).to_string(),
+                None => "Same as the game".to_string(),
+            };
+            rows.extend([
+                row("Handicap", format!("< {} >", odds), true),
+                row(
+                    "  Given by",
+                    format!("< {} >", String::from(self.odds_giver)),
+                    self.handicap().is_some(),
+                ),
+                row(
+                    "  Giver's clock",
+                    format!("< {} >", giver_time),
+                    self.time_control != 0,
+                ),
+            ]);
+        }
+        rows.push(Line::from("Start game").bold());
+        let footer = self.error.as_deref().or(Some(
+            "Up/Down to pick a row, Left/Right to change it, type to edit text, Esc to go back",
+        ));
+        let title = if self.hosting {
+            "Host LAN game"
+        } else {
+            "New game"
+        };
+        Menu::new(title, rows, self.selected).footer(footer)
+    }
+}
+
+/// The puzzle dialog, picking the puzzle file and a theme to train.
+#[derive(Default)]
+pub struct PuzzleForm {
+    selected: usize,
+    path: String,
+    /// Only puzzles with this theme are given, any when empty
+    theme: String,
+    error: Option<String>,
+}
+
+impl PuzzleForm {
+    const ROWS: usize = 3;
+
+    pub fn handle_key(&mut self, key: KeyCode) -> FormAction {
+        match key {
+            KeyCode::Esc => return FormAction::Back,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => self.selected = (self.selected + 1) % Self::ROWS,
+            KeyCode::Enter if self.selected == Self::ROWS - 1 => {
+                if self.path.trim().is_empty() {
+                    self.error = Some("Enter the path of a puzzle file".to_string());
+                } else {
+                    return FormAction::Puzzles(self.setup());
+                }
+            }
+            KeyCode::Enter => self.selected += 1,
+            KeyCode::Backspace => {
+                if let Some(text) = self.text_mut() {
+                    text.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(text) = self.text_mut() {
+                    text.push(c);
+                }
+            }
+            _ => {}
+        }
+        FormAction::None
+    }
+
+    fn text_mut(&mut self) -> Option<&mut String> {
+        match self.selected {
+            0 => Some(&mut self.path),
+            1 => Some(&mut self.theme),
+            _ => None,
+        }
+    }
+
+    fn setup(&self) -> PuzzleSetup {
+        let theme = self.theme.trim();
+        PuzzleSetup {
+            path: self.path.trim().to_string(),
+            theme: (!theme.is_empty()).then(|| theme.to_string()),
+        }
+    }
+
+    /// Shows an error from the model, such as a file that can't be read.
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    pub fn menu(&self) -> Menu<'_> {
+        let theme = if self.theme.is_empty() {
+            "(any)".to_string()
+        } else {
+            self.theme.clone()
+        };
+        let rows = vec![
+            Line::from(format!("{:<18}{}", "Puzzle file", self.path)),
+            Line::from(format!("{:<18}{}", "Theme", theme)),
+            Line::from("Start puzzles").bold(),
+        ];
+        let footer = self.error.as_deref().or(Some(
+            "A Lichess puzzle CSV or an EPD file with bm records. Themes are as in the file, \
+             such as mateIn2. Esc to go back",
+        ));
+        Menu::new("Puzzles", rows, self.selected).footer(footer)
+    }
+}
+
+/// The opening trainer dialog, picking the repertoire file and the side it is for.
+#[derive(Default)]
+pub struct RepertoireForm {
+    selected: usize,
+    path: String,
+    black: bool,
+    error: Option<String>,
+}
+
+impl RepertoireForm {
+    const ROWS: usize = 3;
+
+    pub fn handle_key(&mut self, key: KeyCode) -> FormAction {
+        match key {
+            KeyCode::Esc => return FormAction::Back,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => self.selected = (self.selected + 1) % Self::ROWS,
+            KeyCode::Left | KeyCode::Right if self.selected == 1 => self.black = !self.black,
+            KeyCode::Enter if self.selected == Self::ROWS - 1 => {
+                if self.path.trim().is_empty() {
+                    self.error = Some("Enter the path of a PGN repertoire".to_string());
+                } else {
+                    return FormAction::Repertoire(RepertoireSetup {
+                        path: self.path.trim().to_string(),
+                        side: self.side(),
+                    });
+                }
+            }
+            KeyCode::Enter => self.selected += 1,
+            KeyCode::Backspace if self.selected == 0 => {
+                self.path.pop();
+            }
+            KeyCode::Char(c) if self.selected == 0 => self.path.push(c),
+            _ => {}
+        }
+        FormAction::None
+    }
+
+    fn side(&self) -> Side {
+        if self.black {
+            Side::Black
+        } else {
+            Side::White
+        }
+    }
+
+    /// Shows an error from the model, such as a file that can't be read.
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    pub fn menu(&self) -> Menu<'_> {
+        let rows = vec![
+            Line::from(format!("{:<18}{}", "Repertoire PGN", self.path)),
+            Line::from(format!(
+                "{:<18}< {} >",
+                "Playing",
+                String::from(self.side())
+            )),
+            Line::from("Start training").bold(),
+        ];
+        let footer = self.error.as_deref().or(Some(
+            "Variations in the PGN are drilled as lines of their own. Esc to go back",
+        ));
+        Menu::new("Opening trainer", rows, self.selected).footer(footer)
+    }
+}
+
+/// The endgame drill dialog, picking the endgame and optionally Syzygy tables, or a UCI
+/// engine, to defend the drills the built-in tables don't cover.
+#[derive(Default)]
+pub struct EndgameForm {
+    selected: usize,
+    endgame: usize,
+    tablebases: String,
+    engine: String,
+    error: Option<String>,
+}
+
+impl EndgameForm {
+    const ROWS: usize = 4;
+
+    pub fn handle_key(&mut self, key: KeyCode) -> FormAction {
+        let count = Endgame::ALL.len();
+        match key {
+            KeyCode::Esc => return FormAction::Back,
+            KeyCode::Up => self.selected = self.selected.saturating_sub(1),
+            KeyCode::Down | KeyCode::Tab => self.selected = (self.selected + 1) % Self::ROWS,
+            KeyCode::Left if self.selected == 0 => {
+                self.endgame = (self.endgame + count - 1) % count
+            }
+            KeyCode::Right if self.selected == 0 => self.endgame = (self.endgame + 1) % count,
+            KeyCode::Enter if self.selected == Self::ROWS - 1 => {
+                return FormAction::Endgame(self.setup());
+            }
+            KeyCode::Enter => self.selected += 1,
+            KeyCode::Backspace => {
+                if let Some(text) = self.text_mut() {
+                    text.pop();
+                }
+            }
+            KeyCode::Char(c) => {
+                if let Some(text) = self.text_mut() {
+                    text.push(c);
+                }
+            }
+            _ => {}
+        }
+        FormAction::None
+    }
+
+    fn text_mut(&mut self) -> Option<&mut String> {
+        match self.selected {
+            1 => Some(&mut self.tablebases),
+            2 => Some(&mut self.engine),
+            _ => None,
+        }
+    }
+
+    fn setup(&self) -> EndgameSetup {
+        let optional = |text: &str| {
+            let text = text.trim();
+            (!text.is_empty()).then(|| text.to_string())
+        };
+        EndgameSetup {
+            endgame: Endgame::ALL[self.endgame],
+            tablebases: optional(&self.tablebases),
+            engine: optional(&self.engine),
+        }
+    }
+
+    /// Shows an error from the model, such as a drill that can't be set up.
+    pub fn set_error(&mut self, error: String) {
+        self.error = Some(error);
+    }
+
+    pub fn menu(&self) -> Menu<'_> {
+        let endgame = Endgame::ALL[self.endgame];
+        let rows = vec![
+            Line::from(format!(
+                "{:<18}< {} > {}",
+                "Endgame",
+                endgame,
+                endgame.goal()
+            )),
+            Line::from(format!("{:<18}{}", "Syzygy tables", self.tablebases)),
+            Line::from(format!("{:<18}{}", "Defending engine", self.engine)),
+            Line::from("Start drill").bold(),
+        ];
+        let footer = self.error.as_deref().or(Some(
+            "KQ, KR and KP vs K are defended perfectly. The Lucena and Philidor positions are \
+             too when the Syzygy directory has their tables, and otherwise by the built-in \
+             engine, or the UCI engine given, not perfectly. Esc to go back",
+        ));
+        Menu::new("Endgame drills", rows, self.selected).footer(footer)
+    }
+}
+
+/// Reads the input of the load screen, which is a path to a PGN or FEN file, or a FEN or
+/// PGN typed in directly.
+pub fn load_start_position(input: &str) -> Result<StartPosition, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("Enter a file path or a FEN".to_string());
+    }
+    let path = Path::new(input);
+    let text = if path.is_file() {
+        fs::read_to_string(path).map_err(|e| format!("Could not read {}: {}", input, e))?
+    } else {
+        input.to_string()
+    };
+    let text = text.trim();
+    // FEN never has tags or move numbers
+    if text.starts_with('[') || text.contains("1.") {
+        Ok(StartPosition::Pgn(text.to_string()))
+    } else {
+        Ok(StartPosition::Fen(text.to_string()))
+    }
+}
+
+/// Reads the address to host on, a port on its own listening on every interface.
+fn listen_address(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    if let Ok(port) = input.parse::<u16>() {
+        return Ok(format!("0.0.0.0:{}", port));
+    }
+    input
+        .parse::<std::net::SocketAddr>()
+        .map(|address| address.to_string())
+        .map_err(|_| format!("{:?} is not an address such as 0.0.0.0:7878", input))
+}
+
+/// Reads the host's address and the game code it shows, adding the default port to an
+/// address typed without one.
+pub fn join_address(input: &str) -> Result<(String, String), String> {
+    let mut words = input.split_whitespace();
+    let (Some(address), Some(code), None) = (words.next(), words.next(), words.next()) else {
+        return Err(
+            "Enter the address of the host and the game code it shows, such as \
+             192.168.1.20 3f9a12bc"
+                .to_string(),
+        );
+    };
+    let address = if address.contains(':') {
+        address.to_string()
+    } else {
+        format!("{}:{}", address, DEFAULT_PORT)
+    };
+    Ok((address, code.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn form_builds_setup() {
+        let mut form = NewGameForm {
+            selected: 0,
+            ..NewGameForm::default()
+        };
+        form.handle_key(KeyCode::Right);
+        form.handle_key(KeyCode::Right);
+        form.selected = 2;
+        form.handle_key(KeyCode::Left);
+        assert!(matches!(form.handle_key(KeyCode::Enter), FormAction::None));
+        assert!(form.setup().is_err());
+        form.selected = 3;
+        for c in "stockfish".chars() {
+            form.handle_key(KeyCode::Char(c));
+        }
+        form.selected = 4;
+        form.handle_key(KeyCode::Right);
+
+        form.selected = FIELDS.len() - 1;
+        let FormAction::Start(setup) = form.handle_key(KeyCode::Enter) else {
+            panic!("Form should start the game");
+        };
+        assert_eq!(setup.white, PlayerKind::Engine(2));
+        assert_eq!(setup.black, PlayerKind::External("stockfish".to_string()));
+        assert_eq!(setup.time_control, Some(TimeControl::new(1, 0)));
+        assert_eq!(setup.start, StartPosition::Standard);
+
+        form.selected = 5;
+        form.handle_key(KeyCode::Left);
+        assert_eq!(form.setup().unwrap().variant, VariantKind::Capablanca);
+        form.selected = 6;
+        form.handle_key(KeyCode::Right);
+        assert_eq!(form.setup().unwrap().start, StartPosition::Chess960(None));
+        form.selected = 7;
+        for c in "518".chars() {
+            form.handle_key(KeyCode::Char(c));
+        }
+        assert_eq!(
+            form.setup().unwrap().start,
+            StartPosition::Chess960(Some(518))
+        );
+
+        assert_eq!(form.setup().unwrap().handicap, None);
+        form.selected = 8;
+        form.handle_key(KeyCode::Left);
+        form.selected = 9;
+        form.handle_key(KeyCode::Right);
+        form.selected = 10;
+        form.handle_key(KeyCode::Right);
+        assert_eq!(
+            form.setup().unwrap().handicap,
+            Some(Handicap {
+                giver: Side::Black,
+                odds: Some(Odds::PawnAndMove),
+                time_control: Some(TimeControl::new(1, 0)),
+            })
+        );
+    }
+
+    #[test]
+    fn load_detects_format() {
+        assert!(matches!(
+            load_start_position("8/8/8/8/8/8/8/K6k w - - 0 1"),
+            Ok(StartPosition::Fen(_))
+        ));
+        assert!(matches!(
+            load_start_position("1. e4 e5 2. Nf3"),
+            Ok(StartPosition::Pgn(_))
+        ));
+        assert!(load_start_position("  ").is_err());
+    }
+
+    #[test]
+    fn puzzle_form_needs_a_file() {
+        let mut form = PuzzleForm {
+            selected: 2,
+            ..PuzzleForm::default()
+        };
+        assert!(matches!(form.handle_key(KeyCode::Enter), FormAction::None));
+        assert!(form.error.is_some());
+        form.selected = 0;
+        for c in "puzzles.csv".chars() {
+            form.handle_key(KeyCode::Char(c));
+        }
+        form.handle_key(KeyCode::Enter);
+        for c in "fork".chars() {
+            form.handle_key(KeyCode::Char(c));
+        }
+        form.handle_key(KeyCode::Enter);
+        let FormAction::Puzzles(setup) = form.handle_key(KeyCode::Enter) else {
+            panic!("Form should start the puzzles");
+        };
+        assert_eq!(setup.path, "puzzles.csv");
+        assert_eq!(setup.theme.as_deref(), Some("fork"));
+    }
+}
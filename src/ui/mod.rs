@@ -1,31 +1,59 @@
 use std::{
     collections::HashMap,
-    io::{self, Result},
+    fs,
+    io::{self, Result, Write},
+    path::PathBuf,
     sync::mpsc::{Receiver, Sender},
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use crossterm::{
     cursor,
-    event::{self, Event, KeyCode, KeyEvent, KeyEventKind},
+    event::{
+        self, Event, KeyCode, KeyEvent, KeyEventKind, MouseButton, MouseEvent, MouseEventKind,
+    },
 };
 use log::*;
 
 use crate::{
-    control::{CBPosition, ModelMsg, UiMsg},
-    model::{Board, Piece, PieceType, Side},
+    control::{
+        CBPosition, EndgameState, EndgameStatus, GameResult, GameSetup, GameStatus, Geometry,
+        Handicap, ModelMsg, PlayerKind, Pockets, PuzzleState, PuzzleStatus, RepertoireStatus,
+        UiMsg,
+    },
+    model::{autosave, Board, Material, PieceType, Side},
 };
 use ratatui::{
     prelude::*,
     widgets::{Block, Padding, Paragraph},
 };
 
-use self::{chessboard::Chessboard, infobox::Infobox};
+use self::{
+    chessboard::{Blindfold, Chessboard},
+    config::Config,
+    coordinates::{CoordinateTrainer, CoordinateView},
+    infobox::Infobox,
+    menu::{
+        join_address, load_start_position, EndgameForm, FormAction, MainMenuItem, Menu,
+        NewGameForm, PuzzleForm, RepertoireForm,
+    },
+    pieces::PieceStyle,
+    pocket::Pocket,
+    theme::Theme,
+};
 mod chessboard;
+mod config;
+mod coordinates;
 mod infobox;
+mod menu;
+mod pieces;
+mod pocket;
+mod theme;
 pub mod tui;
 
 pub fn init_ui(send: Sender<UiMsg>, recv: Receiver<ModelMsg>) -> io::Result<()> {
+    let config = Config::load();
+    let themes = config.themes();
     let mut terminal = tui::init()?;
     let mut eden_chess_ui = EdenChessUi {
         send,
@@ -34,9 +62,29 @@ pub fn init_ui(send: Sender<UiMsg>, recv: Receiver<ModelMsg>) -> io::Result<()>
         board: None,
         cursor: CBPosition { col: 'a', row: 1 },
         square_selected: None,
+        drop_selected: None,
         valid_moves: None,
         game_data: GameData::new(),
+        preview_board: None,
+        board_area: Rect::default(),
+        pocket_area: Rect::default(),
+        flip_board: config.flip_board,
+        follow_side_to_move: config.follow_side_to_move,
+        piece_style: config.piece_style,
+        blindfold: config.blindfold,
+        notation: String::new(),
+        theme: config.starting_theme(&themes),
+        themes,
+        screen: Screen::MainMenu(MainMenuItem::NewGame.index()),
+        autosave: autosave_summary(),
+        in_game: false,
+        awaiting_game: false,
+        message: None,
     };
+    if eden_chess_ui.autosave.is_some() {
+        // Offer to pick up where the last session left off
+        eden_chess_ui.screen = Screen::MainMenu(MainMenuItem::Resume.index());
+    }
     eden_chess_ui.run(&mut terminal)?;
     tui::restore()?;
     Ok(())
@@ -49,14 +97,78 @@ struct EdenChessUi {
     board: Option<Board>,
     cursor: CBPosition,
     square_selected: Option<CBPosition>,
+    /// The piece chosen from the pocket to drop, in variants with drops
+    drop_selected: Option<PieceType>,
     valid_moves: Option<Vec<CBPosition>>,
     game_data: GameData,
+    /// The past position being looked at while stepping through the move history
+    preview_board: Option<Board>,
+    board_area: Rect,
+    /// Where the pockets are drawn, empty when the variant has none
+    pocket_area: Rect,
+    flip_board: bool,
+    follow_side_to_move: bool,
+    piece_style: PieceStyle,
+    /// How much of the board is hidden, moves are typed in notation when any of it is
+    blindfold: Blindfold,
+    /// The move being typed when playing blindfold
+    notation: String,
+    themes: Vec<Theme>,
+    theme: usize,
+    screen: Screen,
+    /// A description of the unfinished game from the last session, or why it can't be read
+    autosave: Option<std::result::Result<String, String>>,
+    /// Whether a game has been started, so the menu can go back to it
+    in_game: bool,
+    /// A new game was asked for and the model has not answered yet
+    awaiting_game: bool,
+    /// An error or notice shown under the board
+    message: Option<String>,
+}
+
+enum Screen {
+    MainMenu(usize),
+    NewGame(NewGameForm),
+    Puzzles(PuzzleForm),
+    Repertoire(RepertoireForm),
+    Endgame(EndgameForm),
+    Coordinates(CoordinateTrainer),
+    /// Typing a file path, FEN or PGN to load, with the last error
+    Load(String, Option<String>),
+    /// Typing the address of a network game to join, with the last error
+    Join(String, Option<String>),
+    Settings(usize),
+    Playing,
+}
+
+const SETTINGS_ROWS: usize = 6;
+
+fn autosave_summary() -> Option<std::result::Result<String, String>> {
+    let path = autosave::path()?;
+    autosave::load(&path)
+        .map(|saved| saved.map(|game| game.summary()))
+        .transpose()
 }
 
 pub struct GameData {
     pub which_turn: Side,
     pub turn_count: usize,
     pub move_history: Vec<String>,
+    pub last_move: Option<(CBPosition, CBPosition)>,
+    pub in_check: Option<Side>,
+    pub material: Material,
+    /// The ply whose position is being previewed, `None` when showing the live game
+    pub viewing_ply: Option<usize>,
+    /// Time left for white and black when the last status arrived
+    pub clocks: Option<(Duration, Duration)>,
+    pub clocks_received: Instant,
+    pub players: (PlayerKind, PlayerKind),
+    pub result: Option<GameResult>,
+    pub pockets: Option<Pockets>,
+    pub handicap: Option<Handicap>,
+    pub puzzle: Option<PuzzleStatus>,
+    pub repertoire: Option<RepertoireStatus>,
+    pub endgame: Option<EndgameStatus>,
 }
 impl GameData {
     fn new() -> GameData {
@@ -64,17 +176,82 @@ impl GameData {
             which_turn: Side::White,
             turn_count: 0,
             move_history: Vec::new(),
+            last_move: None,
+            in_check: None,
+            material: Material::default(),
+            viewing_ply: None,
+            clocks: None,
+            clocks_received: Instant::now(),
+            players: (PlayerKind::Human, PlayerKind::Human),
+            result: None,
+            pockets: None,
+            handicap: None,
+            puzzle: None,
+            repertoire: None,
+            endgame: None,
+        }
+    }
+    fn update(&mut self, status: GameStatus) {
+        self.which_turn = status.which_turn;
+        self.turn_count = status.turn_count;
+        self.last_move = status.last_move;
+        self.in_check = status.in_check;
+        self.material = status.material;
+        self.move_history = status.move_history;
+        self.clocks = status.clocks;
+        self.clocks_received = Instant::now();
+        self.players = status.players;
+        self.result = status.result;
+        self.pockets = status.pockets;
+        self.handicap = status.handicap;
+        self.puzzle = status.puzzle;
+        self.repertoire = status.repertoire;
+        self.endgame = status.endgame;
+    }
+
+    /// Time left for `side`, counting down from the last status while it is their turn.
+    pub fn clock(&self, side: Side) -> Option<Duration> {
+        let (white, black) = self.clocks?;
+        let left = match side {
+            Side::White => white,
+            Side::Black => black,
+        };
+        if side == self.which_turn && self.result.is_none() {
+            Some(left.saturating_sub(self.clocks_received.elapsed()))
+        } else {
+            Some(left)
         }
     }
-}
 
-struct MoveRecord<'a> {
-    moving_piece: &'a Piece,
-    from_pos: CBPosition,
-    destination: CBPosition,
-    captures: bool,
-    promotes_to: Option<PieceType>,
+    pub fn player(&self, side: Side) -> &PlayerKind {
+        match side {
+            Side::White => &self.players.0,
+            Side::Black => &self.players.1,
+        }
+    }
+
+    /// Whether the board takes moves from the UI right now.
+    fn human_to_move(&self) -> bool {
+        let puzzle_over = self
+            .puzzle
+            .as_ref()
+            .is_some_and(|puzzle| puzzle.state != PuzzleState::Solving);
+        let line_done = self
+            .repertoire
+            .as_ref()
+            .is_some_and(|repertoire| repertoire.line_done);
+        let drill_over = self
+            .endgame
+            .as_ref()
+            .is_some_and(|endgame| endgame.state != EndgameState::Playing);
+        self.result.is_none()
+            && !puzzle_over
+            && !line_done
+            && !drill_over
+            && *self.player(self.which_turn) == PlayerKind::Human
+    }
 }
+
 impl EdenChessUi {
     pub fn run(&mut self, terminal: &mut tui::Tui) -> io::Result<()> {
         let _ = self.send.send(UiMsg::GetBoardState);
@@ -88,8 +265,111 @@ impl EdenChessUi {
         Ok(())
     }
 
-    fn render_frame(&self, frame: &mut Frame) {
-        if let Some(b) = &self.board {
+    fn render_frame(&mut self, frame: &mut Frame) {
+        if self.in_game {
+            self.render_game(frame);
+        }
+        let area = frame.size();
+        match &self.screen {
+            Screen::MainMenu(selected) => {
+                let rows = MainMenuItem::ALL
+                    .iter()
+                    .map(|item| {
+                        let line = match (item, &self.autosave) {
+                            (MainMenuItem::Resume, Some(Ok(summary))) => {
+                                Line::from(format!("{} ({})", item.label(), summary))
+                            }
+                            _ => Line::from(item.label()),
+                        };
+                        if self.menu_item_enabled(*item) {
+                            line
+                        } else {
+                            line.dim()
+                        }
+                    })
+                    .collect();
+                let footer = self.message.as_deref().or(Some(if self.in_game {
+                    "Enter to choose, Esc to return to the game"
+                } else {
+                    "Enter to choose"
+                }));
+                frame.render_widget(
+                    Menu::new("Eden Chess", rows, *selected).footer(footer),
+                    area,
+                );
+            }
+            Screen::NewGame(form) => frame.render_widget(form.menu(), area),
+            Screen::Puzzles(form) => frame.render_widget(form.menu(), area),
+            Screen::Repertoire(form) => frame.render_widget(form.menu(), area),
+            Screen::Endgame(form) => frame.render_widget(form.menu(), area),
+            Screen::Coordinates(trainer) => {
+                frame.render_widget(CoordinateView::new(trainer, &self.themes[self.theme]), area);
+                self.board_area = CoordinateTrainer::board_area(area);
+            }
+            Screen::Load(input, error) => {
+                let rows = vec![Line::from(format!("{}_", input))];
+                let footer = error.as_deref().or(Some(
+                    "Type a path to a PGN or FEN file, or paste a FEN, then Enter. Esc to go back",
+                ));
+                frame.render_widget(Menu::new("Load PGN/FEN", rows, 0).footer(footer), area);
+            }
+            Screen::Join(input, error) => {
+                let rows = vec![Line::from(format!("{}_", input))];
+                let footer = error.as_deref().or(Some(
+                    "Type the host's address, with :port if it isn't the default, and the game code it \
+                     shows, then Enter",
+                ));
+                frame.render_widget(Menu::new("Join LAN game", rows, 0).footer(footer), area);
+            }
+            Screen::Settings(selected) => {
+                let on_off = |b: bool| if b { "on" } else { "off" };
+                let rows = vec![
+                    Line::from(format!(
+                        "{:<22}< {} >",
+                        "Theme", self.themes[self.theme].name
+                    )),
+                    Line::from(format!(
+                        "{:<22}< {} >",
+                        "Piece style",
+                        self.piece_style.name()
+                    )),
+                    Line::from(format!(
+                        "{:<22}< {} >",
+                        "Flip board",
+                        on_off(self.flip_board)
+                    )),
+                    Line::from(format!(
+                        "{:<22}< {} >",
+                        "Follow side to move",
+                        on_off(self.follow_side_to_move)
+                    )),
+                    Line::from(format!("{:<22}< {} >", "Blindfold", self.blindfold.name())),
+                    Line::from("Save to config file").bold(),
+                ];
+                let footer = self
+                    .message
+                    .as_deref()
+                    .or(Some("Left/Right to change, Esc to go back"));
+                frame.render_widget(Menu::new("Settings", rows, *selected).footer(footer), area);
+            }
+            Screen::Playing => {}
+        }
+    }
+
+    fn render_game(&mut self, frame: &mut Frame) {
+        let (board, last_move, in_check, expected_move) = match &self.preview_board {
+            Some(b) if self.game_data.viewing_ply.is_some() => (Some(b), None, None, None),
+            _ => (
+                self.board.as_ref(),
+                self.game_data.last_move,
+                self.game_data.in_check,
+                self.game_data
+                    .repertoire
+                    .as_ref()
+                    .and_then(|repertoire| repertoire.expected),
+            ),
+        };
+        if let Some(b) = board {
             let block = Block::new().padding(Padding::symmetric(
                 (frame.size().width as f32 * 0.05).floor() as u16,
                 (frame.size().height as f32 * 0.05).floor() as u16,
@@ -99,8 +379,24 @@ impl EdenChessUi {
                 .constraints(vec![Constraint::Percentage(60), Constraint::Percentage(40)])
                 .split(block.inner(frame.size()));
             let left_panel = layout[0];
+            let (left_panel, notation_area) = if self.blindfold.is_on() {
+                let rows = Layout::default()
+                    .constraints([Constraint::Min(0), Constraint::Length(1)])
+                    .split(left_panel);
+                (rows[0], Some(rows[1]))
+            } else {
+                (left_panel, None)
+            };
+            let (left_panel, message_area) = if let Some(message) = &self.message {
+                let rows = Layout::default()
+                    .constraints([Constraint::Min(0), Constraint::Length(1)])
+                    .split(left_panel);
+                (rows[0], Some((rows[1], message)))
+            } else {
+                (left_panel, None)
+            };
             let right_panel = layout[1];
-            let valid_moves = if let Some(p) = self.square_selected {
+            let valid_moves = if self.square_selected.is_some() || self.drop_selected.is_some() {
                 if let Some(ms) = &self.valid_moves {
                     ms
                 } else {
@@ -109,42 +405,336 @@ impl EdenChessUi {
             } else {
                 &Vec::<CBPosition>::new()
             };
-            let ui_board = Chessboard::new(&b, self.cursor, valid_moves);
+            let ui_board = Chessboard::new(b, self.cursor, valid_moves, &self.themes[self.theme])
+                .flipped(self.board_flipped())
+                .piece_style(self.piece_style)
+                .last_move(last_move)
+                .expected_move(expected_move)
+                .in_check(in_check)
+                .blindfold(self.blindfold);
             frame.render_widget(ui_board, left_panel);
-            frame.render_widget(Infobox::new(&self.game_data), right_panel)
+            self.board_area = left_panel;
+            self.pocket_area = Rect::default();
+            if let Some(pockets) = &self.game_data.pockets {
+                // Beside the board, in whatever room the left panel has left
+                let board_width = Chessboard::width(left_panel, Geometry::of(b)) + 2;
+                let area = Rect {
+                    x: left_panel.x + board_width,
+                    width: left_panel.width.saturating_sub(board_width),
+                    ..left_panel
+                };
+                let top = if self.board_flipped() {
+                    Side::White
+                } else {
+                    Side::Black
+                };
+                let selected = self.drop_selected.map(|p| (self.game_data.which_turn, p));
+                frame.render_widget(
+                    Pocket::new(pockets, top)
+                        .selected(selected)
+                        .piece_style(self.piece_style),
+                    area,
+                );
+                self.pocket_area = area;
+            }
+            if let Some((area, message)) = message_area {
+                frame.render_widget(Paragraph::new(message.as_str()).bold(), area);
+            }
+            if let Some(area) = notation_area {
+                let line = Line::from(vec![
+                    Span::raw(format!("Move: {}_  ", self.notation)),
+                    Span::raw("Enter to play, Esc with nothing typed for the menu").dim(),
+                ]);
+                frame.render_widget(Paragraph::new(line), area);
+            }
+            frame.render_widget(
+                Infobox::new(&self.game_data, &self.themes[self.theme]),
+                right_panel,
+            )
         }
     }
 
     fn handle_events(&mut self) -> io::Result<()> {
-        match event::poll(Duration::from_secs(0))? {
-            true => match event::read() {
+        // it's important to check that the event is a key press event as crossterm also
+        // emits key release and repeat events on Windows.
+        if event::poll(Duration::from_millis(16))? {
+            match event::read() {
                 Ok(e) => match e {
                     Event::Key(key_event) if key_event.kind == KeyEventKind::Press => {
-                        self.handle_key_event(key_event)
+                        match self.screen {
+                            Screen::Playing => self.handle_key_event(key_event),
+                            _ => self.handle_menu_key_event(key_event),
+                        }
+                    }
+                    Event::Mouse(mouse_event) if matches!(self.screen, Screen::Playing) => {
+                        self.handle_mouse_event(mouse_event)
                     }
+                    Event::Mouse(mouse_event) => self.handle_menu_mouse_event(mouse_event),
                     _ => {}
                 },
                 Err(e) => error!("{}", e),
-            },
-            false => {} // it's important to check that the event is a key press event as
-                        // crossterm also emits key release and repeat events on Windows.
-        };
+            }
+        }
         Ok(())
     }
     //
 
+    fn menu_item_enabled(&self, item: MainMenuItem) -> bool {
+        // The autosave is of the game being played, so there is nothing else to resume
+        item != MainMenuItem::Resume || (self.autosave.is_some() && !self.in_game)
+    }
+
+    fn handle_menu_key_event(&mut self, e: KeyEvent) {
+        match &mut self.screen {
+            Screen::MainMenu(selected) => match e.code {
+                KeyCode::Up => *selected = selected.saturating_sub(1),
+                KeyCode::Down => *selected = (*selected + 1).min(MainMenuItem::ALL.len() - 1),
+                KeyCode::Esc if self.in_game => self.screen = Screen::Playing,
+                KeyCode::Char('q') => self.exit = true,
+                KeyCode::Enter => {
+                    let item = MainMenuItem::ALL[*selected];
+                    if self.menu_item_enabled(item) {
+                        self.choose_menu_item(item);
+                    }
+                }
+                _ => {}
+            },
+            Screen::NewGame(form) => {
+                let action = form.handle_key(e.code);
+                self.form_action(action, MainMenuItem::NewGame);
+            }
+            Screen::Puzzles(form) => {
+                let action = form.handle_key(e.code);
+                self.form_action(action, MainMenuItem::Puzzles);
+            }
+            Screen::Repertoire(form) => {
+                let action = form.handle_key(e.code);
+                self.form_action(action, MainMenuItem::Openings);
+            }
+            Screen::Endgame(form) => {
+                let action = form.handle_key(e.code);
+                self.form_action(action, MainMenuItem::Endgames);
+            }
+            Screen::Coordinates(trainer) => {
+                let action = trainer.handle_key(e.code);
+                self.form_action(action, MainMenuItem::Coordinates);
+            }
+            Screen::Join(input, error) => match e.code {
+                KeyCode::Esc => self.screen = Screen::MainMenu(MainMenuItem::Join.index()),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Enter => match join_address(input) {
+                    Ok((address, code)) => {
+                        *error = Some(format!("Connecting to {}", address));
+                        self.awaiting_game = true;
+                        if let Err(e) = self.send.send(UiMsg::JoinGame(address, code)) {
+                            error!("{}", e)
+                        }
+                    }
+                    Err(e) => *error = Some(e),
+                },
+                _ => {}
+            },
+            Screen::Load(input, error) => match e.code {
+                KeyCode::Esc => self.screen = Screen::MainMenu(MainMenuItem::Load.index()),
+                KeyCode::Backspace => {
+                    input.pop();
+                }
+                KeyCode::Char(c) => input.push(c),
+                KeyCode::Enter => match load_start_position(input) {
+                    Ok(start) => self.start_game(GameSetup {
+                        start,
+                        ..GameSetup::default()
+                    }),
+                    Err(e) => *error = Some(e),
+                },
+                _ => {}
+            },
+            Screen::Settings(selected) => match e.code {
+                KeyCode::Up => *selected = selected.saturating_sub(1),
+                KeyCode::Down => *selected = (*selected + 1).min(SETTINGS_ROWS - 1),
+                KeyCode::Esc => {
+                    self.message = None;
+                    self.screen = Screen::MainMenu(MainMenuItem::Settings.index());
+                }
+                KeyCode::Left | KeyCode::Right | KeyCode::Enter => {
+                    let row = *selected;
+                    self.change_setting(row, e.code == KeyCode::Left);
+                }
+                _ => {}
+            },
+            Screen::Playing => {}
+        }
+    }
+
+    /// Does what a form asked for, going back to the form's item in the main menu on Esc.
+    fn form_action(&mut self, action: FormAction, item: MainMenuItem) {
+        match action {
+            FormAction::None => {}
+            FormAction::Back => self.screen = Screen::MainMenu(item.index()),
+            FormAction::Start(setup) => self.start_game(setup),
+            FormAction::Host(setup, address) => {
+                self.awaiting_game = true;
+                if let Err(e) = self.send.send(UiMsg::HostGame(setup, address)) {
+                    error!("{}", e)
+                }
+            }
+            FormAction::Puzzles(setup) => {
+                self.awaiting_game = true;
+                if let Err(e) = self.send.send(UiMsg::StartPuzzles(setup)) {
+                    error!("{}", e)
+                }
+            }
+            FormAction::Repertoire(setup) => {
+                self.awaiting_game = true;
+                if let Err(e) = self.send.send(UiMsg::StartRepertoire(setup)) {
+                    error!("{}", e)
+                }
+            }
+            FormAction::Endgame(setup) => {
+                self.awaiting_game = true;
+                if let Err(e) = self.send.send(UiMsg::StartEndgame(setup)) {
+                    error!("{}", e)
+                }
+            }
+        }
+    }
+
+    fn choose_menu_item(&mut self, item: MainMenuItem) {
+        self.message = None;
+        self.screen = match item {
+            MainMenuItem::NewGame => Screen::NewGame(NewGameForm::default()),
+            MainMenuItem::Load => Screen::Load(String::new(), None),
+            MainMenuItem::Puzzles => Screen::Puzzles(PuzzleForm::default()),
+            MainMenuItem::Openings => Screen::Repertoire(RepertoireForm::default()),
+            MainMenuItem::Endgames => Screen::Endgame(EndgameForm::default()),
+            MainMenuItem::Coordinates => Screen::Coordinates(CoordinateTrainer::default()),
+            MainMenuItem::Resume => match &self.autosave {
+                Some(Err(e)) => {
+                    self.message = Some(e.clone());
+                    Screen::MainMenu(item.index())
+                }
+                _ => {
+                    self.awaiting_game = true;
+                    if let Err(e) = self.send.send(UiMsg::ResumeAutosave) {
+                        error!("{}", e)
+                    }
+                    Screen::MainMenu(item.index())
+                }
+            },
+            MainMenuItem::Host => Screen::NewGame(NewGameForm::host()),
+            MainMenuItem::Join => Screen::Join(String::new(), None),
+            MainMenuItem::Settings => Screen::Settings(0),
+            MainMenuItem::Quit => {
+                self.exit = true;
+                Screen::MainMenu(item.index())
+            }
+        }
+    }
+
+    fn change_setting(&mut self, row: usize, backwards: bool) {
+        match row {
+            0 if backwards => self.theme = (self.theme + self.themes.len() - 1) % self.themes.len(),
+            0 => self.theme = (self.theme + 1) % self.themes.len(),
+            1 => self.piece_style = self.piece_style.next(),
+            2 => self.flip_board = !self.flip_board,
+            3 => self.follow_side_to_move = !self.follow_side_to_move,
+            4 => {
+                self.blindfold = self.blindfold.next();
+                self.notation.clear();
+            }
+            _ => {
+                let config = Config {
+                    theme: Some(self.themes[self.theme].name.clone()),
+                    piece_style: self.piece_style,
+                    flip_board: self.flip_board,
+                    follow_side_to_move: self.follow_side_to_move,
+                    blindfold: self.blindfold,
+                    themes: Vec::new(),
+                };
+                self.message = Some(match config.save_settings() {
+                    Ok(path) => format!("Saved to {}", path.display()),
+                    Err(e) => format!("Could not save settings: {}", e),
+                });
+            }
+        }
+    }
+
+    /// Asks the model for a new game, the screen changes once the model accepts it.
+    fn start_game(&mut self, setup: GameSetup) {
+        info!("Starting game {:?}", setup);
+        self.awaiting_game = true;
+        if let Err(e) = self.send.send(UiMsg::NewGame(setup)) {
+            error!("{}", e)
+        }
+    }
+
     fn handle_key_event(&mut self, e: KeyEvent) {
+        if self.blindfold.is_on() && self.handle_notation_key(e.code) {
+            return;
+        }
         match e.code {
             KeyCode::Char('q') => self.exit = true,
-            KeyCode::Left => self.cursor.move_cursor_left(),
-            KeyCode::Right => self.cursor.move_cursor_right(),
-            KeyCode::Up => self.cursor.move_cursor_up(),
-            KeyCode::Down => self.cursor.move_cursor_down(),
+            KeyCode::Char('m') => {
+                self.square_selected = None;
+                self.reset_valid_positions();
+                self.screen = Screen::MainMenu(0);
+            }
+            KeyCode::Left => {
+                self.cursor.move_cursor_left(self.board_flipped(), self.geometry())
+            }
+            KeyCode::Right => {
+                self.cursor.move_cursor_right(self.board_flipped(), self.geometry())
+            }
+            KeyCode::Up => self.cursor.move_cursor_up(self.board_flipped(), self.geometry()),
+            KeyCode::Down => {
+                self.cursor.move_cursor_down(self.board_flipped(), self.geometry())
+            }
+            KeyCode::Char('f') => self.flip_board = !self.flip_board,
+            KeyCode::Char('v') => self.follow_side_to_move = !self.follow_side_to_move,
+            KeyCode::Char('s') => self.piece_style = self.piece_style.next(),
+            KeyCode::Char('t') => {
+                self.theme = (self.theme + 1) % self.themes.len();
+                info!("Switched to theme {}", self.themes[self.theme].name);
+            }
             KeyCode::Char(' ') => {
                 self.handle_space_pressed();
             }
+            KeyCode::Char('d') => self.cycle_drop_piece(),
+            KeyCode::Char('n') if self.game_data.puzzle.is_some() => {
+                self.square_selected = None;
+                self.reset_valid_positions();
+                if let Err(e) = self.send.send(UiMsg::NextPuzzle) {
+                    error!("{}", e)
+                }
+            }
+            KeyCode::Char('n') if self.game_data.repertoire.is_some() => {
+                self.square_selected = None;
+                self.reset_valid_positions();
+                if let Err(e) = self.send.send(UiMsg::NextLine) {
+                    error!("{}", e)
+                }
+            }
+            KeyCode::Char('n') if self.game_data.endgame.is_some() => {
+                self.square_selected = None;
+                self.reset_valid_positions();
+                if let Err(e) = self.send.send(UiMsg::RetryEndgame) {
+                    error!("{}", e)
+                }
+            }
+            KeyCode::Char('p') if self.in_game => {
+                if let Err(e) = self.send.send(UiMsg::GetPgn) {
+                    error!("{}", e)
+                }
+            }
+            KeyCode::PageUp | KeyCode::Char('k') => self.step_history_back(),
+            KeyCode::PageDown | KeyCode::Char('j') => self.step_history_forward(),
+            KeyCode::End => self.return_to_live(),
             KeyCode::Esc => {
                 self.square_selected = None;
+                self.drop_selected = None;
                 self.reset_valid_positions();
             }
 
@@ -152,15 +742,218 @@ impl EdenChessUi {
         };
     }
 
+    /// Types a move when playing blindfold, taking the keys that can be part of one. Esc
+    /// clears the move, or with nothing typed opens the menu since the letters can't.
+    fn handle_notation_key(&mut self, key: KeyCode) -> bool {
+        match key {
+            KeyCode::Char(c) if c.is_ascii_alphanumeric() || "x+#=-@".contains(c) => {
+                self.notation.push(c)
+            }
+            KeyCode::Backspace => {
+                self.notation.pop();
+            }
+            KeyCode::Enter => self.submit_notation(),
+            KeyCode::Esc if !self.notation.is_empty() => self.notation.clear(),
+            KeyCode::Esc => {
+                self.square_selected = None;
+                self.drop_selected = None;
+                self.reset_valid_positions();
+                self.screen = Screen::MainMenu(0);
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    fn submit_notation(&mut self) {
+        let san = std::mem::take(&mut self.notation);
+        if san.is_empty() {
+            return;
+        }
+        if !self.game_data.human_to_move() {
+            self.message = Some("It isn't your move".to_string());
+            return;
+        }
+        self.return_to_live();
+        self.message = None;
+        if let Err(e) = self.send.send(UiMsg::MakeSanMove(san)) {
+            error!("{}", e)
+        }
+    }
+
+    /// The shape of the board being played, for moving the cursor and finding clicks.
+    fn geometry(&self) -> Geometry {
+        self.board.as_ref().map_or(Geometry::STANDARD, Geometry::of)
+    }
+
+    /// Whether black is at the bottom of the board. When following the side to move the
+    /// manual flip is applied on top of that side's perspective, and in a puzzle, the
+    /// opening trainer or an endgame drill on top of the side being trained.
+    fn board_flipped(&self) -> bool {
+        let data = &self.game_data;
+        let trained = (data.puzzle.as_ref().map(|puzzle| puzzle.side))
+            .or(data.repertoire.as_ref().map(|repertoire| repertoire.side))
+            .or(data.endgame.as_ref().map(|endgame| endgame.side));
+        let bottom_is_black = match trained {
+            Some(side) => side == Side::Black,
+            None => self.follow_side_to_move && self.game_data.which_turn == Side::Black,
+        };
+        bottom_is_black != self.flip_board
+    }
+
+    fn handle_mouse_event(&mut self, e: MouseEvent) {
+        if let (MouseEventKind::Down(MouseButton::Left), Some(pockets)) =
+            (e.kind, &self.game_data.pockets)
+        {
+            let top = if self.board_flipped() {
+                Side::White
+            } else {
+                Side::Black
+            };
+            let clicked = Pocket::new(pockets, top).piece_at(self.pocket_area, e.column, e.row);
+            if let Some((side, piece_type)) = clicked {
+                if side == self.game_data.which_turn {
+                    self.select_drop_piece(piece_type);
+                }
+                return;
+            }
+        }
+        let Some(pos) = Chessboard::position_at(
+            self.board_area,
+            e.column,
+            e.row,
+            self.board_flipped(),
+            self.geometry(),
+        ) else {
+            return;
+        };
+        match e.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.cursor = pos;
+                self.handle_space_pressed();
+            }
+            MouseEventKind::Drag(MouseButton::Left) => self.cursor = pos,
+            // Releasing over a different square finishes a drag as a move
+            MouseEventKind::Up(MouseButton::Left)
+                if self.square_selected.is_some_and(|selected| selected != pos) =>
+            {
+                self.cursor = pos;
+                self.handle_space_pressed();
+            }
+            _ => {}
+        }
+    }
+
+    /// Clicks outside the game, only the coordinate trainer's board takes them.
+    fn handle_menu_mouse_event(&mut self, e: MouseEvent) {
+        let Screen::Coordinates(trainer) = &mut self.screen else {
+            return;
+        };
+        if let MouseEventKind::Down(MouseButton::Left) = e.kind {
+            let clicked = Chessboard::position_at(
+                self.board_area,
+                e.column,
+                e.row,
+                trainer.flipped(),
+                Geometry::STANDARD,
+            );
+            if let Some(pos) = clicked {
+                trainer.click(pos);
+            }
+        }
+    }
+
+    fn step_history_back(&mut self) {
+        let current = self
+            .game_data
+            .viewing_ply
+            .unwrap_or(self.game_data.move_history.len());
+        if current > 0 {
+            self.view_ply(current - 1);
+        }
+    }
+
+    fn step_history_forward(&mut self) {
+        if let Some(ply) = self.game_data.viewing_ply {
+            if ply + 1 >= self.game_data.move_history.len() {
+                self.return_to_live();
+            } else {
+                self.view_ply(ply + 1);
+            }
+        }
+    }
+
+    /// Shows the position after `ply` moves, the board is read only until returning to live.
+    fn view_ply(&mut self, ply: usize) {
+        self.game_data.viewing_ply = Some(ply);
+        self.square_selected = None;
+        self.reset_valid_positions();
+        if let Err(e) = self.send.send(UiMsg::GetPositionAt(ply)) {
+            error!("{}", e)
+        }
+    }
+
+    fn return_to_live(&mut self) {
+        self.game_data.viewing_ply = None;
+        self.preview_board = None;
+    }
+
+    /// Chooses the next kind of piece in the pocket of the side to move for dropping, and
+    /// back to none after the last.
+    fn cycle_drop_piece(&mut self) {
+        let Some(pockets) = &self.game_data.pockets else {
+            return;
+        };
+        let piece_types = Pocket::piece_types(pockets, self.game_data.which_turn);
+        let next = match self.drop_selected {
+            Some(selected) => piece_types
+                .iter()
+                .skip_while(|p| **p != selected)
+                .nth(1)
+                .copied(),
+            None => piece_types.first().copied(),
+        };
+        match next {
+            Some(piece_type) => self.select_drop_piece(piece_type),
+            None => {
+                self.drop_selected = None;
+                self.reset_valid_positions();
+            }
+        }
+    }
+
+    fn select_drop_piece(&mut self, piece_type: PieceType) {
+        if self.game_data.viewing_ply.is_some() || !self.game_data.human_to_move() {
+            return;
+        }
+        self.square_selected = None;
+        self.drop_selected = Some(piece_type);
+        self.reset_valid_positions();
+        let _ = self.send.send(UiMsg::GetValidDrops(piece_type));
+    }
+
     fn handle_space_pressed(&mut self) {
-        if let Some(selected_pos) = self.square_selected {
+        if self.game_data.viewing_ply.is_some()
+            || !self.game_data.human_to_move()
+            || self.blindfold.is_on()
+        {
+            return;
+        }
+        if let Some(piece_type) = self.drop_selected.take() {
+            if self
+                .valid_moves
+                .as_ref()
+                .is_some_and(|valids| valids.contains(&self.cursor))
+            {
+                if let Err(e) = self.send.send(UiMsg::DropPiece(piece_type, self.cursor)) {
+                    error!("{}", e)
+                };
+            }
+        } else if let Some(selected_pos) = self.square_selected {
             if let Some(valids) = &self.valid_moves {
                 if selected_pos != self.cursor && valids.contains(&self.cursor) {
                     if let Err(e) = self.send.send(UiMsg::MakeMove((selected_pos, self.cursor))) {
                         error!("{}", e)
-                    } else {
-                        self.game_data.turn_count += 1;
-                        self.game_data.which_turn.flip();
                     };
                 }
                 self.square_selected = None;
@@ -181,33 +974,134 @@ impl EdenChessUi {
         self.reset_valid_positions();
     }
 
-    fn add_move_to_move_history(&mut self, selected: CBPosition) {
-        if let Some(board) = &self.board {
-            if let Some(piece) = board.get(&selected).expect("Selected should be in board") {
-                let move_str = String::new();
-            } else {
-                error!("Selected place isnt occupied!")
+    fn handle_model_events(&mut self) {
+        loop {
+            match self.recv.try_recv() {
+                Ok(msg) => self.handle_model_message(msg),
+                Err(std::sync::mpsc::TryRecvError::Empty) => break,
+                Err(e) => {
+                    error!("{}", e);
+                    self.exit = true;
+                    break;
+                }
             }
-        };
+        }
     }
 
-    fn handle_model_events(&mut self) {
-        match self.recv.try_recv() {
-            Ok(msg) => match msg {
-                ModelMsg::Debug(d) => debug!("{}", d),
-                ModelMsg::MoveIsInvalid => (),
-                ModelMsg::Moves(ms) => self.valid_moves = Some(ms),
-                ModelMsg::BoardState(b) => self.board = Some(b),
-                ModelMsg::Stalemate => todo!(),
-                ModelMsg::Checkmate(_) => todo!(),
-            },
-            Err(e) => match e {
-                std::sync::mpsc::TryRecvError::Empty => {}
-                std::sync::mpsc::TryRecvError::Disconnected => error!("{}", e),
-            },
+    fn handle_model_message(&mut self, msg: ModelMsg) {
+        match msg {
+            ModelMsg::Debug(d) => debug!("{}", d),
+            ModelMsg::MoveIsValid | ModelMsg::MoveIsInvalid => (),
+            ModelMsg::Moves(ms) => self.valid_moves = Some(ms),
+            ModelMsg::LegalMoves(_) => (),
+            ModelMsg::BoardState(b) => self.board = Some(b),
+            ModelMsg::Pgn(text) => {
+                self.message = Some(match save_pgn(&text) {
+                    Ok(path) => format!("Game added to {}", path.display()),
+                    Err(e) => format!("Could not save the game: {}", e),
+                });
+            }
+            ModelMsg::PositionAt(ply, b) => {
+                if self.game_data.viewing_ply == Some(ply) {
+                    self.preview_board = Some(b)
+                }
+            }
+            ModelMsg::GameStatus(status) => {
+                if self.awaiting_game {
+                    self.awaiting_game = false;
+                    self.in_game = true;
+                    self.message = None;
+                    self.return_to_live();
+                    self.square_selected = None;
+                    self.drop_selected = None;
+                    self.reset_valid_positions();
+                    self.screen = Screen::Playing;
+                }
+                if let Some(result) = status.result {
+                    self.message = Some(format!("{}. Press m for the menu", result));
+                }
+                if let Some(puzzle) = &status.puzzle {
+                    self.message = match &puzzle.state {
+                        PuzzleState::Solving => None,
+                        state => Some(format!(
+                            "{}. Your puzzle rating is {}, press n for the next puzzle",
+                            state, puzzle.solver_rating
+                        )),
+                    };
+                }
+                if let Some(repertoire) = &status.repertoire {
+                    self.message = if repertoire.line_done {
+                        Some(format!(
+                            "Line complete with {} mistakes, press n for the next line",
+                            repertoire.mistakes
+                        ))
+                    } else if repertoire.expected.is_some() {
+                        Some("Not in your repertoire, play the highlighted move".to_string())
+                    } else {
+                        None
+                    };
+                }
+                if let Some(endgame) = &status.endgame {
+                    self.message = match (&endgame.state, endgame.within_optimal()) {
+                        (EndgameState::Playing, _) => None,
+                        (EndgameState::Succeeded, Some(true)) => Some(format!(
+                            "Done in {} moves, the fewest possible. Press n to go again",
+                            endgame.moves
+                        )),
+                        (EndgameState::Succeeded, Some(false)) => Some(format!(
+                            "Done in {} moves, {} was possible. Press n to go again",
+                            endgame.moves,
+                            endgame.optimal.unwrap_or_default()
+                        )),
+                        (state, _) => Some(format!("{}. Press n to go again", state)),
+                    };
+                }
+                self.game_data.update(status)
+            }
+            ModelMsg::Stalemate => info!("Stalemate"),
+            ModelMsg::Checkmate(winner) => info!("Checkmate, {:?} wins", winner),
+            ModelMsg::OutOfTime(loser) => info!("{:?} ran out of time", loser),
+            ModelMsg::Error(e) => {
+                warn!("{}", e);
+                self.awaiting_game = false;
+                match &mut self.screen {
+                    Screen::NewGame(form) => form.set_error(e),
+                    Screen::Puzzles(form) => form.set_error(e),
+                    Screen::Repertoire(form) => form.set_error(e),
+                    Screen::Endgame(form) => form.set_error(e),
+                    Screen::Load(_, error) | Screen::Join(_, error) => *error = Some(e),
+                    _ => self.message = Some(e),
+                }
+            }
+            ModelMsg::Network(notice) => {
+                info!("{}", notice);
+                match &mut self.screen {
+                    Screen::Join(_, error) => *error = Some(notice),
+                    _ => self.message = Some(notice),
+                }
+            }
         }
     }
     fn reset_valid_positions(&mut self) {
         self.valid_moves = None;
     }
 }
+
+/// Adds a game to `eden_chess/games.pgn` in the platform data directory, the history of
+/// games saved with `p`.
+fn save_pgn(text: &str) -> std::result::Result<PathBuf, String> {
+    let path = dirs::data_dir()
+        .ok_or("There is no data directory")?
+        .join("eden_chess")
+        .join("games.pgn");
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+    }
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .map_err(|e| e.to_string())?;
+    writeln!(file, "{}", text).map_err(|e| e.to_string())?;
+    Ok(path)
+}
@@ -0,0 +1,184 @@
+use serde::Deserialize;
+
+use crate::model::{Piece, PieceType, Side};
+
+/// How pieces are drawn inside their squares.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PieceStyle {
+    /// FEN style letters, upper case for white and lower case for black
+    #[default]
+    Letters,
+    /// The unicode chess symbols, hollow for white and filled for black
+    Unicode,
+    /// Multi-line ascii art sized to the squares, falling back to letters when they are too
+    /// small for any
+    Art,
+}
+
+const ART_WIDTH: u16 = 5;
+const ART_HEIGHT: u16 = 3;
+const LARGE_ART_WIDTH: u16 = 7;
+const LARGE_ART_HEIGHT: u16 = 5;
+
+impl PieceStyle {
+    pub fn next(&self) -> PieceStyle {
+        match self {
+            PieceStyle::Letters => PieceStyle::Unicode,
+            PieceStyle::Unicode => PieceStyle::Art,
+            PieceStyle::Art => PieceStyle::Letters,
+        }
+    }
+
+    /// The name used for the style in the config file.
+    pub fn name(&self) -> &'static str {
+        match self {
+            PieceStyle::Letters => "letters",
+            PieceStyle::Unicode => "unicode",
+            PieceStyle::Art => "art",
+        }
+    }
+
+    /// The lines used to draw a piece in a square of the given size, the largest art that
+    /// fits for the art style. Fairy pieces have no symbol or art of their own and are drawn
+    /// as letters.
+    pub fn glyph(&self, piece: &Piece, width: u16, height: u16) -> Vec<&'static str> {
+        let art = if width >= LARGE_ART_WIDTH && height >= LARGE_ART_HEIGHT {
+            large_art(piece).map(|art| art.to_vec())
+        } else if width >= ART_WIDTH && height >= ART_HEIGHT {
+            art(piece).map(|art| art.to_vec())
+        } else {
+            None
+        };
+        match (self, art) {
+            (PieceStyle::Unicode, _) => vec![unicode(piece).unwrap_or(letter(piece))],
+            (PieceStyle::Art, Some(art)) => art,
+            _ => vec![letter(piece)],
+        }
+    }
+}
+
+fn letter(piece: &Piece) -> &'static str {
+    match (piece.side, piece.piece_type) {
+        (Side::White, PieceType::King) => "K",
+        (Side::White, PieceType::Queen) => "Q",
+        (Side::White, PieceType::Rook) => "R",
+        (Side::White, PieceType::Bishop) => "B",
+        (Side::White, PieceType::Knight) => "N",
+        (Side::White, PieceType::Pawn) => "P",
+        (Side::Black, PieceType::King) => "k",
+        (Side::Black, PieceType::Queen) => "q",
+        (Side::Black, PieceType::Rook) => "r",
+        (Side::Black, PieceType::Bishop) => "b",
+        (Side::Black, PieceType::Knight) => "n",
+        (Side::Black, PieceType::Pawn) => "p",
+        (Side::White, PieceType::Archbishop) => "A",
+        (Side::White, PieceType::Chancellor) => "C",
+        (Side::White, PieceType::Amazon) => "M",
+        (Side::White, PieceType::Camel) => "L",
+        (Side::White, PieceType::Zebra) => "Z",
+        (Side::White, PieceType::Nightrider) => "H",
+        (Side::Black, PieceType::Archbishop) => "a",
+        (Side::Black, PieceType::Chancellor) => "c",
+        (Side::Black, PieceType::Amazon) => "m",
+        (Side::Black, PieceType::Camel) => "l",
+        (Side::Black, PieceType::Zebra) => "z",
+        (Side::Black, PieceType::Nightrider) => "h",
+        (side, PieceType::Custom(letter)) => {
+            const LETTERS: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz";
+            let letter = match side {
+                Side::White => letter.to_ascii_uppercase(),
+                Side::Black => letter.to_ascii_lowercase(),
+            };
+            LETTERS.find(letter).map_or("?", |i| &LETTERS[i..i + 1])
+        }
+    }
+}
+
+fn unicode(piece: &Piece) -> Option<&'static str> {
+    let symbol = match (piece.side, piece.piece_type) {
+        (Side::White, PieceType::King) => "♔",
+        (Side::White, PieceType::Queen) => "♕",
+        (Side::White, PieceType::Rook) => "♖",
+        (Side::White, PieceType::Bishop) => "♗",
+        (Side::White, PieceType::Knight) => "♘",
+        (Side::White, PieceType::Pawn) => "♙",
+        (Side::Black, PieceType::King) => "♚",
+        (Side::Black, PieceType::Queen) => "♛",
+        (Side::Black, PieceType::Rook) => "♜",
+        (Side::Black, PieceType::Bishop) => "♝",
+        (Side::Black, PieceType::Knight) => "♞",
+        (Side::Black, PieceType::Pawn) => "♟",
+        _ => return None,
+    };
+    Some(symbol)
+}
+
+fn art(piece: &Piece) -> Option<[&'static str; ART_HEIGHT as usize]> {
+    let art = match (piece.side, piece.piece_type) {
+        (Side::White, PieceType::King) => [" _+_ ", " \\ / ", " /_\\ "],
+        (Side::White, PieceType::Queen) => [" \\^/ ", " ) ( ", " /_\\ "],
+        (Side::White, PieceType::Rook) => ["|-|-|", " | | ", " /_\\ "],
+        (Side::White, PieceType::Bishop) => ["  o  ", " ( ) ", " /_\\ "],
+        (Side::White, PieceType::Knight) => [" _/| ", "/o ) ", " /_\\ "],
+        (Side::White, PieceType::Pawn) => ["  _  ", " ( ) ", " /_\\ "],
+        (Side::Black, PieceType::King) => [" _+_ ", " \\#/ ", " /#\\ "],
+        (Side::Black, PieceType::Queen) => [" \\^/ ", " )#( ", " /#\\ "],
+        (Side::Black, PieceType::Rook) => ["|-|-|", " |#| ", " /#\\ "],
+        (Side::Black, PieceType::Bishop) => ["  o  ", " (#) ", " /#\\ "],
+        (Side::Black, PieceType::Knight) => [" _/| ", "/o#) ", " /#\\ "],
+        (Side::Black, PieceType::Pawn) => ["  _  ", " (#) ", " /#\\ "],
+        _ => return None,
+    };
+    Some(art)
+}
+
+/// Art for squares with room for it, the same pieces drawn bigger.
+fn large_art(piece: &Piece) -> Option<[&'static str; LARGE_ART_HEIGHT as usize]> {
+    let art = match (piece.side, piece.piece_type) {
+        (Side::White, PieceType::King) => ["  _+_  ", " (   ) ", "  \\ /  ", "  | |  ", " /___\\ "],
+        (Side::White, PieceType::Queen) => {
+            [" \\ ^ / ", "  ) (  ", "  | |  ", "  | |  ", " /___\\ "]
+        }
+        (Side::White, PieceType::Rook) => [" |_|_| ", "  | |  ", "  | |  ", "  | |  ", " /___\\ "],
+        (Side::White, PieceType::Bishop) => {
+            ["   o   ", "  (/)  ", "  ( )  ", "  | |  ", " /___\\ "]
+        }
+        (Side::White, PieceType::Knight) => {
+            ["  ,^.  ", " / o \\ ", "(_/  | ", "  /  | ", " /___\\ "]
+        }
+        (Side::White, PieceType::Pawn) => ["       ", "   _   ", "  ( )  ", "  | |  ", " /___\\ "],
+        (Side::Black, PieceType::King) => ["  _+_  ", " (###) ", "  \\#/  ", "  |#|  ", " /###\\ "],
+        (Side::Black, PieceType::Queen) => {
+            [" \\ ^ / ", "  )#(  ", "  |#|  ", "  |#|  ", " /###\\ "]
+        }
+        (Side::Black, PieceType::Rook) => [" |_|_| ", "  |#|  ", "  |#|  ", "  |#|  ", " /###\\ "],
+        (Side::Black, PieceType::Bishop) => {
+            ["   o   ", "  (/)  ", "  (#)  ", "  |#|  ", " /###\\ "]
+        }
+        (Side::Black, PieceType::Knight) => {
+            ["  ,^.  ", " /#o#\\ ", "(_/##| ", "  /##| ", " /###\\ "]
+        }
+        (Side::Black, PieceType::Pawn) => ["       ", "   _   ", "  (#)  ", "  |#|  ", " /###\\ "],
+        _ => return None,
+    };
+    Some(art)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn art_fits_the_square() {
+        let knight = Piece::new(Side::White, PieceType::Knight, "g1".into());
+        let style = PieceStyle::Art;
+        assert_eq!(style.glyph(&knight, 3, 1), ["N"]);
+        assert_eq!(style.glyph(&knight, 6, 4).len(), ART_HEIGHT as usize);
+        let large = style.glyph(&knight, 9, 5);
+        assert_eq!(large.len(), LARGE_ART_HEIGHT as usize);
+        assert!(large
+            .iter()
+            .all(|line| line.chars().count() == LARGE_ART_WIDTH as usize));
+    }
+}
@@ -0,0 +1,114 @@
+use ratatui::prelude::*;
+use ratatui::widgets::{Block, Borders, Paragraph, Widget};
+
+use super::pieces::PieceStyle;
+use crate::control::{CBPosition, Pockets};
+use crate::model::{Piece, PieceType, Side};
+
+/// The order pieces are listed in a pocket
+const ORDER: [PieceType; 11] = [
+    PieceType::Amazon,
+    PieceType::Queen,
+    PieceType::Chancellor,
+    PieceType::Archbishop,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+    PieceType::Nightrider,
+    PieceType::Camel,
+    PieceType::Zebra,
+    PieceType::Pawn,
+];
+
+/// The pieces both sides can drop in Crazyhouse, drawn beside the board with the side at
+/// the top of the board on top. The piece chosen for dropping is highlighted.
+pub struct Pocket<'a> {
+    pockets: &'a Pockets,
+    top: Side,
+    selected: Option<(Side, PieceType)>,
+    piece_style: PieceStyle,
+}
+
+impl<'a> Pocket<'a> {
+    pub fn new(pockets: &'a Pockets, top: Side) -> Pocket<'a> {
+        Pocket {
+            pockets,
+            top,
+            selected: None,
+            piece_style: PieceStyle::default(),
+        }
+    }
+    pub fn selected(mut self, selected: Option<(Side, PieceType)>) -> Pocket<'a> {
+        self.selected = selected;
+        self
+    }
+    pub fn piece_style(mut self, piece_style: PieceStyle) -> Pocket<'a> {
+        self.piece_style = piece_style;
+        self
+    }
+
+    /// The kinds of piece `side` holds, in the order they are listed.
+    pub fn piece_types(pockets: &Pockets, side: Side) -> Vec<PieceType> {
+        ORDER
+            .into_iter()
+            .filter(|piece_type| pockets.count(side, *piece_type) > 0)
+            .collect()
+    }
+
+    /// The areas of the top and bottom pockets, each a bordered box.
+    fn halves(area: Rect) -> (Rect, Rect) {
+        let halves = Layout::default()
+            .constraints([Constraint::Length(7), Constraint::Length(7)])
+            .split(area);
+        (halves[0], halves[1])
+    }
+
+    /// Finds the piece listed under a terminal cell, using the same layout as `render`.
+    pub fn piece_at(&self, area: Rect, x: u16, y: u16) -> Option<(Side, PieceType)> {
+        let (top, bottom) = Pocket::halves(area);
+        [(top, self.top), (bottom, self.top.flipped())]
+            .into_iter()
+            .find_map(|(half, side)| {
+                let inner = Block::new().borders(Borders::ALL).inner(half);
+                if x < inner.left() || x >= inner.right() || y < inner.top() {
+                    return None;
+                }
+                Pocket::piece_types(self.pockets, side)
+                    .get((y - inner.top()) as usize)
+                    .map(|piece_type| (side, *piece_type))
+            })
+    }
+}
+
+impl<'a> Widget for Pocket<'a> {
+    fn render(self, area: Rect, buf: &mut Buffer)
+    where
+        Self: Sized,
+    {
+        let (top, bottom) = Pocket::halves(area);
+        for (half, side) in [(top, self.top), (bottom, self.top.flipped())] {
+            let block = Block::new().title(String::from(side)).borders(Borders::ALL);
+            let inner = block.inner(half);
+            block.render(half, buf);
+            let lines: Vec<Line> = Pocket::piece_types(self.pockets, side)
+                .into_iter()
+                .map(|piece_type| {
+                    // Only the piece's look matters here, not where it stands
+                    let piece = Piece::new(side, piece_type, CBPosition { col: 'a', row: 1 });
+                    let glyph = self.piece_style.glyph(&piece, 1, 1).concat();
+                    let line = Line::from(format!(
+                        "{} x{}",
+                        glyph,
+                        self.pockets.count(side, piece_type)
+                    ));
+                    if self.selected == Some((side, piece_type)) {
+                        line.reversed()
+                    } else {
+                        line
+                    }
+                })
+                .collect();
+            Paragraph::new(lines).render(inner, buf);
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use ratatui::style::Color;
+use serde::Deserialize;
+
+/// The colours used to draw the board.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default = "Theme::unnamed")]
+pub struct Theme {
+    pub name: String,
+    pub dark_square: Color,
+    pub light_square: Color,
+    pub dark_square_selected: Color,
+    pub light_square_selected: Color,
+    pub valid_move: Color,
+    /// Valid moves that take a piece
+    pub capture_move: Color,
+    /// The from and to squares of the last move
+    pub last_move: Color,
+    /// The king of the side in check
+    pub check: Color,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme::classic()
+    }
+}
+
+impl Theme {
+    pub fn classic() -> Theme {
+        Theme {
+            name: "classic".to_string(),
+            dark_square: Color::Rgb(76, 58, 46),
+            light_square: Color::Rgb(245, 226, 183),
+            dark_square_selected: Color::Rgb(115, 93, 78),
+            light_square_selected: Color::Rgb(184, 120, 134),
+            valid_move: Color::Rgb(50, 255, 50),
+            capture_move: Color::Rgb(255, 140, 0),
+            last_move: Color::Rgb(170, 162, 58),
+            check: Color::Rgb(220, 40, 40),
+        }
+    }
+
+    /// The classic colours under no name, for the colours a custom theme leaves out. The
+    /// name has to be given, see `Config`.
+    fn unnamed() -> Theme {
+        Theme {
+            name: String::new(),
+            ..Theme::classic()
+        }
+    }
+
+    pub fn high_contrast() -> Theme {
+        Theme {
+            name: "high-contrast".to_string(),
+            dark_square: Color::Rgb(40, 40, 40),
+            light_square: Color::Rgb(235, 235, 235),
+            dark_square_selected: Color::Rgb(0, 90, 255),
+            light_square_selected: Color::Rgb(255, 215, 0),
+            valid_move: Color::Rgb(0, 220, 0),
+            capture_move: Color::Rgb(255, 0, 255),
+            last_move: Color::Rgb(0, 200, 200),
+            check: Color::Rgb(255, 0, 0),
+        }
+    }
+
+    /// Built from the Okabe-Ito palette, which stays distinguishable with the common forms
+    /// of colour blindness.
+    pub fn colour_blind() -> Theme {
+        Theme {
+            name: "colour-blind".to_string(),
+            dark_square: Color::Rgb(0, 114, 178),
+            light_square: Color::Rgb(220, 220, 220),
+            dark_square_selected: Color::Rgb(213, 94, 0),
+            light_square_selected: Color::Rgb(230, 159, 0),
+            valid_move: Color::Rgb(240, 228, 66),
+            capture_move: Color::Rgb(0, 158, 115),
+            last_move: Color::Rgb(86, 180, 233),
+            check: Color::Rgb(204, 121, 167),
+        }
+    }
+
+    /// Only uses the 16 named colours, for terminals without true colour support.
+    pub fn sixteen_colour() -> Theme {
+        Theme {
+            name: "16-colour".to_string(),
+            dark_square: Color::DarkGray,
+            light_square: Color::Gray,
+            dark_square_selected: Color::Magenta,
+            light_square_selected: Color::Yellow,
+            valid_move: Color::Green,
+            capture_move: Color::LightRed,
+            last_move: Color::Cyan,
+            check: Color::Red,
+        }
+    }
+
+    pub fn presets() -> Vec<Theme> {
+        vec![
+            Theme::classic(),
+            Theme::high_contrast(),
+            Theme::colour_blind(),
+            Theme::sixteen_colour(),
+        ]
+    }
+}